@@ -0,0 +1,15 @@
+CREATE TABLE "transaction_attachment" (
+    id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+    transaction_id uuid NOT NULL REFERENCES "transaction" (id)
+        ON DELETE CASCADE,
+    user_id TEXT NOT NULL,
+    -- Name of the file as provided by the client when it was uploaded.
+    file_name TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    -- Size of the attachment's content in bytes.
+    size BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX ON "transaction_attachment"(transaction_id);
+CREATE INDEX ON "transaction_attachment"(user_id);
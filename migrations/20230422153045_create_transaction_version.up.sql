@@ -0,0 +1,53 @@
+-- Every change to a transaction is recorded as a new version containing a
+-- snapshot of the transaction as it was after the change. Versions are kept
+-- even after the transaction itself is deleted, so there is no foreign key to
+-- the transaction table.
+CREATE TABLE "transaction_version" (
+    id BIGSERIAL PRIMARY KEY,
+    transaction_id uuid NOT NULL,
+    user_id TEXT NOT NULL,
+    version INTEGER NOT NULL,
+    -- One of 'created', 'updated', 'deleted', or 'restored'.
+    action TEXT NOT NULL,
+    -- The ID of the user who made the change.
+    actor TEXT NOT NULL,
+    snapshot JSONB NOT NULL,
+    recorded_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+
+    UNIQUE (transaction_id, version)
+);
+
+CREATE INDEX ON "transaction_version"(user_id);
+
+-- Seed the history of existing transactions with their current state so that
+-- future changes have a version to be compared against.
+INSERT INTO "transaction_version" (transaction_id, user_id, version, action, actor, snapshot, recorded_at)
+SELECT
+    t.id,
+    t.user_id,
+    1,
+    'created',
+    t.user_id,
+    jsonb_build_object(
+        'date', t.date,
+        'payee', t.payee,
+        'notes', t.notes,
+        'entries', COALESCE(
+            (
+                SELECT jsonb_agg(
+                    jsonb_build_object(
+                        'account', a.name,
+                        'currency', e.currency,
+                        'amount', e.amount
+                    )
+                    ORDER BY e."order"
+                )
+                FROM transaction_entry e
+                    JOIN account a ON a.id = e.account_id
+                WHERE e.transaction_id = t.id
+            ),
+            '[]'::jsonb
+        )
+    ),
+    t.updated_at
+FROM "transaction" t;
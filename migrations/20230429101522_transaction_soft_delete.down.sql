@@ -0,0 +1,5 @@
+DELETE FROM "transaction"
+WHERE deleted_at IS NOT NULL;
+
+ALTER TABLE "transaction"
+    DROP COLUMN deleted_at;
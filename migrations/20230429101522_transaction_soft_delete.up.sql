@@ -0,0 +1,8 @@
+-- Deleted transactions are moved to the trash by setting `deleted_at`. They
+-- are permanently removed once they have been in the trash for longer than
+-- the configured retention period.
+ALTER TABLE "transaction"
+    ADD COLUMN deleted_at TIMESTAMPTZ;
+
+CREATE INDEX ON "transaction"(deleted_at)
+    WHERE deleted_at IS NOT NULL;
@@ -0,0 +1,2 @@
+DROP TABLE "ledger_lock_change";
+DROP TABLE "ledger_lock";
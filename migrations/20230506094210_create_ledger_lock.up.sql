@@ -0,0 +1,20 @@
+-- Transactions dated on or before a user's lock date can no longer be
+-- created, changed, or deleted.
+CREATE TABLE "ledger_lock" (
+    user_id TEXT PRIMARY KEY,
+    -- A null lock date means the ledger has been fully unlocked.
+    lock_date DATE,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Every change to a user's lock date is recorded so that it is possible to
+-- see when a period was closed or reopened.
+CREATE TABLE "ledger_lock_change" (
+    id BIGSERIAL PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    previous_lock_date DATE,
+    lock_date DATE,
+    changed_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX ON "ledger_lock_change"(user_id);
@@ -0,0 +1,17 @@
+-- Records the closing transaction generated for each closed period so that
+-- closing the same period again updates the existing transaction instead of
+-- creating another one.
+CREATE TABLE "period_closing" (
+    user_id TEXT NOT NULL,
+    period_start DATE NOT NULL,
+    period_end DATE NOT NULL,
+    equity_account TEXT NOT NULL,
+    -- Null if there was nothing to close, or the closing transaction has
+    -- since been purged.
+    transaction_id uuid REFERENCES "transaction" (id)
+        ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+
+    PRIMARY KEY (user_id, period_end)
+);
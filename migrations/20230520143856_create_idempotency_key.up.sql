@@ -0,0 +1,17 @@
+-- Idempotency keys let clients safely retry requests. The response to the
+-- first request made with a key is stored and returned for any retries.
+CREATE TABLE "idempotency_key" (
+    user_id TEXT NOT NULL,
+    key TEXT NOT NULL,
+    -- Hash of the request body, used to detect a key being reused for a
+    -- different request.
+    request_hash TEXT NOT NULL,
+    -- Both null while the original request is still being processed.
+    response_status SMALLINT,
+    response_body JSONB,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+
+    PRIMARY KEY (user_id, key)
+);
+
+CREATE INDEX ON "idempotency_key"(created_at);
@@ -0,0 +1,2 @@
+ALTER TABLE "transaction_version"
+    DROP COLUMN transaction_xid;
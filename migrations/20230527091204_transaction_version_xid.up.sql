@@ -0,0 +1,7 @@
+-- The ID of the database transaction that recorded each version. Sequence
+-- values are not guaranteed to become visible in order, so the change feed
+-- uses this to only hand out versions whose transaction has finished.
+ALTER TABLE "transaction_version"
+    ADD COLUMN transaction_xid xid8 NOT NULL DEFAULT pg_current_xact_id();
+
+CREATE INDEX ON "transaction_version"(user_id, transaction_xid, id);
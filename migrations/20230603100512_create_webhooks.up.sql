@@ -0,0 +1,62 @@
+-- Endpoints that users have registered to receive webhook events.
+CREATE TABLE "webhook_endpoint" (
+    id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id TEXT NOT NULL,
+    url TEXT NOT NULL,
+    -- Used to sign the requests sent to the endpoint.
+    secret TEXT NOT NULL,
+    events TEXT[] NOT NULL,
+    -- The balance threshold watched by the endpoint, if it subscribes to
+    -- balance threshold events.
+    threshold_account TEXT,
+    threshold_currency TEXT,
+    threshold_amount BIGINT,
+    -- Whether the watched balance was at or above the threshold after the last
+    -- change, so that crossing the threshold can be detected.
+    threshold_above BOOLEAN,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX ON "webhook_endpoint"(user_id);
+
+-- The outbox of events. Events are written in the same database transaction
+-- as the change that caused them, so an event is never lost or sent for a
+-- change that was rolled back.
+CREATE TABLE "webhook_event" (
+    id BIGSERIAL PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    event_type TEXT NOT NULL,
+    data JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- The delivery of an event to a single endpoint.
+CREATE TABLE "webhook_delivery" (
+    id BIGSERIAL PRIMARY KEY,
+    event_id BIGINT NOT NULL REFERENCES "webhook_event" (id)
+        ON DELETE CASCADE,
+    endpoint_id uuid NOT NULL REFERENCES "webhook_endpoint" (id)
+        ON DELETE CASCADE,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    delivered_at TIMESTAMPTZ,
+    -- Set once the delivery has failed too many times to be retried.
+    abandoned_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX ON "webhook_delivery"(next_attempt_at)
+    WHERE delivered_at IS NULL AND abandoned_at IS NULL;
+CREATE INDEX ON "webhook_delivery"(endpoint_id);
+
+CREATE TABLE "webhook_delivery_attempt" (
+    id BIGSERIAL PRIMARY KEY,
+    delivery_id BIGINT NOT NULL REFERENCES "webhook_delivery" (id)
+        ON DELETE CASCADE,
+    -- Null if the endpoint could not be reached.
+    response_status SMALLINT,
+    error TEXT,
+    attempted_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX ON "webhook_delivery_attempt"(delivery_id);
@@ -0,0 +1,9 @@
+-- Token buckets used to rate limit users' requests when server instances share
+-- their limits. Each user has a bucket for each class of request.
+CREATE TABLE "rate_limit_bucket" (
+    user_id TEXT NOT NULL,
+    request_class TEXT NOT NULL,
+    tokens DOUBLE PRECISION NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (user_id, request_class)
+);
@@ -0,0 +1,115 @@
+-- Only personal books can be mapped back to a single user, so the contents of
+-- every other book are lost.
+DELETE FROM "book"
+WHERE personal_user_id IS NULL;
+
+ALTER TABLE "account" ADD COLUMN user_id TEXT;
+ALTER TABLE "transaction" ADD COLUMN user_id TEXT;
+ALTER TABLE "transaction_attachment" ADD COLUMN user_id TEXT;
+ALTER TABLE "transaction_version" ADD COLUMN user_id TEXT;
+ALTER TABLE "ledger_lock" ADD COLUMN user_id TEXT;
+ALTER TABLE "ledger_lock_change" ADD COLUMN user_id TEXT;
+ALTER TABLE "period_closing" ADD COLUMN user_id TEXT;
+ALTER TABLE "webhook_endpoint" ADD COLUMN user_id TEXT;
+ALTER TABLE "webhook_event" ADD COLUMN user_id TEXT;
+
+UPDATE "account" t SET user_id = b.personal_user_id FROM "book" b WHERE b.id = t.book_id;
+UPDATE "transaction" t SET user_id = b.personal_user_id FROM "book" b WHERE b.id = t.book_id;
+UPDATE "transaction_attachment" t SET user_id = b.personal_user_id FROM "book" b WHERE b.id = t.book_id;
+UPDATE "transaction_version" t SET user_id = b.personal_user_id FROM "book" b WHERE b.id = t.book_id;
+UPDATE "ledger_lock" t SET user_id = b.personal_user_id FROM "book" b WHERE b.id = t.book_id;
+UPDATE "ledger_lock_change" t SET user_id = b.personal_user_id FROM "book" b WHERE b.id = t.book_id;
+UPDATE "period_closing" t SET user_id = b.personal_user_id FROM "book" b WHERE b.id = t.book_id;
+UPDATE "webhook_endpoint" t SET user_id = b.personal_user_id FROM "book" b WHERE b.id = t.book_id;
+UPDATE "webhook_event" t SET user_id = b.personal_user_id FROM "book" b WHERE b.id = t.book_id;
+
+DROP FUNCTION get_or_create_account(uuid, text);
+
+ALTER TABLE "account"
+    DROP COLUMN book_id,
+    ALTER COLUMN user_id SET NOT NULL,
+    ADD CONSTRAINT "account_name_user_unique" UNIQUE (user_id, name);
+
+ALTER TABLE "transaction"
+    DROP COLUMN book_id,
+    ALTER COLUMN user_id SET NOT NULL;
+
+ALTER TABLE "transaction_attachment"
+    DROP COLUMN book_id,
+    ALTER COLUMN user_id SET NOT NULL;
+
+CREATE INDEX ON "transaction_attachment"(user_id);
+
+ALTER TABLE "transaction_version"
+    DROP COLUMN book_id,
+    ALTER COLUMN user_id SET NOT NULL;
+
+CREATE INDEX ON "transaction_version"(user_id);
+CREATE INDEX ON "transaction_version"(user_id, transaction_xid, id);
+
+ALTER TABLE "ledger_lock"
+    DROP COLUMN book_id,
+    ALTER COLUMN user_id SET NOT NULL,
+    ADD PRIMARY KEY (user_id);
+
+ALTER TABLE "ledger_lock_change"
+    DROP COLUMN book_id,
+    ALTER COLUMN user_id SET NOT NULL;
+
+CREATE INDEX ON "ledger_lock_change"(user_id);
+
+ALTER TABLE "period_closing"
+    DROP COLUMN book_id,
+    ALTER COLUMN user_id SET NOT NULL,
+    ADD PRIMARY KEY (user_id, period_end);
+
+ALTER TABLE "webhook_endpoint"
+    DROP COLUMN book_id,
+    ALTER COLUMN user_id SET NOT NULL;
+
+CREATE INDEX ON "webhook_endpoint"(user_id);
+
+ALTER TABLE "webhook_event"
+    DROP COLUMN book_id,
+    ALTER COLUMN user_id SET NOT NULL;
+
+DROP TABLE "book_invitation";
+DROP TABLE "book_member";
+DROP TABLE "book";
+
+-- Get an account by name for a user, or create it if it doesn't exist. The
+-- function only returns the account's ID, but this can be used to select the
+-- remaining columns if desired. This function was inspired by
+-- https://stackoverflow.com/a/15950324/3762084.
+CREATE OR REPLACE FUNCTION get_or_create_account(owner_id text,
+                                                 account_name text,
+                                                 OUT _account_id uuid)
+AS
+$$
+BEGIN
+    LOOP
+        -- The simplest, and least computationally expensive, case is that the
+        -- account exists and we can select from it.
+        SELECT account.id
+        FROM account
+        WHERE user_id = owner_id
+          AND name = account_name
+        INTO _account_id;
+
+        -- If the select found something, we're done.
+        EXIT WHEN FOUND;
+
+        -- If the select did not find the account, try to insert it. This could
+        -- fail if the account was just inserted so in that case, we let the
+        -- loop continue and pick up the insert in the next try of the select
+        -- statement.
+        INSERT INTO account AS a (user_id, name)
+        VALUES (owner_id, account_name)
+        ON CONFLICT (user_id, name) DO NOTHING
+        RETURNING a.id INTO _account_id;
+
+        -- If the insert succeeded, we're done. Otherwise try it all again.
+        EXIT WHEN FOUND;
+    END LOOP;
+END;
+$$ LANGUAGE "plpgsql";
@@ -0,0 +1,173 @@
+-- A book is a set of accounts and transactions that one or more users keep
+-- together. Everything in the ledger belongs to a book rather than directly to
+-- a user.
+CREATE TABLE "book" (
+    id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+    name TEXT NOT NULL,
+    -- The user whose personal book this is. Every user has a personal book,
+    -- which is created the first time it is needed.
+    personal_user_id TEXT UNIQUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TABLE "book_member" (
+    book_id uuid NOT NULL REFERENCES "book" (id)
+        ON DELETE CASCADE,
+    user_id TEXT NOT NULL,
+    -- One of 'owner', 'editor', or 'viewer'.
+    role TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (book_id, user_id)
+);
+
+CREATE INDEX ON "book_member"(user_id);
+
+-- Invitations are accepted by presenting their token, which is only shown to
+-- the user who created the invitation. Only a hash of the token is stored.
+CREATE TABLE "book_invitation" (
+    id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+    book_id uuid NOT NULL REFERENCES "book" (id)
+        ON DELETE CASCADE,
+    role TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    expires_at TIMESTAMPTZ NOT NULL,
+    accepted_by TEXT,
+    accepted_at TIMESTAMPTZ
+);
+
+CREATE INDEX ON "book_invitation"(book_id);
+
+-- Existing data is moved into a personal book for each user.
+INSERT INTO "book" (name, personal_user_id)
+SELECT 'Personal', user_id
+FROM (
+    SELECT user_id FROM "account"
+    UNION SELECT user_id FROM "transaction"
+    UNION SELECT user_id FROM "transaction_attachment"
+    UNION SELECT user_id FROM "transaction_version"
+    UNION SELECT user_id FROM "ledger_lock"
+    UNION SELECT user_id FROM "ledger_lock_change"
+    UNION SELECT user_id FROM "period_closing"
+    UNION SELECT user_id FROM "webhook_endpoint"
+    UNION SELECT user_id FROM "webhook_event"
+) AS users;
+
+INSERT INTO "book_member" (book_id, user_id, role)
+SELECT id, personal_user_id, 'owner'
+FROM "book";
+
+ALTER TABLE "account" ADD COLUMN book_id uuid REFERENCES "book" (id) ON DELETE CASCADE;
+ALTER TABLE "transaction" ADD COLUMN book_id uuid REFERENCES "book" (id) ON DELETE CASCADE;
+ALTER TABLE "transaction_attachment" ADD COLUMN book_id uuid REFERENCES "book" (id) ON DELETE CASCADE;
+ALTER TABLE "transaction_version" ADD COLUMN book_id uuid REFERENCES "book" (id) ON DELETE CASCADE;
+ALTER TABLE "ledger_lock" ADD COLUMN book_id uuid REFERENCES "book" (id) ON DELETE CASCADE;
+ALTER TABLE "ledger_lock_change" ADD COLUMN book_id uuid REFERENCES "book" (id) ON DELETE CASCADE;
+ALTER TABLE "period_closing" ADD COLUMN book_id uuid REFERENCES "book" (id) ON DELETE CASCADE;
+ALTER TABLE "webhook_endpoint" ADD COLUMN book_id uuid REFERENCES "book" (id) ON DELETE CASCADE;
+ALTER TABLE "webhook_event" ADD COLUMN book_id uuid REFERENCES "book" (id) ON DELETE CASCADE;
+
+UPDATE "account" t SET book_id = b.id FROM "book" b WHERE b.personal_user_id = t.user_id;
+UPDATE "transaction" t SET book_id = b.id FROM "book" b WHERE b.personal_user_id = t.user_id;
+UPDATE "transaction_attachment" t SET book_id = b.id FROM "book" b WHERE b.personal_user_id = t.user_id;
+UPDATE "transaction_version" t SET book_id = b.id FROM "book" b WHERE b.personal_user_id = t.user_id;
+UPDATE "ledger_lock" t SET book_id = b.id FROM "book" b WHERE b.personal_user_id = t.user_id;
+UPDATE "ledger_lock_change" t SET book_id = b.id FROM "book" b WHERE b.personal_user_id = t.user_id;
+UPDATE "period_closing" t SET book_id = b.id FROM "book" b WHERE b.personal_user_id = t.user_id;
+UPDATE "webhook_endpoint" t SET book_id = b.id FROM "book" b WHERE b.personal_user_id = t.user_id;
+UPDATE "webhook_event" t SET book_id = b.id FROM "book" b WHERE b.personal_user_id = t.user_id;
+
+-- Dropping the user columns also drops the constraints and indexes that
+-- include them, so they are recreated for the book columns.
+ALTER TABLE "account"
+    DROP COLUMN user_id,
+    ALTER COLUMN book_id SET NOT NULL,
+    ADD CONSTRAINT "account_name_book_unique" UNIQUE (book_id, name);
+
+ALTER TABLE "transaction"
+    DROP COLUMN user_id,
+    ALTER COLUMN book_id SET NOT NULL;
+
+CREATE INDEX ON "transaction"(book_id, date);
+
+ALTER TABLE "transaction_attachment"
+    DROP COLUMN user_id,
+    ALTER COLUMN book_id SET NOT NULL;
+
+CREATE INDEX ON "transaction_attachment"(book_id);
+
+-- The user who made each change is still recorded as the version's actor.
+ALTER TABLE "transaction_version"
+    DROP COLUMN user_id,
+    ALTER COLUMN book_id SET NOT NULL;
+
+CREATE INDEX ON "transaction_version"(book_id, transaction_xid, id);
+
+ALTER TABLE "ledger_lock"
+    DROP COLUMN user_id,
+    ALTER COLUMN book_id SET NOT NULL,
+    ADD PRIMARY KEY (book_id);
+
+ALTER TABLE "ledger_lock_change"
+    DROP COLUMN user_id,
+    ALTER COLUMN book_id SET NOT NULL;
+
+CREATE INDEX ON "ledger_lock_change"(book_id);
+
+ALTER TABLE "period_closing"
+    DROP COLUMN user_id,
+    ALTER COLUMN book_id SET NOT NULL,
+    ADD PRIMARY KEY (book_id, period_end);
+
+ALTER TABLE "webhook_endpoint"
+    DROP COLUMN user_id,
+    ALTER COLUMN book_id SET NOT NULL;
+
+CREATE INDEX ON "webhook_endpoint"(book_id);
+
+ALTER TABLE "webhook_event"
+    DROP COLUMN user_id,
+    ALTER COLUMN book_id SET NOT NULL;
+
+-- The version of the function that took a legacy user ID was never dropped, so
+-- it is removed here before its signature is reused.
+DROP FUNCTION get_or_create_account(text, text);
+DROP FUNCTION IF EXISTS get_or_create_account(uuid, text);
+
+-- Get an account by name in a book, or create it if it doesn't exist. The
+-- function only returns the account's ID, but this can be used to select the
+-- remaining columns if desired. This function was inspired by
+-- https://stackoverflow.com/a/15950324/3762084.
+CREATE OR REPLACE FUNCTION get_or_create_account(owning_book_id uuid,
+                                                 account_name text,
+                                                 OUT _account_id uuid)
+AS
+$$
+BEGIN
+    LOOP
+        -- The simplest, and least computationally expensive, case is that the
+        -- account exists and we can select from it.
+        SELECT account.id
+        FROM account
+        WHERE book_id = owning_book_id
+          AND name = account_name
+        INTO _account_id;
+
+        -- If the select found something, we're done.
+        EXIT WHEN FOUND;
+
+        -- If the select did not find the account, try to insert it. This could
+        -- fail if the account was just inserted so in that case, we let the
+        -- loop continue and pick up the insert in the next try of the select
+        -- statement.
+        INSERT INTO account AS a (book_id, name)
+        VALUES (owning_book_id, account_name)
+        ON CONFLICT (book_id, name) DO NOTHING
+        RETURNING a.id INTO _account_id;
+
+        -- If the insert succeeded, we're done. Otherwise try it all again.
+        EXIT WHEN FOUND;
+    END LOOP;
+END;
+$$ LANGUAGE "plpgsql";
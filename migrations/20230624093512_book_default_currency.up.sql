@@ -0,0 +1,4 @@
+-- The currency used for amounts in the book that don't name one. Books without
+-- a default currency require every amount to name its currency.
+ALTER TABLE "book" ADD COLUMN default_currency TEXT REFERENCES "currency" (code)
+    ON DELETE SET NULL;
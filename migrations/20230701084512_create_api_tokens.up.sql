@@ -0,0 +1,15 @@
+-- Personal access tokens let scripts and integrations authenticate as a user
+-- without going through the identity provider. Like invitations, only a hash
+-- of each token is stored.
+CREATE TABLE "api_token" (
+    id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    -- One of 'read', 'write', or 'import'.
+    scope TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    last_used_at TIMESTAMPTZ
+);
+
+CREATE INDEX ON "api_token"(user_id);
@@ -0,0 +1,3 @@
+-- The user who moved the lock date. Changes recorded before the actor was
+-- tracked don't have one.
+ALTER TABLE "ledger_lock_change" ADD COLUMN actor TEXT;
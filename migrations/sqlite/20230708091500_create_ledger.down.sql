@@ -0,0 +1,19 @@
+DROP TABLE "api_token";
+DROP TABLE "rate_limit_bucket";
+DROP TABLE "webhook_delivery_attempt";
+DROP TABLE "webhook_delivery";
+DROP TABLE "webhook_event";
+DROP TABLE "webhook_endpoint";
+DROP TABLE "idempotency_key";
+DROP TABLE "period_closing";
+DROP TABLE "ledger_lock_change";
+DROP TABLE "ledger_lock";
+DROP TABLE "transaction_version";
+DROP TABLE "transaction_attachment";
+DROP TABLE "transaction_entry";
+DROP TABLE "transaction";
+DROP TABLE "account";
+DROP TABLE "book_invitation";
+DROP TABLE "book_member";
+DROP TABLE "book";
+DROP TABLE "currency";
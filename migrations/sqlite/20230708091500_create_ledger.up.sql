@@ -0,0 +1,246 @@
+-- The SQLite schema matches the Postgres schema built up by the migrations in
+-- the parent directory, with a few differences:
+--
+-- * UUIDs are stored as 16 byte blobs and are generated by the application.
+-- * Timestamps are stored as RFC 3339 text in UTC and are provided by the
+--   application, which also keeps `updated_at` columns current since there
+--   are no triggers to do it.
+-- * JSON documents and lists are stored as JSON text.
+CREATE TABLE "currency" (
+    code TEXT PRIMARY KEY,
+    symbol TEXT NOT NULL DEFAULT '',
+    minor_units INTEGER NOT NULL
+);
+
+INSERT INTO "currency" (code, minor_units)
+VALUES ('USD', 2);
+
+CREATE TABLE "book" (
+    id BLOB PRIMARY KEY,
+    name TEXT NOT NULL,
+    personal_user_id TEXT UNIQUE,
+    default_currency TEXT REFERENCES "currency" (code)
+        ON DELETE SET NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE TABLE "book_member" (
+    book_id BLOB NOT NULL REFERENCES "book" (id)
+        ON DELETE CASCADE,
+    user_id TEXT NOT NULL,
+    -- One of 'owner', 'editor', or 'viewer'.
+    role TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (book_id, user_id)
+);
+
+CREATE INDEX "book_member_user_id_idx" ON "book_member"(user_id);
+
+CREATE TABLE "book_invitation" (
+    id BLOB PRIMARY KEY,
+    book_id BLOB NOT NULL REFERENCES "book" (id)
+        ON DELETE CASCADE,
+    role TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    created_by TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    expires_at TEXT NOT NULL,
+    accepted_by TEXT,
+    accepted_at TEXT
+);
+
+CREATE INDEX "book_invitation_book_id_idx" ON "book_invitation"(book_id);
+
+CREATE TABLE "account" (
+    id BLOB PRIMARY KEY,
+    book_id BLOB NOT NULL REFERENCES "book" (id)
+        ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    UNIQUE (book_id, name)
+);
+
+CREATE INDEX "account_name_idx" ON "account"(name);
+
+CREATE TABLE "transaction" (
+    id BLOB PRIMARY KEY,
+    book_id BLOB NOT NULL REFERENCES "book" (id)
+        ON DELETE CASCADE,
+    date TEXT NOT NULL,
+    payee TEXT NOT NULL,
+    notes TEXT NOT NULL DEFAULT '',
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    deleted_at TEXT
+);
+
+CREATE INDEX "transaction_book_id_date_idx" ON "transaction"(book_id, date);
+CREATE INDEX "transaction_deleted_at_idx" ON "transaction"(deleted_at)
+    WHERE deleted_at IS NOT NULL;
+
+CREATE TABLE "transaction_entry" (
+    id BLOB PRIMARY KEY,
+    transaction_id BLOB NOT NULL REFERENCES "transaction" (id)
+        ON DELETE CASCADE,
+    "order" INTEGER NOT NULL,
+    account_id BLOB NOT NULL REFERENCES "account" (id)
+        ON DELETE RESTRICT,
+    currency TEXT NOT NULL REFERENCES "currency" (code)
+        ON DELETE RESTRICT,
+    amount INTEGER NOT NULL,
+    UNIQUE (transaction_id, "order")
+);
+
+CREATE INDEX "transaction_entry_account_id_idx" ON "transaction_entry"(account_id);
+
+CREATE TABLE "transaction_attachment" (
+    id BLOB PRIMARY KEY,
+    transaction_id BLOB NOT NULL REFERENCES "transaction" (id)
+        ON DELETE CASCADE,
+    book_id BLOB NOT NULL REFERENCES "book" (id)
+        ON DELETE CASCADE,
+    file_name TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    size INTEGER NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX "transaction_attachment_book_id_idx" ON "transaction_attachment"(book_id);
+CREATE INDEX "transaction_attachment_transaction_id_idx" ON "transaction_attachment"(transaction_id);
+
+-- Only one database transaction can write at a time, so versions are committed
+-- in the order of their IDs and the change feed can page through them by ID
+-- alone. IDs are never reused so that a client's position stays valid.
+CREATE TABLE "transaction_version" (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    transaction_id BLOB NOT NULL,
+    book_id BLOB NOT NULL REFERENCES "book" (id)
+        ON DELETE CASCADE,
+    version INTEGER NOT NULL,
+    -- One of 'created', 'updated', 'deleted', or 'restored'.
+    action TEXT NOT NULL,
+    actor TEXT NOT NULL,
+    snapshot TEXT NOT NULL,
+    recorded_at TEXT NOT NULL,
+    UNIQUE (transaction_id, version)
+);
+
+CREATE INDEX "transaction_version_book_id_id_idx" ON "transaction_version"(book_id, id);
+
+CREATE TABLE "ledger_lock" (
+    book_id BLOB PRIMARY KEY REFERENCES "book" (id)
+        ON DELETE CASCADE,
+    lock_date TEXT,
+    updated_at TEXT NOT NULL
+);
+
+CREATE TABLE "ledger_lock_change" (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    book_id BLOB NOT NULL REFERENCES "book" (id)
+        ON DELETE CASCADE,
+    previous_lock_date TEXT,
+    lock_date TEXT,
+    changed_at TEXT NOT NULL
+);
+
+CREATE INDEX "ledger_lock_change_book_id_idx" ON "ledger_lock_change"(book_id);
+
+CREATE TABLE "period_closing" (
+    book_id BLOB NOT NULL REFERENCES "book" (id)
+        ON DELETE CASCADE,
+    period_start TEXT NOT NULL,
+    period_end TEXT NOT NULL,
+    equity_account TEXT NOT NULL,
+    transaction_id BLOB REFERENCES "transaction" (id)
+        ON DELETE SET NULL,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    PRIMARY KEY (book_id, period_end)
+);
+
+CREATE TABLE "idempotency_key" (
+    user_id TEXT NOT NULL,
+    key TEXT NOT NULL,
+    request_hash TEXT NOT NULL,
+    response_status INTEGER,
+    response_body TEXT,
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (user_id, key)
+);
+
+CREATE INDEX "idempotency_key_created_at_idx" ON "idempotency_key"(created_at);
+
+CREATE TABLE "webhook_endpoint" (
+    id BLOB PRIMARY KEY,
+    book_id BLOB NOT NULL REFERENCES "book" (id)
+        ON DELETE CASCADE,
+    url TEXT NOT NULL,
+    secret TEXT NOT NULL,
+    -- A JSON array of the subscribed event types.
+    events TEXT NOT NULL,
+    threshold_account TEXT,
+    threshold_currency TEXT,
+    threshold_amount INTEGER,
+    threshold_above INTEGER,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX "webhook_endpoint_book_id_idx" ON "webhook_endpoint"(book_id);
+
+CREATE TABLE "webhook_event" (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    book_id BLOB NOT NULL REFERENCES "book" (id)
+        ON DELETE CASCADE,
+    event_type TEXT NOT NULL,
+    data TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE TABLE "webhook_delivery" (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    event_id INTEGER NOT NULL REFERENCES "webhook_event" (id)
+        ON DELETE CASCADE,
+    endpoint_id BLOB NOT NULL REFERENCES "webhook_endpoint" (id)
+        ON DELETE CASCADE,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at TEXT NOT NULL,
+    delivered_at TEXT,
+    abandoned_at TEXT,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX "webhook_delivery_endpoint_id_idx" ON "webhook_delivery"(endpoint_id);
+CREATE INDEX "webhook_delivery_next_attempt_at_idx" ON "webhook_delivery"(next_attempt_at)
+    WHERE delivered_at IS NULL AND abandoned_at IS NULL;
+
+CREATE TABLE "webhook_delivery_attempt" (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    delivery_id INTEGER NOT NULL REFERENCES "webhook_delivery" (id)
+        ON DELETE CASCADE,
+    response_status INTEGER,
+    error TEXT,
+    attempted_at TEXT NOT NULL
+);
+
+CREATE INDEX "webhook_delivery_attempt_delivery_id_idx" ON "webhook_delivery_attempt"(delivery_id);
+
+CREATE TABLE "rate_limit_bucket" (
+    user_id TEXT NOT NULL,
+    request_class TEXT NOT NULL,
+    tokens REAL NOT NULL,
+    updated_at TEXT NOT NULL,
+    PRIMARY KEY (user_id, request_class)
+);
+
+CREATE TABLE "api_token" (
+    id BLOB PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    -- One of 'read', 'write', or 'import'.
+    scope TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    created_at TEXT NOT NULL,
+    last_used_at TEXT
+);
+
+CREATE INDEX "api_token_user_id_idx" ON "api_token"(user_id);
@@ -0,0 +1 @@
+ALTER TABLE "ledger_lock_change" DROP COLUMN actor;
@@ -0,0 +1,4 @@
+-- Counts the writes to each transaction. Every write increments it, so a
+-- transaction changed twice within one database transaction still gets a new
+-- revision each time.
+ALTER TABLE "transaction" ADD COLUMN revision INTEGER NOT NULL DEFAULT 1;
@@ -1,12 +1,48 @@
-use axum::{http::status::StatusCode, response::IntoResponse, Json};
-use axum_jwks::{ParseTokenClaims, TokenError};
+use axum::response::IntoResponse;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
+
+use crate::http_err::ApiError;
+
+use super::scopes::Scope;
 
 #[derive(Deserialize, Serialize)]
+#[serde(from = "RawTokenClaims")]
 pub struct TokenClaims {
     iss: String,
     sub: String,
+    #[serde(skip)]
+    scope: Option<Scope>,
+}
+
+/// The claims as they appear in a token.
+#[derive(Deserialize)]
+struct RawTokenClaims {
+    /// Tokens signed with the development secret may not have an issuer.
+    #[serde(default)]
+    iss: String,
+    sub: String,
+    /// The space separated scopes granted to the token, as in OAuth 2.0.
+    #[serde(default)]
+    scope: String,
+    /// The permissions granted to the token. Some identity providers use
+    /// this instead of `scope`.
+    #[serde(default)]
+    permissions: Vec<String>,
+}
+
+impl From<RawTokenClaims> for TokenClaims {
+    fn from(raw: RawTokenClaims) -> Self {
+        let granted = raw
+            .scope
+            .split_whitespace()
+            .chain(raw.permissions.iter().map(String::as_str));
+
+        Self {
+            scope: Scope::broadest(granted),
+            iss: raw.iss,
+            sub: raw.sub,
+        }
+    }
 }
 
 impl TokenClaims {
@@ -18,6 +54,12 @@ impl TokenClaims {
         &self.sub
     }
 
+    /// Get the broadest scope granted to the token, or `None` if it doesn't
+    /// grant any of this API's scopes.
+    pub fn scope(&self) -> Option<Scope> {
+        self.scope
+    }
+
     /// Get the ID of the user that the token claims represent.
     ///
     /// This is the user who made the request.
@@ -26,38 +68,50 @@ impl TokenClaims {
     }
 }
 
-impl ParseTokenClaims for TokenClaims {
-    type Rejection = JwtError;
-}
-
+#[derive(Debug)]
 pub enum JwtError {
     Invalid,
     Missing,
 }
 
-impl From<TokenError> for JwtError {
-    fn from(value: TokenError) -> Self {
-        match value {
-            TokenError::Missing => Self::Missing,
-            _ => Self::Invalid,
-        }
-    }
-}
-
 impl IntoResponse for JwtError {
     fn into_response(self) -> axum::response::Response {
-        let (status, message) = match self {
-            Self::Invalid => (StatusCode::UNAUTHORIZED, "Invalid authentication token."),
-            Self::Missing => (
-                StatusCode::UNAUTHORIZED,
-                "No authentication token provided.",
-            ),
+        let detail = match self {
+            Self::Invalid => "Invalid authentication token.",
+            Self::Missing => "No authentication token provided.",
         };
 
-        let body = Json(json!({
-            "error": message,
-        }));
+        ApiError::Unauthorized(detail.to_owned()).into_response()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scope_from_scope_or_permissions_claim() {
+        let claims: TokenClaims = serde_json::from_value(serde_json::json!({
+            "iss": "https://auth.example.com/",
+            "sub": "user-id",
+            "scope": "openid ledger:read ledger:write",
+        }))
+        .unwrap();
+        assert_eq!(Some(Scope::LedgerWrite), claims.scope());
+
+        let claims: TokenClaims = serde_json::from_value(serde_json::json!({
+            "iss": "https://auth.example.com/",
+            "sub": "user-id",
+            "permissions": ["ledger:admin"],
+        }))
+        .unwrap();
+        assert_eq!(Some(Scope::LedgerAdmin), claims.scope());
 
-        (status, body).into_response()
+        let claims: TokenClaims = serde_json::from_value(serde_json::json!({
+            "iss": "https://auth.example.com/",
+            "sub": "user-id",
+        }))
+        .unwrap();
+        assert_eq!(None, claims.scope());
     }
 }
@@ -0,0 +1,642 @@
+//! The keys used to verify JWTs.
+//!
+//! Keys come from the JSON Web Key Sets published by trusted authorities, from
+//! a local JWKS file, or from a static secret for development. Key sets are
+//! refreshed periodically, and as soon as a token refers to a key that isn't
+//! known yet, so that keys rotated by an identity provider are picked up
+//! without a restart.
+
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{
+    decode, decode_header,
+    jwk::{AlgorithmParameters, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
+use serde::de::DeserializeOwned;
+use tracing::{debug, error, info, warn};
+
+use crate::shutdown::ShutdownSignal;
+
+use super::{scopes::Scope, JwtError, TokenClaims};
+
+/// The least amount of time between refreshes caused by tokens with unknown
+/// keys. Without it, tokens with made up key IDs could be used to flood the
+/// authorities with requests.
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long to wait for a connection to an authority. Refreshes hold up
+/// requests with unknown keys, so an unresponsive authority mustn't stall them
+/// for long.
+const FETCH_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait for an authority's whole key set.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where keys for verifying JWTs come from.
+#[derive(Clone, Debug)]
+pub struct KeyStoreOptions {
+    /// The audiences that tokens may be issued for.
+    pub audiences: Vec<String>,
+    /// The issuers whose tokens are trusted. Their keys are fetched from
+    /// `{authority}/.well-known/jwks.json`.
+    pub authorities: Vec<String>,
+    /// A file containing a JSON Web Key Set.
+    pub jwks_file: Option<PathBuf>,
+    /// How often key sets are refreshed.
+    pub refresh_interval: Duration,
+    /// Whether tokens that don't grant any of the API's scopes are denied
+    /// access. Otherwise they are treated like tokens from before scopes were
+    /// enforced, which could do anything.
+    pub require_scope: bool,
+    /// A secret for verifying tokens signed with HS256.
+    pub secret: Option<String>,
+}
+
+/// A source of a JSON Web Key Set.
+#[derive(Debug)]
+enum KeySource {
+    Authority(String),
+    File(PathBuf),
+}
+
+impl KeySource {
+    async fn fetch(&self, client: &reqwest::Client) -> anyhow::Result<JwkSet> {
+        match self {
+            Self::Authority(authority) => {
+                let url = format!("{}/.well-known/jwks.json", authority.trim_end_matches('/'));
+
+                Ok(client
+                    .get(&url)
+                    .send()
+                    .await
+                    .and_then(reqwest::Response::error_for_status)
+                    .with_context(|| format!("failed to fetch JWKS from {}", url))?
+                    .json()
+                    .await
+                    .with_context(|| format!("invalid JWKS at {}", url))?)
+            }
+            Self::File(path) => {
+                let content = tokio::fs::read(path)
+                    .await
+                    .with_context(|| format!("failed to read JWKS file {:?}", path))?;
+
+                serde_json::from_slice(&content)
+                    .with_context(|| format!("invalid JWKS in file {:?}", path))
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Key {
+    decoding: DecodingKey,
+    algorithm: Algorithm,
+    /// The authority the key was fetched from, which has to be the issuer of
+    /// tokens signed with it. Keys from a file or the secret aren't tied to
+    /// an issuer.
+    authority: Option<String>,
+}
+
+/// The keys for verifying JWTs, shared by every request.
+#[derive(Clone)]
+pub struct KeyStore(Arc<KeyStoreInner>);
+
+struct KeyStoreInner {
+    client: reqwest::Client,
+    options: KeyStoreOptions,
+    sources: Vec<KeySource>,
+    secret: Option<DecodingKey>,
+    /// The keys from each source, by key ID. Keys are kept in the same order
+    /// as `sources`, so a source that can't be reached keeps its last keys.
+    keys: RwLock<Vec<HashMap<String, Key>>>,
+    /// When the keys were last refreshed. Refreshes hold this lock so that
+    /// concurrent requests with an unknown key trigger a single refresh.
+    last_refresh: tokio::sync::Mutex<Option<Instant>>,
+}
+
+impl KeyStore {
+    /// Create a key store and load its keys.
+    ///
+    /// # Errors
+    /// If no source of keys is configured, or one of the key sets can't be
+    /// loaded.
+    pub async fn new(options: KeyStoreOptions) -> anyhow::Result<Self> {
+        let mut sources: Vec<_> = options
+            .authorities
+            .iter()
+            .cloned()
+            .map(KeySource::Authority)
+            .collect();
+        sources.extend(options.jwks_file.clone().map(KeySource::File));
+
+        if sources.is_empty() && options.secret.is_none() {
+            anyhow::bail!("no source of JWT keys is configured");
+        }
+
+        if options.audiences.is_empty() {
+            anyhow::bail!("no JWT audience is configured");
+        }
+
+        let store = Self(Arc::new(KeyStoreInner {
+            client: reqwest::Client::builder()
+                .connect_timeout(FETCH_CONNECT_TIMEOUT)
+                .timeout(FETCH_TIMEOUT)
+                .build()?,
+            secret: options
+                .secret
+                .as_ref()
+                .map(|secret| DecodingKey::from_secret(secret.as_bytes())),
+            keys: RwLock::new(vec![HashMap::new(); sources.len()]),
+            last_refresh: tokio::sync::Mutex::new(None),
+            options,
+            sources,
+        }));
+
+        let mut last_refresh = store.0.last_refresh.lock().await;
+        for (index, source) in store.0.sources.iter().enumerate() {
+            let keys = source.fetch(&store.0.client).await?;
+            store.set_keys(index, source, keys);
+        }
+        *last_refresh = Some(Instant::now());
+        drop(last_refresh);
+
+        Ok(store)
+    }
+
+    /// Determine if there are keys to verify tokens with.
+    pub fn has_keys(&self) -> bool {
+        self.0.secret.is_some() || self.keys().iter().any(|keys| !keys.is_empty())
+    }
+
+    /// Reload every key set. Sources that can't be loaded keep the keys they
+    /// had before.
+    pub async fn refresh(&self) {
+        let mut last_refresh = self.0.last_refresh.lock().await;
+        self.refresh_locked().await;
+        *last_refresh = Some(Instant::now());
+    }
+
+    /// Reload every key set unless they were reloaded very recently.
+    async fn refresh_if_stale(&self) {
+        let mut last_refresh = self.0.last_refresh.lock().await;
+        if last_refresh.is_some_and(|at| at.elapsed() < MIN_REFRESH_INTERVAL) {
+            return;
+        }
+
+        self.refresh_locked().await;
+        *last_refresh = Some(Instant::now());
+    }
+
+    async fn refresh_locked(&self) {
+        for (index, source) in self.0.sources.iter().enumerate() {
+            match source.fetch(&self.0.client).await {
+                Ok(keys) => self.set_keys(index, source, keys),
+                Err(error) => error!(?error, ?source, "Failed to refresh JWT keys."),
+            }
+        }
+    }
+
+    /// Periodically reload every key set until the application shuts down.
+    pub async fn refresh_periodically(self, mut shutdown: ShutdownSignal) {
+        let mut interval = tokio::time::interval(self.0.options.refresh_interval);
+        // The keys were just loaded when the store was created.
+        interval.tick().await;
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {},
+                _ = shutdown.wait() => return,
+            }
+
+            self.refresh().await;
+        }
+    }
+
+    /// Verify a JWT and parse its claims.
+    ///
+    /// Tokens have to be signed by a known key and issued for one of the
+    /// configured audiences. Tokens signed with an authority's key have to be
+    /// issued by that authority. Tokens signed with other keys have to be
+    /// issued by one of the authorities, if any are configured.
+    pub async fn validate<T: DeserializeOwned>(&self, token: &str) -> Result<T, JwtError> {
+        let header = decode_header(token).map_err(|error| {
+            debug!(?error, "Received token with invalid header.");
+
+            JwtError::Invalid
+        })?;
+
+        let key = match (&header.kid, &self.0.secret) {
+            (None, Some(secret)) if header.alg == Algorithm::HS256 => Key {
+                decoding: secret.clone(),
+                algorithm: Algorithm::HS256,
+                authority: None,
+            },
+            (Some(kid), _) => match self.find_key(kid) {
+                Some(key) => key,
+                None => {
+                    // The authority may have rotated its keys since they were
+                    // last fetched.
+                    debug!(%kid, "Token refers to an unknown key.");
+                    self.refresh_if_stale().await;
+
+                    self.find_key(kid).ok_or(JwtError::Invalid)?
+                }
+            },
+            (None, _) => {
+                debug!(?header, "Header is missing the `kid` attribute.");
+
+                return Err(JwtError::Invalid);
+            }
+        };
+
+        let mut validation = Validation::new(key.algorithm);
+        validation.set_audience(&self.0.options.audiences);
+        match &key.authority {
+            // One authority's keys must not vouch for tokens claiming to be
+            // from another.
+            Some(authority) => validation.set_issuer(&issuers(authority)),
+            None if !self.0.options.authorities.is_empty() => {
+                let all_issuers: Vec<_> = self
+                    .0
+                    .options
+                    .authorities
+                    .iter()
+                    .flat_map(|authority| issuers(authority))
+                    .collect();
+                validation.set_issuer(&all_issuers);
+            }
+            None => (),
+        }
+
+        decode(token, &key.decoding, &validation)
+            .map(|data| data.claims)
+            .map_err(|error| {
+                debug!(?error, "Token is malformed or does not pass validation.");
+
+                JwtError::Invalid
+            })
+    }
+
+    /// Get the scope granted by a validated token.
+    ///
+    /// Tokens issued before the API had scopes don't grant any of them. Until
+    /// scopes are required, those tokens keep the full access they had.
+    pub fn granted_scope(&self, claims: &TokenClaims) -> Option<Scope> {
+        match claims.scope() {
+            Some(scope) => Some(scope),
+            None if self.0.options.require_scope => None,
+            None => Some(Scope::LedgerAdmin),
+        }
+    }
+
+    fn find_key(&self, kid: &str) -> Option<Key> {
+        self.keys().iter().find_map(|keys| keys.get(kid).cloned())
+    }
+
+    fn keys(&self) -> std::sync::RwLockReadGuard<'_, Vec<HashMap<String, Key>>> {
+        self.0
+            .keys
+            .read()
+            .unwrap_or_else(|error| error.into_inner())
+    }
+
+    fn set_keys(&self, index: usize, source: &KeySource, set: JwkSet) {
+        let authority = match source {
+            KeySource::Authority(authority) => Some(authority.clone()),
+            KeySource::File(_) => None,
+        };
+
+        let mut keys = HashMap::new();
+        for jwk in set.keys {
+            let Some(kid) = jwk.common.key_id.clone() else {
+                warn!(?source, "Ignoring JWK without a `kid` attribute.");
+                continue;
+            };
+
+            let algorithm = match (jwk.common.algorithm, &jwk.algorithm) {
+                (Some(algorithm), _) => algorithm,
+                (None, AlgorithmParameters::RSA(_)) => Algorithm::RS256,
+                (None, _) => {
+                    warn!(%kid, ?source, "Ignoring JWK without an algorithm.");
+                    continue;
+                }
+            };
+
+            let decoding = match &jwk.algorithm {
+                // Symmetric keys are base64url encoded, which `from_jwk` doesn't
+                // decode correctly.
+                AlgorithmParameters::OctetKey(params) => URL_SAFE_NO_PAD
+                    .decode(params.value.trim_end_matches('='))
+                    .map(|secret| DecodingKey::from_secret(&secret))
+                    .map_err(anyhow::Error::from),
+                _ => DecodingKey::from_jwk(&jwk).map_err(anyhow::Error::from),
+            };
+
+            match decoding {
+                Ok(decoding) => {
+                    keys.insert(
+                        kid,
+                        Key {
+                            decoding,
+                            algorithm,
+                            authority: authority.clone(),
+                        },
+                    );
+                }
+                Err(error) => warn!(?error, %kid, ?source, "Ignoring invalid JWK."),
+            }
+        }
+
+        info!(?source, count = keys.len(), "Loaded JWT keys.");
+
+        let mut all_keys = self
+            .0
+            .keys
+            .write()
+            .unwrap_or_else(|error| error.into_inner());
+        all_keys[index] = keys;
+    }
+}
+
+/// The `iss` claims accepted from an authority. Identity providers differ on
+/// whether their issuer has a trailing slash, so both forms are accepted.
+fn issuers(authority: &str) -> [String; 2] {
+    let authority = authority.trim_end_matches('/');
+
+    [authority.to_owned(), format!("{}/", authority)]
+}
+
+#[cfg(test)]
+mod test {
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Claims {
+        iss: String,
+        sub: String,
+        aud: String,
+        exp: i64,
+    }
+
+    fn claims(iss: &str, aud: &str) -> Claims {
+        Claims {
+            iss: iss.to_owned(),
+            sub: "user-id".to_owned(),
+            aud: aud.to_owned(),
+            exp: chrono::Utc::now().timestamp() + 60,
+        }
+    }
+
+    fn options() -> KeyStoreOptions {
+        KeyStoreOptions {
+            audiences: vec!["api".to_owned(), "other-api".to_owned()],
+            authorities: vec![],
+            jwks_file: None,
+            refresh_interval: Duration::from_secs(60 * 60),
+            require_scope: false,
+            secret: None,
+        }
+    }
+
+    fn sign(header: Header, claims: &Claims, secret: &str) -> String {
+        encode(
+            &header,
+            claims,
+            &EncodingKey::from_secret(secret.as_bytes()),
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn requires_a_key_source() {
+        assert!(KeyStore::new(options()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn validates_tokens_signed_with_secret() {
+        let store = KeyStore::new(KeyStoreOptions {
+            secret: Some("secret".to_owned()),
+            ..options()
+        })
+        .await
+        .unwrap();
+
+        let expected = claims("https://auth.example.com", "other-api");
+        let token = sign(Header::default(), &expected, "secret");
+        assert_eq!(expected, store.validate::<Claims>(&token).await.unwrap());
+
+        let wrong_secret = sign(Header::default(), &expected, "guess");
+        assert!(store.validate::<Claims>(&wrong_secret).await.is_err());
+
+        let wrong_audience = sign(
+            Header::default(),
+            &claims("https://auth.example.com", "unknown"),
+            "secret",
+        );
+        assert!(store.validate::<Claims>(&wrong_audience).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn validates_tokens_with_keys_from_file() {
+        let path = std::env::temp_dir().join(format!("jwks-{}.json", uuid::Uuid::new_v4()));
+        let write_keys = |kid: &str, secret: &str| {
+            let jwks = serde_json::json!({
+                "keys": [{
+                    "kty": "oct",
+                    "kid": kid,
+                    "alg": "HS256",
+                    "k": base64::Engine::encode(
+                        &base64::engine::general_purpose::URL_SAFE_NO_PAD,
+                        secret,
+                    ),
+                }],
+            });
+            std::fs::write(&path, jwks.to_string()).unwrap();
+        };
+        write_keys("first", "first-secret");
+
+        let store = KeyStore::new(KeyStoreOptions {
+            jwks_file: Some(path.clone()),
+            ..options()
+        })
+        .await
+        .unwrap();
+        assert!(store.has_keys());
+
+        let header = Header {
+            kid: Some("first".to_owned()),
+            ..Header::default()
+        };
+        let token = sign(header, &claims("anyone", "api"), "first-secret");
+        assert!(store.validate::<Claims>(&token).await.is_ok());
+
+        // Rotated keys are picked up once the store is refreshed.
+        write_keys("second", "second-secret");
+        store.refresh().await;
+
+        let header = Header {
+            kid: Some("second".to_owned()),
+            ..Header::default()
+        };
+        let token = sign(header, &claims("anyone", "api"), "second-secret");
+        assert!(store.validate::<Claims>(&token).await.is_ok());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn refreshes_keys_for_unknown_key_id() {
+        let path = std::env::temp_dir().join(format!("jwks-{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(&path, r#"{"keys": []}"#).unwrap();
+
+        let store = KeyStore::new(KeyStoreOptions {
+            jwks_file: Some(path.clone()),
+            ..options()
+        })
+        .await
+        .unwrap();
+        // Pretend the keys were loaded long enough ago to refresh them.
+        *store.0.last_refresh.lock().await = None;
+
+        std::fs::write(
+            &path,
+            r#"{"keys": [{"kty": "oct", "kid": "new", "alg": "HS256", "k": "c2VjcmV0"}]}"#,
+        )
+        .unwrap();
+
+        let header = Header {
+            kid: Some("new".to_owned()),
+            ..Header::default()
+        };
+        let token = sign(header, &claims("anyone", "api"), "secret");
+        assert!(store.validate::<Claims>(&token).await.is_ok());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn validates_issuer_when_authorities_are_configured() {
+        let store = KeyStore(Arc::new(KeyStoreInner {
+            client: reqwest::Client::new(),
+            options: KeyStoreOptions {
+                authorities: vec!["https://auth.example.com".to_owned()],
+                ..options()
+            },
+            sources: vec![],
+            secret: Some(DecodingKey::from_secret(b"secret")),
+            keys: RwLock::new(vec![]),
+            last_refresh: tokio::sync::Mutex::new(None),
+        }));
+
+        for issuer in ["https://auth.example.com", "https://auth.example.com/"] {
+            let token = sign(Header::default(), &claims(issuer, "api"), "secret");
+            assert!(store.validate::<Claims>(&token).await.is_ok(), "{}", issuer);
+        }
+
+        let token = sign(
+            Header::default(),
+            &claims("https://evil.example.com", "api"),
+            "secret",
+        );
+        assert!(store.validate::<Claims>(&token).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn tokens_without_scopes_keep_full_access() {
+        let scoped = serde_json::json!({
+            "sub": "user-id", "aud": "api", "exp": chrono::Utc::now().timestamp() + 60,
+            "scope": "openid ledger:read",
+        });
+        let unscoped = serde_json::json!({
+            "sub": "user-id", "aud": "api", "exp": chrono::Utc::now().timestamp() + 60,
+            "scope": "openid profile",
+        });
+        let sign = |claims: &serde_json::Value| {
+            encode(
+                &Header::default(),
+                claims,
+                &EncodingKey::from_secret(b"secret"),
+            )
+            .unwrap()
+        };
+
+        for (require_scope, expected) in [(false, Some(Scope::LedgerAdmin)), (true, None)] {
+            let store = KeyStore::new(KeyStoreOptions {
+                secret: Some("secret".to_owned()),
+                require_scope,
+                ..options()
+            })
+            .await
+            .unwrap();
+
+            let claims: TokenClaims = store.validate(&sign(&scoped)).await.unwrap();
+            assert_eq!(Some(Scope::LedgerRead), store.granted_scope(&claims));
+
+            let claims: TokenClaims = store.validate(&sign(&unscoped)).await.unwrap();
+            assert_eq!(expected, store.granted_scope(&claims));
+        }
+    }
+
+    #[tokio::test]
+    async fn keys_only_verify_tokens_from_their_authority() {
+        let authorities = ["https://first.example.com", "https://second.example.com"];
+        let keys = authorities
+            .iter()
+            .map(|authority| {
+                HashMap::from([(
+                    format!("{}-key", authority),
+                    Key {
+                        decoding: DecodingKey::from_secret(authority.as_bytes()),
+                        algorithm: Algorithm::HS256,
+                        authority: Some(authority.to_string()),
+                    },
+                )])
+            })
+            .collect();
+        let store = KeyStore(Arc::new(KeyStoreInner {
+            client: reqwest::Client::new(),
+            options: KeyStoreOptions {
+                authorities: authorities.iter().map(|a| a.to_string()).collect(),
+                ..options()
+            },
+            sources: authorities
+                .iter()
+                .map(|authority| KeySource::Authority(authority.to_string()))
+                .collect(),
+            secret: None,
+            keys: RwLock::new(keys),
+            last_refresh: tokio::sync::Mutex::new(Some(Instant::now())),
+        }));
+
+        let header = Header {
+            kid: Some("https://first.example.com-key".to_owned()),
+            ..Header::default()
+        };
+        let token = sign(
+            header.clone(),
+            &claims("https://first.example.com", "api"),
+            "https://first.example.com",
+        );
+        assert!(store.validate::<Claims>(&token).await.is_ok());
+
+        // The second authority is trusted, but not to sign with the first
+        // authority's keys.
+        let token = sign(
+            header,
+            &claims("https://second.example.com", "api"),
+            "https://first.example.com",
+        );
+        assert!(store.validate::<Claims>(&token).await.is_err());
+    }
+}
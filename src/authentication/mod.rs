@@ -1,3 +1,8 @@
 mod jwt;
+pub mod keys;
+pub mod scopes;
+pub mod tokens;
+mod user;
 
 pub use jwt::{JwtError, TokenClaims};
+pub use user::{AuthenticatedUser, Credential};
@@ -0,0 +1,196 @@
+use std::{fmt::Display, str::FromStr};
+
+use axum::http::Method;
+
+/// Routes for operations that change many transactions at once or maintain
+/// the ledger, which need the admin scope.
+const ADMIN_ROUTES: &[&str] = &[
+    "/ledger/closings",
+    "/ledger/lock",
+    "/ledger/trash/:transaction_id",
+    "/ledger/transactions/batch",
+];
+
+/// What a JWT allows its bearer to do. Each scope includes everything the
+/// scopes before it allow.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Scope {
+    /// Can read balances, listings, and reports.
+    LedgerRead,
+    /// Can also create, change, and delete transactions and anything else in
+    /// a ledger.
+    LedgerWrite,
+    /// Can also make bulk changes, close periods, set the lock date, purge
+    /// the trash, and manage books.
+    LedgerAdmin,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct UnknownScope(pub String);
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::LedgerRead => "ledger:read",
+            Self::LedgerWrite => "ledger:write",
+            Self::LedgerAdmin => "ledger:admin",
+        }
+    }
+
+    /// Determine if the scope allows everything `required` does.
+    pub fn includes(&self, required: Scope) -> bool {
+        *self >= required
+    }
+
+    /// Get the scope a request needs.
+    ///
+    /// # Arguments
+    /// * `method` - The request's method.
+    /// * `route` - The route the request matched.
+    ///
+    /// # Returns
+    /// The required scope, or `None` if the request only needs the user to be
+    /// authenticated.
+    pub fn required_for(method: &Method, route: &str) -> Option<Self> {
+        // The same routes are available for the personal book and for books
+        // named in the path.
+        let route = route.strip_prefix("/books/:book_id").unwrap_or(route);
+        let is_read = method == Method::GET || method == Method::HEAD;
+
+        if route.starts_with("/tokens") {
+            // Creating a token checks the scope against the token being
+            // created instead.
+            None
+        } else if is_read || route == "/graphql" {
+            // The GraphQL API only has queries, so it is read-only even
+            // though queries are sent with `POST`.
+            Some(Self::LedgerRead)
+        } else if ADMIN_ROUTES.contains(&route) {
+            Some(Self::LedgerAdmin)
+        } else if route.starts_with("/ledger/") || route == "/invitations/accept" {
+            Some(Self::LedgerWrite)
+        } else {
+            // Everything else manages books, their members, and invitations.
+            Some(Self::LedgerAdmin)
+        }
+    }
+
+    /// Find the broadest scope in a list of granted scopes. Scopes for other
+    /// APIs are ignored.
+    pub fn broadest<'a, I: IntoIterator<Item = &'a str>>(granted: I) -> Option<Self> {
+        granted
+            .into_iter()
+            .filter_map(|scope| scope.parse().ok())
+            .max()
+    }
+}
+
+impl Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = UnknownScope;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ledger:read" => Ok(Self::LedgerRead),
+            "ledger:write" => Ok(Self::LedgerWrite),
+            "ledger:admin" => Ok(Self::LedgerAdmin),
+            other => Err(UnknownScope(other.to_owned())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scope_round_trip() {
+        for scope in [Scope::LedgerRead, Scope::LedgerWrite, Scope::LedgerAdmin] {
+            assert_eq!(Ok(scope), scope.as_str().parse());
+        }
+
+        assert_eq!(
+            Err(UnknownScope("openid".to_owned())),
+            "openid".parse::<Scope>()
+        );
+    }
+
+    #[test]
+    fn broadest_scope_ignores_other_scopes() {
+        assert_eq!(
+            Some(Scope::LedgerWrite),
+            Scope::broadest(["openid", "ledger:write", "ledger:read"])
+        );
+        assert_eq!(None, Scope::broadest(["openid", "profile"]));
+    }
+
+    #[test]
+    fn scopes_required_for_routes() {
+        let cases = [
+            (Method::GET, "/ledger/transactions", Some(Scope::LedgerRead)),
+            (
+                Method::GET,
+                "/books/:book_id/ledger/accounts/:account/balance",
+                Some(Scope::LedgerRead),
+            ),
+            (Method::POST, "/graphql", Some(Scope::LedgerRead)),
+            (Method::GET, "/books", Some(Scope::LedgerRead)),
+            (
+                Method::POST,
+                "/ledger/transactions",
+                Some(Scope::LedgerWrite),
+            ),
+            (
+                Method::DELETE,
+                "/books/:book_id/ledger/transactions/:transaction_id",
+                Some(Scope::LedgerWrite),
+            ),
+            (
+                Method::POST,
+                "/invitations/accept",
+                Some(Scope::LedgerWrite),
+            ),
+            (
+                Method::POST,
+                "/ledger/transactions/batch",
+                Some(Scope::LedgerAdmin),
+            ),
+            (Method::POST, "/ledger/closings", Some(Scope::LedgerAdmin)),
+            (Method::PUT, "/ledger/lock", Some(Scope::LedgerAdmin)),
+            (
+                Method::DELETE,
+                "/ledger/trash/:transaction_id",
+                Some(Scope::LedgerAdmin),
+            ),
+            (Method::POST, "/books", Some(Scope::LedgerAdmin)),
+            (
+                Method::PUT,
+                "/books/:book_id/members/:user_id",
+                Some(Scope::LedgerAdmin),
+            ),
+            (Method::POST, "/tokens", None),
+        ];
+
+        for (method, route, expected) in cases {
+            assert_eq!(
+                expected,
+                Scope::required_for(&method, route),
+                "{} {}",
+                method,
+                route
+            );
+        }
+    }
+
+    #[test]
+    fn scopes_include_narrower_scopes() {
+        assert!(Scope::LedgerAdmin.includes(Scope::LedgerWrite));
+        assert!(Scope::LedgerWrite.includes(Scope::LedgerRead));
+        assert!(!Scope::LedgerRead.includes(Scope::LedgerWrite));
+    }
+}
@@ -0,0 +1,298 @@
+use std::{fmt::Display, str::FromStr};
+
+use axum::http::Method;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::ledger::domain::batch::BatchData;
+
+use super::scopes::Scope;
+
+/// The prefix of every personal access token. It lets tokens be told apart
+/// from JWTs, and makes them easy to find if they are leaked.
+pub const TOKEN_PREFIX: &str = "zbpat_";
+
+/// Routes that import-only tokens can send new transactions to. Batches are
+/// also checked with [`TokenScope::allows_batch`].
+const IMPORT_ROUTES: &[&str] = &["/ledger/transactions", "/ledger/transactions/batch"];
+
+/// What a personal access token can be used for.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenScope {
+    /// Can read the ledgers of the user's books.
+    Read,
+    /// Can also create, change, and delete anything in the ledgers of the
+    /// user's books.
+    Write,
+    /// Can only create transactions, individually or in batches.
+    Import,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct UnknownScope(pub String);
+
+impl TokenScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Read => "read",
+            Self::Write => "write",
+            Self::Import => "import",
+        }
+    }
+
+    /// Determine if a token with the scope can be used for a request.
+    ///
+    /// Tokens are never allowed to manage books or other tokens, so a leaked
+    /// token can't be used to keep access after it is revoked.
+    ///
+    /// # Arguments
+    /// * `method` - The request's method.
+    /// * `route` - The route the request matched.
+    pub fn allows(&self, method: &Method, route: &str) -> bool {
+        // The same routes are available for the personal book and for books
+        // named in the path.
+        let route = route.strip_prefix("/books/:book_id").unwrap_or(route);
+
+        let is_read = method == Method::GET || method == Method::HEAD;
+        let is_book_listing = route.is_empty() || route == "/books";
+        let is_ledger = route == "/graphql" || route.starts_with("/ledger/");
+
+        match self {
+            // The GraphQL API only has queries, so it is read-only even though
+            // queries are sent with `POST`.
+            Self::Read => {
+                (is_ledger && (is_read || route == "/graphql")) || (is_book_listing && is_read)
+            }
+            Self::Write => is_ledger || (is_book_listing && is_read),
+            Self::Import => method == Method::POST && IMPORT_ROUTES.contains(&route),
+        }
+    }
+
+    /// Determine if a token with the scope can apply a batch of changes. The
+    /// route a batch is sent to can't tell what the batch does, so import
+    /// tokens are limited to batches that only create transactions here.
+    pub fn allows_batch(&self, batch: &BatchData) -> bool {
+        match self {
+            Self::Import => batch.only_creates(),
+            Self::Read | Self::Write => true,
+        }
+    }
+
+    /// Get the scope a user needs to create a token with this scope, so that
+    /// tokens can't do more than the user who created them.
+    pub fn required_scope(&self) -> Scope {
+        match self {
+            Self::Read => Scope::LedgerRead,
+            // Write tokens can use every ledger route, and import tokens can
+            // send batches, which are bulk operations.
+            Self::Write | Self::Import => Scope::LedgerAdmin,
+        }
+    }
+}
+
+impl Display for TokenScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for TokenScope {
+    type Err = UnknownScope;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(Self::Read),
+            "write" => Ok(Self::Write),
+            "import" => Ok(Self::Import),
+            other => Err(UnknownScope(other.to_owned())),
+        }
+    }
+}
+
+/// A personal access token belonging to a user.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ApiToken {
+    pub id: Uuid,
+    pub user_id: String,
+    pub name: String,
+    pub scope: TokenScope,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// Data for a new personal access token provided by a user.
+#[derive(Debug, Deserialize, Serialize, ToSchema, Validate)]
+pub struct NewApiTokenData {
+    /// A name to recognize the token by, such as the script that uses it.
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+
+    pub scope: TokenScope,
+}
+
+/// A new personal access token that has not been persisted yet.
+///
+/// The token is only available until it is persisted. Afterwards, only its
+/// hash is known.
+#[derive(Debug)]
+pub struct NewApiToken {
+    user_id: String,
+    name: String,
+    scope: TokenScope,
+    token: String,
+}
+
+impl NewApiToken {
+    /// Create a personal access token with a newly generated secret.
+    ///
+    /// # Arguments
+    /// * `user_id` - The ID of the user the token authenticates as.
+    /// * `data` - The token information provided by the user.
+    pub fn new<S: Into<String>>(user_id: S, data: NewApiTokenData) -> Self {
+        Self {
+            user_id: user_id.into(),
+            name: data.name,
+            scope: data.scope,
+            token: format!(
+                "{}{}{}",
+                TOKEN_PREFIX,
+                Uuid::new_v4().simple(),
+                Uuid::new_v4().simple()
+            ),
+        }
+    }
+
+    pub fn user_id(&self) -> &str {
+        &self.user_id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn scope(&self) -> TokenScope {
+        self.scope
+    }
+
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    pub fn token_hash(&self) -> String {
+        hash_token(&self.token)
+    }
+}
+
+/// Determine if a bearer token is a personal access token rather than a JWT.
+pub fn is_api_token(token: &str) -> bool {
+    token.starts_with(TOKEN_PREFIX)
+}
+
+/// Hash a personal access token so it can be looked up without storing the
+/// token itself.
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scope_round_trip() {
+        for scope in [TokenScope::Read, TokenScope::Write, TokenScope::Import] {
+            assert_eq!(Ok(scope), scope.as_str().parse());
+            assert_eq!(
+                serde_json::json!(scope.as_str()),
+                serde_json::to_value(scope).unwrap()
+            );
+        }
+
+        assert_eq!(
+            Err(UnknownScope("admin".to_owned())),
+            "admin".parse::<TokenScope>()
+        );
+    }
+
+    #[test]
+    fn read_scope_only_allows_reading() {
+        let scope = TokenScope::Read;
+
+        assert!(scope.allows(&Method::GET, "/ledger/transactions"));
+        assert!(scope.allows(&Method::GET, "/books/:book_id/ledger/accounts"));
+        assert!(scope.allows(&Method::POST, "/graphql"));
+        assert!(scope.allows(&Method::GET, "/books"));
+        assert!(scope.allows(&Method::GET, "/books/:book_id"));
+        assert!(!scope.allows(&Method::POST, "/ledger/transactions"));
+        assert!(!scope.allows(&Method::DELETE, "/books/:book_id"));
+        assert!(!scope.allows(&Method::GET, "/books/:book_id/members"));
+        assert!(!scope.allows(&Method::GET, "/tokens"));
+    }
+
+    #[test]
+    fn write_scope_allows_changing_the_ledger() {
+        let scope = TokenScope::Write;
+
+        assert!(scope.allows(&Method::GET, "/ledger/transactions"));
+        assert!(scope.allows(&Method::PUT, "/ledger/transactions/:transaction_id"));
+        assert!(scope.allows(&Method::POST, "/books/:book_id/ledger/closings"));
+        assert!(!scope.allows(&Method::POST, "/books"));
+        assert!(!scope.allows(&Method::POST, "/books/:book_id/invitations"));
+        assert!(!scope.allows(&Method::POST, "/tokens"));
+    }
+
+    #[test]
+    fn import_scope_only_allows_creating_transactions() {
+        let scope = TokenScope::Import;
+
+        assert!(scope.allows(&Method::POST, "/ledger/transactions"));
+        assert!(scope.allows(&Method::POST, "/books/:book_id/ledger/transactions/batch"));
+        assert!(!scope.allows(&Method::GET, "/ledger/transactions"));
+        assert!(!scope.allows(&Method::DELETE, "/ledger/transactions/:transaction_id"));
+        assert!(!scope.allows(&Method::GET, "/books"));
+    }
+
+    #[test]
+    fn import_scope_only_allows_batches_of_new_transactions() {
+        let batch = |operations: serde_json::Value| -> BatchData {
+            serde_json::from_value(serde_json::json!({ "operations": operations })).unwrap()
+        };
+        let transaction = serde_json::json!({
+            "date": "2023-04-15",
+            "payee": "Gas",
+            "entries": [{"account": "Expenses:Gas", "amount": {"currency": "USD", "value": 100}}]
+        });
+        let creates = batch(serde_json::json!([{"op": "create", "transaction": transaction}]));
+        let updates = batch(serde_json::json!([
+            {"op": "create", "transaction": transaction},
+            {"op": "update", "id": Uuid::new_v4(), "transaction": transaction},
+        ]));
+        let deletes = batch(serde_json::json!([{"op": "delete", "id": Uuid::new_v4()}]));
+
+        assert!(TokenScope::Import.allows_batch(&creates));
+        assert!(!TokenScope::Import.allows_batch(&updates));
+        assert!(!TokenScope::Import.allows_batch(&deletes));
+        assert!(TokenScope::Write.allows_batch(&deletes));
+    }
+
+    #[test]
+    fn new_token_is_hashed() {
+        let token = NewApiToken::new(
+            "user-id",
+            NewApiTokenData {
+                name: "Importer".to_owned(),
+                scope: TokenScope::Import,
+            },
+        );
+
+        assert!(is_api_token(token.token()));
+        assert!(!is_api_token("eyJhbGciOiJSUzI1NiJ9.e30.c2ln"));
+        assert_eq!(64, token.token_hash().len());
+        assert_eq!(hash_token(token.token()), token.token_hash());
+    }
+}
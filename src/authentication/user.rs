@@ -0,0 +1,152 @@
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRef, FromRequestParts, MatchedPath},
+    http::{header::AUTHORIZATION, request::Parts, Method},
+    response::{IntoResponse, Response},
+};
+use tracing::error;
+
+use crate::{http_err::ApiError, ledger::services::ApiTokenService};
+
+use super::{
+    keys::KeyStore,
+    scopes::Scope,
+    tokens::{is_api_token, TokenScope},
+    JwtError, TokenClaims,
+};
+
+/// The user making a request.
+///
+/// Users authenticate with either a JWT from the identity provider or one of
+/// their personal access tokens. Both identify the user the same way, and are
+/// limited to the requests their scopes allow.
+#[derive(Clone, Debug)]
+pub struct AuthenticatedUser {
+    pub user_id: String,
+    pub credential: Credential,
+}
+
+/// How a user authenticated.
+#[derive(Clone, Copy, Debug)]
+pub enum Credential {
+    /// A JWT granting the contained scope, or no scope at all.
+    Jwt(Option<Scope>),
+    /// A personal access token with the contained scope.
+    ApiToken(TokenScope),
+}
+
+impl AuthenticatedUser {
+    /// Ensure the user's JWT grants everything `scope` does.
+    pub fn require_scope(&self, scope: Scope) -> Result<(), ApiError> {
+        match self.credential {
+            Credential::Jwt(Some(granted)) if granted.includes(scope) => Ok(()),
+            Credential::Jwt(_) => Err(ApiError::Forbidden(format!(
+                "This request requires the {} scope.",
+                scope
+            ))),
+            Credential::ApiToken(token_scope) => Err(token_scope_forbidden(token_scope)),
+        }
+    }
+
+    /// Ensure the user is allowed to make a request to a route.
+    fn authorize(&self, method: &Method, route: &str) -> Result<(), ApiError> {
+        match self.credential {
+            Credential::Jwt(_) => match Scope::required_for(method, route) {
+                Some(scope) => self.require_scope(scope),
+                None => Ok(()),
+            },
+            Credential::ApiToken(scope) if scope.allows(method, route) => Ok(()),
+            Credential::ApiToken(scope) => Err(token_scope_forbidden(scope)),
+        }
+    }
+}
+
+fn token_scope_forbidden(scope: TokenScope) -> ApiError {
+    ApiError::Forbidden(format!(
+        "Personal access tokens with the {} scope can't be used for this request.",
+        scope
+    ))
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AuthenticatedUser
+where
+    ApiTokenService: FromRef<S>,
+    KeyStore: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        // Middleware may have already authenticated the request, in which case
+        // the token doesn't have to be looked up again.
+        let user = match parts.extensions.get::<Self>() {
+            Some(user) => user.clone(),
+            None => {
+                let user = authenticate(parts, state).await?;
+                parts.extensions.insert(user.clone());
+
+                user
+            }
+        };
+
+        let route = parts
+            .extensions
+            .get::<MatchedPath>()
+            .map(MatchedPath::as_str)
+            .unwrap_or_default();
+
+        user.authorize(&parts.method, route)
+            .map_err(IntoResponse::into_response)?;
+
+        Ok(user)
+    }
+}
+
+async fn authenticate<S>(parts: &Parts, state: &S) -> Result<AuthenticatedUser, Response>
+where
+    ApiTokenService: FromRef<S>,
+    KeyStore: FromRef<S>,
+    S: Send + Sync,
+{
+    let token = parts
+        .headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split_once(' '))
+        .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+        .map(|(_, token)| token.trim())
+        .ok_or_else(|| JwtError::Missing.into_response())?;
+
+    if !is_api_token(token) {
+        let key_store = KeyStore::from_ref(state);
+        let claims: TokenClaims = key_store
+            .validate(token)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        crate::telemetry::record_user_id(claims.user_id());
+
+        return Ok(AuthenticatedUser {
+            user_id: claims.user_id().to_owned(),
+            credential: Credential::Jwt(key_store.granted_scope(&claims)),
+        });
+    }
+
+    let token = ApiTokenService::from_ref(state)
+        .authenticate(token)
+        .await
+        .map_err(|error| {
+            error!(?error, "Failed to look up personal access token.");
+
+            ApiError::InternalServerError.into_response()
+        })?
+        .ok_or_else(|| JwtError::Invalid.into_response())?;
+
+    crate::telemetry::record_user_id(&token.user_id);
+
+    Ok(AuthenticatedUser {
+        user_id: token.user_id,
+        credential: Credential::ApiToken(token.scope),
+    })
+}
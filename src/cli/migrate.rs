@@ -1,15 +1,31 @@
-use sqlx::{migrate::Migrator, postgres::PgPoolOptions};
+use std::time::Duration;
+
+use sqlx::migrate::Migrator;
+
+use crate::database::Database;
 
 static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
 
+/// SQLite databases are created from a consolidated schema of their own, since
+/// the Postgres migrations rely on features SQLite doesn't have.
+static SQLITE_MIGRATOR: Migrator = sqlx::migrate!("./migrations/sqlite");
+
+/// How long to wait for a connection to the database being migrated.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
 pub struct MigrationOpts {
     pub database_url: String,
 }
 
 pub async fn run_migrations(opts: MigrationOpts) -> anyhow::Result<()> {
-    let pool = PgPoolOptions::new().connect(&opts.database_url).await?;
+    let db = Database::connect(&opts.database_url, 1, CONNECT_TIMEOUT).await?;
+
+    match &db {
+        Database::Postgres(pool) => MIGRATOR.run(&**pool).await?,
+        Database::Sqlite(pool) => SQLITE_MIGRATOR.run(&**pool).await?,
+    }
 
-    MIGRATOR.run(&pool).await?;
+    db.close().await;
 
     Ok(())
 }
@@ -1,10 +1,19 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, net::IpAddr, path::PathBuf, time::Duration};
 
-use clap::{Args, Parser, Subcommand};
+use anyhow::Context;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use tracing::debug;
-use tracing_subscriber::EnvFilter;
+use tracing_subscriber::{filter::LevelFilter, EnvFilter};
+use utoipa::OpenApi;
 
-use crate::server;
+use crate::{
+    authentication::keys::KeyStoreOptions,
+    ledger::http::ApiDoc,
+    rate_limit::{Limit, Limits, RateLimitBackend},
+    server::{self, LedgerBackend},
+    telemetry,
+};
 
 mod migrate;
 
@@ -18,17 +27,36 @@ struct Cli {
     /// If provided, errors will be sent to Sentry.
     #[clap(long = "sentry-dsn", env = "SENTRY_DSN")]
     sentry_dsn: Option<String>,
+
+    /// The gRPC endpoint of an OpenTelemetry collector, such as
+    /// `http://localhost:4317`.
+    ///
+    /// If provided, request and database spans are exported to the collector
+    /// over OTLP.
+    #[clap(long = "otlp-endpoint", env = "OTEL_EXPORTER_OTLP_ENDPOINT")]
+    otlp_endpoint: Option<String>,
+
+    /// The service name that exported spans are reported under.
+    #[clap(
+        long = "otel-service-name",
+        env = "OTEL_SERVICE_NAME",
+        default_value = "zeroed-books-api"
+    )]
+    otel_service_name: String,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     Migrate(MigrateOpts),
+    /// Write the OpenAPI description of the HTTP API.
+    Openapi(OpenApiOpts),
     Serve(ServeOpts),
 }
 
 #[derive(Args)]
 struct MigrateOpts {
-    /// Connection string for the database.
+    /// Connection string for the database. URLs starting with `sqlite:` use
+    /// a SQLite database, which is created if it doesn't exist.
     #[clap(long = "database-url", env = "DATABASE_URL")]
     database_url: String,
 }
@@ -41,8 +69,37 @@ impl From<MigrateOpts> for migrate::MigrationOpts {
     }
 }
 
+#[derive(Args)]
+struct OpenApiOpts {
+    /// File to write the document to. If omitted, the document is written to
+    /// standard output.
+    #[clap(long = "output", short = 'o')]
+    output: Option<PathBuf>,
+}
+
 #[derive(Args)]
 struct ServeOpts {
+    /// The maximum size of a single transaction attachment in bytes.
+    #[clap(long = "attachment-max-size", default_value = "10485760")]
+    attachment_max_size: u64,
+
+    /// The maximum combined size in bytes of all attachments in a single
+    /// book.
+    #[clap(long = "attachment-quota", default_value = "104857600")]
+    attachment_quota: u64,
+
+    /// Directory used to store the content of transaction attachments.
+    #[clap(
+        long = "attachment-storage-path",
+        env = "ATTACHMENT_STORAGE_PATH",
+        default_value = "attachments"
+    )]
+    attachment_storage_path: PathBuf,
+
+    /// The IP address to listen for requests on.
+    #[clap(long = "bind", env = "BIND_ADDRESS", default_value = "0.0.0.0")]
+    bind_address: IpAddr,
+
     /// The number of connections to use for the database pool.
     #[clap(long = "database-pool-size", default_value = "16")]
     database_pool_size: u32,
@@ -51,29 +108,212 @@ struct ServeOpts {
     #[clap(long = "database-timeout", default_value = "5")]
     database_timeout: u8,
 
-    /// Connection string for the application database.
+    /// Connection string for the application database. URLs starting with
+    /// `sqlite:` use a SQLite database, which is created if it doesn't exist.
+    /// Not needed when the ledger is kept in memory.
     #[clap(long = "database-url", env = "DATABASE_URL")]
-    database_url: String,
+    database_url: Option<String>,
+
+    /// The number of hours that idempotency keys and their stored responses
+    /// are kept.
+    #[clap(long = "idempotency-key-ttl-hours", default_value = "24")]
+    idempotency_key_ttl_hours: u32,
 
     /// The audience identifier for this application. Tokens must be issued
-    /// specifically for this audience in order for them to validate
-    /// successfully.
-    #[clap(long = "jwt-audience", env = "JWT_AUDIENCE")]
-    jwt_audience: String,
-
-    /// Authority used to validate JWTs
-    #[clap(long = "jwt-authority", env = "JWT_AUTHORITY")]
-    jwt_authority: String,
+    /// specifically for one of the audiences in order for them to validate
+    /// successfully. Multiple audiences may be separated by commas.
+    #[clap(
+        long = "jwt-audience",
+        env = "JWT_AUDIENCE",
+        required = true,
+        value_delimiter = ','
+    )]
+    jwt_audience: Vec<String>,
+
+    /// An issuer whose JWTs are trusted. Its keys are fetched from
+    /// `{authority}/.well-known/jwks.json`. Multiple authorities may be
+    /// separated by commas. If none are given, tokens from any issuer are
+    /// accepted as long as they are signed by a key from the JWKS file or the
+    /// secret.
+    #[clap(long = "jwt-authority", env = "JWT_AUTHORITY", value_delimiter = ',')]
+    jwt_authority: Vec<String>,
+
+    /// A file containing a JSON Web Key Set to verify JWTs with, in addition
+    /// to the keys of the authorities. The file is read again whenever keys
+    /// are refreshed.
+    #[clap(long = "jwt-jwks-file", env = "JWT_JWKS_FILE")]
+    jwt_jwks_file: Option<PathBuf>,
+
+    /// The number of minutes between refreshes of the JWT keys. Keys are also
+    /// refreshed when a token is signed by an unknown key.
+    #[clap(
+        long = "jwt-refresh-interval-minutes",
+        default_value = "60",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    jwt_refresh_interval_minutes: u64,
+
+    /// Deny access to JWTs that don't grant any of the API's scopes. Without
+    /// this, such tokens are given full access, as they were before scopes
+    /// were enforced.
+    #[clap(long = "jwt-require-scope", env = "JWT_REQUIRE_SCOPE")]
+    jwt_require_scope: bool,
+
+    /// A secret to verify JWTs signed with HS256. This is intended for
+    /// development and integration tests, where running an identity provider
+    /// is impractical.
+    #[clap(long = "jwt-secret", env = "JWT_SECRET", hide_env_values = true)]
+    jwt_secret: Option<String>,
+
+    /// A separate port to serve Prometheus metrics on. If omitted, metrics
+    /// are served at `/metrics` on the main port.
+    #[clap(long = "metrics-port", env = "METRICS_PORT")]
+    metrics_port: Option<u16>,
+
+    /// Where the ledger's transactions are kept. With `memory`, nothing is
+    /// stored in a database and everything is lost when the server stops,
+    /// which is only useful for demos. Attachments and webhooks aren't
+    /// available in memory.
+    #[clap(
+        long = "storage",
+        env = "LEDGER_STORAGE",
+        value_enum,
+        default_value = "database"
+    )]
+    storage: Storage,
+
+    /// The port to listen for requests on.
+    #[clap(long = "port", env = "PORT", default_value = "8000")]
+    port: u16,
+
+    /// Where rate limiting state is kept. With `database`, every server
+    /// instance using the same database shares the same limits.
+    #[clap(
+        long = "rate-limit-store",
+        env = "RATE_LIMIT_STORE",
+        value_enum,
+        default_value = "memory"
+    )]
+    rate_limit_store: RateLimitStore,
+
+    /// The number of read requests each user may make per minute.
+    #[clap(
+        long = "rate-limit-reads-per-minute",
+        default_value = "600",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    rate_limit_reads_per_minute: u32,
+
+    /// The number of report requests, such as periodic balances or GraphQL
+    /// queries, each user may make per minute.
+    #[clap(
+        long = "rate-limit-reports-per-minute",
+        default_value = "30",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    rate_limit_reports_per_minute: u32,
+
+    /// The number of write requests each user may make per minute.
+    #[clap(
+        long = "rate-limit-writes-per-minute",
+        default_value = "120",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    rate_limit_writes_per_minute: u32,
+
+    /// The number of days that deleted transactions are kept in the trash
+    /// before being permanently removed.
+    #[clap(long = "trash-retention-days", default_value = "30")]
+    trash_retention_days: u32,
+
+    /// Allow webhook endpoints on loopback, private and link-local addresses.
+    /// Only enable this if nothing on the server's network needs protecting
+    /// from requests made on behalf of users.
+    #[clap(long = "webhook-allow-private-addresses")]
+    webhook_allow_private_addresses: bool,
+
+    /// The number of seconds to wait for a webhook endpoint to respond.
+    #[clap(long = "webhook-timeout-seconds", default_value = "10")]
+    webhook_timeout_seconds: u8,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum RateLimitStore {
+    /// Don't limit requests.
+    Disabled,
+    /// Each server instance limits requests on its own.
+    Memory,
+    /// Limits are shared through the application database.
+    #[value(alias = "postgres")]
+    Database,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Storage {
+    /// Keep the ledger and everything else in memory.
+    Memory,
+    /// Keep the ledger in the application database, whether that is Postgres
+    /// or SQLite.
+    #[value(alias = "postgres")]
+    Database,
+}
+
+impl From<Storage> for LedgerBackend {
+    fn from(storage: Storage) -> Self {
+        match storage {
+            Storage::Memory => LedgerBackend::Memory,
+            Storage::Database => LedgerBackend::Database,
+        }
+    }
+}
+
+impl From<RateLimitStore> for Option<RateLimitBackend> {
+    fn from(store: RateLimitStore) -> Self {
+        match store {
+            RateLimitStore::Disabled => None,
+            RateLimitStore::Memory => Some(RateLimitBackend::Memory),
+            RateLimitStore::Database => Some(RateLimitBackend::Database),
+        }
+    }
 }
 
 impl From<ServeOpts> for server::Options {
     fn from(opts: ServeOpts) -> Self {
         Self {
+            attachment_max_size: opts.attachment_max_size,
+            attachment_quota: opts.attachment_quota,
+            attachment_storage_path: opts.attachment_storage_path,
+            bind_address: opts.bind_address,
             database_pool_size: opts.database_pool_size,
             database_timeout_seconds: opts.database_timeout,
             database_url: opts.database_url,
-            jwt_audience: opts.jwt_audience,
-            jwt_authority: opts.jwt_authority,
+            idempotency_key_ttl_hours: opts.idempotency_key_ttl_hours,
+            jwt_keys: KeyStoreOptions {
+                audiences: opts.jwt_audience,
+                authorities: opts.jwt_authority,
+                jwks_file: opts.jwt_jwks_file,
+                refresh_interval: Duration::from_secs(opts.jwt_refresh_interval_minutes * 60),
+                require_scope: opts.jwt_require_scope,
+                secret: opts.jwt_secret,
+            },
+            ledger_backend: opts.storage.into(),
+            metrics_port: opts.metrics_port,
+            port: opts.port,
+            rate_limit_backend: opts.rate_limit_store.into(),
+            rate_limits: Limits {
+                read: Limit {
+                    per_minute: opts.rate_limit_reads_per_minute,
+                },
+                write: Limit {
+                    per_minute: opts.rate_limit_writes_per_minute,
+                },
+                report: Limit {
+                    per_minute: opts.rate_limit_reports_per_minute,
+                },
+            },
+            trash_retention_days: opts.trash_retention_days,
+            webhook_allow_private_addresses: opts.webhook_allow_private_addresses,
+            webhook_timeout_seconds: opts.webhook_timeout_seconds,
         }
     }
 }
@@ -105,21 +345,69 @@ pub async fn run_with_sys_args() -> anyhow::Result<()> {
         None
     };
 
+    let otel_layer = match &cli.otlp_endpoint {
+        Some(endpoint) => {
+            let tracer = telemetry::otlp_tracer(endpoint, cli.otel_service_name.clone())?;
+
+            // Dependencies like the HTTP client used for exporting emit their
+            // own detailed spans, which would be exported in an endless loop.
+            Some(
+                tracing_opentelemetry::layer()
+                    .with_tracer(tracer)
+                    .with_filter(LevelFilter::INFO),
+            )
+        }
+        None => None,
+    };
+
     let fmt_layer = tracing_subscriber::fmt::layer().with_filter(EnvFilter::from_default_env());
 
     tracing_subscriber::registry()
         .with(fmt_layer)
         .with(sentry_tracing_layer)
+        .with(otel_layer)
         .init();
 
-    match cli.command {
+    let result = run_command(cli.command).await;
+
+    if cli.otlp_endpoint.is_some() {
+        // Flush the spans that haven't been exported yet. This blocks until
+        // the exporter is done, so it has to be kept off the async runtime.
+        tokio::task::spawn_blocking(opentelemetry::global::shutdown_tracer_provider).await?;
+    }
+
+    result
+}
+
+async fn run_command(command: Commands) -> anyhow::Result<()> {
+    match command {
         Commands::Migrate(opts) => Ok(migrate::run_migrations(opts.into()).await?),
+        Commands::Openapi(opts) => {
+            let document = ApiDoc::openapi()
+                .to_pretty_json()
+                .context("Failed to serialize OpenAPI document.")?;
+
+            match opts.output {
+                Some(path) => tokio::fs::write(&path, document + "\n")
+                    .await
+                    .with_context(|| format!("Failed to write {}.", path.display())),
+                None => {
+                    println!("{}", document);
+
+                    Ok(())
+                }
+            }
+        }
         Commands::Serve(opts) => {
-            let migrate_opts = MigrateOpts {
-                database_url: opts.database_url.clone(),
-            };
+            // The in-memory database used with a memory ledger is migrated
+            // when it is opened.
+            if let (Storage::Database, Some(database_url)) = (opts.storage, &opts.database_url) {
+                let migrate_opts = MigrateOpts {
+                    database_url: database_url.clone(),
+                };
 
-            migrate::run_migrations(migrate_opts.into()).await?;
+                migrate::run_migrations(migrate_opts.into()).await?;
+            }
 
             server::serve(opts.into()).await
         }
@@ -1,6 +1,11 @@
-use std::ops::Deref;
+use std::{ops::Deref, str::FromStr, time::Duration};
 
-use sqlx::PgPool;
+use chrono::{DateTime, DurationRound, Utc};
+use sqlx::{
+    postgres::PgPoolOptions,
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+    PgPool, SqlitePool,
+};
 
 #[derive(Clone)]
 pub struct PostgresConnection(PgPool);
@@ -18,3 +23,142 @@ impl Deref for PostgresConnection {
         &self.0
     }
 }
+
+/// A connection to a SQLite database, for self-hosted instances that don't
+/// want to run Postgres.
+///
+/// Not to be confused with [`sqlx::SqliteConnection`], which is a single
+/// connection rather than a pool.
+#[derive(Clone)]
+pub struct SqliteConnection(SqlitePool);
+
+impl SqliteConnection {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self(pool)
+    }
+}
+
+impl Deref for SqliteConnection {
+    type Target = SqlitePool;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// The database backing the application, chosen by the scheme of its URL.
+#[derive(Clone)]
+pub enum Database {
+    Postgres(PostgresConnection),
+    Sqlite(SqliteConnection),
+}
+
+impl Database {
+    /// Connect to the database at a URL. URLs starting with `sqlite:` open a
+    /// SQLite database, which is created if it doesn't exist yet. Anything
+    /// else is treated as a Postgres URL.
+    ///
+    /// SQLite only allows one writer at a time, and a transaction that starts
+    /// out reading can fail when it tries to write while another connection
+    /// is writing. SQLite pools are therefore limited to a single connection,
+    /// regardless of `max_connections`.
+    pub async fn connect(
+        url: &str,
+        max_connections: u32,
+        acquire_timeout: Duration,
+    ) -> anyhow::Result<Self> {
+        if is_sqlite_url(url) {
+            let options = SqliteConnectOptions::from_str(url)?
+                .create_if_missing(true)
+                .foreign_keys(true);
+            let pool = SqlitePoolOptions::new()
+                .max_connections(1)
+                .acquire_timeout(acquire_timeout)
+                // In-memory databases are lost when their connection closes.
+                .idle_timeout(None)
+                .max_lifetime(None)
+                .connect_with(options)
+                .await?;
+
+            Ok(Self::Sqlite(SqliteConnection::new(pool)))
+        } else {
+            let pool = PgPoolOptions::new()
+                .max_connections(max_connections)
+                .acquire_timeout(acquire_timeout)
+                .connect(url)
+                .await?;
+
+            Ok(Self::Postgres(PostgresConnection::new(pool)))
+        }
+    }
+
+    /// Open a private SQLite database in memory with the schema already in
+    /// place. Nothing in it outlives the connection, so it is lost when the
+    /// server stops.
+    pub async fn in_memory(acquire_timeout: Duration) -> anyhow::Result<SqliteConnection> {
+        let db = match Self::connect("sqlite::memory:", 1, acquire_timeout).await? {
+            Self::Sqlite(db) => db,
+            Self::Postgres(_) => unreachable!("connected to Postgres with a SQLite URL"),
+        };
+
+        sqlx::migrate!("./migrations/sqlite").run(&*db).await?;
+
+        Ok(db)
+    }
+
+    /// Check out a connection from the pool and return it right away.
+    pub async fn acquire(&self) -> sqlx::Result<()> {
+        match self {
+            Self::Postgres(db) => db.acquire().await.map(drop),
+            Self::Sqlite(db) => db.acquire().await.map(drop),
+        }
+    }
+
+    pub async fn close(&self) {
+        match self {
+            Self::Postgres(db) => db.close().await,
+            Self::Sqlite(db) => db.close().await,
+        }
+    }
+
+    /// The number of idle connections in the pool.
+    pub fn num_idle(&self) -> usize {
+        match self {
+            Self::Postgres(db) => db.num_idle(),
+            Self::Sqlite(db) => db.num_idle(),
+        }
+    }
+
+    /// Run a trivial query to check that the database is reachable.
+    pub async fn ping(&self) -> sqlx::Result<()> {
+        match self {
+            Self::Postgres(db) => sqlx::query("SELECT 1").execute(&**db).await.map(drop),
+            Self::Sqlite(db) => sqlx::query("SELECT 1").execute(&**db).await.map(drop),
+        }
+    }
+
+    /// The number of connections in the pool, both idle and in use.
+    pub fn size(&self) -> u32 {
+        match self {
+            Self::Postgres(db) => db.size(),
+            Self::Sqlite(db) => db.size(),
+        }
+    }
+}
+
+/// Determine if a database URL refers to a SQLite database.
+pub fn is_sqlite_url(url: &str) -> bool {
+    url.starts_with("sqlite:")
+}
+
+/// Get the current time for a SQLite timestamp.
+///
+/// SQLite has no clock of its own that matches Postgres, so timestamps are
+/// provided by the application, truncated to the microseconds Postgres keeps.
+/// Cursors derived from timestamps then behave the same with either database.
+pub fn sqlite_now() -> DateTime<Utc> {
+    let now = Utc::now();
+
+    now.duration_trunc(chrono::Duration::microseconds(1))
+        .unwrap_or(now)
+}
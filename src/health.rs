@@ -0,0 +1,82 @@
+//! Health checks for container orchestrators.
+//!
+//! `/healthz` reports whether the process is alive and able to answer
+//! requests, while `/readyz` reports whether it can do useful work and should
+//! receive traffic. Neither requires authentication.
+
+use axum::{extract::State, http::StatusCode, routing::get, Json, Router};
+use serde::Serialize;
+use tracing::warn;
+
+use crate::{authentication::keys::KeyStore, database::Database, server::AppState};
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/healthz", get(get_liveness))
+        .route("/readyz", get(get_readiness))
+}
+
+#[derive(Serialize)]
+struct HealthRep {
+    status: &'static str,
+}
+
+#[derive(Serialize)]
+struct ReadinessRep {
+    status: &'static str,
+    checks: ReadinessChecksRep,
+}
+
+#[derive(Serialize)]
+struct ReadinessChecksRep {
+    database: &'static str,
+    jwks: &'static str,
+}
+
+async fn get_liveness() -> Json<HealthRep> {
+    Json(HealthRep { status: "ok" })
+}
+
+async fn get_readiness(
+    State(db): State<Database>,
+    State(key_store): State<KeyStore>,
+) -> (StatusCode, Json<ReadinessRep>) {
+    let database = match db.ping().await {
+        Ok(_) => true,
+        Err(error) => {
+            warn!(?error, "Readiness check failed to reach the database.");
+
+            false
+        }
+    };
+
+    // Keys that can't be refreshed are kept, so this only fails if there
+    // are no keys to verify tokens with at all.
+    let jwks = key_store.has_keys();
+
+    let ready = database && jwks;
+    let status_code = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (
+        status_code,
+        Json(ReadinessRep {
+            status: if ready { "ok" } else { "unavailable" },
+            checks: ReadinessChecksRep {
+                database: check_status(database),
+                jwks: check_status(jwks),
+            },
+        }),
+    )
+}
+
+fn check_status(passed: bool) -> &'static str {
+    if passed {
+        "ok"
+    } else {
+        "failed"
+    }
+}
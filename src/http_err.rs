@@ -1,34 +1,151 @@
-use axum::http::StatusCode;
+//! The errors returned by the HTTP API.
+//!
+//! Every error is described by an [RFC 7807] problem details document with a
+//! stable, machine readable `code` that clients can rely on instead of the
+//! human readable `detail`.
+//!
+//! [RFC 7807]: https://www.rfc-editor.org/rfc/rfc7807
+
+use std::{collections::HashMap, time::Duration};
+
+use axum::extract::rejection::{JsonRejection, PathRejection, QueryRejection};
+use axum::http::{header, HeaderValue, StatusCode};
 use axum::response::{IntoResponse, Response};
 use axum::Json;
 use serde::Serialize;
 use tracing::error;
-use validator::ValidationErrors;
+use utoipa::ToSchema;
+use validator::{ValidationErrors, ValidationErrorsKind};
+
+/// The media type of problem details documents.
+pub const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+/// The key `validator` uses for errors about a whole struct rather than one of
+/// its fields.
+const STRUCT_ERRORS_KEY: &str = "__all__";
 
 #[derive(Debug)]
 pub enum ApiError {
     BadRequestReason(String),
 
+    Conflict(String),
+
+    /// The user is authenticated but is not allowed to make the request.
+    Forbidden(String),
+
     InternalServerError,
 
+    NotFound(String),
+
+    PayloadTooLarge(String),
+
+    /// The change touches a period that has been locked.
+    PeriodLocked(String),
+
+    TooManyRequests {
+        retry_after: Duration,
+    },
+
+    /// The request's authentication token is missing or invalid.
+    Unauthorized(String),
+
+    /// The request references a currency that doesn't exist. The value is the
+    /// currency's code.
+    UnknownCurrency(String),
+
+    UnsupportedMediaType(String),
+
     ValidationError(ValidationErrors),
 }
 
+impl ApiError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::BadRequestReason(_) => ErrorCode::BadRequest,
+            Self::Conflict(_) => ErrorCode::Conflict,
+            Self::Forbidden(_) => ErrorCode::Forbidden,
+            Self::InternalServerError => ErrorCode::InternalError,
+            Self::NotFound(_) => ErrorCode::NotFound,
+            Self::PayloadTooLarge(_) => ErrorCode::PayloadTooLarge,
+            Self::PeriodLocked(_) => ErrorCode::PeriodLocked,
+            Self::TooManyRequests { .. } => ErrorCode::RateLimited,
+            Self::Unauthorized(_) => ErrorCode::Unauthorized,
+            Self::UnknownCurrency(_) => ErrorCode::UnknownCurrency,
+            Self::UnsupportedMediaType(_) => ErrorCode::UnsupportedMediaType,
+            Self::ValidationError(_) => ErrorCode::ValidationFailed,
+        }
+    }
+}
+
+impl ApiError {
+    /// Convert the rejection of one of axum's extractors, described by the
+    /// status and body of the response axum would have sent instead.
+    fn from_rejection(status: StatusCode, detail: String) -> Self {
+        match status {
+            StatusCode::PAYLOAD_TOO_LARGE => Self::PayloadTooLarge(detail),
+            StatusCode::UNSUPPORTED_MEDIA_TYPE => Self::UnsupportedMediaType(detail),
+            status if status.is_server_error() => {
+                error!(%status, detail, "Failed to extract request.");
+
+                Self::InternalServerError
+            }
+            _ => Self::BadRequestReason(detail),
+        }
+    }
+}
+
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
-        match self {
-            Self::BadRequestReason(reason) => {
-                (StatusCode::BAD_REQUEST, Json(ErrorRep { message: reason })).into_response()
+        let code = self.code();
+
+        let (detail, errors, retry_after) = match self {
+            Self::BadRequestReason(detail)
+            | Self::Conflict(detail)
+            | Self::Forbidden(detail)
+            | Self::NotFound(detail)
+            | Self::PayloadTooLarge(detail)
+            | Self::PeriodLocked(detail)
+            | Self::Unauthorized(detail)
+            | Self::UnsupportedMediaType(detail) => (detail, Vec::new(), None),
+            Self::InternalServerError => ("Internal server error.".to_owned(), Vec::new(), None),
+            Self::TooManyRequests { retry_after } => {
+                // Clients can't act on fractions of a second, so round up to
+                // avoid retrying before a token is available.
+                let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+
+                (
+                    format!("Too many requests. Try again in {} seconds.", seconds),
+                    Vec::new(),
+                    Some(seconds),
+                )
+            }
+            Self::UnknownCurrency(currency) => (
+                format!("The currency {:?} does not exist.", currency),
+                Vec::new(),
+                None,
+            ),
+            Self::ValidationError(errors) => {
+                crate::monitoring::record_validation_failures(&errors);
+
+                (
+                    "The request contains invalid fields.".to_owned(),
+                    FieldProblemRep::from_validation_errors(&errors),
+                    None,
+                )
             }
-            Self::InternalServerError => (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorRep {
-                    message: "Internal server error.".to_owned(),
-                }),
-            )
-                .into_response(),
-            Self::ValidationError(error) => (StatusCode::BAD_REQUEST, Json(error)).into_response(),
+        };
+
+        let mut response = ProblemRep::new(code, detail)
+            .with_errors(errors)
+            .into_response();
+
+        if let Some(seconds) = retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(seconds));
         }
+
+        response
     }
 }
 
@@ -40,6 +157,24 @@ impl From<anyhow::Error> for ApiError {
     }
 }
 
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        Self::from_rejection(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(rejection: PathRejection) -> Self {
+        Self::from_rejection(rejection.status(), rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        Self::from_rejection(rejection.status(), rejection.body_text())
+    }
+}
+
 impl From<ValidationErrors> for ApiError {
     fn from(value: ValidationErrors) -> Self {
         Self::ValidationError(value)
@@ -48,7 +183,279 @@ impl From<ValidationErrors> for ApiError {
 
 pub type ApiResponse<T> = Result<T, ApiError>;
 
-#[derive(Serialize)]
-pub struct ErrorRep {
-    pub message: String,
+/// A stable identifier for a kind of problem.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    BadRequest,
+    Conflict,
+    Forbidden,
+    InternalError,
+    NotFound,
+    PayloadTooLarge,
+    PeriodLocked,
+    PreconditionFailed,
+    RateLimited,
+    Unauthorized,
+    UnknownCurrency,
+    UnsupportedMediaType,
+    ValidationFailed,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::BadRequest => "bad_request",
+            Self::Conflict => "conflict",
+            Self::Forbidden => "forbidden",
+            Self::InternalError => "internal_error",
+            Self::NotFound => "not_found",
+            Self::PayloadTooLarge => "payload_too_large",
+            Self::PeriodLocked => "period_locked",
+            Self::PreconditionFailed => "precondition_failed",
+            Self::RateLimited => "rate_limited",
+            Self::Unauthorized => "unauthorized",
+            Self::UnknownCurrency => "unknown_currency",
+            Self::UnsupportedMediaType => "unsupported_media_type",
+            Self::ValidationFailed => "validation_failed",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::BadRequest | Self::UnknownCurrency | Self::ValidationFailed => {
+                StatusCode::BAD_REQUEST
+            }
+            Self::Conflict | Self::PeriodLocked => StatusCode::CONFLICT,
+            Self::Forbidden => StatusCode::FORBIDDEN,
+            Self::InternalError => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::PreconditionFailed => StatusCode::PRECONDITION_FAILED,
+            Self::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        }
+    }
+
+    /// A short summary of the problem that doesn't change between
+    /// occurrences.
+    pub fn title(&self) -> &'static str {
+        match self {
+            Self::BadRequest => "The request is malformed.",
+            Self::Conflict => "The request conflicts with the current state of the ledger.",
+            Self::Forbidden => "The request is not allowed.",
+            Self::InternalError => "An unexpected error occurred.",
+            Self::NotFound => "The resource does not exist.",
+            Self::PayloadTooLarge => "The request body is too large.",
+            Self::PeriodLocked => "The change touches a locked period.",
+            Self::PreconditionFailed => "The resource was modified.",
+            Self::RateLimited => "Too many requests.",
+            Self::Unauthorized => "The request is not authenticated.",
+            Self::UnknownCurrency => "The currency does not exist.",
+            Self::UnsupportedMediaType => "The content type is not supported.",
+            Self::ValidationFailed => "The request contains invalid fields.",
+        }
+    }
+}
+
+/// A problem details document describing why a request failed.
+#[derive(Serialize, ToSchema)]
+pub struct ProblemRep {
+    /// A URI reference identifying the kind of problem, relative to the API.
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    /// The HTTP status code of the response.
+    pub status: u16,
+    /// A human readable explanation of this occurrence of the problem.
+    pub detail: String,
+    pub code: ErrorCode,
+    /// The ID of the request that failed, which is also sent in the
+    /// `X-Request-Id` header. Include this when reporting a problem.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// The problems with each invalid field, for `validation_failed`
+    /// problems.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldProblemRep>,
+}
+
+impl ProblemRep {
+    /// Describe a problem with the request currently being handled.
+    pub fn new(code: ErrorCode, detail: String) -> Self {
+        Self {
+            problem_type: format!("/problems/{}", code.as_str()),
+            title: code.title().to_owned(),
+            status: code.status().as_u16(),
+            detail,
+            code,
+            request_id: crate::telemetry::current_request_id(),
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn with_errors(mut self, errors: Vec<FieldProblemRep>) -> Self {
+        self.errors = errors;
+
+        self
+    }
+}
+
+impl IntoResponse for ProblemRep {
+    fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+
+        (
+            status,
+            [(header::CONTENT_TYPE, PROBLEM_CONTENT_TYPE)],
+            Json(self),
+        )
+            .into_response()
+    }
+}
+
+/// A single problem with the value of a field.
+#[derive(Debug, PartialEq, Serialize, ToSchema)]
+pub struct FieldProblemRep {
+    /// The path to the field, such as `entries[1].amount.currency`. Problems
+    /// with the request as a whole have an empty path.
+    pub field: String,
+    /// A machine readable identifier for the problem, such as `length` or
+    /// `unbalanced`.
+    pub code: String,
+    pub message: Option<String>,
+    /// Details about the problem, such as the bounds of a length check or the
+    /// imbalance of each currency in a transaction.
+    #[schema(value_type = Object)]
+    pub params: HashMap<String, serde_json::Value>,
+}
+
+impl FieldProblemRep {
+    /// Flatten the nested errors from `validator` into a list of problems,
+    /// ordered by field.
+    pub fn from_validation_errors(errors: &ValidationErrors) -> Vec<Self> {
+        let mut problems = Vec::new();
+        Self::collect(errors, "", &mut problems);
+
+        problems.sort_by(|a, b| a.field.cmp(&b.field));
+
+        problems
+    }
+
+    fn collect(errors: &ValidationErrors, prefix: &str, problems: &mut Vec<Self>) {
+        for (field, kind) in errors.errors() {
+            let path = match (*field, prefix) {
+                (STRUCT_ERRORS_KEY, _) => prefix.to_owned(),
+                (field, "") => field.to_owned(),
+                (field, prefix) => format!("{}.{}", prefix, field),
+            };
+
+            match kind {
+                ValidationErrorsKind::Field(errors) => {
+                    problems.extend(errors.iter().map(|error| {
+                        Self {
+                            field: path.clone(),
+                            code: error.code.to_string(),
+                            message: error.message.as_ref().map(|message| message.to_string()),
+                            params: error
+                                .params
+                                .iter()
+                                .map(|(key, value)| (key.to_string(), value.clone()))
+                                .collect(),
+                        }
+                    }))
+                }
+                ValidationErrorsKind::Struct(errors) => Self::collect(errors, &path, problems),
+                ValidationErrorsKind::List(errors) => {
+                    for (index, errors) in errors {
+                        Self::collect(errors, &format!("{}[{}]", path, index), problems);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use validator::ValidationError;
+
+    use super::*;
+
+    #[test]
+    fn validation_errors_are_flattened() {
+        let mut amount_errors = ValidationErrors::new();
+        amount_errors.add("currency", ValidationError::new("length"));
+
+        let mut entry_errors = ValidationErrors::new();
+        entry_errors.errors_mut().insert(
+            "amount",
+            ValidationErrorsKind::Struct(Box::new(amount_errors)),
+        );
+
+        let mut errors = ValidationErrors::new();
+        errors.add(STRUCT_ERRORS_KEY, ValidationError::new("unbalanced"));
+        errors.add("payee", ValidationError::new("length"));
+        errors.errors_mut().insert(
+            "entries",
+            ValidationErrorsKind::List(BTreeMap::from([(1, Box::new(entry_errors))])),
+        );
+
+        let problems = FieldProblemRep::from_validation_errors(&errors);
+        let fields: Vec<_> = problems
+            .iter()
+            .map(|problem| (problem.field.as_str(), problem.code.as_str()))
+            .collect();
+
+        assert_eq!(
+            vec![
+                ("", "unbalanced"),
+                ("entries[1].amount.currency", "length"),
+                ("payee", "length"),
+            ],
+            fields
+        );
+    }
+
+    #[test]
+    fn error_codes_match_serialization() {
+        for code in [
+            ErrorCode::BadRequest,
+            ErrorCode::Forbidden,
+            ErrorCode::PeriodLocked,
+            ErrorCode::UnknownCurrency,
+            ErrorCode::ValidationFailed,
+        ] {
+            assert_eq!(
+                serde_json::json!(code.as_str()),
+                serde_json::to_value(code).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn error_is_a_problem_document() {
+        let response = ApiError::NotFound("Transaction not found.".to_owned()).into_response();
+
+        assert_eq!(StatusCode::NOT_FOUND, response.status());
+        assert_eq!(
+            PROBLEM_CONTENT_TYPE,
+            response.headers()[header::CONTENT_TYPE]
+        );
+
+        let problem = ProblemRep::new(ErrorCode::NotFound, "Transaction not found.".to_owned());
+        assert_eq!(
+            serde_json::json!({
+                "type": "/problems/not_found",
+                "title": "The resource does not exist.",
+                "status": 404,
+                "detail": "Transaction not found.",
+                "code": "not_found",
+            }),
+            serde_json::to_value(problem).unwrap()
+        );
+    }
 }
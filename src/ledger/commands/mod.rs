@@ -1,54 +1,409 @@
+use std::sync::Arc;
+
 use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
 use uuid::Uuid;
+use validator::ValidationErrors;
 
-use super::domain::transactions::{NewTransaction, Transaction};
+use super::domain::{
+    attachments::Attachment,
+    batch::BatchOperation,
+    closing::{ClosingError, PeriodClosing},
+    history::TransactionSnapshot,
+    transactions::{NewTransaction, Transaction},
+};
 
 pub mod postgres;
+pub mod sqlite;
+
+pub type DynTransactionCommands = Arc<dyn TransactionCommands + Send + Sync>;
 
 #[async_trait]
 pub trait TransactionCommands {
-    /// Delete a transaction.
+    /// Apply a batch of changes atomically.
+    ///
+    /// The operations are applied in order within a single database
+    /// transaction. If any of them fails, none of the changes are kept.
     ///
     /// # Arguments
     ///
-    /// * `user_id` - The ID of the transaction's owner.
-    /// * `transaction_id` - The ID of the transaction to delete.
+    /// * `book_id` - The ID of the book containing the transactions.
+    /// * `actor` - The ID of the user making the change.
+    /// * `operations` - The validated operations to apply.
     ///
     /// # Returns
     ///
-    /// A [`Result`][anyhow::Result] containing either an empty success value,
-    /// or an error that occurred. Attempting to delete a transaction that does
-    /// not exist is not an error.
-    async fn delete_transaction(&self, user_id: &str, transaction_id: Uuid) -> anyhow::Result<()>;
+    /// The result of each operation, in the same order as the operations.
+    async fn apply_batch(
+        &self,
+        book_id: Uuid,
+        actor: &str,
+        operations: Vec<BatchOperation>,
+    ) -> Result<Vec<BatchOperationResult>, ApplyBatchError>;
+
+    /// Close a period's income and expenses into an equity account.
+    ///
+    /// The period's previous closing is read, its closing transaction replaced,
+    /// and the new closing recorded within a single database transaction. The
+    /// period's closing stays locked until then, so concurrent closings of the
+    /// same period are applied one after the other instead of each creating a
+    /// closing transaction.
+    ///
+    /// # Arguments
+    ///
+    /// * `book_id` - The ID of the book to close the period in.
+    /// * `actor` - The ID of the user closing the period.
+    /// * `period_start` - The first day of the period.
+    /// * `period_end` - The last day of the period.
+    /// * `equity_account` - The account that balances are closed into.
+    /// * `preview` - If set, the closing is computed but nothing is persisted.
+    async fn close_period(
+        &self,
+        book_id: Uuid,
+        actor: &str,
+        period_start: NaiveDate,
+        period_end: NaiveDate,
+        equity_account: &str,
+        preview: bool,
+    ) -> Result<ClosingOutcome, ClosePeriodError>;
+
+    /// Move a transaction to the trash.
+    ///
+    /// Trashed transactions are excluded from balances and listings until
+    /// they are restored or purged. Transactions in a locked period may not be
+    /// deleted.
+    ///
+    /// # Arguments
+    ///
+    /// * `book_id` - The ID of the book containing the transaction.
+    /// * `actor` - The ID of the user making the change.
+    /// * `transaction_id` - The ID of the transaction to delete.
+    async fn delete_transaction(
+        &self,
+        book_id: Uuid,
+        actor: &str,
+        transaction_id: Uuid,
+    ) -> Result<(), DeleteTransactionError>;
 
     /// Persist a new transaction.
     ///
     /// # Arguments
+    /// * `actor` - The ID of the user making the change.
     /// * `transaction` - The transaction to persist.
     ///
     /// # Returns
     ///
     /// A result containing either an error or the information about the
     /// transaction that was persisted.
-    async fn persist_transaction(&self, transaction: NewTransaction)
-        -> anyhow::Result<Transaction>;
+    async fn persist_transaction(
+        &self,
+        actor: &str,
+        transaction: NewTransaction,
+    ) -> Result<Transaction, PersistTransactionError>;
+
+    /// Permanently remove every trashed transaction that was deleted before a
+    /// cutoff.
+    ///
+    /// # Arguments
+    ///
+    /// * `deleted_before` - Transactions trashed before this time are purged.
+    ///
+    /// # Returns
+    ///
+    /// The number of transactions that were purged, and the attachments that
+    /// were removed along with them.
+    async fn purge_deleted_transactions(
+        &self,
+        deleted_before: DateTime<Utc>,
+    ) -> anyhow::Result<PurgedTransactions>;
+
+    /// Permanently remove a transaction from the trash.
+    ///
+    /// # Arguments
+    ///
+    /// * `book_id` - The ID of the book containing the transaction.
+    /// * `transaction_id` - The ID of the trashed transaction.
+    ///
+    /// # Returns
+    ///
+    /// The purged transaction and its attachments, which were removed along
+    /// with it. The count is zero if no matching transaction was found in the
+    /// trash.
+    async fn purge_transaction(
+        &self,
+        book_id: Uuid,
+        transaction_id: Uuid,
+    ) -> anyhow::Result<PurgedTransactions>;
+
+    /// Move a transaction out of the trash.
+    ///
+    /// # Arguments
+    ///
+    /// * `book_id` - The ID of the book containing the transaction.
+    /// * `actor` - The ID of the user making the change.
+    /// * `transaction_id` - The ID of the trashed transaction.
+    ///
+    /// # Returns
+    ///
+    /// The restored transaction.
+    async fn restore_deleted_transaction(
+        &self,
+        book_id: Uuid,
+        actor: &str,
+        transaction_id: Uuid,
+    ) -> Result<Transaction, RestoreTransactionError>;
+
+    /// Restore a transaction to the state recorded in one of its versions.
+    ///
+    /// If the transaction has since been trashed, it is moved out of the
+    /// trash. If it has been purged, it is recreated with its original ID. Restoring is recorded as a new version of the transaction.
+    ///
+    /// # Arguments
+    ///
+    /// * `book_id` - The ID of the book containing the transaction.
+    /// * `actor` - The ID of the user making the change.
+    /// * `transaction_id` - The ID of the transaction to restore.
+    /// * `version` - The version number to restore the transaction to.
+    async fn restore_transaction_version(
+        &self,
+        book_id: Uuid,
+        actor: &str,
+        transaction_id: Uuid,
+        version: i32,
+    ) -> Result<Transaction, RestoreVersionError>;
 
     /// Update an existing transaction.
     ///
     /// # Arguments
     ///
+    /// * `actor` - The ID of the user making the change.
     /// * `transaction_id` - The ID of the transaction to update.
-    /// * `update` - The updated transaction fields.
+    /// * `update` - The updated transaction fields. The transaction must
+    ///   already be in the book that the update belongs to.
+    /// * `expected_revision` - If provided, the update is only applied if the
+    ///   transaction is still at this [revision][Transaction::revision].
     async fn update_transaction(
         &self,
+        actor: &str,
         transaction_id: Uuid,
         update: NewTransaction,
+        expected_revision: Option<i64>,
     ) -> Result<Transaction, UpdateTransactionError>;
 }
 
+/// The result of closing a period.
+pub struct ClosingOutcome {
+    pub closing: PeriodClosing,
+    /// The contents of the closing transaction, if there was anything to
+    /// close.
+    pub transaction: Option<TransactionSnapshot>,
+}
+
+/// The transactions removed from the trash by a purge.
+pub struct PurgedTransactions {
+    pub count: u64,
+    /// The attachments of the purged transactions, so their content can be
+    /// cleaned up.
+    pub attachments: Vec<Attachment>,
+}
+
+/// The outcome of a single operation in a batch.
+pub enum BatchOperationResult {
+    Created(Transaction),
+    Updated(Transaction),
+    Deleted(Uuid),
+}
+
+#[derive(Debug)]
+pub enum ApplyBatchError {
+    /// The operation at `index` failed, so the whole batch was rolled back.
+    OperationFailed {
+        index: usize,
+        error: BatchOperationError,
+    },
+    DatabaseError(anyhow::Error),
+    Unknown(anyhow::Error),
+}
+
+impl ApplyBatchError {
+    pub(crate) fn from_delete(index: usize, error: DeleteTransactionError) -> Self {
+        match error {
+            DeleteTransactionError::TransactionNotFound => Self::OperationFailed {
+                index,
+                error: BatchOperationError::TransactionNotFound,
+            },
+            DeleteTransactionError::PeriodLocked(lock_date) => Self::OperationFailed {
+                index,
+                error: BatchOperationError::PeriodLocked(lock_date),
+            },
+            DeleteTransactionError::DatabaseError(error) => Self::DatabaseError(error),
+            DeleteTransactionError::Unknown(error) => Self::Unknown(error),
+        }
+    }
+
+    pub(crate) fn from_persist(index: usize, error: PersistTransactionError) -> Self {
+        match error {
+            PersistTransactionError::PeriodLocked(lock_date) => Self::OperationFailed {
+                index,
+                error: BatchOperationError::PeriodLocked(lock_date),
+            },
+            PersistTransactionError::UnknownCurrency(code) => Self::OperationFailed {
+                index,
+                error: BatchOperationError::UnknownCurrency(code),
+            },
+            PersistTransactionError::DatabaseError(error) => Self::DatabaseError(error),
+            PersistTransactionError::Unknown(error) => Self::Unknown(error),
+        }
+    }
+
+    pub(crate) fn from_update(index: usize, error: UpdateTransactionError) -> Self {
+        match error {
+            UpdateTransactionError::TransactionNotFound => Self::OperationFailed {
+                index,
+                error: BatchOperationError::TransactionNotFound,
+            },
+            // Batch updates never provide an expected revision.
+            UpdateTransactionError::Conflict(current) => Self::Unknown(anyhow::anyhow!(
+                "unexpected conflict updating transaction {}",
+                current.id
+            )),
+            UpdateTransactionError::PeriodLocked(lock_date) => Self::OperationFailed {
+                index,
+                error: BatchOperationError::PeriodLocked(lock_date),
+            },
+            UpdateTransactionError::UnknownCurrency(code) => Self::OperationFailed {
+                index,
+                error: BatchOperationError::UnknownCurrency(code),
+            },
+            UpdateTransactionError::DatabaseError(error) => Self::DatabaseError(error),
+            UpdateTransactionError::Unknown(error) => Self::Unknown(error),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ClosePeriodError {
+    /// The period ends before it starts.
+    InvalidPeriod,
+
+    /// The closing transaction could not be generated.
+    Closing(ClosingError),
+
+    /// The closing transaction falls within the locked period ending on the
+    /// contained date.
+    PeriodLocked(NaiveDate),
+
+    Unknown(anyhow::Error),
+}
+
+impl From<DeleteTransactionError> for ClosePeriodError {
+    fn from(error: DeleteTransactionError) -> Self {
+        match error {
+            DeleteTransactionError::PeriodLocked(lock_date) => Self::PeriodLocked(lock_date),
+            other => Self::Unknown(anyhow::anyhow!(
+                "failed to delete closing transaction: {:?}",
+                other
+            )),
+        }
+    }
+}
+
+impl From<PersistTransactionError> for ClosePeriodError {
+    fn from(error: PersistTransactionError) -> Self {
+        match error {
+            PersistTransactionError::PeriodLocked(lock_date) => Self::PeriodLocked(lock_date),
+            other => Self::Unknown(anyhow::anyhow!(
+                "failed to persist closing transaction: {:?}",
+                other
+            )),
+        }
+    }
+}
+
+impl From<UpdateTransactionError> for ClosePeriodError {
+    fn from(error: UpdateTransactionError) -> Self {
+        match error {
+            UpdateTransactionError::PeriodLocked(lock_date) => Self::PeriodLocked(lock_date),
+            other => Self::Unknown(anyhow::anyhow!(
+                "failed to update closing transaction: {:?}",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum BatchOperationError {
+    /// There is no active transaction with the ID given in the operation.
+    TransactionNotFound,
+    /// The operation touches the locked period ending on the contained date.
+    PeriodLocked(NaiveDate),
+    /// The operation references a currency with the contained code that
+    /// doesn't exist.
+    UnknownCurrency(String),
+}
+
+#[derive(Debug)]
+pub enum DeleteTransactionError {
+    /// There is no active transaction with the provided ID.
+    TransactionNotFound,
+    /// The transaction falls within the locked period ending on the contained
+    /// date.
+    PeriodLocked(NaiveDate),
+    DatabaseError(anyhow::Error),
+    Unknown(anyhow::Error),
+}
+
+#[derive(Debug)]
+pub enum PersistTransactionError {
+    /// The transaction falls within the locked period ending on the contained
+    /// date.
+    PeriodLocked(NaiveDate),
+    /// An entry references a currency with the contained code that doesn't
+    /// exist.
+    UnknownCurrency(String),
+    DatabaseError(anyhow::Error),
+    Unknown(anyhow::Error),
+}
+
 #[derive(Debug)]
 pub enum UpdateTransactionError {
     TransactionNotFound,
+    /// The transaction was modified after the expected revision. The value is
+    /// the current version of the transaction.
+    Conflict(Box<Transaction>),
+    /// Either the current or the updated date of the transaction falls within
+    /// the locked period ending on the contained date.
+    PeriodLocked(NaiveDate),
+    /// An entry references a currency with the contained code that doesn't
+    /// exist.
+    UnknownCurrency(String),
+    DatabaseError(anyhow::Error),
+    Unknown(anyhow::Error),
+}
+
+#[derive(Debug)]
+pub enum RestoreTransactionError {
+    /// There is no trashed transaction with the provided ID.
+    TransactionNotFound,
+    /// The transaction falls within the locked period ending on the contained
+    /// date.
+    PeriodLocked(NaiveDate),
+    DatabaseError(anyhow::Error),
+    Unknown(anyhow::Error),
+}
+
+#[derive(Debug)]
+pub enum RestoreVersionError {
+    VersionNotFound,
+    /// The recorded snapshot no longer passes validation.
+    Invalid(ValidationErrors),
+    /// Either the current or the restored date of the transaction falls
+    /// within the locked period ending on the contained date.
+    PeriodLocked(NaiveDate),
+    /// The version references a currency with the contained code that no
+    /// longer exists.
+    UnknownCurrency(String),
     DatabaseError(anyhow::Error),
     Unknown(anyhow::Error),
 }
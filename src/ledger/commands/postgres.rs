@@ -1,138 +1,864 @@
-use crate::ledger::{
-    domain,
-    models::{self},
+use crate::{
+    database::PostgresConnection,
+    ledger::{
+        domain::{
+            self,
+            batch::BatchOperation,
+            changes::ChangeNotification,
+            closing::{self, PeriodClosing, CLOSED_ACCOUNT_ROOTS},
+            currency::UnknownCurrency,
+            history::{ChangeAction, TransactionSnapshot},
+            locking::{LedgerLock, PeriodLocked},
+        },
+        models::{self},
+        notifications::CHANGES_CHANNEL,
+        queries::postgres::fetch_period_balances,
+    },
+    models::ledger::TransactionAttachment,
+    repos::webhooks,
 };
 
 use anyhow::Context;
 use async_trait::async_trait;
-use sqlx::{PgPool, Postgres, QueryBuilder};
-use tracing::{debug, info};
+use chrono::{DateTime, NaiveDate, Utc};
+use sqlx::{types::Json, PgConnection, Postgres, QueryBuilder};
+use tracing::{debug, info, instrument};
 use uuid::Uuid;
 
-use super::{TransactionCommands, UpdateTransactionError};
+use super::{
+    ApplyBatchError, BatchOperationResult, ClosePeriodError, ClosingOutcome,
+    DeleteTransactionError, PersistTransactionError, PurgedTransactions, RestoreTransactionError,
+    RestoreVersionError, TransactionCommands, UpdateTransactionError,
+};
+
+/// Commands that change the ledger stored in the Postgres database backing
+/// the application.
+pub struct PostgresCommands(pub PostgresConnection);
+
+/// Insert the entries of a transaction, creating any accounts referenced by
+/// the entries that do not exist yet.
+///
+/// Currencies are never created, so every currency referenced by the entries
+/// must already exist.
+async fn insert_entries<E>(
+    conn: &mut PgConnection,
+    book_id: Uuid,
+    entries: Vec<models::NewTransactionEntry>,
+) -> Result<(), E>
+where
+    E: From<sqlx::Error> + From<UnknownCurrency>,
+{
+    let mut currencies: Vec<String> = entries.iter().map(|entry| entry.currency.clone()).collect();
+    currencies.sort();
+    currencies.dedup();
+
+    let known_currencies = sqlx::query_scalar!(
+        r#"SELECT code FROM currency WHERE code = ANY($1)"#,
+        &currencies
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    if let Some(unknown) = currencies
+        .into_iter()
+        .find(|code| !known_currencies.contains(code))
+    {
+        return Err(UnknownCurrency(unknown).into());
+    }
+
+    let mut entry_query_builder: QueryBuilder<'_, Postgres> = QueryBuilder::new(
+        r#"INSERT INTO transaction_entry (transaction_id, "order", account_id, currency, amount)"#,
+    );
+
+    entry_query_builder.push_values(entries, |mut b, entry| {
+        b.push_bind(entry.transaction_id)
+            .push_bind(entry.order)
+            .push("get_or_create_account(")
+            .push_bind_unseparated(book_id)
+            .push_bind(entry.account.name)
+            .push_unseparated(")")
+            .push_bind(entry.currency)
+            .push_bind(entry.amount);
+    });
+
+    entry_query_builder.build().execute(conn).await?;
+
+    Ok(())
+}
+
+/// Fetch the entries of a transaction along with their accounts and
+/// currencies.
+async fn fetch_entries(
+    conn: &mut PgConnection,
+    transaction_id: Uuid,
+) -> sqlx::Result<Vec<models::FullTransactionEntry>> {
+    sqlx::query_as::<_, models::FullTransactionEntry>(
+        r#"
+        SELECT e.*, a.*, c.*
+        FROM transaction_entry e
+        LEFT JOIN account a ON e.account_id = a.id
+        LEFT JOIN currency c ON e.currency = c.code
+        WHERE e.transaction_id = $1
+        ORDER BY e."order"
+        "#,
+    )
+    .bind(transaction_id)
+    .fetch_all(conn)
+    .await
+}
+
+/// Fetch a book's ledger lock.
+///
+/// The lock is held until the end of the database transaction so that the lock
+/// date cannot be moved while a change is being checked against it.
+async fn fetch_ledger_lock(conn: &mut PgConnection, book_id: Uuid) -> sqlx::Result<LedgerLock> {
+    let lock_date = sqlx::query_scalar!(
+        r#"
+        SELECT lock_date
+        FROM ledger_lock
+        WHERE book_id = $1
+        FOR SHARE
+        "#,
+        book_id,
+    )
+    .fetch_optional(conn)
+    .await?
+    .flatten();
+
+    Ok(LedgerLock { lock_date })
+}
+
+/// Record a new version of a transaction, queue the webhook events for the
+/// change, and notify the book's connected clients once it is committed.
+///
+/// This should be called in the same database transaction as the change being
+/// recorded so that a change can never be persisted without its history.
+async fn record_version(
+    conn: &mut PgConnection,
+    book_id: Uuid,
+    transaction_id: Uuid,
+    action: ChangeAction,
+    actor: &str,
+    snapshot: &TransactionSnapshot,
+) -> sqlx::Result<i32> {
+    let version = sqlx::query_scalar!(
+        r#"
+        INSERT INTO transaction_version (transaction_id, book_id, version, action, actor, snapshot)
+        SELECT $1, $2, COALESCE(MAX(version), 0) + 1, $3, $4, $5
+        FROM transaction_version
+        WHERE transaction_id = $1
+        RETURNING version
+        "#,
+        transaction_id,
+        book_id,
+        action.as_str(),
+        actor,
+        Json(snapshot) as _,
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    debug!(%transaction_id, version, %action, "Recorded transaction version.");
+
+    let event_data = serde_json::json!({
+        "transaction_id": transaction_id,
+        "version": version,
+        "transaction": snapshot,
+    });
+    webhooks::enqueue_event(conn, book_id, action.into(), &event_data).await?;
+    webhooks::enqueue_threshold_events(conn, book_id).await?;
 
-pub struct PostgresCommands<'a>(pub &'a PgPool);
+    let notification = ChangeNotification {
+        book_id,
+        transaction_id,
+        action,
+        version,
+    };
+    sqlx::query!(
+        "SELECT pg_notify($1, $2::jsonb::text)",
+        CHANGES_CHANNEL,
+        Json(&notification) as _,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(version)
+}
+
+/// Move a transaction to the trash using an existing database transaction.
+async fn delete_transaction_in(
+    conn: &mut PgConnection,
+    book_id: Uuid,
+    actor: &str,
+    transaction_id: Uuid,
+) -> Result<(), DeleteTransactionError> {
+    let deleted_transaction = sqlx::query_as!(
+        models::Transaction,
+        r#"
+        UPDATE "transaction"
+        SET deleted_at = now(), revision = revision + 1
+        WHERE book_id = $1 AND id = $2 AND deleted_at IS NULL
+        RETURNING id, book_id, date, payee, notes, created_at, updated_at, deleted_at, revision
+        "#,
+        book_id,
+        transaction_id,
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(DeleteTransactionError::TransactionNotFound)?;
+
+    // Returning early rolls back the database transaction, so the transaction
+    // stays active if its period is locked.
+    fetch_ledger_lock(conn, book_id)
+        .await?
+        .ensure_unlocked([deleted_transaction.date])?;
+
+    let entries = fetch_entries(conn, transaction_id).await?;
+    let snapshot = TransactionSnapshot::from(&deleted_transaction.try_into_domain(&entries)?);
+
+    record_version(
+        conn,
+        book_id,
+        transaction_id,
+        ChangeAction::Deleted,
+        actor,
+        &snapshot,
+    )
+    .await?;
+
+    info!(%book_id, %transaction_id, "Moved transaction to trash.");
+
+    Ok(())
+}
+
+/// Persist a new transaction using an existing database transaction.
+async fn persist_transaction_in(
+    conn: &mut PgConnection,
+    actor: &str,
+    transaction: domain::transactions::NewTransaction,
+) -> Result<domain::transactions::Transaction, PersistTransactionError> {
+    let transaction_model: models::NewTransaction = (&transaction).into();
+
+    fetch_ledger_lock(conn, transaction_model.book_id)
+        .await?
+        .ensure_unlocked([transaction.date()])?;
+
+    let persisted_transaction = sqlx::query_as!(
+        models::Transaction,
+        r#"
+        INSERT INTO transaction (book_id, "date", payee, notes)
+        VALUES ($1, $2, $3, $4)
+        RETURNING id, book_id, date, payee, notes, created_at, updated_at, deleted_at, revision
+        "#,
+        transaction_model.book_id,
+        transaction_model.date,
+        transaction_model.payee,
+        transaction_model.notes,
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let entry_models = models::NewTransactionEntry::from_domain_entries(
+        persisted_transaction.id,
+        transaction.book_id(),
+        transaction.entries(),
+    )
+    .context("Failed to map transaction entries to model.")?;
+
+    insert_entries::<PersistTransactionError>(conn, transaction_model.book_id, entry_models)
+        .await?;
+    record_version(
+        conn,
+        transaction_model.book_id,
+        persisted_transaction.id,
+        ChangeAction::Created,
+        actor,
+        &TransactionSnapshot::from(&transaction),
+    )
+    .await?;
+
+    info!(id = %persisted_transaction.id, "Persisted new transaction.");
+
+    let entries = fetch_entries(conn, persisted_transaction.id).await?;
+
+    Ok(persisted_transaction.try_into_domain(&entries)?)
+}
+
+/// Update an existing transaction using an existing database transaction.
+async fn update_transaction_in(
+    conn: &mut PgConnection,
+    actor: &str,
+    transaction_id: Uuid,
+    update: domain::transactions::NewTransaction,
+    expected_revision: Option<i64>,
+) -> Result<domain::transactions::Transaction, UpdateTransactionError> {
+    let transaction_changeset = models::NewTransaction::from(&update);
+    let transaction_entries = models::NewTransactionEntry::from_domain_entries(
+        transaction_id,
+        transaction_changeset.book_id,
+        update.entries(),
+    )
+    .context("Failed to convert domain entries to model.")?;
+
+    let current_transaction = sqlx::query_as!(
+        models::Transaction,
+        r#"
+        SELECT id, book_id, date, payee, notes, created_at, updated_at, deleted_at, revision
+        FROM transaction
+        WHERE id = $1 AND book_id = $2 AND deleted_at IS NULL
+        FOR UPDATE
+        "#,
+        transaction_id,
+        transaction_changeset.book_id,
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(UpdateTransactionError::TransactionNotFound)?;
+
+    if let Some(expected_revision) = expected_revision {
+        let entries = fetch_entries(conn, transaction_id).await?;
+        let current = current_transaction.try_into_domain(&entries)?;
+
+        if current.revision != expected_revision {
+            debug!(%transaction_id, expected_revision, current_revision = current.revision, "Rejected update of stale transaction.");
+
+            return Err(UpdateTransactionError::Conflict(Box::new(current)));
+        }
+    }
+
+    // Moving a transaction either into or out of a locked period would change
+    // the locked period's balances.
+    fetch_ledger_lock(conn, transaction_changeset.book_id)
+        .await?
+        .ensure_unlocked([current_transaction.date, transaction_changeset.date])?;
+
+    let old_entry_delete = sqlx::query!(
+        r#"
+        DELETE FROM transaction_entry
+        WHERE transaction_id = $1
+        "#,
+        transaction_id
+    )
+    .execute(&mut *conn)
+    .await?;
+    debug!(%transaction_id, rows = old_entry_delete.rows_affected(), "Cleared out old transaction entries.");
+
+    insert_entries::<UpdateTransactionError>(
+        conn,
+        transaction_changeset.book_id,
+        transaction_entries,
+    )
+    .await?;
+
+    // Replacing the entries touches the transaction's modification time, so
+    // the transaction itself is updated last to return its final state.
+    let updated_transaction = sqlx::query_as!(
+        models::Transaction,
+        r#"
+        UPDATE transaction
+        SET
+            date = $3,
+            payee = $4,
+            notes = $5,
+            revision = revision + 1
+        WHERE id = $1 AND book_id = $2 AND deleted_at IS NULL
+        RETURNING id, book_id, date, payee, notes, created_at, updated_at, deleted_at, revision
+        "#,
+        transaction_id,
+        transaction_changeset.book_id,
+        transaction_changeset.date,
+        transaction_changeset.payee,
+        transaction_changeset.notes
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    record_version(
+        conn,
+        transaction_changeset.book_id,
+        transaction_id,
+        ChangeAction::Updated,
+        actor,
+        &TransactionSnapshot::from(&update),
+    )
+    .await?;
+
+    let updated_entries = fetch_entries(conn, transaction_id).await?;
+
+    info!(%transaction_id, "Updated transaction.");
+
+    Ok(updated_transaction
+        .try_into_domain(&updated_entries)
+        .context("Failed to convert transaction model into domain object.")?)
+}
 
 #[async_trait]
-impl<'a> TransactionCommands for PostgresCommands<'a> {
-    async fn delete_transaction(&self, owner_id: &str, transaction_id: Uuid) -> anyhow::Result<()> {
-        Ok(sqlx::query!(
+impl TransactionCommands for PostgresCommands {
+    #[instrument(skip_all)]
+    async fn apply_batch(
+        &self,
+        book_id: Uuid,
+        actor: &str,
+        operations: Vec<BatchOperation>,
+    ) -> Result<Vec<BatchOperationResult>, ApplyBatchError> {
+        let mut tx = self.0.begin().await?;
+        let mut results = Vec::with_capacity(operations.len());
+
+        // Returning early on any failure drops the database transaction, which
+        // rolls back the operations that were already applied.
+        for (index, operation) in operations.into_iter().enumerate() {
+            let result = match operation {
+                BatchOperation::Create(transaction) => {
+                    persist_transaction_in(&mut tx, actor, transaction)
+                        .await
+                        .map(BatchOperationResult::Created)
+                        .map_err(|error| ApplyBatchError::from_persist(index, error))?
+                }
+                BatchOperation::Update {
+                    transaction_id,
+                    transaction,
+                } => update_transaction_in(&mut tx, actor, transaction_id, transaction, None)
+                    .await
+                    .map(BatchOperationResult::Updated)
+                    .map_err(|error| ApplyBatchError::from_update(index, error))?,
+                BatchOperation::Delete { transaction_id } => {
+                    delete_transaction_in(&mut tx, book_id, actor, transaction_id)
+                        .await
+                        .map(|()| BatchOperationResult::Deleted(transaction_id))
+                        .map_err(|error| ApplyBatchError::from_delete(index, error))?
+                }
+            };
+
+            results.push(result);
+        }
+
+        tx.commit().await?;
+
+        info!(%book_id, operations = results.len(), "Applied transaction batch.");
+
+        Ok(results)
+    }
+
+    #[instrument(skip_all)]
+    async fn close_period(
+        &self,
+        book_id: Uuid,
+        actor: &str,
+        period_start: NaiveDate,
+        period_end: NaiveDate,
+        equity_account: &str,
+        preview: bool,
+    ) -> Result<ClosingOutcome, ClosePeriodError> {
+        let mut tx = self.0.begin().await?;
+
+        // A period that was never closed has no row to lock, so a placeholder
+        // is inserted first. Concurrent closings of the period then wait for
+        // each other on that row, and each sees the transaction generated by
+        // the one before it.
+        sqlx::query!(
             r#"
-            DELETE FROM "transaction"
-            WHERE user_id = $1 AND id = $2
+            INSERT INTO period_closing (book_id, period_start, period_end, equity_account)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (book_id, period_end) DO NOTHING
             "#,
-            owner_id,
-            transaction_id,
+            book_id,
+            period_start,
+            period_end,
+            equity_account,
         )
-        .execute(self.0)
-        .await
-        .map(|result| {
-            info!(user_id = %owner_id, %transaction_id, rows = result.rows_affected(), "Deleted transaction.");
-        })?)
+        .execute(&mut tx)
+        .await?;
+
+        let previous_transaction_id = sqlx::query_scalar!(
+            r#"
+            SELECT transaction_id
+            FROM period_closing
+            WHERE book_id = $1 AND period_end = $2
+            FOR UPDATE
+            "#,
+            book_id,
+            period_end,
+        )
+        .fetch_one(&mut tx)
+        .await?;
+
+        // The previous closing transaction is left out of the balances since
+        // it would otherwise cancel out the balances being closed.
+        let balances = fetch_period_balances(
+            &mut tx,
+            book_id,
+            CLOSED_ACCOUNT_ROOTS,
+            period_start,
+            period_end,
+            previous_transaction_id,
+        )
+        .await?;
+
+        let transaction =
+            closing::closing_transaction(book_id, period_end, equity_account, &balances)
+                .map_err(ClosePeriodError::Closing)?;
+        let snapshot = transaction.as_ref().map(TransactionSnapshot::from);
+
+        let mut closing = PeriodClosing {
+            period_start,
+            period_end,
+            equity_account: equity_account.to_owned(),
+            transaction_id: previous_transaction_id,
+        };
+
+        // Dropping the database transaction rolls back the placeholder.
+        if preview {
+            return Ok(ClosingOutcome {
+                closing,
+                transaction: snapshot,
+            });
+        }
+
+        closing.transaction_id = match (previous_transaction_id, transaction) {
+            (Some(transaction_id), Some(transaction)) => {
+                match update_transaction_in(
+                    &mut tx,
+                    actor,
+                    transaction_id,
+                    transaction.clone(),
+                    None,
+                )
+                .await
+                {
+                    Ok(updated) => Some(updated.id),
+                    // The previous closing transaction was deleted, so a new
+                    // one has to be created instead.
+                    Err(UpdateTransactionError::TransactionNotFound) => Some(
+                        persist_transaction_in(&mut tx, actor, transaction)
+                            .await?
+                            .id,
+                    ),
+                    Err(error) => return Err(error.into()),
+                }
+            }
+            (None, Some(transaction)) => Some(
+                persist_transaction_in(&mut tx, actor, transaction)
+                    .await?
+                    .id,
+            ),
+            (Some(transaction_id), None) => {
+                match delete_transaction_in(&mut tx, book_id, actor, transaction_id).await {
+                    Ok(()) | Err(DeleteTransactionError::TransactionNotFound) => None,
+                    Err(error) => return Err(error.into()),
+                }
+            }
+            (None, None) => None,
+        };
+
+        sqlx::query!(
+            r#"
+            UPDATE period_closing
+            SET
+                period_start = $3,
+                equity_account = $4,
+                transaction_id = $5,
+                updated_at = now()
+            WHERE book_id = $1 AND period_end = $2
+            "#,
+            book_id,
+            period_end,
+            closing.period_start,
+            closing.equity_account,
+            closing.transaction_id,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        info!(%book_id, %period_end, transaction_id = ?closing.transaction_id, "Closed period.");
+
+        Ok(ClosingOutcome {
+            closing,
+            transaction: snapshot,
+        })
+    }
+
+    #[instrument(skip_all)]
+    async fn delete_transaction(
+        &self,
+        book_id: Uuid,
+        actor: &str,
+        transaction_id: Uuid,
+    ) -> Result<(), DeleteTransactionError> {
+        let mut tx = self.0.begin().await?;
+
+        delete_transaction_in(&mut tx, book_id, actor, transaction_id).await?;
+
+        tx.commit().await?;
+
+        Ok(())
     }
 
+    #[instrument(skip_all)]
     async fn persist_transaction(
         &self,
+        actor: &str,
         transaction: domain::transactions::NewTransaction,
-    ) -> anyhow::Result<domain::transactions::Transaction> {
-        let transaction_model: models::NewTransaction = (&transaction).into();
+    ) -> Result<domain::transactions::Transaction, PersistTransactionError> {
+        let mut tx = self.0.begin().await?;
+
+        let persisted = persist_transaction_in(&mut tx, actor, transaction).await?;
 
+        tx.commit().await?;
+
+        Ok(persisted)
+    }
+
+    #[instrument(skip_all)]
+    async fn purge_deleted_transactions(
+        &self,
+        deleted_before: DateTime<Utc>,
+    ) -> anyhow::Result<PurgedTransactions> {
         let mut tx = self.0.begin().await?;
 
-        let persisted_transaction = sqlx::query_as!(
-            models::Transaction,
+        // Locking the expired transactions keeps them from being restored
+        // while they are purged. One that was restored before the lock was
+        // taken no longer matches, so its attachments are left alone.
+        let transaction_ids = sqlx::query_scalar!(
             r#"
-            INSERT INTO transaction (user_id, "date", payee, notes)
-            VALUES ($1, $2, $3, $4)
-            RETURNING id, user_id, date, payee, notes, created_at, updated_at
+            SELECT id
+            FROM "transaction"
+            WHERE deleted_at < $1
+            FOR UPDATE
             "#,
-            transaction_model.user_id,
-            transaction_model.date,
-            transaction_model.payee,
-            transaction_model.notes,
+            deleted_before,
         )
-        .fetch_one(&mut tx)
+        .fetch_all(&mut tx)
+        .await?;
+
+        let attachments = sqlx::query_as!(
+            TransactionAttachment,
+            r#"
+            DELETE FROM transaction_attachment
+            WHERE transaction_id = ANY($1)
+            RETURNING id, transaction_id, book_id, file_name, content_type, size, created_at
+            "#,
+            &transaction_ids,
+        )
+        .fetch_all(&mut tx)
+        .await?
+        .drain(..)
+        .map(domain::attachments::Attachment::try_from)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM "transaction"
+            WHERE id = ANY($1)
+            "#,
+            &transaction_ids,
+        )
+        .execute(&mut tx)
         .await?;
 
-        let entry_models = models::NewTransactionEntry::from_domain_entries(
-            persisted_transaction.id,
-            transaction.user_id().to_owned(),
-            transaction.entries(),
-        )
-        .context("Failed to map transaction entries to model.")?;
-
-        let mut entry_query_builder: QueryBuilder<'_, Postgres> = QueryBuilder::new(
-            r#"INSERT INTO transaction_entry (transaction_id, "order", account_id, currency, amount)"#,
-        );
-
-        entry_query_builder.push_values(entry_models, |mut b, entry| {
-            b.push_bind(entry.transaction_id)
-                .push_bind(entry.order)
-                .push("get_or_create_account(")
-                .push_bind_unseparated(&transaction_model.user_id)
-                .push_bind(entry.account.name)
-                .push_unseparated(")")
-                .push_bind(entry.currency)
-                .push_bind(entry.amount);
-        });
-
-        entry_query_builder.build().execute(&mut tx).await?;
         tx.commit().await?;
 
-        info!(id = %persisted_transaction.id, "Persisted new transaction.");
+        info!(%deleted_before, count = result.rows_affected(), attachments = attachments.len(), "Purged trashed transactions.");
+
+        Ok(PurgedTransactions {
+            count: result.rows_affected(),
+            attachments,
+        })
+    }
+
+    #[instrument(skip_all)]
+    async fn purge_transaction(
+        &self,
+        book_id: Uuid,
+        transaction_id: Uuid,
+    ) -> anyhow::Result<PurgedTransactions> {
+        let mut tx = self.0.begin().await?;
+
+        // Locking the transaction keeps it from being restored, and keeps
+        // attachments from being added to it, while it is purged.
+        let found = sqlx::query_scalar!(
+            r#"
+            SELECT id
+            FROM "transaction"
+            WHERE book_id = $1 AND id = $2 AND deleted_at IS NOT NULL
+            FOR UPDATE
+            "#,
+            book_id,
+            transaction_id,
+        )
+        .fetch_optional(&mut tx)
+        .await?
+        .is_some();
+
+        if !found {
+            info!(%book_id, %transaction_id, found, "Purged transaction from trash.");
 
-        let entries = sqlx::query_as::<_, models::FullTransactionEntry>(
+            return Ok(PurgedTransactions {
+                count: 0,
+                attachments: Vec::new(),
+            });
+        }
+
+        let attachments = sqlx::query_as!(
+            TransactionAttachment,
             r#"
-            SELECT e.*, a.*, c.*
-            FROM transaction_entry e
-            LEFT JOIN account a ON e.account_id = a.id
-            LEFT JOIN currency c ON e.currency = c.code
-            WHERE e.transaction_id = $1
+            DELETE FROM transaction_attachment
+            WHERE transaction_id = $1
+            RETURNING id, transaction_id, book_id, file_name, content_type, size, created_at
             "#,
+            transaction_id,
         )
-        .bind(persisted_transaction.id)
-        .fetch_all(self.0)
+        .fetch_all(&mut tx)
+        .await?
+        .drain(..)
+        .map(domain::attachments::Attachment::try_from)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM "transaction"
+            WHERE id = $1
+            "#,
+            transaction_id,
+        )
+        .execute(&mut tx)
         .await?;
 
-        Ok(persisted_transaction.try_into_domain(&entries)?)
+        tx.commit().await?;
+
+        info!(%book_id, %transaction_id, found, attachments = attachments.len(), "Purged transaction from trash.");
+
+        Ok(PurgedTransactions {
+            count: result.rows_affected(),
+            attachments,
+        })
     }
 
-    async fn update_transaction(
+    #[instrument(skip_all)]
+    async fn restore_deleted_transaction(
         &self,
+        book_id: Uuid,
+        actor: &str,
         transaction_id: Uuid,
-        update: domain::transactions::NewTransaction,
-    ) -> Result<domain::transactions::Transaction, UpdateTransactionError> {
-        let transaction_changeset = models::NewTransaction::from(&update);
-        let transaction_entries = models::NewTransactionEntry::from_domain_entries(
+    ) -> Result<domain::transactions::Transaction, RestoreTransactionError> {
+        let mut tx = self.0.begin().await?;
+
+        let restored_transaction = sqlx::query_as!(
+            models::Transaction,
+            r#"
+            UPDATE "transaction"
+            SET deleted_at = NULL, revision = revision + 1
+            WHERE book_id = $1 AND id = $2 AND deleted_at IS NOT NULL
+            RETURNING id, book_id, date, payee, notes, created_at, updated_at, deleted_at, revision
+            "#,
+            book_id,
             transaction_id,
-            transaction_changeset.user_id.clone(),
-            update.entries(),
         )
-        .context("Failed to convert domain entries to model.")?;
+        .fetch_optional(&mut tx)
+        .await?
+        .ok_or(RestoreTransactionError::TransactionNotFound)?;
+
+        fetch_ledger_lock(&mut tx, book_id)
+            .await?
+            .ensure_unlocked([restored_transaction.date])?;
+
+        let entries = fetch_entries(&mut tx, transaction_id).await?;
+        let restored = restored_transaction.try_into_domain(&entries)?;
+
+        record_version(
+            &mut tx,
+            book_id,
+            transaction_id,
+            ChangeAction::Restored,
+            actor,
+            &TransactionSnapshot::from(&restored),
+        )
+        .await?;
+
+        tx.commit().await?;
 
+        info!(%book_id, %transaction_id, "Restored transaction from trash.");
+
+        Ok(restored)
+    }
+
+    #[instrument(skip_all)]
+    async fn restore_transaction_version(
+        &self,
+        book_id: Uuid,
+        actor: &str,
+        transaction_id: Uuid,
+        version: i32,
+    ) -> Result<domain::transactions::Transaction, RestoreVersionError> {
         let mut tx = self.0.begin().await?;
 
-        let updated_transaction = sqlx::query_as!(
+        let snapshot = sqlx::query_scalar!(
+            r#"
+            SELECT snapshot AS "snapshot: Json<TransactionSnapshot>"
+            FROM transaction_version
+            WHERE book_id = $1 AND transaction_id = $2 AND version = $3
+            "#,
+            book_id,
+            transaction_id,
+            version,
+        )
+        .fetch_optional(&mut tx)
+        .await?
+        .ok_or(RestoreVersionError::VersionNotFound)?
+        .0;
+
+        let restored = snapshot
+            .to_new_transaction(book_id)
+            .map_err(RestoreVersionError::Invalid)?;
+        let transaction_changeset = models::NewTransaction::from(&restored);
+
+        let current_date = sqlx::query_scalar!(
+            r#"
+            SELECT date
+            FROM transaction
+            WHERE id = $1 AND book_id = $2 AND deleted_at IS NULL
+            FOR UPDATE
+            "#,
+            transaction_id,
+            book_id,
+        )
+        .fetch_optional(&mut tx)
+        .await?;
+
+        fetch_ledger_lock(&mut tx, book_id)
+            .await?
+            .ensure_unlocked(current_date.into_iter().chain([restored.date()]))?;
+
+        // The transaction is taken out of the trash if it was deleted after
+        // the version was recorded, or recreated with its original ID if it
+        // has since been purged.
+        let restored_transaction = sqlx::query_as!(
             models::Transaction,
             r#"
-            UPDATE transaction
+            INSERT INTO transaction (id, book_id, "date", payee, notes)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (id) DO UPDATE
             SET
-                date = $3,
-                payee = $4,
-                notes = $5
-            WHERE id = $1 AND user_id = $2
-            RETURNING id, user_id, date, payee, notes, created_at, updated_at
+                date = EXCLUDED.date,
+                payee = EXCLUDED.payee,
+                notes = EXCLUDED.notes,
+                deleted_at = NULL,
+                revision = transaction.revision + 1
+            WHERE transaction.book_id = EXCLUDED.book_id
+            RETURNING id, book_id, date, payee, notes, created_at, updated_at, deleted_at, revision
             "#,
             transaction_id,
-            &transaction_changeset.user_id,
+            transaction_changeset.book_id,
             transaction_changeset.date,
             transaction_changeset.payee,
-            transaction_changeset.notes
+            transaction_changeset.notes,
         )
-        .fetch_one(&mut tx)
-        .await?;
+        .fetch_optional(&mut tx)
+        .await?
+        .ok_or(RestoreVersionError::VersionNotFound)?;
 
-        let old_entry_delete = sqlx::query!(
+        sqlx::query!(
             r#"
             DELETE FROM transaction_entry
             WHERE transaction_id = $1
@@ -141,45 +867,115 @@ impl<'a> TransactionCommands for PostgresCommands<'a> {
         )
         .execute(&mut tx)
         .await?;
-        debug!(%transaction_id, rows = old_entry_delete.rows_affected(), "Cleared out old transaction entries.");
-
-        let mut entry_query_builder: QueryBuilder<'_, Postgres> = QueryBuilder::new(
-            r#"INSERT INTO transaction_entry (transaction_id, "order", account_id, currency, amount)"#,
-        );
-
-        entry_query_builder.push_values(transaction_entries, |mut b, entry| {
-            b.push_bind(entry.transaction_id)
-                .push_bind(entry.order)
-                .push("get_or_create_account(")
-                .push_bind_unseparated(&transaction_changeset.user_id)
-                .push_bind(entry.account.name)
-                .push(")")
-                .push_bind(entry.currency)
-                .push_bind(entry.amount);
-        });
-
-        entry_query_builder.build().execute(&mut tx).await?;
-        tx.commit().await?;
 
-        let updated_entries = sqlx::query_as::<_, models::FullTransactionEntry>(
-            r#"
-            SELECT e.*, a.*, c.*
-            FROM transaction_entry e
-            LEFT JOIN account a ON e.account_id = a.id
-            LEFT JOIN currency c ON e.currency = c.code
-            WHERE e.transaction_id = $1
-            "#,
+        let entry_models = models::NewTransactionEntry::from_domain_entries(
+            transaction_id,
+            book_id,
+            restored.entries(),
+        )
+        .context("Failed to convert domain entries to model.")?;
+
+        insert_entries::<RestoreVersionError>(&mut tx, book_id, entry_models).await?;
+        record_version(
+            &mut tx,
+            book_id,
+            transaction_id,
+            ChangeAction::Restored,
+            actor,
+            &snapshot,
         )
-        .bind(transaction_id)
-        .fetch_all(self.0)
         .await?;
 
-        info!(%transaction_id, "Updated transaction.");
+        tx.commit().await?;
+
+        info!(%transaction_id, version, "Restored transaction version.");
+
+        let mut conn = self.0.acquire().await?;
+        let entries = fetch_entries(&mut conn, transaction_id).await?;
 
-        Ok(updated_transaction
-            .try_into_domain(&updated_entries)
+        Ok(restored_transaction
+            .try_into_domain(&entries)
             .context("Failed to convert transaction model into domain object.")?)
     }
+
+    #[instrument(skip_all)]
+    async fn update_transaction(
+        &self,
+        actor: &str,
+        transaction_id: Uuid,
+        update: domain::transactions::NewTransaction,
+        expected_revision: Option<i64>,
+    ) -> Result<domain::transactions::Transaction, UpdateTransactionError> {
+        let mut tx = self.0.begin().await?;
+
+        let updated =
+            update_transaction_in(&mut tx, actor, transaction_id, update, expected_revision)
+                .await?;
+
+        tx.commit().await?;
+
+        Ok(updated)
+    }
+}
+
+impl From<sqlx::Error> for ApplyBatchError {
+    fn from(error: sqlx::Error) -> Self {
+        Self::DatabaseError(error.into())
+    }
+}
+
+impl From<anyhow::Error> for ClosePeriodError {
+    fn from(error: anyhow::Error) -> Self {
+        Self::Unknown(error)
+    }
+}
+
+impl From<sqlx::Error> for ClosePeriodError {
+    fn from(error: sqlx::Error) -> Self {
+        Self::Unknown(error.into())
+    }
+}
+
+impl From<anyhow::Error> for DeleteTransactionError {
+    fn from(error: anyhow::Error) -> Self {
+        Self::Unknown(error)
+    }
+}
+
+impl From<PeriodLocked> for DeleteTransactionError {
+    fn from(PeriodLocked(lock_date): PeriodLocked) -> Self {
+        Self::PeriodLocked(lock_date)
+    }
+}
+
+impl From<sqlx::Error> for DeleteTransactionError {
+    fn from(error: sqlx::Error) -> Self {
+        Self::DatabaseError(error.into())
+    }
+}
+
+impl From<anyhow::Error> for PersistTransactionError {
+    fn from(error: anyhow::Error) -> Self {
+        Self::Unknown(error)
+    }
+}
+
+impl From<PeriodLocked> for PersistTransactionError {
+    fn from(PeriodLocked(lock_date): PeriodLocked) -> Self {
+        Self::PeriodLocked(lock_date)
+    }
+}
+
+impl From<UnknownCurrency> for PersistTransactionError {
+    fn from(UnknownCurrency(code): UnknownCurrency) -> Self {
+        Self::UnknownCurrency(code)
+    }
+}
+
+impl From<sqlx::Error> for PersistTransactionError {
+    fn from(error: sqlx::Error) -> Self {
+        Self::DatabaseError(error.into())
+    }
 }
 
 impl From<anyhow::Error> for UpdateTransactionError {
@@ -196,3 +992,57 @@ impl From<sqlx::Error> for UpdateTransactionError {
         }
     }
 }
+
+impl From<PeriodLocked> for UpdateTransactionError {
+    fn from(PeriodLocked(lock_date): PeriodLocked) -> Self {
+        Self::PeriodLocked(lock_date)
+    }
+}
+
+impl From<UnknownCurrency> for UpdateTransactionError {
+    fn from(UnknownCurrency(code): UnknownCurrency) -> Self {
+        Self::UnknownCurrency(code)
+    }
+}
+
+impl From<anyhow::Error> for RestoreTransactionError {
+    fn from(error: anyhow::Error) -> Self {
+        Self::Unknown(error)
+    }
+}
+
+impl From<PeriodLocked> for RestoreTransactionError {
+    fn from(PeriodLocked(lock_date): PeriodLocked) -> Self {
+        Self::PeriodLocked(lock_date)
+    }
+}
+
+impl From<sqlx::Error> for RestoreTransactionError {
+    fn from(error: sqlx::Error) -> Self {
+        Self::DatabaseError(error.into())
+    }
+}
+
+impl From<anyhow::Error> for RestoreVersionError {
+    fn from(error: anyhow::Error) -> Self {
+        Self::Unknown(error)
+    }
+}
+
+impl From<sqlx::Error> for RestoreVersionError {
+    fn from(error: sqlx::Error) -> Self {
+        Self::DatabaseError(error.into())
+    }
+}
+
+impl From<PeriodLocked> for RestoreVersionError {
+    fn from(PeriodLocked(lock_date): PeriodLocked) -> Self {
+        Self::PeriodLocked(lock_date)
+    }
+}
+
+impl From<UnknownCurrency> for RestoreVersionError {
+    fn from(UnknownCurrency(code): UnknownCurrency) -> Self {
+        Self::UnknownCurrency(code)
+    }
+}
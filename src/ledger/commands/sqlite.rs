@@ -0,0 +1,999 @@
+use crate::{
+    database::{sqlite_now, SqliteConnection},
+    ledger::{
+        domain::{
+            self,
+            batch::BatchOperation,
+            changes::ChangeNotification,
+            closing::{self, PeriodClosing, CLOSED_ACCOUNT_ROOTS},
+            currency::UnknownCurrency,
+            history::{ChangeAction, TransactionSnapshot},
+            locking::LedgerLock,
+        },
+        models::{self},
+        notifications::ChangeNotifier,
+        queries::sqlite::fetch_period_balances,
+    },
+    models::ledger::TransactionAttachment,
+    repos::sqlite::webhooks,
+};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, Utc};
+use sqlx::types::Json;
+use tracing::{debug, info, instrument};
+use uuid::Uuid;
+
+use super::{
+    ApplyBatchError, BatchOperationResult, ClosePeriodError, ClosingOutcome,
+    DeleteTransactionError, PersistTransactionError, PurgedTransactions, RestoreTransactionError,
+    RestoreVersionError, TransactionCommands, UpdateTransactionError,
+};
+
+/// Commands that change the ledger stored in a SQLite database.
+///
+/// SQLite has no equivalent of `NOTIFY`, so changes are announced to the
+/// instance's own subscribers once they are committed.
+pub struct SqliteCommands {
+    db: SqliteConnection,
+    notifier: Option<ChangeNotifier>,
+}
+
+impl SqliteCommands {
+    /// Create commands for a SQLite database.
+    ///
+    /// # Arguments
+    /// * `db` - The database containing the ledger.
+    /// * `notifier` - Used to announce changes to connected clients, if
+    ///   provided.
+    pub fn new(db: SqliteConnection, notifier: Option<ChangeNotifier>) -> Self {
+        Self { db, notifier }
+    }
+
+    /// Announce changes after the database transaction recording them was
+    /// committed.
+    fn notify(&self, changes: Vec<ChangeNotification>) {
+        if let Some(notifier) = &self.notifier {
+            for change in changes {
+                notifier.publish(change);
+            }
+        }
+    }
+}
+
+/// Insert the entries of a transaction, creating any accounts referenced by
+/// the entries that do not exist yet.
+///
+/// Currencies are never created, so every currency referenced by the entries
+/// must already exist.
+async fn insert_entries<E>(
+    conn: &mut sqlx::SqliteConnection,
+    book_id: Uuid,
+    entries: Vec<models::NewTransactionEntry>,
+) -> Result<(), E>
+where
+    E: From<sqlx::Error> + From<UnknownCurrency>,
+{
+    let mut currencies: Vec<String> = entries.iter().map(|entry| entry.currency.clone()).collect();
+    currencies.sort();
+    currencies.dedup();
+
+    let known_currencies: Vec<String> = sqlx::query_scalar(
+        r#"SELECT code FROM currency WHERE code IN (SELECT value FROM json_each($1))"#,
+    )
+    .bind(Json(&currencies))
+    .fetch_all(&mut *conn)
+    .await?;
+
+    if let Some(unknown) = currencies
+        .into_iter()
+        .find(|code| !known_currencies.contains(code))
+    {
+        return Err(UnknownCurrency(unknown).into());
+    }
+
+    for entry in entries {
+        let account_id = get_or_create_account(conn, book_id, &entry.account.name).await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO transaction_entry (id, transaction_id, "order", account_id, currency, amount)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(entry.transaction_id)
+        .bind(entry.order)
+        .bind(account_id)
+        .bind(entry.currency)
+        .bind(entry.amount)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Get the ID of an account, creating the account if it does not exist yet.
+///
+/// This takes the place of the `get_or_create_account` function in the
+/// Postgres schema.
+async fn get_or_create_account(
+    conn: &mut sqlx::SqliteConnection,
+    book_id: Uuid,
+    name: &str,
+) -> sqlx::Result<Uuid> {
+    sqlx::query(
+        r#"
+        INSERT INTO account (id, book_id, name, created_at)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (book_id, name) DO NOTHING
+        "#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(book_id)
+    .bind(name)
+    .bind(sqlite_now())
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query_scalar(
+        r#"
+        SELECT id
+        FROM account
+        WHERE book_id = $1 AND name = $2
+        "#,
+    )
+    .bind(book_id)
+    .bind(name)
+    .fetch_one(conn)
+    .await
+}
+
+/// Fetch the entries of a transaction along with their accounts and
+/// currencies.
+async fn fetch_entries(
+    conn: &mut sqlx::SqliteConnection,
+    transaction_id: Uuid,
+) -> sqlx::Result<Vec<models::FullTransactionEntry>> {
+    sqlx::query_as::<_, models::FullTransactionEntry>(
+        r#"
+        SELECT
+            e.id, e.transaction_id, e."order", e.account_id, e.currency, e.amount,
+            a.id, a.name, a.created_at, a.book_id,
+            c.code, c.symbol, c.minor_units
+        FROM transaction_entry e
+        LEFT JOIN account a ON e.account_id = a.id
+        LEFT JOIN currency c ON e.currency = c.code
+        WHERE e.transaction_id = $1
+        ORDER BY e."order"
+        "#,
+    )
+    .bind(transaction_id)
+    .fetch_all(conn)
+    .await
+}
+
+/// Fetch a book's ledger lock.
+///
+/// SQLite only allows one writer at a time, so the lock date cannot be moved
+/// while a change is being checked against it.
+async fn fetch_ledger_lock(
+    conn: &mut sqlx::SqliteConnection,
+    book_id: Uuid,
+) -> sqlx::Result<LedgerLock> {
+    let lock_date: Option<Option<NaiveDate>> = sqlx::query_scalar(
+        r#"
+        SELECT lock_date
+        FROM ledger_lock
+        WHERE book_id = $1
+        "#,
+    )
+    .bind(book_id)
+    .fetch_optional(conn)
+    .await?;
+
+    Ok(LedgerLock {
+        lock_date: lock_date.flatten(),
+    })
+}
+
+/// Record a new version of a transaction and queue the webhook events for the
+/// change. The notification for connected clients is added to `changes`, to be
+/// sent once the change is committed.
+///
+/// This should be called in the same database transaction as the change being
+/// recorded so that a change can never be persisted without its history.
+async fn record_version(
+    conn: &mut sqlx::SqliteConnection,
+    changes: &mut Vec<ChangeNotification>,
+    book_id: Uuid,
+    transaction_id: Uuid,
+    action: ChangeAction,
+    actor: &str,
+    snapshot: &TransactionSnapshot,
+) -> sqlx::Result<i32> {
+    let version: i32 = sqlx::query_scalar(
+        r#"
+        INSERT INTO transaction_version (transaction_id, book_id, version, action, actor, snapshot, recorded_at)
+        SELECT $1, $2, COALESCE(MAX(version), 0) + 1, $3, $4, $5, $6
+        FROM transaction_version
+        WHERE transaction_id = $1
+        RETURNING version
+        "#,
+    )
+    .bind(transaction_id)
+    .bind(book_id)
+    .bind(action.as_str())
+    .bind(actor)
+    .bind(Json(snapshot))
+    .bind(sqlite_now())
+    .fetch_one(&mut *conn)
+    .await?;
+
+    debug!(%transaction_id, version, %action, "Recorded transaction version.");
+
+    let event_data = serde_json::json!({
+        "transaction_id": transaction_id,
+        "version": version,
+        "transaction": snapshot,
+    });
+    webhooks::enqueue_event(conn, book_id, action.into(), &event_data).await?;
+    webhooks::enqueue_threshold_events(conn, book_id).await?;
+
+    changes.push(ChangeNotification {
+        book_id,
+        transaction_id,
+        action,
+        version,
+    });
+
+    Ok(version)
+}
+
+/// Move a transaction to the trash using an existing database transaction.
+async fn delete_transaction_in(
+    conn: &mut sqlx::SqliteConnection,
+    changes: &mut Vec<ChangeNotification>,
+    book_id: Uuid,
+    actor: &str,
+    transaction_id: Uuid,
+) -> Result<(), DeleteTransactionError> {
+    let now = sqlite_now();
+    let deleted_transaction: models::Transaction = sqlx::query_as(
+        r#"
+        UPDATE "transaction"
+        SET deleted_at = $3, updated_at = $3, revision = revision + 1
+        WHERE book_id = $1 AND id = $2 AND deleted_at IS NULL
+        RETURNING id, book_id, date, payee, notes, created_at, updated_at, deleted_at, revision
+        "#,
+    )
+    .bind(book_id)
+    .bind(transaction_id)
+    .bind(now)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(DeleteTransactionError::TransactionNotFound)?;
+
+    // Returning early rolls back the database transaction, so the transaction
+    // stays active if its period is locked.
+    fetch_ledger_lock(conn, book_id)
+        .await?
+        .ensure_unlocked([deleted_transaction.date])?;
+
+    let entries = fetch_entries(conn, transaction_id).await?;
+    let snapshot = TransactionSnapshot::from(&deleted_transaction.try_into_domain(&entries)?);
+
+    record_version(
+        conn,
+        changes,
+        book_id,
+        transaction_id,
+        ChangeAction::Deleted,
+        actor,
+        &snapshot,
+    )
+    .await?;
+
+    info!(%book_id, %transaction_id, "Moved transaction to trash.");
+
+    Ok(())
+}
+
+/// Persist a new transaction using an existing database transaction.
+async fn persist_transaction_in(
+    conn: &mut sqlx::SqliteConnection,
+    changes: &mut Vec<ChangeNotification>,
+    actor: &str,
+    transaction: domain::transactions::NewTransaction,
+) -> Result<domain::transactions::Transaction, PersistTransactionError> {
+    let transaction_model: models::NewTransaction = (&transaction).into();
+
+    fetch_ledger_lock(conn, transaction_model.book_id)
+        .await?
+        .ensure_unlocked([transaction.date()])?;
+
+    let persisted_transaction: models::Transaction = sqlx::query_as(
+        r#"
+        INSERT INTO "transaction" (id, book_id, "date", payee, notes, created_at, updated_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $6)
+        RETURNING id, book_id, date, payee, notes, created_at, updated_at, deleted_at, revision
+        "#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(transaction_model.book_id)
+    .bind(transaction_model.date)
+    .bind(transaction_model.payee)
+    .bind(transaction_model.notes)
+    .bind(sqlite_now())
+    .fetch_one(&mut *conn)
+    .await?;
+
+    let entry_models = models::NewTransactionEntry::from_domain_entries(
+        persisted_transaction.id,
+        transaction.book_id(),
+        transaction.entries(),
+    )
+    .context("Failed to map transaction entries to model.")?;
+
+    insert_entries::<PersistTransactionError>(conn, transaction_model.book_id, entry_models)
+        .await?;
+    record_version(
+        conn,
+        changes,
+        transaction_model.book_id,
+        persisted_transaction.id,
+        ChangeAction::Created,
+        actor,
+        &TransactionSnapshot::from(&transaction),
+    )
+    .await?;
+
+    info!(id = %persisted_transaction.id, "Persisted new transaction.");
+
+    let entries = fetch_entries(conn, persisted_transaction.id).await?;
+
+    Ok(persisted_transaction.try_into_domain(&entries)?)
+}
+
+/// Update an existing transaction using an existing database transaction.
+async fn update_transaction_in(
+    conn: &mut sqlx::SqliteConnection,
+    changes: &mut Vec<ChangeNotification>,
+    actor: &str,
+    transaction_id: Uuid,
+    update: domain::transactions::NewTransaction,
+    expected_revision: Option<i64>,
+) -> Result<domain::transactions::Transaction, UpdateTransactionError> {
+    let transaction_changeset = models::NewTransaction::from(&update);
+    let transaction_entries = models::NewTransactionEntry::from_domain_entries(
+        transaction_id,
+        transaction_changeset.book_id,
+        update.entries(),
+    )
+    .context("Failed to convert domain entries to model.")?;
+
+    let current_transaction: models::Transaction = sqlx::query_as(
+        r#"
+        SELECT id, book_id, date, payee, notes, created_at, updated_at, deleted_at, revision
+        FROM "transaction"
+        WHERE id = $1 AND book_id = $2 AND deleted_at IS NULL
+        "#,
+    )
+    .bind(transaction_id)
+    .bind(transaction_changeset.book_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(UpdateTransactionError::TransactionNotFound)?;
+
+    if let Some(expected_revision) = expected_revision {
+        let entries = fetch_entries(conn, transaction_id).await?;
+        let current = current_transaction.try_into_domain(&entries)?;
+
+        if current.revision != expected_revision {
+            debug!(%transaction_id, expected_revision, current_revision = current.revision, "Rejected update of stale transaction.");
+
+            return Err(UpdateTransactionError::Conflict(Box::new(current)));
+        }
+    }
+
+    // Moving a transaction either into or out of a locked period would change
+    // the locked period's balances.
+    fetch_ledger_lock(conn, transaction_changeset.book_id)
+        .await?
+        .ensure_unlocked([current_transaction.date, transaction_changeset.date])?;
+
+    let old_entry_delete = sqlx::query(
+        r#"
+        DELETE FROM transaction_entry
+        WHERE transaction_id = $1
+        "#,
+    )
+    .bind(transaction_id)
+    .execute(&mut *conn)
+    .await?;
+    debug!(%transaction_id, rows = old_entry_delete.rows_affected(), "Cleared out old transaction entries.");
+
+    insert_entries::<UpdateTransactionError>(
+        conn,
+        transaction_changeset.book_id,
+        transaction_entries,
+    )
+    .await?;
+
+    // Replacing the entries changes the transaction, so its modification time
+    // is updated even if none of its own fields changed.
+    let updated_transaction: models::Transaction = sqlx::query_as(
+        r#"
+        UPDATE "transaction"
+        SET
+            date = $3,
+            payee = $4,
+            notes = $5,
+            updated_at = $6,
+            revision = revision + 1
+        WHERE id = $1 AND book_id = $2 AND deleted_at IS NULL
+        RETURNING id, book_id, date, payee, notes, created_at, updated_at, deleted_at, revision
+        "#,
+    )
+    .bind(transaction_id)
+    .bind(transaction_changeset.book_id)
+    .bind(transaction_changeset.date)
+    .bind(transaction_changeset.payee)
+    .bind(transaction_changeset.notes)
+    .bind(sqlite_now())
+    .fetch_one(&mut *conn)
+    .await?;
+
+    record_version(
+        conn,
+        changes,
+        transaction_changeset.book_id,
+        transaction_id,
+        ChangeAction::Updated,
+        actor,
+        &TransactionSnapshot::from(&update),
+    )
+    .await?;
+
+    let updated_entries = fetch_entries(conn, transaction_id).await?;
+
+    info!(%transaction_id, "Updated transaction.");
+
+    Ok(updated_transaction
+        .try_into_domain(&updated_entries)
+        .context("Failed to convert transaction model into domain object.")?)
+}
+
+#[async_trait]
+impl TransactionCommands for SqliteCommands {
+    #[instrument(skip_all)]
+    async fn apply_batch(
+        &self,
+        book_id: Uuid,
+        actor: &str,
+        operations: Vec<BatchOperation>,
+    ) -> Result<Vec<BatchOperationResult>, ApplyBatchError> {
+        let mut tx = self.db.begin().await?;
+        let mut changes = vec![];
+        let mut results = Vec::with_capacity(operations.len());
+
+        // Returning early on any failure drops the database transaction, which
+        // rolls back the operations that were already applied.
+        for (index, operation) in operations.into_iter().enumerate() {
+            let result = match operation {
+                BatchOperation::Create(transaction) => {
+                    persist_transaction_in(&mut tx, &mut changes, actor, transaction)
+                        .await
+                        .map(BatchOperationResult::Created)
+                        .map_err(|error| ApplyBatchError::from_persist(index, error))?
+                }
+                BatchOperation::Update {
+                    transaction_id,
+                    transaction,
+                } => update_transaction_in(
+                    &mut tx,
+                    &mut changes,
+                    actor,
+                    transaction_id,
+                    transaction,
+                    None,
+                )
+                .await
+                .map(BatchOperationResult::Updated)
+                .map_err(|error| ApplyBatchError::from_update(index, error))?,
+                BatchOperation::Delete { transaction_id } => {
+                    delete_transaction_in(&mut tx, &mut changes, book_id, actor, transaction_id)
+                        .await
+                        .map(|()| BatchOperationResult::Deleted(transaction_id))
+                        .map_err(|error| ApplyBatchError::from_delete(index, error))?
+                }
+            };
+
+            results.push(result);
+        }
+
+        tx.commit().await?;
+        self.notify(changes);
+
+        info!(%book_id, operations = results.len(), "Applied transaction batch.");
+
+        Ok(results)
+    }
+
+    #[instrument(skip_all)]
+    async fn close_period(
+        &self,
+        book_id: Uuid,
+        actor: &str,
+        period_start: NaiveDate,
+        period_end: NaiveDate,
+        equity_account: &str,
+        preview: bool,
+    ) -> Result<ClosingOutcome, ClosePeriodError> {
+        let now = sqlite_now();
+        let mut tx = self.db.begin().await?;
+        let mut changes = vec![];
+
+        // Inserting a placeholder for a period that was never closed takes
+        // SQLite's write lock, so concurrent closings of the period wait for
+        // each other, and each sees the transaction generated by the one
+        // before it.
+        sqlx::query(
+            r#"
+            INSERT INTO period_closing (book_id, period_start, period_end, equity_account, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $5)
+            ON CONFLICT (book_id, period_end) DO NOTHING
+            "#,
+        )
+        .bind(book_id)
+        .bind(period_start)
+        .bind(period_end)
+        .bind(equity_account)
+        .bind(now)
+        .execute(&mut tx)
+        .await?;
+
+        let previous_transaction_id = sqlx::query_scalar::<_, Option<Uuid>>(
+            r#"
+            SELECT transaction_id
+            FROM period_closing
+            WHERE book_id = $1 AND period_end = $2
+            "#,
+        )
+        .bind(book_id)
+        .bind(period_end)
+        .fetch_one(&mut tx)
+        .await?;
+
+        // The previous closing transaction is left out of the balances since
+        // it would otherwise cancel out the balances being closed.
+        let balances = fetch_period_balances(
+            &mut tx,
+            book_id,
+            CLOSED_ACCOUNT_ROOTS,
+            period_start,
+            period_end,
+            previous_transaction_id,
+        )
+        .await?;
+
+        let transaction =
+            closing::closing_transaction(book_id, period_end, equity_account, &balances)
+                .map_err(ClosePeriodError::Closing)?;
+        let snapshot = transaction.as_ref().map(TransactionSnapshot::from);
+
+        let mut closing = PeriodClosing {
+            period_start,
+            period_end,
+            equity_account: equity_account.to_owned(),
+            transaction_id: previous_transaction_id,
+        };
+
+        // Dropping the database transaction rolls back the placeholder.
+        if preview {
+            return Ok(ClosingOutcome {
+                closing,
+                transaction: snapshot,
+            });
+        }
+
+        closing.transaction_id = match (previous_transaction_id, transaction) {
+            (Some(transaction_id), Some(transaction)) => {
+                match update_transaction_in(
+                    &mut tx,
+                    &mut changes,
+                    actor,
+                    transaction_id,
+                    transaction.clone(),
+                    None,
+                )
+                .await
+                {
+                    Ok(updated) => Some(updated.id),
+                    // The previous closing transaction was deleted, so a new
+                    // one has to be created instead.
+                    Err(UpdateTransactionError::TransactionNotFound) => Some(
+                        persist_transaction_in(&mut tx, &mut changes, actor, transaction)
+                            .await?
+                            .id,
+                    ),
+                    Err(error) => return Err(error.into()),
+                }
+            }
+            (None, Some(transaction)) => Some(
+                persist_transaction_in(&mut tx, &mut changes, actor, transaction)
+                    .await?
+                    .id,
+            ),
+            (Some(transaction_id), None) => {
+                match delete_transaction_in(&mut tx, &mut changes, book_id, actor, transaction_id)
+                    .await
+                {
+                    Ok(()) | Err(DeleteTransactionError::TransactionNotFound) => None,
+                    Err(error) => return Err(error.into()),
+                }
+            }
+            (None, None) => None,
+        };
+
+        sqlx::query(
+            r#"
+            UPDATE period_closing
+            SET
+                period_start = $3,
+                equity_account = $4,
+                transaction_id = $5,
+                updated_at = $6
+            WHERE book_id = $1 AND period_end = $2
+            "#,
+        )
+        .bind(book_id)
+        .bind(period_end)
+        .bind(closing.period_start)
+        .bind(&closing.equity_account)
+        .bind(closing.transaction_id)
+        .bind(now)
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+        self.notify(changes);
+
+        info!(%book_id, %period_end, transaction_id = ?closing.transaction_id, "Closed period.");
+
+        Ok(ClosingOutcome {
+            closing,
+            transaction: snapshot,
+        })
+    }
+
+    #[instrument(skip_all)]
+    async fn delete_transaction(
+        &self,
+        book_id: Uuid,
+        actor: &str,
+        transaction_id: Uuid,
+    ) -> Result<(), DeleteTransactionError> {
+        let mut tx = self.db.begin().await?;
+        let mut changes = vec![];
+
+        delete_transaction_in(&mut tx, &mut changes, book_id, actor, transaction_id).await?;
+
+        tx.commit().await?;
+        self.notify(changes);
+
+        Ok(())
+    }
+
+    #[instrument(skip_all)]
+    async fn persist_transaction(
+        &self,
+        actor: &str,
+        transaction: domain::transactions::NewTransaction,
+    ) -> Result<domain::transactions::Transaction, PersistTransactionError> {
+        let mut tx = self.db.begin().await?;
+        let mut changes = vec![];
+
+        let persisted = persist_transaction_in(&mut tx, &mut changes, actor, transaction).await?;
+
+        tx.commit().await?;
+        self.notify(changes);
+
+        Ok(persisted)
+    }
+
+    #[instrument(skip_all)]
+    async fn purge_deleted_transactions(
+        &self,
+        deleted_before: DateTime<Utc>,
+    ) -> anyhow::Result<PurgedTransactions> {
+        // SQLite only has a single writer, so no transaction can be restored
+        // between removing the attachments and removing the transactions.
+        let mut tx = self.db.begin().await?;
+
+        let attachments = sqlx::query_as::<_, TransactionAttachment>(
+            r#"
+            DELETE FROM transaction_attachment
+            WHERE transaction_id IN (
+                SELECT id FROM "transaction" WHERE deleted_at < $1
+            )
+            RETURNING id, transaction_id, book_id, file_name, content_type, size, created_at
+            "#,
+        )
+        .bind(deleted_before)
+        .fetch_all(&mut tx)
+        .await?
+        .drain(..)
+        .map(domain::attachments::Attachment::try_from)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let result = sqlx::query(
+            r#"
+            DELETE FROM "transaction"
+            WHERE deleted_at < $1
+            "#,
+        )
+        .bind(deleted_before)
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        info!(%deleted_before, count = result.rows_affected(), attachments = attachments.len(), "Purged trashed transactions.");
+
+        Ok(PurgedTransactions {
+            count: result.rows_affected(),
+            attachments,
+        })
+    }
+
+    #[instrument(skip_all)]
+    async fn purge_transaction(
+        &self,
+        book_id: Uuid,
+        transaction_id: Uuid,
+    ) -> anyhow::Result<PurgedTransactions> {
+        // As with the expired transactions, the single writer keeps the
+        // transaction from changing between the two deletes.
+        let mut tx = self.db.begin().await?;
+
+        let attachments = sqlx::query_as::<_, TransactionAttachment>(
+            r#"
+            DELETE FROM transaction_attachment
+            WHERE transaction_id IN (
+                SELECT id
+                FROM "transaction"
+                WHERE book_id = $1 AND id = $2 AND deleted_at IS NOT NULL
+            )
+            RETURNING id, transaction_id, book_id, file_name, content_type, size, created_at
+            "#,
+        )
+        .bind(book_id)
+        .bind(transaction_id)
+        .fetch_all(&mut tx)
+        .await?
+        .drain(..)
+        .map(domain::attachments::Attachment::try_from)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let result = sqlx::query(
+            r#"
+            DELETE FROM "transaction"
+            WHERE book_id = $1 AND id = $2 AND deleted_at IS NOT NULL
+            "#,
+        )
+        .bind(book_id)
+        .bind(transaction_id)
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        let found = result.rows_affected() > 0;
+
+        info!(%book_id, %transaction_id, found, attachments = attachments.len(), "Purged transaction from trash.");
+
+        Ok(PurgedTransactions {
+            count: result.rows_affected(),
+            attachments,
+        })
+    }
+
+    #[instrument(skip_all)]
+    async fn restore_deleted_transaction(
+        &self,
+        book_id: Uuid,
+        actor: &str,
+        transaction_id: Uuid,
+    ) -> Result<domain::transactions::Transaction, RestoreTransactionError> {
+        let mut tx = self.db.begin().await?;
+        let mut changes = vec![];
+
+        let restored_transaction: models::Transaction = sqlx::query_as(
+            r#"
+            UPDATE "transaction"
+            SET deleted_at = NULL, updated_at = $3, revision = revision + 1
+            WHERE book_id = $1 AND id = $2 AND deleted_at IS NOT NULL
+            RETURNING id, book_id, date, payee, notes, created_at, updated_at, deleted_at, revision
+            "#,
+        )
+        .bind(book_id)
+        .bind(transaction_id)
+        .bind(sqlite_now())
+        .fetch_optional(&mut tx)
+        .await?
+        .ok_or(RestoreTransactionError::TransactionNotFound)?;
+
+        fetch_ledger_lock(&mut tx, book_id)
+            .await?
+            .ensure_unlocked([restored_transaction.date])?;
+
+        let entries = fetch_entries(&mut tx, transaction_id).await?;
+        let restored = restored_transaction.try_into_domain(&entries)?;
+
+        record_version(
+            &mut tx,
+            &mut changes,
+            book_id,
+            transaction_id,
+            ChangeAction::Restored,
+            actor,
+            &TransactionSnapshot::from(&restored),
+        )
+        .await?;
+
+        tx.commit().await?;
+        self.notify(changes);
+
+        info!(%book_id, %transaction_id, "Restored transaction from trash.");
+
+        Ok(restored)
+    }
+
+    #[instrument(skip_all)]
+    async fn restore_transaction_version(
+        &self,
+        book_id: Uuid,
+        actor: &str,
+        transaction_id: Uuid,
+        version: i32,
+    ) -> Result<domain::transactions::Transaction, RestoreVersionError> {
+        let mut tx = self.db.begin().await?;
+        let mut changes = vec![];
+
+        let snapshot = sqlx::query_scalar::<_, Json<TransactionSnapshot>>(
+            r#"
+            SELECT snapshot
+            FROM transaction_version
+            WHERE book_id = $1 AND transaction_id = $2 AND version = $3
+            "#,
+        )
+        .bind(book_id)
+        .bind(transaction_id)
+        .bind(version)
+        .fetch_optional(&mut tx)
+        .await?
+        .ok_or(RestoreVersionError::VersionNotFound)?
+        .0;
+
+        let restored = snapshot
+            .to_new_transaction(book_id)
+            .map_err(RestoreVersionError::Invalid)?;
+        let transaction_changeset = models::NewTransaction::from(&restored);
+
+        let current_date: Option<NaiveDate> = sqlx::query_scalar(
+            r#"
+            SELECT date
+            FROM "transaction"
+            WHERE id = $1 AND book_id = $2 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(transaction_id)
+        .bind(book_id)
+        .fetch_optional(&mut tx)
+        .await?;
+
+        fetch_ledger_lock(&mut tx, book_id)
+            .await?
+            .ensure_unlocked(current_date.into_iter().chain([restored.date()]))?;
+
+        // The transaction is taken out of the trash if it was deleted after
+        // the version was recorded, or recreated with its original ID if it
+        // has since been purged.
+        let restored_transaction: models::Transaction = sqlx::query_as(
+            r#"
+            INSERT INTO "transaction" (id, book_id, "date", payee, notes, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $6)
+            ON CONFLICT (id) DO UPDATE
+            SET
+                date = excluded.date,
+                payee = excluded.payee,
+                notes = excluded.notes,
+                updated_at = excluded.updated_at,
+                deleted_at = NULL,
+                revision = "transaction".revision + 1
+            WHERE "transaction".book_id = excluded.book_id
+            RETURNING id, book_id, date, payee, notes, created_at, updated_at, deleted_at, revision
+            "#,
+        )
+        .bind(transaction_id)
+        .bind(transaction_changeset.book_id)
+        .bind(transaction_changeset.date)
+        .bind(transaction_changeset.payee)
+        .bind(transaction_changeset.notes)
+        .bind(sqlite_now())
+        .fetch_optional(&mut tx)
+        .await?
+        .ok_or(RestoreVersionError::VersionNotFound)?;
+
+        sqlx::query(
+            r#"
+            DELETE FROM transaction_entry
+            WHERE transaction_id = $1
+            "#,
+        )
+        .bind(transaction_id)
+        .execute(&mut tx)
+        .await?;
+
+        let entry_models = models::NewTransactionEntry::from_domain_entries(
+            transaction_id,
+            book_id,
+            restored.entries(),
+        )
+        .context("Failed to convert domain entries to model.")?;
+
+        insert_entries::<RestoreVersionError>(&mut tx, book_id, entry_models).await?;
+        record_version(
+            &mut tx,
+            &mut changes,
+            book_id,
+            transaction_id,
+            ChangeAction::Restored,
+            actor,
+            &snapshot,
+        )
+        .await?;
+
+        tx.commit().await?;
+        self.notify(changes);
+
+        info!(%transaction_id, version, "Restored transaction version.");
+
+        let mut conn = self.db.acquire().await?;
+        let entries = fetch_entries(&mut conn, transaction_id).await?;
+
+        Ok(restored_transaction
+            .try_into_domain(&entries)
+            .context("Failed to convert transaction model into domain object.")?)
+    }
+
+    #[instrument(skip_all)]
+    async fn update_transaction(
+        &self,
+        actor: &str,
+        transaction_id: Uuid,
+        update: domain::transactions::NewTransaction,
+        expected_revision: Option<i64>,
+    ) -> Result<domain::transactions::Transaction, UpdateTransactionError> {
+        let mut tx = self.db.begin().await?;
+        let mut changes = vec![];
+
+        let updated = update_transaction_in(
+            &mut tx,
+            &mut changes,
+            actor,
+            transaction_id,
+            update,
+            expected_revision,
+        )
+        .await?;
+
+        tx.commit().await?;
+        self.notify(changes);
+
+        Ok(updated)
+    }
+}
@@ -0,0 +1,1277 @@
+//! Behavior that every ledger backend must share.
+//!
+//! Each test runs against the in-memory ledger, against an in-memory SQLite
+//! database, and against Postgres when `DATABASE_URL` is set. Postgres tests
+//! work in a book of their own, which is deleted once the test passes.
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use chrono::{Datelike, Duration, NaiveDate, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    database::{sqlite_now, Database, PostgresConnection},
+    repos::{
+        attachments::{DynAttachmentRepo, PersistedAttachment},
+        locks::DynLedgerLockRepo,
+        transactions::{DynTransactionRepo, TransactionQuery},
+    },
+};
+
+use super::{
+    commands::{
+        postgres::PostgresCommands, sqlite::SqliteCommands, ApplyBatchError, BatchOperationError,
+        BatchOperationResult, DeleteTransactionError, DynTransactionCommands,
+        PersistTransactionError, UpdateTransactionError,
+    },
+    domain::{
+        attachments::{AttachmentLimits, NewAttachment},
+        batch::BatchOperation,
+        closing::AccountBalance,
+        concurrency::entity_tag,
+        currency::Currency,
+        history::ChangeAction,
+        transactions::{
+            NewTransaction, NewTransactionData, NewTransactionEntryAmountData,
+            NewTransactionEntryData, Transaction, TransactionCursor,
+        },
+    },
+    memory::MemoryLedger,
+    queries::{
+        postgres::PostgresQueries, sqlite::SqliteQueries, DynAccountQueries, DynCurrencyQueries,
+        DynTransactionQueries, ReportInterval,
+    },
+};
+
+struct Backend {
+    accounts: DynAccountQueries,
+    /// Attachments are only kept by the database backends.
+    attachments: Option<DynAttachmentRepo>,
+    commands: DynTransactionCommands,
+    currencies: DynCurrencyQueries,
+    locks: DynLedgerLockRepo,
+    queries: DynTransactionQueries,
+    repo: DynTransactionRepo,
+    book_id: Uuid,
+    pool: Option<PgPool>,
+}
+
+fn memory_backend() -> Backend {
+    let ledger = Arc::new(MemoryLedger::new(
+        [
+            Currency::new("JPY".to_owned(), 0),
+            Currency::new("USD".to_owned(), 2),
+        ],
+        None,
+    ));
+
+    Backend {
+        accounts: ledger.clone(),
+        attachments: None,
+        commands: ledger.clone(),
+        currencies: ledger.clone(),
+        locks: ledger.clone(),
+        queries: ledger.clone(),
+        repo: ledger,
+        book_id: Uuid::new_v4(),
+        pool: None,
+    }
+}
+
+async fn postgres_backend() -> Option<Backend> {
+    let url = std::env::var("DATABASE_URL").ok()?;
+    let pool = PgPool::connect(&url).await.unwrap();
+
+    sqlx::query(
+        "INSERT INTO currency (code, symbol, minor_units) VALUES ('JPY', '¥', 0), ('USD', '$', 2) ON CONFLICT DO NOTHING",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    let book_id: Uuid = sqlx::query_scalar("INSERT INTO book (name) VALUES ($1) RETURNING id")
+        .bind("Conformance")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+    let db = PostgresConnection::new(pool.clone());
+
+    Some(Backend {
+        accounts: Arc::new(PostgresQueries(db.clone())),
+        attachments: Some(Arc::new(db.clone())),
+        commands: Arc::new(PostgresCommands(db.clone())),
+        currencies: Arc::new(PostgresQueries(db.clone())),
+        locks: Arc::new(db.clone()),
+        queries: Arc::new(PostgresQueries(db.clone())),
+        repo: Arc::new(db),
+        book_id,
+        pool: Some(pool),
+    })
+}
+
+async fn sqlite_backend() -> Backend {
+    let db = Database::in_memory(std::time::Duration::from_secs(5))
+        .await
+        .unwrap();
+    sqlx::query("INSERT INTO currency (code, symbol, minor_units) VALUES ('JPY', '¥', 0)")
+        .execute(&*db)
+        .await
+        .unwrap();
+
+    let book_id = Uuid::new_v4();
+    sqlx::query("INSERT INTO book (id, name, created_at) VALUES ($1, $2, $3)")
+        .bind(book_id)
+        .bind("Conformance")
+        .bind(sqlite_now())
+        .execute(&*db)
+        .await
+        .unwrap();
+
+    Backend {
+        accounts: Arc::new(SqliteQueries(db.clone())),
+        attachments: Some(Arc::new(db.clone())),
+        commands: Arc::new(SqliteCommands::new(db.clone(), None)),
+        currencies: Arc::new(SqliteQueries(db.clone())),
+        locks: Arc::new(db.clone()),
+        queries: Arc::new(SqliteQueries(db.clone())),
+        repo: Arc::new(db),
+        book_id,
+        // Each test has a database of its own, which is dropped along with it.
+        pool: None,
+    }
+}
+
+impl Backend {
+    async fn clean_up(self) {
+        if let Some(pool) = self.pool {
+            // Entries keep their accounts from being deleted along with the
+            // book, so the transactions have to go first.
+            for statement in [
+                "DELETE FROM transaction WHERE book_id = $1",
+                "DELETE FROM book WHERE id = $1",
+            ] {
+                sqlx::query(statement)
+                    .bind(self.book_id)
+                    .execute(&pool)
+                    .await
+                    .unwrap();
+            }
+        }
+    }
+
+    fn transaction(
+        &self,
+        date: NaiveDate,
+        payee: &str,
+        entries: &[(&str, &str, i32)],
+    ) -> NewTransaction {
+        NewTransaction::from_data(
+            self.book_id,
+            NewTransactionData {
+                date,
+                payee: payee.to_owned(),
+                notes: None,
+                entries: entries
+                    .iter()
+                    .map(|(account, currency, value)| NewTransactionEntryData {
+                        account: (*account).to_owned(),
+                        amount: Some(NewTransactionEntryAmountData {
+                            currency: Some((*currency).to_owned()),
+                            value: *value,
+                        }),
+                    })
+                    .collect(),
+            },
+        )
+        .unwrap()
+    }
+
+    /// List a page of transactions, converted to the domain's
+    /// representation.
+    async fn list(&self, query: TransactionQuery) -> (Vec<Transaction>, Option<TransactionCursor>) {
+        let collection = self.repo.list_transactions(query).await.unwrap();
+        let transactions = collection
+            .items
+            .into_iter()
+            .map(|item| Transaction::try_from(item).unwrap())
+            .collect();
+
+        (transactions, collection.next)
+    }
+
+    fn query(&self) -> TransactionQuery {
+        TransactionQuery {
+            book_id: self.book_id,
+            after: None,
+            account: None,
+            trashed: false,
+        }
+    }
+}
+
+fn days_ago(days: i64) -> NaiveDate {
+    Utc::now().date_naive() - Duration::days(days)
+}
+
+macro_rules! conformance_tests {
+    ($($name:ident),* $(,)?) => {
+        mod memory {
+            $(
+                #[tokio::test]
+                async fn $name() {
+                    super::$name(super::memory_backend()).await;
+                }
+            )*
+        }
+
+        mod sqlite {
+            $(
+                #[tokio::test]
+                async fn $name() {
+                    super::$name(super::sqlite_backend().await).await;
+                }
+            )*
+        }
+
+        mod postgres {
+            $(
+                #[tokio::test]
+                async fn $name() {
+                    if let Some(backend) = super::postgres_backend().await {
+                        super::$name(backend).await;
+                    }
+                }
+            )*
+        }
+    };
+}
+
+conformance_tests!(
+    applies_batches_atomically,
+    closes_periods,
+    enforces_lock_date,
+    finds_currencies_by_code,
+    lists_changes,
+    matches_child_accounts,
+    paginates_transactions,
+    persists_transactions,
+    rejects_stale_updates,
+    rejects_unknown_currencies,
+    reports_balances,
+    restores_versions,
+    revises_each_batch_update,
+    trashes_and_restores_transactions,
+);
+
+async fn persists_transactions(backend: Backend) {
+    let date = days_ago(3);
+    let created = backend
+        .commands
+        .persist_transaction(
+            "alice",
+            backend.transaction(
+                date,
+                "Grocer",
+                &[
+                    ("Expenses:Food", "USD", 1250),
+                    ("Assets:Cash", "USD", -1250),
+                ],
+            ),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(created.book_id, backend.book_id);
+    assert_eq!(created.date, date);
+    assert_eq!(created.payee, "Grocer");
+    assert_eq!(created.entries.len(), 2);
+    assert_eq!(created.entries[0].account(), "Expenses:Food");
+    assert_eq!(created.entries[0].amount().value(), 1250);
+
+    let found = backend
+        .queries
+        .get_transaction(backend.book_id, created.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(found.id, created.id);
+    assert_eq!(found.revision, created.revision);
+    assert_eq!(found.entries, created.entries);
+
+    let other_book = backend
+        .queries
+        .get_transaction(Uuid::new_v4(), created.id)
+        .await
+        .unwrap();
+    assert!(other_book.is_none());
+
+    backend.clean_up().await;
+}
+
+async fn rejects_unknown_currencies(backend: Backend) {
+    let result = backend
+        .commands
+        .persist_transaction(
+            "alice",
+            backend.transaction(
+                days_ago(1),
+                "Abroad",
+                &[
+                    ("Expenses:Travel", "XYZ", 100),
+                    ("Assets:Cash", "XYZ", -100),
+                ],
+            ),
+        )
+        .await;
+
+    assert!(matches!(result, Err(PersistTransactionError::UnknownCurrency(code)) if code == "XYZ"));
+
+    let page = backend
+        .repo
+        .list_transactions(backend.query())
+        .await
+        .unwrap();
+    assert!(page.items.is_empty());
+
+    backend.clean_up().await;
+}
+
+async fn matches_child_accounts(backend: Backend) {
+    backend
+        .commands
+        .persist_transaction(
+            "alice",
+            backend.transaction(
+                days_ago(2),
+                "Market",
+                &[
+                    ("Expenses:Food:Groceries", "USD", 100),
+                    ("Expenses:Foodie", "USD", 50),
+                    ("Assets:Cash", "USD", -150),
+                ],
+            ),
+        )
+        .await
+        .unwrap();
+
+    let food = backend
+        .accounts
+        .get_account_balance(backend.book_id, "Expenses:Food".to_owned())
+        .await
+        .unwrap();
+    assert_eq!(food.len(), 1);
+    assert_eq!(food[0].value(), 100);
+
+    let expenses = backend
+        .accounts
+        .get_account_balance(backend.book_id, "Expenses".to_owned())
+        .await
+        .unwrap();
+    assert_eq!(expenses[0].value(), 150);
+
+    let balances = backend
+        .accounts
+        .get_account_balances(
+            backend.book_id,
+            &["Expenses:Food".to_owned(), "Income".to_owned()],
+        )
+        .await
+        .unwrap();
+    assert_eq!(balances.len(), 1);
+    assert_eq!(balances["Expenses:Food"][0].value(), 100);
+
+    let filtered = backend
+        .repo
+        .list_transactions(TransactionQuery {
+            account: Some("Expenses:Foodie".to_owned()),
+            ..backend.query()
+        })
+        .await
+        .unwrap();
+    assert_eq!(filtered.items.len(), 1);
+
+    let unmatched = backend
+        .repo
+        .list_transactions(TransactionQuery {
+            account: Some("Expenses:Foo".to_owned()),
+            ..backend.query()
+        })
+        .await
+        .unwrap();
+    assert!(unmatched.items.is_empty());
+
+    backend.clean_up().await;
+}
+
+async fn trashes_and_restores_transactions(backend: Backend) {
+    let created = backend
+        .commands
+        .persist_transaction(
+            "alice",
+            backend.transaction(
+                days_ago(1),
+                "Cafe",
+                &[
+                    ("Expenses:Coffee", "USD", 450),
+                    ("Assets:Cash", "USD", -450),
+                ],
+            ),
+        )
+        .await
+        .unwrap();
+
+    backend
+        .commands
+        .delete_transaction(backend.book_id, "alice", created.id)
+        .await
+        .unwrap();
+    assert!(backend
+        .queries
+        .get_transaction(backend.book_id, created.id)
+        .await
+        .unwrap()
+        .is_none());
+
+    let trashed = backend
+        .repo
+        .list_transactions(TransactionQuery {
+            trashed: true,
+            ..backend.query()
+        })
+        .await
+        .unwrap();
+    assert_eq!(trashed.items.len(), 1);
+
+    let result = backend
+        .commands
+        .delete_transaction(backend.book_id, "alice", created.id)
+        .await;
+    assert!(matches!(
+        result,
+        Err(DeleteTransactionError::TransactionNotFound)
+    ));
+
+    let restored = backend
+        .commands
+        .restore_deleted_transaction(backend.book_id, "bob", created.id)
+        .await
+        .unwrap();
+    assert_eq!(restored.id, created.id);
+    assert!(restored.deleted_at.is_none());
+
+    let history = backend
+        .queries
+        .get_transaction_history(backend.book_id, created.id)
+        .await
+        .unwrap();
+    let actions = history
+        .iter()
+        .map(|version| version.action)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        actions,
+        vec![
+            ChangeAction::Created,
+            ChangeAction::Deleted,
+            ChangeAction::Restored
+        ]
+    );
+    assert_eq!(history[2].actor, "bob");
+    assert_eq!(history[2].version, 3);
+
+    // Only trashed transactions can be purged.
+    let purged = backend
+        .commands
+        .purge_transaction(backend.book_id, created.id)
+        .await
+        .unwrap();
+    assert_eq!(purged.count, 0);
+
+    let attachment = match &backend.attachments {
+        Some(attachments) => {
+            let limits = AttachmentLimits {
+                max_size: 1024,
+                book_quota: 1024,
+            };
+            let new_attachment = NewAttachment::new(
+                backend.book_id,
+                created.id,
+                "receipt.pdf",
+                "application/pdf",
+                b"%PDF-1.7",
+                &limits,
+                0,
+            )
+            .unwrap();
+
+            match attachments
+                .persist_attachment(&new_attachment, limits.book_quota)
+                .await
+                .unwrap()
+            {
+                PersistedAttachment::Persisted(attachment) => Some(attachment),
+                _ => panic!("attachment should be persisted"),
+            }
+        }
+        None => None,
+    };
+
+    backend
+        .commands
+        .delete_transaction(backend.book_id, "alice", created.id)
+        .await
+        .unwrap();
+    let purged = backend
+        .commands
+        .purge_transaction(Uuid::new_v4(), created.id)
+        .await
+        .unwrap();
+    assert_eq!(purged.count, 0);
+    assert!(purged.attachments.is_empty());
+
+    let purged = backend
+        .commands
+        .purge_transaction(backend.book_id, created.id)
+        .await
+        .unwrap();
+    assert_eq!(purged.count, 1);
+    assert_eq!(
+        purged
+            .attachments
+            .iter()
+            .map(|attachment| attachment.id)
+            .collect::<Vec<_>>(),
+        attachment
+            .iter()
+            .map(|attachment| attachment.id)
+            .collect::<Vec<_>>()
+    );
+
+    let trashed = backend
+        .repo
+        .list_transactions(TransactionQuery {
+            trashed: true,
+            ..backend.query()
+        })
+        .await
+        .unwrap();
+    assert!(trashed.items.is_empty());
+
+    backend.clean_up().await;
+}
+
+async fn rejects_stale_updates(backend: Backend) {
+    let created = backend
+        .commands
+        .persist_transaction(
+            "alice",
+            backend.transaction(
+                days_ago(1),
+                "Cafe",
+                &[
+                    ("Expenses:Coffee", "USD", 450),
+                    ("Assets:Cash", "USD", -450),
+                ],
+            ),
+        )
+        .await
+        .unwrap();
+
+    let updated = backend
+        .commands
+        .update_transaction(
+            "alice",
+            created.id,
+            backend.transaction(
+                days_ago(1),
+                "Bakery",
+                &[("Expenses:Food", "USD", 300), ("Assets:Cash", "USD", -300)],
+            ),
+            Some(created.revision),
+        )
+        .await
+        .unwrap();
+    assert_eq!(updated.payee, "Bakery");
+    assert_ne!(updated.revision, created.revision);
+
+    let result = backend
+        .commands
+        .update_transaction(
+            "bob",
+            created.id,
+            backend.transaction(
+                days_ago(1),
+                "Diner",
+                &[("Expenses:Food", "USD", 900), ("Assets:Cash", "USD", -900)],
+            ),
+            Some(created.revision),
+        )
+        .await;
+    match result {
+        Err(UpdateTransactionError::Conflict(current)) => assert_eq!(current.payee, "Bakery"),
+        other => panic!("expected a conflict, got {:?}", other.map(|t| t.payee)),
+    }
+
+    let result = backend
+        .commands
+        .update_transaction(
+            "bob",
+            Uuid::new_v4(),
+            backend.transaction(
+                days_ago(1),
+                "Diner",
+                &[("Expenses:Food", "USD", 900), ("Assets:Cash", "USD", -900)],
+            ),
+            None,
+        )
+        .await;
+    assert!(matches!(
+        result,
+        Err(UpdateTransactionError::TransactionNotFound)
+    ));
+
+    backend.clean_up().await;
+}
+
+async fn closes_periods(backend: Backend) {
+    let period_start = days_ago(60);
+    let period_end = days_ago(30);
+    backend
+        .commands
+        .persist_transaction(
+            "alice",
+            backend.transaction(
+                days_ago(45),
+                "Employer",
+                &[
+                    ("Assets:Cash", "USD", 10000),
+                    ("Income:Salary", "USD", -10000),
+                ],
+            ),
+        )
+        .await
+        .unwrap();
+
+    let preview = backend
+        .commands
+        .close_period(
+            backend.book_id,
+            "alice",
+            period_start,
+            period_end,
+            "Equity:Retained",
+            true,
+        )
+        .await
+        .unwrap();
+    assert!(preview.transaction.is_some());
+    assert_eq!(backend.list(backend.query()).await.0.len(), 1);
+
+    let first = backend
+        .commands
+        .close_period(
+            backend.book_id,
+            "alice",
+            period_start,
+            period_end,
+            "Equity:Retained",
+            false,
+        )
+        .await
+        .unwrap();
+    let closing_id = first.closing.transaction_id.unwrap();
+
+    // Closing the period again replaces the closing transaction rather than
+    // adding a second one, and ignores it when computing the balances.
+    let second = backend
+        .commands
+        .close_period(
+            backend.book_id,
+            "alice",
+            period_start,
+            period_end,
+            "Equity:Retained",
+            false,
+        )
+        .await
+        .unwrap();
+    assert_eq!(second.closing.transaction_id, Some(closing_id));
+
+    let (transactions, _) = backend.list(backend.query()).await;
+    assert_eq!(transactions.len(), 2);
+    let closing = transactions
+        .iter()
+        .find(|transaction| transaction.id == closing_id)
+        .unwrap();
+    assert_eq!(closing.date, period_end);
+    assert_eq!(closing.entries.len(), 2);
+
+    backend.clean_up().await;
+}
+
+async fn enforces_lock_date(backend: Backend) {
+    let lock_date = days_ago(10);
+    let open = backend
+        .commands
+        .persist_transaction(
+            "alice",
+            backend.transaction(
+                days_ago(5),
+                "Open",
+                &[("Expenses:Food", "USD", 100), ("Assets:Cash", "USD", -100)],
+            ),
+        )
+        .await
+        .unwrap();
+    let closed = backend
+        .commands
+        .persist_transaction(
+            "alice",
+            backend.transaction(
+                days_ago(20),
+                "Closed",
+                &[("Expenses:Food", "USD", 100), ("Assets:Cash", "USD", -100)],
+            ),
+        )
+        .await
+        .unwrap();
+
+    // Setting the same date again doesn't count as a change.
+    for _ in 0..2 {
+        backend
+            .locks
+            .set_lock_date(backend.book_id, "alice", Some(lock_date))
+            .await
+            .unwrap();
+    }
+    let changes = backend
+        .locks
+        .list_lock_changes(backend.book_id)
+        .await
+        .unwrap();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].actor.as_deref(), Some("alice"));
+    assert_eq!(changes[0].previous_lock_date, None);
+    assert_eq!(changes[0].lock_date, Some(lock_date));
+
+    let result = backend
+        .commands
+        .persist_transaction(
+            "alice",
+            backend.transaction(
+                lock_date,
+                "Late",
+                &[("Expenses:Food", "USD", 100), ("Assets:Cash", "USD", -100)],
+            ),
+        )
+        .await;
+    assert!(
+        matches!(result, Err(PersistTransactionError::PeriodLocked(date)) if date == lock_date)
+    );
+
+    let result = backend
+        .commands
+        .update_transaction(
+            "alice",
+            open.id,
+            backend.transaction(
+                days_ago(15),
+                "Moved",
+                &[("Expenses:Food", "USD", 100), ("Assets:Cash", "USD", -100)],
+            ),
+            None,
+        )
+        .await;
+    assert!(matches!(result, Err(UpdateTransactionError::PeriodLocked(date)) if date == lock_date));
+
+    let result = backend
+        .commands
+        .delete_transaction(backend.book_id, "alice", closed.id)
+        .await;
+    assert!(matches!(result, Err(DeleteTransactionError::PeriodLocked(date)) if date == lock_date));
+
+    backend
+        .commands
+        .delete_transaction(backend.book_id, "alice", open.id)
+        .await
+        .unwrap();
+
+    backend.clean_up().await;
+}
+
+async fn paginates_transactions(backend: Backend) {
+    for day in 0..55 {
+        backend
+            .commands
+            .persist_transaction(
+                "alice",
+                backend.transaction(
+                    days_ago(day),
+                    &format!("Payee {}", day),
+                    &[("Expenses:Food", "USD", 100), ("Assets:Cash", "USD", -100)],
+                ),
+            )
+            .await
+            .unwrap();
+    }
+
+    let (first, next) = backend.list(backend.query()).await;
+    assert_eq!(first.len(), 50);
+    assert_eq!(first[0].payee, "Payee 0");
+    assert_eq!(first[49].payee, "Payee 49");
+    assert_eq!(first[0].entries.len(), 2);
+    assert_eq!(first[0].entries[0].account(), "Expenses:Food");
+
+    let (second, next) = backend
+        .list(TransactionQuery {
+            after: next,
+            ..backend.query()
+        })
+        .await;
+    let payees = second
+        .iter()
+        .map(|transaction| transaction.payee.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        payees,
+        vec!["Payee 50", "Payee 51", "Payee 52", "Payee 53", "Payee 54"]
+    );
+    assert!(next.is_none());
+
+    backend.clean_up().await;
+}
+
+async fn revises_each_batch_update(backend: Backend) {
+    let created = backend
+        .commands
+        .persist_transaction(
+            "alice",
+            backend.transaction(
+                days_ago(1),
+                "Cafe",
+                &[
+                    ("Expenses:Coffee", "USD", 450),
+                    ("Assets:Cash", "USD", -450),
+                ],
+            ),
+        )
+        .await
+        .unwrap();
+
+    // Both updates are made in the same database transaction, so they happen
+    // at the same time as far as the database's clock is concerned.
+    let results = backend
+        .commands
+        .apply_batch(
+            backend.book_id,
+            "alice",
+            vec![
+                BatchOperation::Update {
+                    transaction_id: created.id,
+                    transaction: backend.transaction(
+                        days_ago(1),
+                        "Bakery",
+                        &[("Expenses:Food", "USD", 300), ("Assets:Cash", "USD", -300)],
+                    ),
+                },
+                BatchOperation::Update {
+                    transaction_id: created.id,
+                    transaction: backend.transaction(
+                        days_ago(1),
+                        "Diner",
+                        &[("Expenses:Food", "USD", 900), ("Assets:Cash", "USD", -900)],
+                    ),
+                },
+            ],
+        )
+        .await
+        .unwrap();
+    let revisions = results
+        .iter()
+        .map(|result| match result {
+            BatchOperationResult::Updated(transaction) => transaction.revision,
+            _ => panic!("expected an updated transaction"),
+        })
+        .collect::<Vec<_>>();
+    assert_ne!(
+        entity_tag(revisions[0]),
+        entity_tag(revisions[1]),
+        "each update should get its own ETag"
+    );
+
+    // An ETag from between the two updates is stale.
+    let result = backend
+        .commands
+        .update_transaction(
+            "bob",
+            created.id,
+            backend.transaction(
+                days_ago(1),
+                "Grocer",
+                &[("Expenses:Food", "USD", 200), ("Assets:Cash", "USD", -200)],
+            ),
+            Some(revisions[0]),
+        )
+        .await;
+    match result {
+        Err(UpdateTransactionError::Conflict(current)) => assert_eq!(current.payee, "Diner"),
+        other => panic!("expected a conflict, got {:?}", other.map(|t| t.payee)),
+    }
+
+    backend.clean_up().await;
+}
+
+async fn applies_batches_atomically(backend: Backend) {
+    let result = backend
+        .commands
+        .apply_batch(
+            backend.book_id,
+            "alice",
+            vec![
+                BatchOperation::Create(backend.transaction(
+                    days_ago(1),
+                    "Kept?",
+                    &[("Expenses:Food", "USD", 100), ("Assets:Cash", "USD", -100)],
+                )),
+                BatchOperation::Delete {
+                    transaction_id: Uuid::new_v4(),
+                },
+            ],
+        )
+        .await;
+    assert!(matches!(
+        result,
+        Err(ApplyBatchError::OperationFailed {
+            index: 1,
+            error: BatchOperationError::TransactionNotFound
+        })
+    ));
+    assert!(backend
+        .repo
+        .list_transactions(backend.query())
+        .await
+        .unwrap()
+        .items
+        .is_empty());
+
+    let results = backend
+        .commands
+        .apply_batch(
+            backend.book_id,
+            "alice",
+            vec![
+                BatchOperation::Create(backend.transaction(
+                    days_ago(1),
+                    "First",
+                    &[("Expenses:Food", "USD", 100), ("Assets:Cash", "USD", -100)],
+                )),
+                BatchOperation::Create(backend.transaction(
+                    days_ago(2),
+                    "Second",
+                    &[("Expenses:Food", "JPY", 500), ("Assets:Cash", "JPY", -500)],
+                )),
+            ],
+        )
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 2);
+
+    let second_id = match &results[1] {
+        BatchOperationResult::Created(transaction) => transaction.id,
+        _ => panic!("expected a created transaction"),
+    };
+    let results = backend
+        .commands
+        .apply_batch(
+            backend.book_id,
+            "alice",
+            vec![BatchOperation::Delete {
+                transaction_id: second_id,
+            }],
+        )
+        .await
+        .unwrap();
+    assert!(matches!(results[0], BatchOperationResult::Deleted(id) if id == second_id));
+
+    let (page, _) = backend.list(backend.query()).await;
+    assert_eq!(page.len(), 1);
+    assert_eq!(page[0].payee, "First");
+
+    backend.clean_up().await;
+}
+
+async fn lists_changes(backend: Backend) {
+    let created = backend
+        .commands
+        .persist_transaction(
+            "alice",
+            backend.transaction(
+                days_ago(1),
+                "Cafe",
+                &[
+                    ("Expenses:Coffee", "USD", 450),
+                    ("Assets:Cash", "USD", -450),
+                ],
+            ),
+        )
+        .await
+        .unwrap();
+    backend
+        .commands
+        .update_transaction(
+            "alice",
+            created.id,
+            backend.transaction(
+                days_ago(1),
+                "Bakery",
+                &[("Expenses:Food", "USD", 300), ("Assets:Cash", "USD", -300)],
+            ),
+            None,
+        )
+        .await
+        .unwrap();
+    backend
+        .commands
+        .delete_transaction(backend.book_id, "alice", created.id)
+        .await
+        .unwrap();
+
+    let feed = backend
+        .queries
+        .list_changes(backend.book_id, None)
+        .await
+        .unwrap();
+    let changes = feed
+        .changes
+        .iter()
+        .map(|change| (change.transaction_id, change.version, change.action))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        changes,
+        vec![
+            (created.id, 1, ChangeAction::Created),
+            (created.id, 2, ChangeAction::Updated),
+            (created.id, 3, ChangeAction::Deleted),
+        ]
+    );
+    assert_eq!(feed.changes[1].snapshot.as_ref().unwrap().payee, "Bakery");
+    assert!(feed.changes[2].snapshot.is_none());
+    assert!(!feed.has_more);
+
+    let caught_up = backend
+        .queries
+        .list_changes(backend.book_id, Some(feed.next))
+        .await
+        .unwrap();
+    assert!(caught_up.changes.is_empty());
+    assert_eq!(caught_up.next, feed.next);
+
+    backend.clean_up().await;
+}
+
+async fn restores_versions(backend: Backend) {
+    let created = backend
+        .commands
+        .persist_transaction(
+            "alice",
+            backend.transaction(
+                days_ago(1),
+                "Cafe",
+                &[
+                    ("Expenses:Coffee", "USD", 450),
+                    ("Assets:Cash", "USD", -450),
+                ],
+            ),
+        )
+        .await
+        .unwrap();
+    backend
+        .commands
+        .update_transaction(
+            "alice",
+            created.id,
+            backend.transaction(
+                days_ago(2),
+                "Bakery",
+                &[("Expenses:Food", "USD", 300), ("Assets:Cash", "USD", -300)],
+            ),
+            None,
+        )
+        .await
+        .unwrap();
+
+    let restored = backend
+        .commands
+        .restore_transaction_version(backend.book_id, "bob", created.id, 1)
+        .await
+        .unwrap();
+    assert_eq!(restored.id, created.id);
+    assert_eq!(restored.payee, "Cafe");
+    assert_eq!(restored.date, created.date);
+    assert_eq!(restored.entries, created.entries);
+
+    let history = backend
+        .queries
+        .get_transaction_history(backend.book_id, created.id)
+        .await
+        .unwrap();
+    assert_eq!(history.len(), 3);
+    assert_eq!(history[2].action, ChangeAction::Restored);
+    assert_eq!(history[2].snapshot, history[0].snapshot);
+
+    backend.clean_up().await;
+}
+
+async fn reports_balances(backend: Backend) {
+    let older = days_ago(40);
+    let recent = days_ago(2);
+    let excluded = backend
+        .commands
+        .persist_transaction(
+            "alice",
+            backend.transaction(
+                older,
+                "Employer",
+                &[
+                    ("Assets:Cash", "USD", 10000),
+                    ("Income:Salary", "USD", -10000),
+                ],
+            ),
+        )
+        .await
+        .unwrap();
+    backend
+        .commands
+        .persist_transaction(
+            "alice",
+            backend.transaction(
+                recent,
+                "Grocer",
+                &[
+                    ("Expenses:Food", "USD", 2500),
+                    ("Assets:Cash", "USD", -2500),
+                ],
+            ),
+        )
+        .await
+        .unwrap();
+
+    let balances = backend
+        .accounts
+        .get_period_balances(backend.book_id, &["Assets", "Income"], older, recent, None)
+        .await
+        .unwrap();
+    assert_eq!(
+        balances,
+        vec![
+            AccountBalance {
+                account: "Assets:Cash".to_owned(),
+                currency: "USD".to_owned(),
+                amount: 7500,
+            },
+            AccountBalance {
+                account: "Income:Salary".to_owned(),
+                currency: "USD".to_owned(),
+                amount: -10000,
+            },
+        ]
+    );
+
+    let balances = backend
+        .accounts
+        .get_period_balances(
+            backend.book_id,
+            &["Assets"],
+            older,
+            recent,
+            Some(excluded.id),
+        )
+        .await
+        .unwrap();
+    assert_eq!(balances[0].amount, -2500);
+
+    let popular = backend
+        .accounts
+        .list_accounts_by_popularity(backend.book_id, None)
+        .await
+        .unwrap();
+    assert_eq!(popular.len(), 3);
+    assert_eq!(popular[0], "Assets:Cash");
+
+    let searched = backend
+        .accounts
+        .list_accounts_by_popularity(backend.book_id, Some("FOOD".to_owned()))
+        .await
+        .unwrap();
+    assert_eq!(searched, vec!["Expenses:Food".to_owned()]);
+
+    let mut active = backend
+        .accounts
+        .list_active_accounts(backend.book_id)
+        .await
+        .unwrap();
+    active.sort();
+    assert_eq!(
+        active,
+        vec!["Assets:Cash", "Expenses:Food", "Income:Salary"]
+    );
+
+    let mut want_monthly: BTreeMap<NaiveDate, i32> = BTreeMap::new();
+    for (date, amount) in [(older, 10000), (recent, -2500)] {
+        *want_monthly.entry(date.with_day(1).unwrap()).or_default() += amount;
+    }
+    let monthly = backend
+        .accounts
+        .get_monthly_balance(backend.book_id, "Assets")
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|(month, amounts)| (month, amounts[0].value()))
+        .collect::<BTreeMap<_, _>>();
+    assert_eq!(monthly, want_monthly);
+
+    let cumulative = backend
+        .accounts
+        .periodic_cumulative_balance(backend.book_id, "Assets:Cash", ReportInterval::Daily)
+        .await
+        .unwrap();
+    let usd = cumulative["USD"]
+        .balances()
+        .iter()
+        .map(|balance| (balance.instant(), balance.amount()))
+        .collect::<Vec<_>>();
+    assert_eq!(usd, vec![(older, 10000), (recent, 7500)]);
+
+    let batched = backend
+        .accounts
+        .periodic_cumulative_balances(
+            backend.book_id,
+            &["Assets:Cash".to_owned(), "Liabilities".to_owned()],
+            ReportInterval::Daily,
+        )
+        .await
+        .unwrap();
+    assert_eq!(batched.len(), 1);
+    let usd = batched["Assets:Cash"]["USD"]
+        .balances()
+        .iter()
+        .map(|balance| (balance.instant(), balance.amount()))
+        .collect::<Vec<_>>();
+    assert_eq!(usd, vec![(older, 10000), (recent, 7500)]);
+
+    backend.clean_up().await;
+}
+
+async fn finds_currencies_by_code(backend: Backend) {
+    let currencies = backend
+        .currencies
+        .get_currencies_by_code(vec!["JPY".to_owned(), "XYZ".to_owned()])
+        .await
+        .unwrap();
+
+    assert_eq!(currencies.len(), 1);
+    assert_eq!(currencies["JPY"], Currency::new("JPY".to_owned(), 0));
+
+    backend.clean_up().await;
+}
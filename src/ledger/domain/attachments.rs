@@ -0,0 +1,360 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+/// Content types that are accepted for attachments, along with the leading
+/// bytes ("magic numbers") that a file of that type must start with.
+const ALLOWED_CONTENT_TYPES: &[(&str, &[u8])] = &[
+    ("application/pdf", b"%PDF-"),
+    ("image/gif", b"GIF8"),
+    ("image/jpeg", b"\xFF\xD8\xFF"),
+    ("image/png", b"\x89PNG\r\n\x1A\n"),
+    ("image/webp", b"RIFF"),
+];
+
+/// A file attached to a transaction, such as a receipt.
+pub struct Attachment {
+    pub id: Uuid,
+    pub transaction_id: Uuid,
+    pub book_id: Uuid,
+    pub file_name: String,
+    pub content_type: String,
+    pub size: u64,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Attachment {
+    /// Get the key that the attachment's content is stored under.
+    pub fn storage_key(&self) -> String {
+        self.id.to_string()
+    }
+}
+
+/// Limits on the size of attachments.
+#[derive(Clone, Copy, Debug)]
+pub struct AttachmentLimits {
+    /// The maximum size of a single attachment in bytes.
+    pub max_size: u64,
+
+    /// The maximum combined size of all attachments in a single book in
+    /// bytes.
+    pub book_quota: u64,
+}
+
+/// A new attachment that has not been persisted yet.
+#[derive(Debug, PartialEq)]
+pub struct NewAttachment {
+    id: Uuid,
+    transaction_id: Uuid,
+    book_id: Uuid,
+    file_name: String,
+    content_type: String,
+    size: u64,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum NewAttachmentError {
+    /// The attachment has no content.
+    Empty,
+
+    /// The content type is not one of the accepted types. The value is the
+    /// content type that was provided.
+    UnsupportedContentType(String),
+
+    /// The content does not match the provided content type.
+    ContentMismatch(String),
+
+    /// The attachment is larger than the maximum size of a single attachment.
+    TooLarge { size: u64, max_size: u64 },
+
+    /// Storing the attachment would exceed the book's quota.
+    QuotaExceeded { used: u64, size: u64, quota: u64 },
+}
+
+impl NewAttachment {
+    /// Construct a new attachment after validating its content.
+    ///
+    /// # Arguments
+    /// * `book_id` - The ID of the book that the attachment belongs to.
+    /// * `transaction_id` - The ID of the transaction the file is attached to.
+    /// * `file_name` - The name of the attached file.
+    /// * `content_type` - The MIME type of the file as reported by the client.
+    /// * `content` - The content of the file.
+    /// * `limits` - The size limits to enforce.
+    /// * `used` - The number of bytes already used by the book's attachments.
+    ///
+    /// # Returns
+    /// The new attachment if the content is acceptable, or the reason it was
+    /// rejected.
+    pub fn new(
+        book_id: Uuid,
+        transaction_id: Uuid,
+        file_name: &str,
+        content_type: &str,
+        content: &[u8],
+        limits: &AttachmentLimits,
+        used: u64,
+    ) -> Result<Self, NewAttachmentError> {
+        let content_type = normalize_content_type(content_type);
+        let size = content.len() as u64;
+
+        if size == 0 {
+            return Err(NewAttachmentError::Empty);
+        }
+
+        let magic = ALLOWED_CONTENT_TYPES
+            .iter()
+            .find(|(allowed, _)| *allowed == content_type)
+            .map(|(_, magic)| *magic)
+            .ok_or_else(|| NewAttachmentError::UnsupportedContentType(content_type.clone()))?;
+
+        if !content.starts_with(magic)
+            || (content_type == "image/webp" && content.get(8..12) != Some(b"WEBP"))
+        {
+            return Err(NewAttachmentError::ContentMismatch(content_type));
+        }
+
+        if size > limits.max_size {
+            return Err(NewAttachmentError::TooLarge {
+                size,
+                max_size: limits.max_size,
+            });
+        }
+
+        if used.saturating_add(size) > limits.book_quota {
+            return Err(NewAttachmentError::QuotaExceeded {
+                used,
+                size,
+                quota: limits.book_quota,
+            });
+        }
+
+        Ok(Self {
+            id: Uuid::new_v4(),
+            transaction_id,
+            book_id,
+            file_name: sanitize_file_name(file_name),
+            content_type,
+            size,
+        })
+    }
+
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+
+    pub fn transaction_id(&self) -> Uuid {
+        self.transaction_id
+    }
+
+    pub fn book_id(&self) -> Uuid {
+        self.book_id
+    }
+
+    pub fn file_name(&self) -> &str {
+        &self.file_name
+    }
+
+    pub fn content_type(&self) -> &str {
+        &self.content_type
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Get the key that the attachment's content is stored under.
+    pub fn storage_key(&self) -> String {
+        self.id.to_string()
+    }
+}
+
+/// Strip any parameters (such as a charset) from a content type and normalize
+/// its case.
+fn normalize_content_type(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
+
+/// Reduce a client-provided file name to its final path component with any
+/// control characters or quotes removed.
+fn sanitize_file_name(file_name: &str) -> String {
+    let base_name = file_name
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_control() && *c != '"')
+        .collect::<String>();
+    let trimmed = base_name.trim();
+
+    if trimmed.is_empty() {
+        "attachment".to_owned()
+    } else {
+        trimmed.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const LIMITS: AttachmentLimits = AttachmentLimits {
+        max_size: 64,
+        book_quota: 128,
+    };
+
+    fn new_attachment(
+        file_name: &str,
+        content_type: &str,
+        content: &[u8],
+        used: u64,
+    ) -> Result<NewAttachment, NewAttachmentError> {
+        NewAttachment::new(
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            file_name,
+            content_type,
+            content,
+            &LIMITS,
+            used,
+        )
+    }
+
+    #[test]
+    fn new_attachment_valid_pdf() {
+        let attachment = new_attachment(
+            "receipt.pdf",
+            "application/pdf",
+            b"%PDF-1.7 receipt contents",
+            0,
+        )
+        .expect("should be valid");
+
+        assert_eq!("receipt.pdf", attachment.file_name());
+        assert_eq!("application/pdf", attachment.content_type());
+        assert_eq!(25, attachment.size());
+    }
+
+    #[test]
+    fn new_attachment_normalizes_content_type() {
+        let attachment = new_attachment(
+            "photo.png",
+            "Image/PNG; charset=binary",
+            b"\x89PNG\r\n\x1A\n",
+            0,
+        )
+        .expect("should be valid");
+
+        assert_eq!("image/png", attachment.content_type());
+    }
+
+    #[test]
+    fn new_attachment_webp() {
+        new_attachment(
+            "photo.webp",
+            "image/webp",
+            b"RIFF\x00\x00\x00\x00WEBPVP8 ",
+            0,
+        )
+        .expect("should be valid");
+
+        let error = new_attachment(
+            "audio.wav",
+            "image/webp",
+            b"RIFF\x00\x00\x00\x00WAVEfmt ",
+            0,
+        )
+        .expect_err("WAV files are not WEBP images");
+
+        assert_eq!(
+            NewAttachmentError::ContentMismatch("image/webp".to_owned()),
+            error
+        );
+    }
+
+    #[test]
+    fn new_attachment_empty() {
+        let error =
+            new_attachment("receipt.pdf", "application/pdf", b"", 0).expect_err("empty content");
+
+        assert_eq!(NewAttachmentError::Empty, error);
+    }
+
+    #[test]
+    fn new_attachment_unsupported_content_type() {
+        let error = new_attachment("script.sh", "text/x-shellscript", b"#!/bin/sh", 0)
+            .expect_err("scripts are not allowed");
+
+        assert_eq!(
+            NewAttachmentError::UnsupportedContentType("text/x-shellscript".to_owned()),
+            error
+        );
+    }
+
+    #[test]
+    fn new_attachment_content_mismatch() {
+        let error = new_attachment("receipt.pdf", "application/pdf", b"<html></html>", 0)
+            .expect_err("content is not a PDF");
+
+        assert_eq!(
+            NewAttachmentError::ContentMismatch("application/pdf".to_owned()),
+            error
+        );
+    }
+
+    #[test]
+    fn new_attachment_too_large() {
+        let mut content = b"%PDF-".to_vec();
+        content.resize(65, b' ');
+
+        let error = new_attachment("receipt.pdf", "application/pdf", &content, 0)
+            .expect_err("content exceeds maximum size");
+
+        assert_eq!(
+            NewAttachmentError::TooLarge {
+                size: 65,
+                max_size: 64
+            },
+            error
+        );
+    }
+
+    #[test]
+    fn new_attachment_quota_exceeded() {
+        let content = b"%PDF-1.7 receipt contents";
+
+        let error = new_attachment("receipt.pdf", "application/pdf", content, 120)
+            .expect_err("content exceeds quota");
+
+        assert_eq!(
+            NewAttachmentError::QuotaExceeded {
+                used: 120,
+                size: 25,
+                quota: 128
+            },
+            error
+        );
+    }
+
+    #[test]
+    fn new_attachment_sanitizes_file_name() {
+        let cases = [
+            ("../../etc/passwd.pdf", "passwd.pdf"),
+            ("C:\\Users\\me\\receipt.pdf", "receipt.pdf"),
+            ("re\"ceipt\n.pdf", "receipt.pdf"),
+            ("   ", "attachment"),
+            ("folder/", "attachment"),
+        ];
+
+        for (file_name, want) in cases {
+            let attachment =
+                new_attachment(file_name, "application/pdf", b"%PDF-", 0).expect("should be valid");
+
+            assert_eq!(want, attachment.file_name(), "sanitizing {:?}", file_name);
+        }
+    }
+}
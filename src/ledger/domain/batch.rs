@@ -0,0 +1,205 @@
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::{ValidationError, ValidationErrors, ValidationErrorsKind};
+
+use super::transactions::{NewTransaction, NewTransactionData};
+
+/// The maximum number of operations in a single batch.
+pub const MAX_BATCH_SIZE: usize = 100;
+
+/// A batch of changes provided by a user.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct BatchData {
+    pub operations: Vec<BatchOperationData>,
+}
+
+impl BatchData {
+    /// Use a currency for every amount in the batch that doesn't name one.
+    pub fn apply_default_currency(&mut self, currency: &str) {
+        for operation in &mut self.operations {
+            match operation {
+                BatchOperationData::Create { transaction }
+                | BatchOperationData::Update { transaction, .. } => {
+                    transaction.apply_default_currency(currency)
+                }
+                BatchOperationData::Delete { .. } => (),
+            }
+        }
+    }
+
+    /// Determine if every operation in the batch creates a new transaction.
+    pub fn only_creates(&self) -> bool {
+        self.operations
+            .iter()
+            .all(|operation| matches!(operation, BatchOperationData::Create { .. }))
+    }
+}
+
+/// A single change within a batch provided by a user.
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase", tag = "op")]
+pub enum BatchOperationData {
+    Create {
+        transaction: NewTransactionData,
+    },
+    Update {
+        id: Uuid,
+        transaction: NewTransactionData,
+    },
+    Delete {
+        id: Uuid,
+    },
+}
+
+/// A validated change within a batch.
+#[derive(Debug, PartialEq)]
+pub enum BatchOperation {
+    Create(NewTransaction),
+    Update {
+        transaction_id: Uuid,
+        transaction: NewTransaction,
+    },
+    Delete {
+        transaction_id: Uuid,
+    },
+}
+
+impl BatchOperation {
+    /// Validate every operation in a batch.
+    ///
+    /// # Arguments
+    /// * `book_id` - The ID of the book that the transactions belong to.
+    /// * `data` - The batch provided by the user.
+    ///
+    /// # Returns
+    /// The validated operations in their original order, or the validation
+    /// errors of every invalid operation keyed by its index under
+    /// `operations`.
+    pub fn from_batch(book_id: Uuid, data: BatchData) -> Result<Vec<Self>, ValidationErrors> {
+        // The length is checked by hand since the `length` validator would
+        // echo the entire batch back as a parameter of the error.
+        if data.operations.is_empty() || data.operations.len() > MAX_BATCH_SIZE {
+            let mut error = ValidationError::new("length");
+            error.add_param("min".into(), &1);
+            error.add_param("max".into(), &MAX_BATCH_SIZE);
+
+            let mut errors = ValidationErrors::new();
+            errors.add("operations", error);
+
+            return Err(errors);
+        }
+
+        let mut operations = Vec::with_capacity(data.operations.len());
+        let mut operation_errors: BTreeMap<usize, Box<ValidationErrors>> = BTreeMap::new();
+
+        for (index, operation) in data.operations.into_iter().enumerate() {
+            let validated = match operation {
+                BatchOperationData::Create { transaction } => {
+                    NewTransaction::from_data(book_id, transaction).map(Self::Create)
+                }
+                BatchOperationData::Update { id, transaction } => {
+                    NewTransaction::from_data(book_id, transaction).map(|transaction| {
+                        Self::Update {
+                            transaction_id: id,
+                            transaction,
+                        }
+                    })
+                }
+                BatchOperationData::Delete { id } => Ok(Self::Delete { transaction_id: id }),
+            };
+
+            match validated {
+                Ok(operation) => operations.push(operation),
+                Err(errors) => {
+                    operation_errors.insert(index, Box::new(errors));
+                }
+            }
+        }
+
+        if operation_errors.is_empty() {
+            Ok(operations)
+        } else {
+            let mut errors = ValidationErrors::new();
+            errors
+                .errors_mut()
+                .insert("operations", ValidationErrorsKind::List(operation_errors));
+
+            Err(errors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    fn transaction_json(payee: &str) -> serde_json::Value {
+        json!({
+            "date": "2023-04-15",
+            "payee": payee,
+            "entries": [
+                {"account": "Expenses:Gas", "amount": {"currency": "USD", "value": 100}},
+                {"account": "Assets:Checking"}
+            ]
+        })
+    }
+
+    fn batch(operations: serde_json::Value) -> BatchData {
+        serde_json::from_value(json!({ "operations": operations })).expect("should deserialize")
+    }
+
+    #[test]
+    fn from_batch_valid_operations() {
+        let id = Uuid::new_v4();
+        let data = batch(json!([
+            {"op": "create", "transaction": transaction_json("Gas")},
+            {"op": "update", "id": id, "transaction": transaction_json("Gas Station")},
+            {"op": "delete", "id": id},
+        ]));
+
+        let operations = BatchOperation::from_batch(Uuid::new_v4(), data).expect("should be valid");
+
+        assert_eq!(3, operations.len());
+        assert!(matches!(&operations[0], BatchOperation::Create(t) if t.payee() == "Gas"));
+        assert!(matches!(
+            &operations[1],
+            BatchOperation::Update { transaction_id, transaction }
+                if *transaction_id == id && transaction.payee() == "Gas Station"
+        ));
+        assert_eq!(BatchOperation::Delete { transaction_id: id }, operations[2]);
+    }
+
+    #[test]
+    fn from_batch_collects_errors_by_index() {
+        let data = batch(json!([
+            {"op": "create", "transaction": transaction_json("Gas")},
+            {"op": "create", "transaction": transaction_json("")},
+            {"op": "delete", "id": Uuid::new_v4()},
+            {"op": "update", "id": Uuid::new_v4(), "transaction": transaction_json("")},
+        ]));
+
+        let error =
+            BatchOperation::from_batch(Uuid::new_v4(), data).expect_err("should be invalid");
+
+        let operation_errors = match error.errors().get("operations") {
+            Some(ValidationErrorsKind::List(errors)) => errors,
+            other => panic!("Expected list of errors, got {:?}", other),
+        };
+
+        assert_eq!(vec![&1, &3], operation_errors.keys().collect::<Vec<_>>());
+        assert!(operation_errors[&1].field_errors().contains_key("payee"));
+    }
+
+    #[test]
+    fn from_batch_empty() {
+        let error = BatchOperation::from_batch(Uuid::new_v4(), batch(json!([])))
+            .expect_err("should be invalid");
+
+        assert_eq!("length", error.field_errors()["operations"][0].code);
+    }
+}
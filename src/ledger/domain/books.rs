@@ -0,0 +1,307 @@
+use std::{fmt::Display, str::FromStr};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::Validate;
+
+/// How long an invitation can be accepted for after it is created.
+pub const INVITATION_TTL_DAYS: i64 = 7;
+
+/// A set of accounts and transactions kept by one or more users.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Book {
+    pub id: Uuid,
+    pub name: String,
+    /// Whether this is the user's personal book. Requests that don't name a
+    /// book use the user's personal book.
+    pub personal: bool,
+    /// The currency used for amounts that don't name one.
+    pub default_currency: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// The name and settings of a book provided by one of its owners.
+#[derive(Debug, Deserialize, Serialize, ToSchema, Validate)]
+pub struct BookData {
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+
+    /// The code of the currency used for amounts that don't name one.
+    #[validate(length(equal = 3))]
+    pub default_currency: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum SaveBookError {
+    /// The book's default currency doesn't exist. The value is the currency's
+    /// code.
+    UnknownCurrency(String),
+
+    Unknown(anyhow::Error),
+}
+
+#[derive(Debug)]
+pub enum DeleteBookError {
+    /// Personal books can't be deleted since requests that don't name a book
+    /// use them.
+    PersonalBook,
+
+    Unknown(anyhow::Error),
+}
+
+/// What a member of a book is allowed to do. Each role includes everything
+/// the roles before it are allowed to do.
+#[derive(
+    Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize, ToSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Can read everything in the book.
+    Viewer,
+    /// Can also create, change, and delete transactions and attachments.
+    Editor,
+    /// Can also manage the book's members, invitations, lock date, and
+    /// webhooks.
+    Owner,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct UnknownRole(pub String);
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Viewer => "viewer",
+            Self::Editor => "editor",
+            Self::Owner => "owner",
+        }
+    }
+
+    /// Determine if the role is allowed to do everything `required` is.
+    pub fn includes(&self, required: Role) -> bool {
+        *self >= required
+    }
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = UnknownRole;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "viewer" => Ok(Self::Viewer),
+            "editor" => Ok(Self::Editor),
+            "owner" => Ok(Self::Owner),
+            other => Err(UnknownRole(other.to_owned())),
+        }
+    }
+}
+
+/// A book along with the role a user has in it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Membership {
+    pub book: Book,
+    pub role: Role,
+}
+
+/// A user who is a member of a book.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Member {
+    pub user_id: String,
+    pub role: Role,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub enum MemberChangeError {
+    /// The user is not a member of the book.
+    MemberNotFound,
+
+    /// The change would leave the book without an owner.
+    LastOwner,
+
+    /// The user owns the book as their personal book, so they can't leave it
+    /// or be demoted.
+    PersonalBookOwner,
+
+    Unknown(anyhow::Error),
+}
+
+/// An invitation to join a book.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Invitation {
+    pub id: Uuid,
+    pub book_id: Uuid,
+    /// The role the user accepting the invitation receives.
+    pub role: Role,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub enum AcceptInvitationError {
+    /// There is no pending invitation with the token. Invitations that have
+    /// expired or were already accepted are treated the same way.
+    InvitationNotFound,
+
+    /// The user is already a member of the book.
+    AlreadyMember,
+
+    Unknown(anyhow::Error),
+}
+
+/// Data for a new invitation provided by an owner of the book.
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct NewInvitationData {
+    pub role: Role,
+}
+
+/// A new invitation that has not been persisted yet.
+///
+/// The token is only available until the invitation is persisted. Afterwards,
+/// only its hash is known.
+#[derive(Debug)]
+pub struct NewInvitation {
+    book_id: Uuid,
+    role: Role,
+    created_by: String,
+    token: String,
+    expires_at: DateTime<Utc>,
+}
+
+impl NewInvitation {
+    /// Create an invitation with a newly generated token.
+    ///
+    /// # Arguments
+    /// * `book_id` - The ID of the book the invitation is for.
+    /// * `created_by` - The ID of the user creating the invitation.
+    /// * `data` - The invitation information provided by the user.
+    pub fn new<S: Into<String>>(book_id: Uuid, created_by: S, data: NewInvitationData) -> Self {
+        Self {
+            book_id,
+            role: data.role,
+            created_by: created_by.into(),
+            token: generate_token(),
+            expires_at: Utc::now() + Duration::days(INVITATION_TTL_DAYS),
+        }
+    }
+
+    pub fn book_id(&self) -> Uuid {
+        self.book_id
+    }
+
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    pub fn created_by(&self) -> &str {
+        &self.created_by
+    }
+
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    pub fn token_hash(&self) -> String {
+        hash_token(&self.token)
+    }
+
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        self.expires_at
+    }
+}
+
+/// Generate a token that is hard to guess for accepting an invitation.
+pub fn generate_token() -> String {
+    format!(
+        "zbinv_{}{}",
+        Uuid::new_v4().simple(),
+        Uuid::new_v4().simple()
+    )
+}
+
+/// Hash an invitation token so it can be looked up without storing the token
+/// itself.
+pub fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn role_round_trip() {
+        for role in [Role::Viewer, Role::Editor, Role::Owner] {
+            assert_eq!(Ok(role), role.as_str().parse());
+            assert_eq!(
+                serde_json::json!(role.as_str()),
+                serde_json::to_value(role).unwrap()
+            );
+        }
+
+        assert_eq!(
+            Err(UnknownRole("admin".to_owned())),
+            "admin".parse::<Role>()
+        );
+    }
+
+    #[test]
+    fn role_includes_lower_roles() {
+        assert!(Role::Owner.includes(Role::Editor));
+        assert!(Role::Editor.includes(Role::Viewer));
+        assert!(Role::Editor.includes(Role::Editor));
+        assert!(!Role::Viewer.includes(Role::Editor));
+        assert!(!Role::Editor.includes(Role::Owner));
+    }
+
+    #[test]
+    fn validate_book_data() {
+        let valid = BookData {
+            name: "Business".to_owned(),
+            default_currency: Some("EUR".to_owned()),
+        };
+        assert!(valid.validate().is_ok());
+
+        let invalid = BookData {
+            name: "".to_owned(),
+            default_currency: Some("EURO".to_owned()),
+        };
+        let errors = invalid.validate().expect_err("invalid book data");
+        let field_errors = errors.field_errors();
+
+        assert_eq!("length", field_errors["name"][0].code);
+        assert_eq!("length", field_errors["default_currency"][0].code);
+    }
+
+    #[test]
+    fn new_invitation_token_is_hashed() {
+        let invitation = NewInvitation::new(
+            Uuid::new_v4(),
+            "user-id",
+            NewInvitationData { role: Role::Editor },
+        );
+
+        assert!(invitation.token().starts_with("zbinv_"));
+        assert_eq!(64, invitation.token_hash().len());
+        assert_eq!(hash_token(invitation.token()), invitation.token_hash());
+        assert_ne!(
+            invitation.token_hash(),
+            NewInvitation::new(
+                invitation.book_id(),
+                "user-id",
+                NewInvitationData { role: Role::Editor }
+            )
+            .token_hash()
+        );
+    }
+}
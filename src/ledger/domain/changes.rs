@@ -0,0 +1,107 @@
+use std::{fmt::Display, str::FromStr};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::history::{ChangeAction, TransactionSnapshot};
+
+/// A position in a user's feed of transaction changes.
+///
+/// Changes are ordered by the database transaction that recorded them and
+/// then by their ID, so the token holds both values for the last change a
+/// client has seen.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ChangeToken {
+    pub after_xid: i64,
+    pub after_id: i64,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct InvalidChangeToken;
+
+impl Display for ChangeToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.after_xid, self.after_id)
+    }
+}
+
+impl FromStr for ChangeToken {
+    type Err = InvalidChangeToken;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (xid, id) = s.split_once('/').ok_or(InvalidChangeToken)?;
+
+        Ok(Self {
+            after_xid: xid.parse().map_err(|_| InvalidChangeToken)?,
+            after_id: id.parse().map_err(|_| InvalidChangeToken)?,
+        })
+    }
+}
+
+/// A single change to one of a user's transactions.
+pub struct TransactionChange {
+    pub transaction_id: Uuid,
+    pub version: i32,
+    pub action: ChangeAction,
+    pub recorded_at: DateTime<Utc>,
+    /// The state of the transaction after the change. This is absent if the
+    /// transaction was deleted.
+    pub snapshot: Option<TransactionSnapshot>,
+}
+
+/// A page of changes from a user's feed.
+pub struct ChangeFeed {
+    /// The changes ordered from oldest to newest.
+    pub changes: Vec<TransactionChange>,
+    /// The token to request the following changes with.
+    pub next: ChangeToken,
+    /// Whether more changes are already available after `next`.
+    pub has_more: bool,
+}
+
+/// A notice that one of a book's transactions changed, pushed to the
+/// connected clients of the book's members.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ChangeNotification {
+    pub book_id: Uuid,
+    pub transaction_id: Uuid,
+    pub action: ChangeAction,
+    pub version: i32,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn change_token_round_trip() {
+        let token = ChangeToken {
+            after_xid: 8231,
+            after_id: 42,
+        };
+
+        assert_eq!(Ok(token), token.to_string().parse());
+    }
+
+    #[test]
+    fn change_notification_round_trip() {
+        let notification = ChangeNotification {
+            book_id: Uuid::new_v4(),
+            transaction_id: Uuid::new_v4(),
+            action: ChangeAction::Deleted,
+            version: 3,
+        };
+
+        let encoded = serde_json::to_value(&notification).unwrap();
+
+        assert_eq!("deleted", encoded["action"]);
+        assert_eq!(notification, serde_json::from_value(encoded).unwrap());
+    }
+
+    #[test]
+    fn change_token_invalid() {
+        assert_eq!(Err(InvalidChangeToken), "8231".parse::<ChangeToken>());
+        assert_eq!(Err(InvalidChangeToken), "8231/abc".parse::<ChangeToken>());
+    }
+}
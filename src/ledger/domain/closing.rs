@@ -0,0 +1,247 @@
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+use uuid::Uuid;
+use validator::ValidationErrors;
+
+use super::transactions::{
+    NewTransaction, NewTransactionData, NewTransactionEntryAmountData, NewTransactionEntryData,
+};
+
+/// The account that income and expenses are closed into by default.
+pub const DEFAULT_EQUITY_ACCOUNT: &str = "Equity:Retained Earnings";
+
+/// The root accounts whose subtrees are zeroed out when closing a period.
+pub const CLOSED_ACCOUNT_ROOTS: &[&str] = &["Income", "Expenses"];
+
+/// The balance of a single account in a single currency.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AccountBalance {
+    pub account: String,
+    pub currency: String,
+    pub amount: i64,
+}
+
+/// A period whose income and expenses have been closed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PeriodClosing {
+    pub period_start: NaiveDate,
+    pub period_end: NaiveDate,
+    pub equity_account: String,
+    /// The ID of the closing transaction, if there was anything to close.
+    pub transaction_id: Option<Uuid>,
+}
+
+#[derive(Debug)]
+pub enum ClosingError {
+    /// A closing amount does not fit in a single transaction entry.
+    AmountOutOfRange { account: String, currency: String },
+
+    /// The generated closing transaction failed validation, eg. because the
+    /// equity account name is empty.
+    Invalid(ValidationErrors),
+}
+
+/// Build the transaction that closes a period's income and expense balances
+/// into an equity account.
+///
+/// Every non-zero balance is reversed, and the equity account receives the
+/// net amount for each currency, so the transaction is balanced per currency.
+///
+/// # Arguments
+/// * `book_id` - The ID of the book being closed.
+/// * `period_end` - The last day of the period, used as the transaction date.
+/// * `equity_account` - The account that balances are closed into.
+/// * `balances` - The balances of the accounts to close.
+///
+/// # Returns
+/// The closing transaction, or `None` if there is nothing to close.
+pub fn closing_transaction(
+    book_id: Uuid,
+    period_end: NaiveDate,
+    equity_account: &str,
+    balances: &[AccountBalance],
+) -> Result<Option<NewTransaction>, ClosingError> {
+    let mut entries = vec![];
+    let mut equity_totals: BTreeMap<&str, i64> = BTreeMap::new();
+
+    for balance in balances.iter().filter(|balance| balance.amount != 0) {
+        entries.push(entry(&balance.account, &balance.currency, -balance.amount)?);
+
+        *equity_totals.entry(&balance.currency).or_insert(0) += balance.amount;
+    }
+
+    if entries.is_empty() {
+        return Ok(None);
+    }
+
+    for (currency, total) in equity_totals {
+        if total != 0 {
+            entries.push(entry(equity_account, currency, total)?);
+        }
+    }
+
+    let data = NewTransactionData {
+        date: period_end,
+        payee: format!("Closing entries for period ending {}", period_end),
+        notes: None,
+        entries,
+    };
+
+    NewTransaction::from_data(book_id, data)
+        .map(Some)
+        .map_err(ClosingError::Invalid)
+}
+
+fn entry(
+    account: &str,
+    currency: &str,
+    amount: i64,
+) -> Result<NewTransactionEntryData, ClosingError> {
+    let value = i32::try_from(amount).map_err(|_| ClosingError::AmountOutOfRange {
+        account: account.to_owned(),
+        currency: currency.to_owned(),
+    })?;
+
+    Ok(NewTransactionEntryData {
+        account: account.to_owned(),
+        amount: Some(NewTransactionEntryAmountData {
+            currency: Some(currency.to_owned()),
+            value,
+        }),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn balance(account: &str, currency: &str, amount: i64) -> AccountBalance {
+        AccountBalance {
+            account: account.to_owned(),
+            currency: currency.to_owned(),
+            amount,
+        }
+    }
+
+    fn year_end() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2022, 12, 31).unwrap()
+    }
+
+    fn entry_values(transaction: &NewTransaction) -> Vec<(&str, &str, i32)> {
+        transaction
+            .entries()
+            .iter()
+            .map(|entry| {
+                (
+                    entry.account(),
+                    entry.amount().currency(),
+                    entry.amount().value(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn closing_transaction_nothing_to_close() {
+        let transaction = closing_transaction(
+            Uuid::new_v4(),
+            year_end(),
+            DEFAULT_EQUITY_ACCOUNT,
+            &[balance("Expenses:Food", "USD", 0)],
+        )
+        .expect("should be valid");
+
+        assert!(transaction.is_none());
+    }
+
+    #[test]
+    fn closing_transaction_single_currency() {
+        let transaction = closing_transaction(
+            Uuid::new_v4(),
+            year_end(),
+            DEFAULT_EQUITY_ACCOUNT,
+            &[
+                balance("Expenses:Food", "USD", 300),
+                balance("Expenses:Gas", "USD", 200),
+                balance("Income:Salary", "USD", -1000),
+            ],
+        )
+        .expect("should be valid")
+        .expect("should have entries");
+
+        assert_eq!(year_end(), transaction.date());
+        assert_eq!(
+            vec![
+                ("Expenses:Food", "USD", -300),
+                ("Expenses:Gas", "USD", -200),
+                ("Income:Salary", "USD", 1000),
+                (DEFAULT_EQUITY_ACCOUNT, "USD", -500),
+            ],
+            entry_values(&transaction)
+        );
+    }
+
+    #[test]
+    fn closing_transaction_multiple_currencies() {
+        let transaction = closing_transaction(
+            Uuid::new_v4(),
+            year_end(),
+            "Equity:Closing",
+            &[
+                balance("Expenses:Travel", "USD", 100),
+                balance("Expenses:Travel", "EUR", 50),
+                balance("Income:Refunds", "EUR", -50),
+            ],
+        )
+        .expect("should be valid")
+        .expect("should have entries");
+
+        // The EUR balances cancel out, so the equity account only receives
+        // the USD total.
+        assert_eq!(
+            vec![
+                ("Expenses:Travel", "USD", -100),
+                ("Expenses:Travel", "EUR", -50),
+                ("Income:Refunds", "EUR", 50),
+                ("Equity:Closing", "USD", 100),
+            ],
+            entry_values(&transaction)
+        );
+    }
+
+    #[test]
+    fn closing_transaction_amount_out_of_range() {
+        let error = closing_transaction(
+            Uuid::new_v4(),
+            year_end(),
+            DEFAULT_EQUITY_ACCOUNT,
+            &[
+                balance("Expenses:Rent", "USD", i64::from(i32::MAX)),
+                balance("Expenses:Food", "USD", 1),
+            ],
+        )
+        .expect_err("equity total does not fit in an entry");
+
+        match error {
+            ClosingError::AmountOutOfRange { account, currency } => {
+                assert_eq!(DEFAULT_EQUITY_ACCOUNT, account);
+                assert_eq!("USD", currency);
+            }
+            other => panic!("Expected out of range error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn closing_transaction_empty_equity_account() {
+        let error = closing_transaction(
+            Uuid::new_v4(),
+            year_end(),
+            "",
+            &[balance("Expenses:Food", "USD", 100)],
+        )
+        .expect_err("equity account is required");
+
+        assert!(matches!(error, ClosingError::Invalid(_)));
+    }
+}
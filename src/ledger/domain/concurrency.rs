@@ -0,0 +1,73 @@
+/// A precondition from an `If-Match` header.
+#[derive(Debug, Eq, PartialEq)]
+pub enum Precondition {
+    /// The request applies to any current version of the resource.
+    Any,
+    /// The request only applies if the resource is still at this revision.
+    Revision(i64),
+}
+
+impl Precondition {
+    /// Get the revision the resource is expected to be at, if any.
+    pub fn expected_revision(&self) -> Option<i64> {
+        match self {
+            Self::Any => None,
+            Self::Revision(revision) => Some(*revision),
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct InvalidEntityTag;
+
+/// Build the entity tag for a revision of a resource.
+pub fn entity_tag(revision: i64) -> String {
+    format!("\"{}\"", revision)
+}
+
+/// Parse the value of an `If-Match` header.
+///
+/// Only a single entity tag, as produced by [`entity_tag`], or `*` are
+/// accepted. Weak tags are rejected since `If-Match` requires a strong
+/// comparison.
+pub fn parse_if_match(value: &str) -> Result<Precondition, InvalidEntityTag> {
+    let value = value.trim();
+    if value == "*" {
+        return Ok(Precondition::Any);
+    }
+
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .and_then(|revision| revision.parse().ok())
+        .map(Precondition::Revision)
+        .ok_or(InvalidEntityTag)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn entity_tag_round_trip() {
+        let tag = entity_tag(1684585136123456);
+
+        assert_eq!(
+            Ok(Precondition::Revision(1684585136123456)),
+            parse_if_match(&tag)
+        );
+    }
+
+    #[test]
+    fn parse_if_match_wildcard() {
+        assert_eq!(Ok(Precondition::Any), parse_if_match(" * "));
+    }
+
+    #[test]
+    fn parse_if_match_invalid() {
+        assert_eq!(Err(InvalidEntityTag), parse_if_match("42"));
+        assert_eq!(Err(InvalidEntityTag), parse_if_match("W/\"42\""));
+        assert_eq!(Err(InvalidEntityTag), parse_if_match("\"abc\""));
+        assert_eq!(Err(InvalidEntityTag), parse_if_match("\"1\", \"2\""));
+    }
+}
@@ -144,6 +144,11 @@ impl CurrencyAmount {
     }
 }
 
+/// The error returned when a change references a currency that doesn't exist.
+/// The value is the currency's code.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UnknownCurrency(pub String);
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -0,0 +1,336 @@
+use std::{fmt::Display, str::FromStr};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::ValidationErrors;
+
+use super::transactions::{
+    NewTransaction, NewTransactionData, NewTransactionEntryAmountData, NewTransactionEntryData,
+    Transaction,
+};
+
+/// The kind of change that produced a transaction version.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeAction {
+    Created,
+    Updated,
+    Deleted,
+    Restored,
+}
+
+impl ChangeAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Created => "created",
+            Self::Updated => "updated",
+            Self::Deleted => "deleted",
+            Self::Restored => "restored",
+        }
+    }
+}
+
+impl Display for ChangeAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ChangeAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "created" => Ok(Self::Created),
+            "updated" => Ok(Self::Updated),
+            "deleted" => Ok(Self::Deleted),
+            "restored" => Ok(Self::Restored),
+            other => Err(anyhow::anyhow!("unknown change action: {:?}", other)),
+        }
+    }
+}
+
+/// The state of a transaction at a point in time.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct TransactionSnapshot {
+    pub date: NaiveDate,
+    pub payee: String,
+    pub notes: String,
+    pub entries: Vec<SnapshotEntry>,
+}
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct SnapshotEntry {
+    pub account: String,
+    pub currency: String,
+    pub amount: i32,
+}
+
+/// A recorded version of a transaction.
+pub struct TransactionVersion {
+    pub transaction_id: Uuid,
+    pub version: i32,
+    pub action: ChangeAction,
+    /// The ID of the user who made the change.
+    pub actor: String,
+    pub snapshot: TransactionSnapshot,
+    pub recorded_at: DateTime<Utc>,
+}
+
+/// The differences between two snapshots of a transaction.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct TransactionDiff {
+    pub date: Option<ValueChange<NaiveDate>>,
+    pub payee: Option<ValueChange<String>>,
+    pub notes: Option<ValueChange<String>>,
+    /// Entries present in the newer snapshot but not the older one.
+    pub added_entries: Vec<SnapshotEntry>,
+    /// Entries present in the older snapshot but not the newer one.
+    pub removed_entries: Vec<SnapshotEntry>,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub struct ValueChange<T> {
+    pub from: T,
+    pub to: T,
+}
+
+fn value_change<T: Clone + PartialEq>(from: &T, to: &T) -> Option<ValueChange<T>> {
+    if from == to {
+        None
+    } else {
+        Some(ValueChange {
+            from: from.clone(),
+            to: to.clone(),
+        })
+    }
+}
+
+/// Get the entries from `left` that do not have a counterpart in `right`.
+///
+/// Entries are compared as a multiset so that a transaction with two identical
+/// entries differs from one with a single copy of that entry.
+fn entries_difference(left: &[SnapshotEntry], right: &[SnapshotEntry]) -> Vec<SnapshotEntry> {
+    let mut unmatched: Vec<&SnapshotEntry> = right.iter().collect();
+    let mut difference = vec![];
+
+    for entry in left {
+        match unmatched.iter().position(|other| *other == entry) {
+            Some(index) => {
+                unmatched.swap_remove(index);
+            }
+            None => difference.push(entry.clone()),
+        }
+    }
+
+    difference
+}
+
+impl TransactionSnapshot {
+    /// Compute the changes required to go from this snapshot to a newer one.
+    pub fn diff(&self, newer: &Self) -> TransactionDiff {
+        TransactionDiff {
+            date: value_change(&self.date, &newer.date),
+            payee: value_change(&self.payee, &newer.payee),
+            notes: value_change(&self.notes, &newer.notes),
+            added_entries: entries_difference(&newer.entries, &self.entries),
+            removed_entries: entries_difference(&self.entries, &newer.entries),
+        }
+    }
+
+    /// Build a new transaction with the same contents as the snapshot.
+    ///
+    /// The snapshot goes through the same validation as any other new
+    /// transaction.
+    ///
+    /// # Arguments
+    /// * `book_id` - The ID of the book that the transaction belongs to.
+    pub fn to_new_transaction(&self, book_id: Uuid) -> Result<NewTransaction, ValidationErrors> {
+        let data = NewTransactionData {
+            date: self.date,
+            payee: self.payee.clone(),
+            notes: if self.notes.is_empty() {
+                None
+            } else {
+                Some(self.notes.clone())
+            },
+            entries: self
+                .entries
+                .iter()
+                .map(|entry| NewTransactionEntryData {
+                    account: entry.account.clone(),
+                    amount: Some(NewTransactionEntryAmountData {
+                        currency: Some(entry.currency.clone()),
+                        value: entry.amount,
+                    }),
+                })
+                .collect(),
+        };
+
+        NewTransaction::from_data(book_id, data)
+    }
+}
+
+impl From<&Transaction> for TransactionSnapshot {
+    fn from(transaction: &Transaction) -> Self {
+        Self {
+            date: transaction.date,
+            payee: transaction.payee.clone(),
+            notes: transaction.notes.clone(),
+            entries: transaction
+                .entries
+                .iter()
+                .map(|entry| SnapshotEntry {
+                    account: entry.account().to_owned(),
+                    currency: entry.amount().currency().code().to_owned(),
+                    amount: entry.amount().value(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<&NewTransaction> for TransactionSnapshot {
+    fn from(transaction: &NewTransaction) -> Self {
+        Self {
+            date: transaction.date(),
+            payee: transaction.payee().to_owned(),
+            notes: transaction.notes().unwrap_or_default().to_owned(),
+            entries: transaction
+                .entries()
+                .iter()
+                .map(|entry| SnapshotEntry {
+                    account: entry.account().to_owned(),
+                    currency: entry.amount().currency().to_owned(),
+                    amount: entry.amount().value(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(account: &str, amount: i32) -> SnapshotEntry {
+        SnapshotEntry {
+            account: account.to_owned(),
+            currency: "USD".to_owned(),
+            amount,
+        }
+    }
+
+    fn snapshot(payee: &str, entries: Vec<SnapshotEntry>) -> TransactionSnapshot {
+        TransactionSnapshot {
+            date: NaiveDate::from_ymd_opt(2023, 4, 15).unwrap(),
+            payee: payee.to_owned(),
+            notes: "".to_owned(),
+            entries,
+        }
+    }
+
+    #[test]
+    fn diff_identical_snapshots() {
+        let old = snapshot(
+            "Gas",
+            vec![entry("Expenses:Gas", 100), entry("Assets:Checking", -100)],
+        );
+
+        assert_eq!(TransactionDiff::default(), old.diff(&old.clone()));
+    }
+
+    #[test]
+    fn diff_changed_fields() {
+        let old = snapshot("Gas", vec![]);
+        let mut new = snapshot("Gas Station", vec![]);
+        new.date = NaiveDate::from_ymd_opt(2023, 4, 16).unwrap();
+        new.notes = "Filled up".to_owned();
+
+        let diff = old.diff(&new);
+
+        assert_eq!(
+            Some(ValueChange {
+                from: NaiveDate::from_ymd_opt(2023, 4, 15).unwrap(),
+                to: NaiveDate::from_ymd_opt(2023, 4, 16).unwrap(),
+            }),
+            diff.date
+        );
+        assert_eq!(
+            Some(ValueChange {
+                from: "Gas".to_owned(),
+                to: "Gas Station".to_owned(),
+            }),
+            diff.payee
+        );
+        assert_eq!(
+            Some(ValueChange {
+                from: "".to_owned(),
+                to: "Filled up".to_owned(),
+            }),
+            diff.notes
+        );
+    }
+
+    #[test]
+    fn diff_changed_entries() {
+        let old = snapshot(
+            "Gas",
+            vec![entry("Expenses:Gas", 100), entry("Assets:Checking", -100)],
+        );
+        let new = snapshot(
+            "Gas",
+            vec![
+                entry("Expenses:Gas", 100),
+                entry("Liabilities:Credit", -100),
+            ],
+        );
+
+        let diff = old.diff(&new);
+
+        assert_eq!(vec![entry("Liabilities:Credit", -100)], diff.added_entries);
+        assert_eq!(vec![entry("Assets:Checking", -100)], diff.removed_entries);
+    }
+
+    #[test]
+    fn diff_duplicate_entries() {
+        let old = snapshot(
+            "Split",
+            vec![
+                entry("Expenses:Food", 50),
+                entry("Expenses:Food", 50),
+                entry("Assets:Checking", -100),
+            ],
+        );
+        let new = snapshot(
+            "Split",
+            vec![entry("Expenses:Food", 50), entry("Assets:Checking", -50)],
+        );
+
+        let diff = old.diff(&new);
+
+        assert_eq!(vec![entry("Assets:Checking", -50)], diff.added_entries);
+        assert_eq!(
+            vec![entry("Expenses:Food", 50), entry("Assets:Checking", -100)],
+            diff.removed_entries
+        );
+    }
+
+    #[test]
+    fn snapshot_to_new_transaction() {
+        let old = snapshot(
+            "Gas",
+            vec![entry("Expenses:Gas", 100), entry("Assets:Checking", -100)],
+        );
+
+        let book_id = Uuid::new_v4();
+        let transaction = old
+            .to_new_transaction(book_id)
+            .expect("snapshot should be valid");
+
+        assert_eq!(book_id, transaction.book_id());
+        assert_eq!(None, transaction.notes());
+        assert_eq!(old, TransactionSnapshot::from(&transaction));
+    }
+}
@@ -0,0 +1,126 @@
+use sha2::{Digest, Sha256};
+
+/// The maximum length of an idempotency key provided by a client.
+pub const MAX_KEY_LENGTH: usize = 255;
+
+/// A response stored for an idempotency key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StoredResponse {
+    pub status: u16,
+    pub body: serde_json::Value,
+}
+
+/// A previously used idempotency key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IdempotencyRecord {
+    pub request_hash: String,
+    /// The response to the original request, or `None` if the original
+    /// request is still being processed.
+    pub response: Option<StoredResponse>,
+}
+
+/// How to handle a request whose idempotency key has already been used.
+#[derive(Debug, PartialEq)]
+pub enum Replay {
+    /// Return the response to the original request.
+    Response(StoredResponse),
+
+    /// The key was used for a request with a different body.
+    Mismatch,
+
+    /// The original request has not finished yet.
+    InProgress,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum InvalidKey {
+    Empty,
+    TooLong,
+}
+
+/// Ensure a client-provided idempotency key is acceptable.
+pub fn validate_key(key: &str) -> Result<(), InvalidKey> {
+    if key.is_empty() {
+        Err(InvalidKey::Empty)
+    } else if key.len() > MAX_KEY_LENGTH {
+        Err(InvalidKey::TooLong)
+    } else {
+        Ok(())
+    }
+}
+
+/// Hash a request body so it can be compared against later requests using
+/// the same key.
+pub fn hash_request(body: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(body))
+}
+
+impl IdempotencyRecord {
+    /// Determine how to handle a request that reuses this record's key.
+    ///
+    /// # Arguments
+    /// * `request_hash` - The hash of the new request's body.
+    pub fn replay(&self, request_hash: &str) -> Replay {
+        if self.request_hash != request_hash {
+            return Replay::Mismatch;
+        }
+
+        match &self.response {
+            Some(response) => Replay::Response(response.clone()),
+            None => Replay::InProgress,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    fn record(body: &[u8], response: Option<StoredResponse>) -> IdempotencyRecord {
+        IdempotencyRecord {
+            request_hash: hash_request(body),
+            response,
+        }
+    }
+
+    #[test]
+    fn validate_key_length() {
+        assert_eq!(Err(InvalidKey::Empty), validate_key(""));
+        assert_eq!(Ok(()), validate_key("a"));
+        assert_eq!(Ok(()), validate_key(&"a".repeat(MAX_KEY_LENGTH)));
+        assert_eq!(
+            Err(InvalidKey::TooLong),
+            validate_key(&"a".repeat(MAX_KEY_LENGTH + 1))
+        );
+    }
+
+    #[test]
+    fn replay_stored_response() {
+        let response = StoredResponse {
+            status: 201,
+            body: json!({"payee": "Gas"}),
+        };
+        let record = record(b"request", Some(response.clone()));
+
+        assert_eq!(
+            Replay::Response(response),
+            record.replay(&hash_request(b"request"))
+        );
+    }
+
+    #[test]
+    fn replay_different_request() {
+        let record = record(b"request", None);
+
+        assert_eq!(Replay::Mismatch, record.replay(&hash_request(b"other")));
+    }
+
+    #[test]
+    fn replay_in_progress() {
+        let record = record(b"request", None);
+
+        assert_eq!(Replay::InProgress, record.replay(&hash_request(b"request")));
+    }
+}
@@ -0,0 +1,90 @@
+use chrono::{DateTime, NaiveDate, Utc};
+
+/// A book's ledger lock. Transactions dated on or before the lock date may
+/// not be created, changed, or deleted.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct LedgerLock {
+    pub lock_date: Option<NaiveDate>,
+}
+
+/// The error returned when a change touches a locked period. The value is the
+/// lock date that was violated.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PeriodLocked(pub NaiveDate);
+
+impl LedgerLock {
+    /// Determine if a date falls within the locked period.
+    pub fn is_locked(&self, date: NaiveDate) -> bool {
+        self.lock_date
+            .map(|lock_date| date <= lock_date)
+            .unwrap_or(false)
+    }
+
+    /// Ensure that none of the provided dates fall within the locked period.
+    ///
+    /// # Arguments
+    /// * `dates` - The dates touched by a change, eg. both the old and new
+    ///   date of an updated transaction.
+    pub fn ensure_unlocked<I: IntoIterator<Item = NaiveDate>>(
+        &self,
+        dates: I,
+    ) -> Result<(), PeriodLocked> {
+        match self.lock_date {
+            Some(lock_date) if dates.into_iter().any(|date| self.is_locked(date)) => {
+                Err(PeriodLocked(lock_date))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A recorded change to a user's lock date.
+pub struct LockChange {
+    /// The ID of the user who changed the lock date. Changes recorded before
+    /// the user was tracked don't have one.
+    pub actor: Option<String>,
+    pub previous_lock_date: Option<NaiveDate>,
+    pub lock_date: Option<NaiveDate>,
+    pub changed_at: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2023, 4, day).unwrap()
+    }
+
+    #[test]
+    fn unlocked_ledger() {
+        let lock = LedgerLock::default();
+
+        assert!(!lock.is_locked(date(1)));
+        assert_eq!(Ok(()), lock.ensure_unlocked([date(1), date(30)]));
+    }
+
+    #[test]
+    fn lock_date_is_inclusive() {
+        let lock = LedgerLock {
+            lock_date: Some(date(15)),
+        };
+
+        assert!(lock.is_locked(date(14)));
+        assert!(lock.is_locked(date(15)));
+        assert!(!lock.is_locked(date(16)));
+    }
+
+    #[test]
+    fn ensure_unlocked_any_locked_date() {
+        let lock = LedgerLock {
+            lock_date: Some(date(15)),
+        };
+
+        assert_eq!(Ok(()), lock.ensure_unlocked([date(16), date(20)]));
+        assert_eq!(
+            Err(PeriodLocked(date(15))),
+            lock.ensure_unlocked([date(20), date(10)])
+        );
+    }
+}
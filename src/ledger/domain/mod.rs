@@ -1,3 +1,13 @@
+pub mod attachments;
+pub mod batch;
+pub mod books;
+pub mod changes;
+pub mod closing;
+pub mod concurrency;
 pub mod currency;
+pub mod history;
+pub mod idempotency;
+pub mod locking;
 pub mod reports;
 pub mod transactions;
+pub mod webhooks;
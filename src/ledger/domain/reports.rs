@@ -3,12 +3,14 @@ use chrono::NaiveDate;
 use super::currency::Currency;
 
 /// A collection of instant balances associated with a specific currency.
+#[derive(Clone)]
 pub struct InstantBalances {
     currency: Currency,
     balances: Vec<InstantBalance>,
 }
 
 /// An account balance at a certain instant in time.
+#[derive(Clone)]
 pub struct InstantBalance {
     instant: NaiveDate,
     amount: i32,
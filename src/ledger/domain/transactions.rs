@@ -11,6 +11,7 @@ use super::currency::{Currency, CurrencyAmount};
 
 pub use new_transaction::{NewTransaction, NewTransactionEntry};
 pub use new_transaction_data::NewTransactionData;
+pub use new_transaction_entry_data::{NewTransactionEntryAmountData, NewTransactionEntryData};
 
 #[derive(Debug, Eq, PartialEq)]
 pub enum NewTransactionError {
@@ -22,15 +23,22 @@ pub enum NewTransactionError {
     Unbalanced(HashMap<Currency, i32>),
 }
 
+#[derive(Debug)]
 pub struct Transaction {
     pub id: Uuid,
-    pub user_id: String,
+    pub book_id: Uuid,
     pub date: NaiveDate,
     pub payee: String,
     pub notes: String,
     pub entries: Vec<TransactionEntry>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    /// The time the transaction was moved to the trash, if it has been.
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// A number identifying the current revision of the transaction. It
+    /// changes every time the transaction is modified, so it can be used to
+    /// detect concurrent modifications.
+    pub revision: i64,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -5,6 +5,7 @@ use std::{
 
 use chrono::NaiveDate;
 use tracing::{debug, error, trace};
+use uuid::Uuid;
 use validator::{Validate, ValidationError, ValidationErrors, ValidationErrorsKind};
 
 use super::{
@@ -13,9 +14,9 @@ use super::{
 };
 
 /// A new transaction that has not been persisted yet.
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct NewTransaction {
-    user_id: String,
+    book_id: Uuid,
     date: NaiveDate,
     payee: String,
     notes: Option<String>,
@@ -23,14 +24,14 @@ pub struct NewTransaction {
 }
 
 /// A new transaction entry that has not been persisted yet.
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct NewTransactionEntry {
     account: String,
     amount: NewTransactionEntryAmount,
 }
 
 /// A monetary amount for a new transaction.
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct NewTransactionEntryAmount {
     currency: String,
     value: i32,
@@ -50,10 +51,23 @@ fn try_balance(entries: &mut [NewTransactionEntryData]) {
     // Sum all the entries to find any outstanding (non-zero) balances.
     for entry in entries {
         match &entry.amount {
-            Some(amount) => {
-                let current_value = currency_sums.entry(amount.currency.clone()).or_insert(0);
+            Some(NewTransactionEntryAmountData {
+                currency: Some(currency),
+                value,
+            }) => {
+                let current_value = currency_sums.entry(currency.clone()).or_insert(0);
 
-                *current_value += amount.value;
+                *current_value += value;
+            }
+            Some(_) => {
+                // The amount is missing a currency, which fails validation,
+                // so there is nothing to balance against.
+                debug!(
+                    ?entry,
+                    "Can't auto-balance entries due to an amount without a currency."
+                );
+
+                return;
             }
             None => {
                 if balancing_entry.is_some() {
@@ -92,7 +106,7 @@ fn try_balance(entries: &mut [NewTransactionEntryData]) {
         let balancing_amount = -unbalanced_sum.1;
 
         entry.amount = Some(NewTransactionEntryAmountData {
-            currency: unbalanced_sum.0.clone(),
+            currency: Some(unbalanced_sum.0.clone()),
             value: balancing_amount,
         });
     }
@@ -105,14 +119,14 @@ impl NewTransaction {
     /// validation rules.
     ///
     /// # Arguments
-    /// * `user_id` - The ID of the user who owns the transaction.
+    /// * `book_id` - The ID of the book that the transaction belongs to.
     /// * `data` - The input data describing the transaction.
     ///
     /// # Returns
     /// The new transaction if the data is valid, or a set of
     ///  [`ValidationErrors`] otherwise.
-    pub fn from_data<S: Into<String>>(
-        user_id: S,
+    pub fn from_data(
+        book_id: Uuid,
         mut data: NewTransactionData,
     ) -> Result<Self, ValidationErrors> {
         try_balance(&mut data.entries);
@@ -131,18 +145,22 @@ impl NewTransaction {
                     .iter()
                     .enumerate()
                     .map(|(index, data_entry)| {
-                        if let Some(ref amount) = data_entry.amount {
+                        if let Some(NewTransactionEntryAmountData {
+                            currency: Some(currency),
+                            value,
+                        }) = &data_entry.amount
+                        {
                             Ok(NewTransactionEntry {
                                 account: data_entry.account.clone(),
                                 amount: NewTransactionEntryAmount {
-                                    currency: amount.currency.clone(),
-                                    value: amount.value,
+                                    currency: currency.clone(),
+                                    value: *value,
                                 },
                             })
                         } else {
                             // Since we already validated and balanced the
                             // entries, there shouldn't be any entries without
-                            // an amount.
+                            // an amount or currency.
                             error!(
                                 transaction = ?data,
                                 entry = ?data_entry,
@@ -177,7 +195,7 @@ impl NewTransaction {
                     })?;
 
                 Ok(Self {
-                    user_id: user_id.into(),
+                    book_id,
                     date: data.date,
                     payee: data.payee,
                     notes: data.notes,
@@ -187,8 +205,8 @@ impl NewTransaction {
         }
     }
 
-    pub fn user_id(&self) -> &str {
-        &self.user_id
+    pub fn book_id(&self) -> Uuid {
+        self.book_id
     }
 
     pub fn date(&self) -> NaiveDate {
@@ -234,7 +252,7 @@ mod test {
 
     #[test]
     fn new_auto_balanced_transaction_single_currency() {
-        let user_id = "user-id".to_owned();
+        let book_id = Uuid::new_v4();
         let date = NaiveDate::from_ymd_opt(2023, 4, 15).unwrap();
         let payee = "Gas".to_owned();
         let notes = None;
@@ -247,7 +265,7 @@ mod test {
                 NewTransactionEntryData {
                     account: "Expenses:Gas".to_owned(),
                     amount: Some(NewTransactionEntryAmountData {
-                        currency: "USD".to_owned(),
+                        currency: Some("USD".to_owned()),
                         value: 2783,
                     }),
                 },
@@ -259,7 +277,7 @@ mod test {
         };
 
         let want_transaction = NewTransaction {
-            user_id: user_id.clone(),
+            book_id,
             date: date,
             payee: payee,
             notes: notes,
@@ -281,14 +299,14 @@ mod test {
             ],
         };
 
-        let got_transaction = NewTransaction::from_data(user_id, data).expect("should be valid");
+        let got_transaction = NewTransaction::from_data(book_id, data).expect("should be valid");
 
         assert_eq!(want_transaction, got_transaction);
     }
 
     #[test]
     fn new_auto_balanced_transaction_multi_currency() {
-        let user_id = "user-id".to_owned();
+        let book_id = Uuid::new_v4();
         let date = NaiveDate::from_ymd_opt(2023, 4, 15).unwrap();
         let payee = "Gas".to_owned();
         let notes = None;
@@ -301,7 +319,7 @@ mod test {
                 NewTransactionEntryData {
                     account: "Expenses:Gas".to_owned(),
                     amount: Some(NewTransactionEntryAmountData {
-                        currency: "USD".to_owned(),
+                        currency: Some("USD".to_owned()),
                         value: 2783,
                     }),
                 },
@@ -312,14 +330,14 @@ mod test {
                 NewTransactionEntryData {
                     account: "Expenses:Food".to_owned(),
                     amount: Some(NewTransactionEntryAmountData {
-                        currency: "EUR".to_owned(),
+                        currency: Some("EUR".to_owned()),
                         value: 543,
                     }),
                 },
                 NewTransactionEntryData {
                     account: "Liabilities:Credit".to_owned(),
                     amount: Some(NewTransactionEntryAmountData {
-                        currency: "EUR".to_owned(),
+                        currency: Some("EUR".to_owned()),
                         value: -543,
                     }),
                 },
@@ -327,7 +345,7 @@ mod test {
         };
 
         let want_transaction = NewTransaction {
-            user_id: user_id.clone(),
+            book_id,
             date: date,
             payee: payee,
             notes: notes,
@@ -363,14 +381,14 @@ mod test {
             ],
         };
 
-        let got_transaction = NewTransaction::from_data(user_id, data).expect("should be valid");
+        let got_transaction = NewTransaction::from_data(book_id, data).expect("should be valid");
 
         assert_eq!(want_transaction, got_transaction);
     }
 
     #[test]
     fn new_auto_balanced_transaction_single_currency_zero_amount() {
-        let user_id = "user-id".to_owned();
+        let book_id = Uuid::new_v4();
         let date = NaiveDate::from_ymd_opt(2023, 4, 15).unwrap();
         let payee = "Gas".to_owned();
         let notes = None;
@@ -383,7 +401,7 @@ mod test {
                 NewTransactionEntryData {
                     account: "Expenses:Gas".to_owned(),
                     amount: Some(NewTransactionEntryAmountData {
-                        currency: "USD".to_owned(),
+                        currency: Some("USD".to_owned()),
                         value: 0,
                     }),
                 },
@@ -394,7 +412,7 @@ mod test {
             ],
         };
 
-        let error = NewTransaction::from_data(user_id, data)
+        let error = NewTransaction::from_data(book_id, data)
             .expect_err("should error for missing amount and no outstanding balance");
         let errors = error.errors();
 
@@ -1,13 +1,14 @@
 use std::{borrow::Cow, collections::HashMap};
 
 use chrono::NaiveDate;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use validator::{Validate, ValidationError};
 
-use super::new_transaction_entry_data::NewTransactionEntryData;
+use super::new_transaction_entry_data::{NewTransactionEntryAmountData, NewTransactionEntryData};
 
 /// Data for a new transaction provided by a user.
-#[derive(Debug, Deserialize, Validate)]
+#[derive(Debug, Deserialize, Serialize, ToSchema, Validate)]
 pub struct NewTransactionData {
     /// The date that the transaction was made.
     pub date: NaiveDate,
@@ -26,6 +27,15 @@ pub struct NewTransactionData {
     pub entries: Vec<NewTransactionEntryData>,
 }
 
+impl NewTransactionData {
+    /// Use a currency for every amount that doesn't name one.
+    pub fn apply_default_currency(&mut self, currency: &str) {
+        for entry in &mut self.entries {
+            entry.apply_default_currency(currency);
+        }
+    }
+}
+
 fn validate_entries_balanced(
     entries: &Vec<NewTransactionEntryData>,
 ) -> Result<(), ValidationError> {
@@ -33,10 +43,12 @@ fn validate_entries_balanced(
     // currency in the case of an imbalance.
     let mut currency_sums: HashMap<String, i32> = HashMap::new();
     for entry in entries {
-        if let Some(ref amount) = entry.amount {
-            let current_value = currency_sums.entry(amount.currency.clone()).or_insert(0);
-
-            *current_value += amount.value;
+        if let Some(NewTransactionEntryAmountData {
+            currency: Some(currency),
+            value,
+        }) = &entry.amount
+        {
+            *currency_sums.entry(currency.clone()).or_insert(0) += value;
         }
     }
 
@@ -68,8 +80,6 @@ fn validate_entries_balanced(
 mod test {
     use validator::ValidationErrorsKind;
 
-    use crate::ledger::domain::transactions::new_transaction_entry_data::NewTransactionEntryAmountData;
-
     use super::*;
 
     #[test]
@@ -103,14 +113,14 @@ mod test {
                 NewTransactionEntryData {
                     account: "Expenses:Food".to_owned(),
                     amount: Some(NewTransactionEntryAmountData {
-                        currency: "USD".to_owned(),
+                        currency: Some("USD".to_owned()),
                         value: 100,
                     }),
                 },
                 NewTransactionEntryData {
                     account: "Assets:Checking".to_owned(),
                     amount: Some(NewTransactionEntryAmountData {
-                        currency: "USD".to_owned(),
+                        currency: Some("USD".to_owned()),
                         value: -100,
                     }),
                 },
@@ -130,7 +140,7 @@ mod test {
                 NewTransactionEntryData {
                     account: "Expenses:Food".to_owned(),
                     amount: Some(NewTransactionEntryAmountData {
-                        currency: "USD".to_owned(),
+                        currency: Some("USD".to_owned()),
                         value: 100,
                     }),
                 },
@@ -139,7 +149,7 @@ mod test {
                     // Unbalanced because both entries are positive. Leaves
                     // $2.00 unaccounted for.
                     amount: Some(NewTransactionEntryAmountData {
-                        currency: "USD".to_owned(),
+                        currency: Some("USD".to_owned()),
                         value: 100,
                     }),
                 },
@@ -168,28 +178,28 @@ mod test {
                 NewTransactionEntryData {
                     account: "Expenses:Food".to_owned(),
                     amount: Some(NewTransactionEntryAmountData {
-                        currency: "USD".to_owned(),
+                        currency: Some("USD".to_owned()),
                         value: 100,
                     }),
                 },
                 NewTransactionEntryData {
                     account: "Assets:Checking".to_owned(),
                     amount: Some(NewTransactionEntryAmountData {
-                        currency: "USD".to_owned(),
+                        currency: Some("USD".to_owned()),
                         value: -100,
                     }),
                 },
                 NewTransactionEntryData {
                     account: "Expenses:Food".to_owned(),
                     amount: Some(NewTransactionEntryAmountData {
-                        currency: "EUR".to_owned(),
+                        currency: Some("EUR".to_owned()),
                         value: 200,
                     }),
                 },
                 NewTransactionEntryData {
                     account: "Assets:Checking".to_owned(),
                     amount: Some(NewTransactionEntryAmountData {
-                        currency: "EUR".to_owned(),
+                        currency: Some("EUR".to_owned()),
                         value: -200,
                     }),
                 },
@@ -209,7 +219,7 @@ mod test {
                 NewTransactionEntryData {
                     account: "Expenses:Food".to_owned(),
                     amount: Some(NewTransactionEntryAmountData {
-                        currency: "USD".to_owned(),
+                        currency: Some("USD".to_owned()),
                         value: 100,
                     }),
                 },
@@ -218,14 +228,14 @@ mod test {
                     // Unbalanced because both entries are positive. Leaves
                     // $2.00 unaccounted for.
                     amount: Some(NewTransactionEntryAmountData {
-                        currency: "USD".to_owned(),
+                        currency: Some("USD".to_owned()),
                         value: 100,
                     }),
                 },
                 NewTransactionEntryData {
                     account: "Expenses:Food".to_owned(),
                     amount: Some(NewTransactionEntryAmountData {
-                        currency: "EUR".to_owned(),
+                        currency: Some("EUR".to_owned()),
                         value: 200,
                     }),
                 },
@@ -234,7 +244,7 @@ mod test {
                     // Unbalanced because both entries are positive. Leaves
                     // €4.00 unaccounted for.
                     amount: Some(NewTransactionEntryAmountData {
-                        currency: "EUR".to_owned(),
+                        currency: Some("EUR".to_owned()),
                         value: 200,
                     }),
                 },
@@ -264,14 +274,14 @@ mod test {
                 NewTransactionEntryData {
                     account: "Expenses:Food".to_owned(),
                     amount: Some(NewTransactionEntryAmountData {
-                        currency: "USD".to_owned(),
+                        currency: Some("USD".to_owned()),
                         value: 100,
                     }),
                 },
                 NewTransactionEntryData {
                     account: "".to_owned(),
                     amount: Some(NewTransactionEntryAmountData {
-                        currency: "USD".to_owned(),
+                        currency: Some("USD".to_owned()),
                         value: -100,
                     }),
                 },
@@ -305,4 +315,75 @@ mod test {
             entries_errors[&1].field_errors()["account"][0].code
         );
     }
+
+    #[test]
+    fn transaction_validate_missing_currency() {
+        // Amounts without a currency are left out of the balance check, so
+        // the missing currencies are the only errors.
+        let data = NewTransactionData {
+            date: NaiveDate::from_ymd_opt(2023, 6, 24).unwrap(),
+            payee: "Groceries".to_owned(),
+            notes: None,
+            entries: vec![
+                NewTransactionEntryData {
+                    account: "Expenses:Food".to_owned(),
+                    amount: Some(NewTransactionEntryAmountData {
+                        currency: None,
+                        value: 100,
+                    }),
+                },
+                NewTransactionEntryData {
+                    account: "Assets:Checking".to_owned(),
+                    amount: Some(NewTransactionEntryAmountData {
+                        currency: None,
+                        value: -100,
+                    }),
+                },
+            ],
+        };
+
+        let errors = data.validate().expect_err("missing currency");
+        let entries_errors = match errors.errors().get("entries") {
+            Some(ValidationErrorsKind::List(errors)) => errors,
+            other => panic!("Expected to receive list of errors, got {:?}", other),
+        };
+        let amount_errors = match entries_errors[&0].errors().get("amount") {
+            Some(ValidationErrorsKind::Struct(errors)) => errors,
+            other => panic!("Expected to receive amount errors, got {:?}", other),
+        };
+        assert_eq!("required", amount_errors.field_errors()["currency"][0].code);
+    }
+
+    #[test]
+    fn apply_default_currency_fills_missing_currencies() {
+        let mut data = NewTransactionData {
+            date: NaiveDate::from_ymd_opt(2023, 6, 24).unwrap(),
+            payee: "Groceries".to_owned(),
+            notes: None,
+            entries: vec![
+                NewTransactionEntryData {
+                    account: "Expenses:Food".to_owned(),
+                    amount: Some(NewTransactionEntryAmountData {
+                        currency: None,
+                        value: 100,
+                    }),
+                },
+                NewTransactionEntryData {
+                    account: "Assets:Checking".to_owned(),
+                    amount: Some(NewTransactionEntryAmountData {
+                        currency: Some("USD".to_owned()),
+                        value: -100,
+                    }),
+                },
+            ],
+        };
+
+        data.apply_default_currency("EUR");
+
+        let filled = data.entries[0].amount.as_ref().unwrap();
+        assert_eq!(Some("EUR"), filled.currency.as_deref());
+
+        let explicit = data.entries[1].amount.as_ref().unwrap();
+        assert_eq!(Some("USD"), explicit.currency.as_deref());
+    }
 }
@@ -1,8 +1,9 @@
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use validator::Validate;
 
 /// An entry in a new transaction.
-#[derive(Debug, Deserialize, Serialize, Validate)]
+#[derive(Debug, Deserialize, Serialize, ToSchema, Validate)]
 pub struct NewTransactionEntryData {
     /// The account that money is being transferred from/to.
     #[validate(length(min = 1))]
@@ -16,11 +17,12 @@ pub struct NewTransactionEntryData {
 }
 
 /// An amount of money in a specific currency.
-#[derive(Debug, Deserialize, Serialize, Validate)]
+#[derive(Debug, Deserialize, Serialize, ToSchema, Validate)]
 pub struct NewTransactionEntryAmountData {
-    /// The unique currency code.
-    #[validate(length(equal = 3))]
-    pub currency: String,
+    /// The unique currency code. If it is omitted, the book's default currency
+    /// is used.
+    #[validate(required, length(equal = 3))]
+    pub currency: Option<String>,
 
     /// The amount as an integer. This is computed by `x * 10^n` where `x` is
     /// the monetary amount, and `n` is the number of significant decimal places
@@ -28,6 +30,15 @@ pub struct NewTransactionEntryAmountData {
     pub value: i32,
 }
 
+impl NewTransactionEntryData {
+    /// Use a currency for the entry's amount if it doesn't name one.
+    pub fn apply_default_currency(&mut self, currency: &str) {
+        if let Some(amount) = &mut self.amount {
+            amount.currency.get_or_insert_with(|| currency.to_owned());
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -0,0 +1,577 @@
+use std::{fmt::Display, net::IpAddr, str::FromStr};
+
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::{Validate, ValidationError, ValidationErrors};
+
+use super::history::ChangeAction;
+
+/// The header carrying the signature of a webhook request.
+pub const SIGNATURE_HEADER: &str = "Zeroed-Books-Signature";
+
+/// The number of attempts made to deliver an event before giving up.
+pub const MAX_DELIVERY_ATTEMPTS: i32 = 8;
+
+/// The delay before the first retry. Each subsequent retry waits twice as
+/// long as the previous one.
+const BASE_RETRY_DELAY_SECONDS: i64 = 30;
+
+/// The longest delay between two delivery attempts.
+const MAX_RETRY_DELAY_SECONDS: i64 = 6 * 60 * 60;
+
+/// The kinds of events that endpoints can subscribe to.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EventType {
+    TransactionCreated,
+    TransactionUpdated,
+    TransactionDeleted,
+    TransactionRestored,
+    /// The balance of an account moved from one side of an endpoint's
+    /// threshold to the other.
+    BalanceThresholdCrossed,
+}
+
+impl EventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::TransactionCreated => "transaction.created",
+            Self::TransactionUpdated => "transaction.updated",
+            Self::TransactionDeleted => "transaction.deleted",
+            Self::TransactionRestored => "transaction.restored",
+            Self::BalanceThresholdCrossed => "balance.threshold_crossed",
+        }
+    }
+}
+
+impl Display for EventType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for EventType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "transaction.created" => Ok(Self::TransactionCreated),
+            "transaction.updated" => Ok(Self::TransactionUpdated),
+            "transaction.deleted" => Ok(Self::TransactionDeleted),
+            "transaction.restored" => Ok(Self::TransactionRestored),
+            "balance.threshold_crossed" => Ok(Self::BalanceThresholdCrossed),
+            other => Err(anyhow::anyhow!("unknown event type: {:?}", other)),
+        }
+    }
+}
+
+impl From<ChangeAction> for EventType {
+    fn from(action: ChangeAction) -> Self {
+        match action {
+            ChangeAction::Created => Self::TransactionCreated,
+            ChangeAction::Updated => Self::TransactionUpdated,
+            ChangeAction::Deleted => Self::TransactionDeleted,
+            ChangeAction::Restored => Self::TransactionRestored,
+        }
+    }
+}
+
+/// A balance that an endpoint wants to be notified about. An event is sent
+/// whenever the account's balance in the currency moves from below the
+/// threshold to at or above it, or back.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize, ToSchema, Validate)]
+pub struct BalanceThreshold {
+    /// The account to watch. This includes any child accounts.
+    #[validate(length(min = 1))]
+    pub account: String,
+    #[validate(length(min = 1))]
+    pub currency: String,
+    /// The threshold in the currency's minor units.
+    pub amount: i64,
+}
+
+/// Data for a new webhook endpoint provided by a user.
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+pub struct NewWebhookEndpointData {
+    #[validate(url)]
+    pub url: String,
+
+    /// The names of the event types to send to the endpoint.
+    #[validate(length(min = 1))]
+    pub events: Vec<String>,
+
+    /// Required if the endpoint subscribes to balance threshold events.
+    #[validate]
+    pub balance_threshold: Option<BalanceThreshold>,
+}
+
+/// A validated webhook endpoint that has not been persisted yet.
+#[derive(Debug, PartialEq)]
+pub struct NewWebhookEndpoint {
+    book_id: Uuid,
+    url: String,
+    secret: String,
+    events: Vec<EventType>,
+    balance_threshold: Option<BalanceThreshold>,
+}
+
+impl NewWebhookEndpoint {
+    /// Validate a new endpoint and generate the secret used to sign its
+    /// requests.
+    ///
+    /// # Arguments
+    /// * `book_id` - The ID of the book that the endpoint receives events for.
+    /// * `data` - The endpoint information provided by the user.
+    pub fn from_data(
+        book_id: Uuid,
+        data: NewWebhookEndpointData,
+    ) -> Result<Self, ValidationErrors> {
+        data.validate()?;
+
+        let mut errors = ValidationErrors::new();
+
+        if !(data.url.starts_with("https://") || data.url.starts_with("http://")) {
+            errors.add("url", ValidationError::new("scheme"));
+        }
+
+        let mut events = Vec::with_capacity(data.events.len());
+        for name in &data.events {
+            match name.parse::<EventType>() {
+                Ok(event) if !events.contains(&event) => events.push(event),
+                Ok(_) => (),
+                Err(_) => {
+                    let mut error = ValidationError::new("unknown_event");
+                    error.add_param("event".into(), name);
+                    errors.add("events", error);
+                }
+            }
+        }
+
+        let wants_threshold = events.contains(&EventType::BalanceThresholdCrossed);
+        match (wants_threshold, &data.balance_threshold) {
+            (true, None) => errors.add("balance_threshold", ValidationError::new("required")),
+            (false, Some(_)) => errors.add("balance_threshold", ValidationError::new("unused")),
+            _ => (),
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        Ok(Self {
+            book_id,
+            url: data.url,
+            secret: generate_secret(),
+            events,
+            balance_threshold: data.balance_threshold,
+        })
+    }
+
+    pub fn book_id(&self) -> Uuid {
+        self.book_id
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn secret(&self) -> &str {
+        &self.secret
+    }
+
+    pub fn events(&self) -> &[EventType] {
+        &self.events
+    }
+
+    pub fn balance_threshold(&self) -> Option<&BalanceThreshold> {
+        self.balance_threshold.as_ref()
+    }
+}
+
+/// A registered webhook endpoint.
+pub struct WebhookEndpoint {
+    pub id: Uuid,
+    pub book_id: Uuid,
+    pub url: String,
+    /// The secret used to sign requests to the endpoint.
+    pub secret: String,
+    pub events: Vec<EventType>,
+    pub balance_threshold: Option<BalanceThreshold>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// An event waiting to be delivered to an endpoint.
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub endpoint_id: Uuid,
+    pub url: String,
+    pub secret: String,
+    pub event: WebhookEvent,
+    /// The number of attempts that have already been made.
+    pub attempts: i32,
+}
+
+/// What to do with a delivery after an attempt.
+#[derive(Debug, Eq, PartialEq)]
+pub enum NextStep {
+    Delivered,
+    RetryAt(DateTime<Utc>),
+    /// The delivery failed too many times and will not be retried.
+    Abandon,
+}
+
+impl WebhookDelivery {
+    /// Determine what happens to the delivery after an attempt.
+    ///
+    /// # Arguments
+    /// * `result` - The result of the attempt that was just made.
+    /// * `now` - The time the attempt finished.
+    pub fn next_step(&self, result: &AttemptResult, now: DateTime<Utc>) -> NextStep {
+        if result.succeeded() {
+            return NextStep::Delivered;
+        }
+
+        match retry_delay(self.attempts + 1) {
+            Some(delay) => NextStep::RetryAt(now + delay),
+            None => NextStep::Abandon,
+        }
+    }
+}
+
+/// An event from the outbox.
+#[derive(Debug, Serialize)]
+pub struct WebhookEvent {
+    pub id: i64,
+    #[serde(rename = "type", serialize_with = "serialize_event_type")]
+    pub event_type: EventType,
+    pub created_at: DateTime<Utc>,
+    pub data: serde_json::Value,
+}
+
+fn serialize_event_type<S: serde::Serializer>(
+    event_type: &EventType,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(event_type.as_str())
+}
+
+/// The result of a single attempt to deliver an event.
+#[derive(Debug, Eq, PartialEq)]
+pub struct AttemptResult {
+    /// The status code returned by the endpoint, if it responded.
+    pub response_status: Option<u16>,
+    /// A description of why the request failed, if it did.
+    pub error: Option<String>,
+}
+
+impl AttemptResult {
+    pub fn succeeded(&self) -> bool {
+        matches!(self.response_status, Some(status) if (200..300).contains(&status))
+    }
+}
+
+/// A recorded attempt to deliver an event, used for the delivery log.
+pub struct DeliveryAttempt {
+    pub delivery_id: i64,
+    pub event_id: i64,
+    pub event_type: EventType,
+    pub response_status: Option<u16>,
+    pub error: Option<String>,
+    pub attempted_at: DateTime<Utc>,
+}
+
+/// Generate a new secret for signing requests to an endpoint.
+pub fn generate_secret() -> String {
+    format!(
+        "whsec_{}{}",
+        Uuid::new_v4().simple(),
+        Uuid::new_v4().simple()
+    )
+}
+
+/// Determine if an address is reachable on the public internet.
+///
+/// Webhooks are only sent to public addresses, so that users can't use them
+/// to make requests to the server itself, to other services on its network,
+/// or to the cloud provider's metadata service at `169.254.169.254`.
+pub fn is_public_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                // Shared address space used for carrier-grade NAT.
+                || (first == 100 && (64..128).contains(&second))
+                // "This network" and reserved addresses.
+                || first == 0
+                || first >= 240)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(mapped) = ip.to_ipv4_mapped() {
+                return is_public_address(IpAddr::V4(mapped));
+            }
+
+            let first = ip.segments()[0];
+
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_multicast()
+                // Unique local addresses.
+                || (first & 0xfe00) == 0xfc00
+                // Link-local addresses.
+                || (first & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+/// Sign the body of a webhook request.
+///
+/// The signature is an HMAC-SHA256 of the timestamp and body joined by a
+/// `.`, so receivers can reject replays of old requests.
+///
+/// # Returns
+/// The value of the [signature header][SIGNATURE_HEADER], in the form
+/// `t=<timestamp>,v1=<hex signature>`.
+pub fn sign_payload(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+
+    format!("t={},v1={:x}", timestamp, mac.finalize().into_bytes())
+}
+
+/// Get the delay before retrying a failed delivery.
+///
+/// # Arguments
+/// * `attempts` - The number of attempts that have been made, including the
+///   one that just failed.
+///
+/// # Returns
+/// The delay before the next attempt, or `None` if the delivery should be
+/// abandoned.
+pub fn retry_delay(attempts: i32) -> Option<Duration> {
+    if attempts >= MAX_DELIVERY_ATTEMPTS {
+        return None;
+    }
+
+    let exponent = u32::try_from(attempts.max(1) - 1).unwrap_or(0);
+    let seconds = 2i64
+        .checked_pow(exponent)
+        .and_then(|factor| factor.checked_mul(BASE_RETRY_DELAY_SECONDS))
+        .map_or(MAX_RETRY_DELAY_SECONDS, |seconds| {
+            seconds.min(MAX_RETRY_DELAY_SECONDS)
+        });
+
+    Some(Duration::seconds(seconds))
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    fn endpoint_data(value: serde_json::Value) -> NewWebhookEndpointData {
+        serde_json::from_value(value).expect("should deserialize")
+    }
+
+    #[test]
+    fn event_type_round_trip() {
+        for event in [
+            EventType::TransactionCreated,
+            EventType::TransactionUpdated,
+            EventType::TransactionDeleted,
+            EventType::TransactionRestored,
+            EventType::BalanceThresholdCrossed,
+        ] {
+            assert_eq!(event, event.as_str().parse().unwrap());
+        }
+    }
+
+    #[test]
+    fn from_data_valid() {
+        let book_id = Uuid::new_v4();
+        let endpoint = NewWebhookEndpoint::from_data(
+            book_id,
+            endpoint_data(json!({
+                "url": "https://example.com/hooks",
+                "events": ["transaction.created", "transaction.created", "transaction.deleted"]
+            })),
+        )
+        .expect("endpoint should be valid");
+
+        assert_eq!(book_id, endpoint.book_id());
+        assert_eq!(
+            &[EventType::TransactionCreated, EventType::TransactionDeleted],
+            endpoint.events()
+        );
+        assert!(endpoint.secret().starts_with("whsec_"));
+    }
+
+    #[test]
+    fn from_data_unknown_event() {
+        let errors = NewWebhookEndpoint::from_data(
+            Uuid::new_v4(),
+            endpoint_data(json!({
+                "url": "https://example.com/hooks",
+                "events": ["transaction.exploded"]
+            })),
+        )
+        .expect_err("endpoint should be invalid");
+
+        assert!(errors.field_errors().contains_key("events"));
+    }
+
+    #[test]
+    fn from_data_invalid_scheme() {
+        let errors = NewWebhookEndpoint::from_data(
+            Uuid::new_v4(),
+            endpoint_data(json!({
+                "url": "ftp://example.com/hooks",
+                "events": ["transaction.created"]
+            })),
+        )
+        .expect_err("endpoint should be invalid");
+
+        assert_eq!("scheme", errors.field_errors()["url"][0].code);
+    }
+
+    #[test]
+    fn from_data_threshold_required() {
+        let errors = NewWebhookEndpoint::from_data(
+            Uuid::new_v4(),
+            endpoint_data(json!({
+                "url": "https://example.com/hooks",
+                "events": ["balance.threshold_crossed"]
+            })),
+        )
+        .expect_err("endpoint should be invalid");
+
+        assert_eq!(
+            "required",
+            errors.field_errors()["balance_threshold"][0].code
+        );
+    }
+
+    #[test]
+    fn from_data_threshold_unused() {
+        let errors = NewWebhookEndpoint::from_data(
+            Uuid::new_v4(),
+            endpoint_data(json!({
+                "url": "https://example.com/hooks",
+                "events": ["transaction.created"],
+                "balance_threshold": {"account": "Assets:Checking", "currency": "USD", "amount": 0}
+            })),
+        )
+        .expect_err("endpoint should be invalid");
+
+        assert_eq!("unused", errors.field_errors()["balance_threshold"][0].code);
+    }
+
+    #[test]
+    fn sign_payload_known_value() {
+        // Computed with `printf '1684585136.{}' | openssl dgst -sha256 -hmac secret`.
+        assert_eq!(
+            "t=1684585136,v1=e24d410097164279739d1e15cd9e8d9127f75a441ddde4884d0c34b061c28fea",
+            sign_payload("secret", 1684585136, b"{}")
+        );
+    }
+
+    #[test]
+    fn is_public_address_rejects_internal() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public_address(ip.parse().unwrap()), "{} is public", ip);
+        }
+
+        for ip in ["93.184.216.34", "2606:2800:220:1:248:1893:25c8:1946"] {
+            assert!(
+                is_public_address(ip.parse().unwrap()),
+                "{} is not public",
+                ip
+            );
+        }
+    }
+
+    #[test]
+    fn retry_delay_backs_off() {
+        assert_eq!(Some(Duration::seconds(30)), retry_delay(1));
+        assert_eq!(Some(Duration::seconds(60)), retry_delay(2));
+        assert_eq!(Some(Duration::seconds(1920)), retry_delay(7));
+        assert_eq!(None, retry_delay(MAX_DELIVERY_ATTEMPTS));
+    }
+
+    #[test]
+    fn next_step_after_attempt() {
+        let now = Utc::now();
+        let mut delivery = WebhookDelivery {
+            id: 1,
+            endpoint_id: Uuid::new_v4(),
+            url: "https://example.com/hooks".to_owned(),
+            secret: generate_secret(),
+            event: WebhookEvent {
+                id: 1,
+                event_type: EventType::TransactionCreated,
+                created_at: now,
+                data: json!({}),
+            },
+            attempts: 0,
+        };
+        let failed = AttemptResult {
+            response_status: Some(500),
+            error: None,
+        };
+
+        assert_eq!(
+            NextStep::Delivered,
+            delivery.next_step(
+                &AttemptResult {
+                    response_status: Some(200),
+                    error: None
+                },
+                now
+            )
+        );
+        assert_eq!(
+            NextStep::RetryAt(now + Duration::seconds(30)),
+            delivery.next_step(&failed, now)
+        );
+
+        delivery.attempts = MAX_DELIVERY_ATTEMPTS - 1;
+        assert_eq!(NextStep::Abandon, delivery.next_step(&failed, now));
+    }
+
+    #[test]
+    fn attempt_result_succeeded() {
+        let result = |status| AttemptResult {
+            response_status: status,
+            error: None,
+        };
+
+        assert!(result(Some(204)).succeeded());
+        assert!(!result(Some(500)).succeeded());
+        assert!(!result(None).succeeded());
+    }
+}
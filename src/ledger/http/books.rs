@@ -0,0 +1,541 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRef, FromRequestParts, State},
+    http::{request::Parts, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post, put},
+    Router,
+};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    authentication::{AuthenticatedUser, Credential},
+    http_err::{ApiError, ApiResponse},
+    ledger::{
+        domain::books::{
+            AcceptInvitationError, BookData, DeleteBookError, MemberChangeError, NewInvitationData,
+            Role, SaveBookError,
+        },
+        services::{AttachmentService, BookService},
+    },
+    server::AppState,
+};
+
+use super::{
+    extract::{Json, Path},
+    reps,
+};
+
+/// The book a request is made against, along with the user making it.
+///
+/// Routes with a `book_id` parameter use that book. Other routes use the
+/// user's personal book. Users who are not members of the book are told it
+/// doesn't exist, so book IDs can't be probed.
+pub struct BookAccess {
+    pub book_id: Uuid,
+    pub user_id: String,
+    /// How the user authenticated.
+    pub credential: Credential,
+    pub role: Role,
+    /// The currency used for amounts that don't name one.
+    pub default_currency: Option<String>,
+}
+
+impl BookAccess {
+    /// Ensure the user's role in the book allows everything `role` does.
+    pub fn require(&self, role: Role) -> Result<(), ApiError> {
+        if self.role.includes(role) {
+            Ok(())
+        } else {
+            Err(ApiError::Forbidden(format!(
+                "This request requires the {} role in the book.",
+                role
+            )))
+        }
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for BookAccess {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let user = AuthenticatedUser::from_request_parts(parts, state).await?;
+
+        // Routes without any parameters have nothing to extract, which is
+        // the same as not naming a book.
+        let book_id = Path::<HashMap<String, String>>::from_request_parts(parts, state)
+            .await
+            .ok()
+            .and_then(|Path(params)| params.get("book_id").cloned())
+            .map(|book_id| book_id.parse::<Uuid>())
+            .transpose()
+            .map_err(|_| book_not_found().into_response())?;
+
+        let book_service = BookService::from_ref(state);
+        let membership = book_service
+            .get_membership(&user.user_id, book_id)
+            .await
+            .map_err(|error| {
+                error!(?error, "Failed to fetch book membership.");
+
+                ApiError::InternalServerError.into_response()
+            })?
+            .ok_or_else(|| book_not_found().into_response())?;
+
+        Ok(Self {
+            book_id: membership.book.id,
+            user_id: user.user_id,
+            credential: user.credential,
+            role: membership.role,
+            default_currency: membership.book.default_currency,
+        })
+    }
+}
+
+fn book_not_found() -> ApiError {
+    ApiError::NotFound("No book found with the provided ID.".to_owned())
+}
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/books", get(get_books).post(create_book))
+        .route(
+            "/books/:book_id",
+            get(get_book).put(update_book).delete(delete_book),
+        )
+        .route("/books/:book_id/members", get(get_members))
+        .route(
+            "/books/:book_id/members/:user_id",
+            put(set_member_role).delete(remove_member),
+        )
+        .route(
+            "/books/:book_id/invitations",
+            get(get_invitations).post(create_invitation),
+        )
+        .route(
+            "/books/:book_id/invitations/:invitation_id",
+            delete(revoke_invitation),
+        )
+        .route("/invitations/accept", post(accept_invitation))
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct AcceptInvitationData {
+    /// The token from the invitation.
+    token: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/invitations/accept",
+    tag = "books",
+    request_body = AcceptInvitationData,
+    responses(
+        (status = 200, description = "The user was added to the book.", body = Book),
+        (status = 404, description = "The invitation doesn't exist, has expired, or was already used.", body = ProblemRep),
+        (status = 409, description = "The user is already a member of the book.", body = ProblemRep),
+    )
+)]
+async fn accept_invitation(
+    user: AuthenticatedUser,
+    State(book_service): State<BookService>,
+    Json(data): Json<AcceptInvitationData>,
+) -> ApiResponse<Json<reps::Book>> {
+    match book_service
+        .accept_invitation(&data.token, &user.user_id)
+        .await
+    {
+        Ok(membership) => Ok(Json((&membership).into())),
+        Err(AcceptInvitationError::InvitationNotFound) => Err(ApiError::NotFound(
+            "No pending invitation found with the provided token.".to_owned(),
+        )),
+        Err(AcceptInvitationError::AlreadyMember) => Err(ApiError::Conflict(
+            "You are already a member of the book.".to_owned(),
+        )),
+        Err(AcceptInvitationError::Unknown(error)) => {
+            error!(?error, "Failed to accept invitation.");
+
+            Err(ApiError::InternalServerError)
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/books",
+    tag = "books",
+    request_body = BookData,
+    responses(
+        (status = 201, description = "The book was created, with the user as its owner.", body = Book),
+        (status = 400, description = "The request body is invalid, or the default currency does not exist.", body = ProblemRep),
+    )
+)]
+async fn create_book(
+    user: AuthenticatedUser,
+    State(book_service): State<BookService>,
+    Json(data): Json<BookData>,
+) -> ApiResponse<(StatusCode, Json<reps::Book>)> {
+    data.validate()?;
+
+    match book_service.create_book(&user.user_id, &data).await {
+        Ok(membership) => Ok((StatusCode::CREATED, Json((&membership).into()))),
+        Err(error) => Err(save_book_error(error)),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/books/{book_id}/invitations",
+    tag = "books",
+    params(("book_id" = Uuid, Path, description = "The ID of the book.")),
+    request_body = NewInvitationData,
+    responses(
+        (status = 201, description = "The invitation was created. Its token is only included in this response.", body = BookInvitation),
+        (status = 403, description = "The user is not an owner of the book.", body = ProblemRep),
+        (status = 404, description = "No book exists with the ID.", body = ProblemRep),
+    )
+)]
+async fn create_invitation(
+    book: BookAccess,
+    State(book_service): State<BookService>,
+    Json(data): Json<NewInvitationData>,
+) -> ApiResponse<(StatusCode, Json<reps::BookInvitation>)> {
+    book.require(Role::Owner)?;
+
+    match book_service
+        .create_invitation(book.book_id, &book.user_id, data)
+        .await
+    {
+        Ok((invitation, token)) => Ok((
+            StatusCode::CREATED,
+            Json(reps::BookInvitation::with_token(&invitation, token)),
+        )),
+        Err(error) => {
+            error!(?error, book_id = %book.book_id, "Failed to create invitation.");
+
+            Err(ApiError::InternalServerError)
+        }
+    }
+}
+
+/// Delete a book along with its accounts, transactions, and attachments.
+#[utoipa::path(
+    delete,
+    path = "/books/{book_id}",
+    tag = "books",
+    params(("book_id" = Uuid, Path, description = "The ID of the book.")),
+    responses(
+        (status = 204, description = "The book was deleted."),
+        (status = 403, description = "The user is not an owner of the book.", body = ProblemRep),
+        (status = 404, description = "No book exists with the ID.", body = ProblemRep),
+        (status = 409, description = "The book is a personal book, which can't be deleted.", body = ProblemRep),
+    )
+)]
+async fn delete_book(
+    book: BookAccess,
+    State(app_state): State<AppState>,
+) -> ApiResponse<StatusCode> {
+    book.require(Role::Owner)?;
+
+    let book_service = BookService::from_ref(&app_state);
+    let attachment_service = AttachmentService::from_ref(&app_state);
+
+    match book_service.delete_book(book.book_id).await {
+        Ok(attachments) => {
+            attachment_service
+                .remove_attachment_content(&attachments)
+                .await;
+
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Err(DeleteBookError::PersonalBook) => Err(ApiError::Conflict(
+            "Personal books can't be deleted.".to_owned(),
+        )),
+        Err(DeleteBookError::Unknown(error)) => {
+            error!(?error, book_id = %book.book_id, "Failed to delete book.");
+
+            Err(ApiError::InternalServerError)
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/books/{book_id}",
+    tag = "books",
+    params(("book_id" = Uuid, Path, description = "The ID of the book.")),
+    responses(
+        (status = 200, description = "The book.", body = Book),
+        (status = 404, description = "No book exists with the ID.", body = ProblemRep),
+    )
+)]
+async fn get_book(
+    book: BookAccess,
+    State(book_service): State<BookService>,
+) -> ApiResponse<Json<reps::Book>> {
+    match book_service
+        .get_membership(&book.user_id, Some(book.book_id))
+        .await
+    {
+        Ok(Some(membership)) => Ok(Json((&membership).into())),
+        Ok(None) => Err(book_not_found()),
+        Err(error) => {
+            error!(?error, book_id = %book.book_id, "Failed to fetch book.");
+
+            Err(ApiError::InternalServerError)
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/books",
+    tag = "books",
+    responses(
+        (status = 200, description = "The books the user is a member of, ordered by name.", body = [Book]),
+    )
+)]
+async fn get_books(
+    user: AuthenticatedUser,
+    State(book_service): State<BookService>,
+) -> ApiResponse<Json<Vec<reps::Book>>> {
+    match book_service.list_books(&user.user_id).await {
+        Ok(books) => Ok(Json(books.iter().map(Into::into).collect())),
+        Err(error) => {
+            error!(?error, "Failed to list books.");
+
+            Err(ApiError::InternalServerError)
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/books/{book_id}/invitations",
+    tag = "books",
+    params(("book_id" = Uuid, Path, description = "The ID of the book.")),
+    responses(
+        (status = 200, description = "The invitations that can still be accepted, oldest first.", body = [BookInvitation]),
+        (status = 403, description = "The user is not an owner of the book.", body = ProblemRep),
+        (status = 404, description = "No book exists with the ID.", body = ProblemRep),
+    )
+)]
+async fn get_invitations(
+    book: BookAccess,
+    State(book_service): State<BookService>,
+) -> ApiResponse<Json<Vec<reps::BookInvitation>>> {
+    book.require(Role::Owner)?;
+
+    match book_service.list_invitations(book.book_id).await {
+        Ok(invitations) => Ok(Json(invitations.iter().map(Into::into).collect())),
+        Err(error) => {
+            error!(?error, book_id = %book.book_id, "Failed to list invitations.");
+
+            Err(ApiError::InternalServerError)
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/books/{book_id}/members",
+    tag = "books",
+    params(("book_id" = Uuid, Path, description = "The ID of the book.")),
+    responses(
+        (status = 200, description = "The book's members in the order they joined.", body = [BookMember]),
+        (status = 404, description = "No book exists with the ID.", body = ProblemRep),
+    )
+)]
+async fn get_members(
+    book: BookAccess,
+    State(book_service): State<BookService>,
+) -> ApiResponse<Json<Vec<reps::BookMember>>> {
+    match book_service.list_members(book.book_id).await {
+        Ok(members) => Ok(Json(members.iter().map(Into::into).collect())),
+        Err(error) => {
+            error!(?error, book_id = %book.book_id, "Failed to list members.");
+
+            Err(ApiError::InternalServerError)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct MemberPath {
+    user_id: String,
+}
+
+/// Remove a member from a book.
+///
+/// Owners can remove any member, and every member can remove themselves to
+/// leave the book.
+#[utoipa::path(
+    delete,
+    path = "/books/{book_id}/members/{user_id}",
+    tag = "books",
+    params(("book_id" = Uuid, Path, description = "The ID of the book."), ("user_id" = String, Path, description = "The ID of the member.")),
+    responses(
+        (status = 204, description = "The member was removed."),
+        (status = 403, description = "The user is not an owner of the book.", body = ProblemRep),
+        (status = 404, description = "No book or member exists with the ID.", body = ProblemRep),
+        (status = 409, description = "The member is the book's last owner, or owns it as their personal book.", body = ProblemRep),
+    )
+)]
+async fn remove_member(
+    book: BookAccess,
+    State(book_service): State<BookService>,
+    Path(MemberPath { user_id }): Path<MemberPath>,
+) -> ApiResponse<StatusCode> {
+    if user_id != book.user_id {
+        book.require(Role::Owner)?;
+    }
+
+    match book_service.remove_member(book.book_id, &user_id).await {
+        Ok(()) => Ok(StatusCode::NO_CONTENT),
+        Err(error) => Err(member_change_error(error, book.book_id)),
+    }
+}
+
+#[derive(Deserialize)]
+struct InvitationPath {
+    invitation_id: Uuid,
+}
+
+#[utoipa::path(
+    delete,
+    path = "/books/{book_id}/invitations/{invitation_id}",
+    tag = "books",
+    params(("book_id" = Uuid, Path, description = "The ID of the book."), ("invitation_id" = Uuid, Path, description = "The ID of the invitation.")),
+    responses(
+        (status = 204, description = "The invitation was revoked."),
+        (status = 403, description = "The user is not an owner of the book.", body = ProblemRep),
+        (status = 404, description = "No book or pending invitation exists with the ID.", body = ProblemRep),
+    )
+)]
+async fn revoke_invitation(
+    book: BookAccess,
+    State(book_service): State<BookService>,
+    Path(InvitationPath { invitation_id }): Path<InvitationPath>,
+) -> ApiResponse<StatusCode> {
+    book.require(Role::Owner)?;
+
+    match book_service
+        .revoke_invitation(book.book_id, invitation_id)
+        .await
+    {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(ApiError::NotFound(
+            "No pending invitation found with the provided ID.".to_owned(),
+        )),
+        Err(error) => {
+            error!(?error, %invitation_id, "Failed to revoke invitation.");
+
+            Err(ApiError::InternalServerError)
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct SetMemberRoleData {
+    role: Role,
+}
+
+#[utoipa::path(
+    put,
+    path = "/books/{book_id}/members/{user_id}",
+    tag = "books",
+    params(("book_id" = Uuid, Path, description = "The ID of the book."), ("user_id" = String, Path, description = "The ID of the member.")),
+    request_body = SetMemberRoleData,
+    responses(
+        (status = 200, description = "The member's new role.", body = BookMember),
+        (status = 403, description = "The user is not an owner of the book.", body = ProblemRep),
+        (status = 404, description = "No book or member exists with the ID.", body = ProblemRep),
+        (status = 409, description = "The member is the book's last owner, or owns it as their personal book.", body = ProblemRep),
+    )
+)]
+async fn set_member_role(
+    book: BookAccess,
+    State(book_service): State<BookService>,
+    Path(MemberPath { user_id }): Path<MemberPath>,
+    Json(data): Json<SetMemberRoleData>,
+) -> ApiResponse<Json<reps::BookMember>> {
+    book.require(Role::Owner)?;
+
+    match book_service
+        .set_member_role(book.book_id, &user_id, data.role)
+        .await
+    {
+        Ok(member) => Ok(Json((&member).into())),
+        Err(error) => Err(member_change_error(error, book.book_id)),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/books/{book_id}",
+    tag = "books",
+    params(("book_id" = Uuid, Path, description = "The ID of the book.")),
+    request_body = BookData,
+    responses(
+        (status = 200, description = "The updated book.", body = Book),
+        (status = 400, description = "The request body is invalid, or the default currency does not exist.", body = ProblemRep),
+        (status = 403, description = "The user is not an owner of the book.", body = ProblemRep),
+        (status = 404, description = "No book exists with the ID.", body = ProblemRep),
+    )
+)]
+async fn update_book(
+    book: BookAccess,
+    State(book_service): State<BookService>,
+    Json(data): Json<BookData>,
+) -> ApiResponse<Json<reps::Book>> {
+    book.require(Role::Owner)?;
+    data.validate()?;
+
+    match book_service
+        .update_book(book.book_id, &book.user_id, &data)
+        .await
+    {
+        Ok(membership) => Ok(Json((&membership).into())),
+        Err(error) => Err(save_book_error(error)),
+    }
+}
+
+fn save_book_error(error: SaveBookError) -> ApiError {
+    match error {
+        SaveBookError::UnknownCurrency(currency) => ApiError::UnknownCurrency(currency),
+        SaveBookError::Unknown(error) => {
+            error!(?error, "Failed to save book.");
+
+            ApiError::InternalServerError
+        }
+    }
+}
+
+fn member_change_error(error: MemberChangeError, book_id: Uuid) -> ApiError {
+    match error {
+        MemberChangeError::MemberNotFound => {
+            ApiError::NotFound("No member found with the provided ID.".to_owned())
+        }
+        MemberChangeError::LastOwner => {
+            ApiError::Conflict("A book must have at least one owner.".to_owned())
+        }
+        MemberChangeError::PersonalBookOwner => ApiError::Conflict(
+            "The owner of a personal book can't leave it or be demoted.".to_owned(),
+        ),
+        MemberChangeError::Unknown(error) => {
+            error!(?error, %book_id, "Failed to change book member.");
+
+            ApiError::InternalServerError
+        }
+    }
+}
@@ -0,0 +1,162 @@
+//! Extractors that reject malformed requests with problem details.
+//!
+//! Axum's own `Json`, `Path` and `Query` extractors reject requests with a
+//! plain text body. These wrap them so that every rejection is an
+//! [`ApiError`], like any other error returned by the API. They are used in
+//! place of axum's extractors throughout the handlers.
+
+use async_trait::async_trait;
+use axum::{
+    extract::{
+        rejection::{JsonRejection, PathRejection, QueryRejection},
+        FromRequest, FromRequestParts,
+    },
+    http::{request::Parts, Request},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+
+use crate::http_err::ApiError;
+
+/// A JSON request or response body.
+pub struct Json<T>(pub T);
+
+#[async_trait]
+impl<T, S, B> FromRequest<S, B> for Json<T>
+where
+    axum::Json<T>: FromRequest<S, B, Rejection = JsonRejection>,
+    S: Send + Sync,
+    B: Send + 'static,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(request: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::Json(value) = axum::Json::from_request(request, state).await?;
+
+        Ok(Self(value))
+    }
+}
+
+impl<T: Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> Response {
+        axum::Json(self.0).into_response()
+    }
+}
+
+/// Parameters parsed from the request's path.
+pub struct Path<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Path<T>
+where
+    axum::extract::Path<T>: FromRequestParts<S, Rejection = PathRejection>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::extract::Path(value) =
+            axum::extract::Path::from_request_parts(parts, state).await?;
+
+        Ok(Self(value))
+    }
+}
+
+/// Parameters parsed from the request's query string.
+pub struct Query<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Query<T>
+where
+    axum::extract::Query<T>: FromRequestParts<S, Rejection = QueryRejection>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::extract::Query(value) =
+            axum::extract::Query::from_request_parts(parts, state).await?;
+
+        Ok(Self(value))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use axum::{
+        body::Body,
+        http::{header, StatusCode},
+    };
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::http_err::ErrorCode;
+
+    #[derive(Debug, Deserialize)]
+    struct Data {
+        value: i32,
+    }
+
+    async fn extract_json(content_type: &str, body: &'static str) -> Result<Json<Data>, ApiError> {
+        let request = Request::builder()
+            .header(header::CONTENT_TYPE, content_type)
+            .body(Body::from(body))
+            .unwrap();
+
+        Json::from_request(request, &()).await
+    }
+
+    async fn extract_query(uri: &str) -> Result<Query<Data>, ApiError> {
+        let (mut parts, _) = Request::builder().uri(uri).body(()).unwrap().into_parts();
+
+        Query::from_request_parts(&mut parts, &()).await
+    }
+
+    #[tokio::test]
+    async fn json_rejections_are_problems() {
+        for (content_type, body, code) in [
+            ("application/json", "{", ErrorCode::BadRequest),
+            (
+                "application/json",
+                r#"{"value": "one"}"#,
+                ErrorCode::BadRequest,
+            ),
+            (
+                "text/plain",
+                r#"{"value": 1}"#,
+                ErrorCode::UnsupportedMediaType,
+            ),
+        ] {
+            let error = extract_json(content_type, body)
+                .await
+                .err()
+                .expect("body should be rejected");
+
+            assert_eq!(code, error.code(), "{:?}", error);
+        }
+    }
+
+    #[tokio::test]
+    async fn json_valid() {
+        let Json(data) = extract_json("application/json", r#"{"value": 1}"#)
+            .await
+            .expect("body should be accepted");
+
+        assert_eq!(1, data.value);
+    }
+
+    #[tokio::test]
+    async fn query_rejection_is_problem() {
+        let response = extract_query("/?value=one")
+            .await
+            .err()
+            .expect("query should be rejected")
+            .into_response();
+
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        assert_eq!(
+            crate::http_err::PROBLEM_CONTENT_TYPE,
+            response.headers()[header::CONTENT_TYPE]
+        );
+    }
+}
@@ -0,0 +1,415 @@
+//! A GraphQL API for reading the ledger.
+//!
+//! This lets clients like the dashboard fetch accounts, balances, reports and
+//! transactions in a single request. Account balances and periodic balances
+//! are loaded in batches, so asking for the balances of every account in a
+//! list costs one query. Queries that are too deeply nested or ask for too
+//! many fields are rejected before anything is loaded.
+
+mod loaders;
+mod types;
+
+use async_graphql::{
+    dataloader::DataLoader, Context, EmptyMutation, EmptySubscription, ErrorExtensions, Object,
+    Schema,
+};
+use async_graphql_axum::{GraphQLRequest, GraphQLResponse};
+use axum::{extract::State, routing::post, Router};
+use tracing::error;
+use uuid::Uuid;
+
+use crate::{
+    http_err::ErrorCode, ledger::services::LedgerService, repos::transactions::TransactionQuery,
+    server::AppState,
+};
+
+use self::{
+    loaders::{AccountBalanceLoader, PeriodicBalanceLoader},
+    types::{Account, Transaction, TransactionPage},
+};
+
+use super::{books::BookAccess, reps::EncodedTransactionCursor};
+
+pub type LedgerSchema = Schema<Query, EmptyMutation, EmptySubscription>;
+
+/// The deepest nesting of fields a query may have. The schema's deepest
+/// meaningful query, the periodic balances of the accounts in a page of
+/// transactions, is 7 levels deep.
+const MAX_QUERY_DEPTH: usize = 10;
+
+/// The most fields a query may select, counting each alias separately.
+const MAX_QUERY_COMPLEXITY: usize = 250;
+
+/// The ID of the book a request is made against.
+struct CurrentBook(Uuid);
+
+pub fn build_schema() -> LedgerSchema {
+    Schema::build(Query, EmptyMutation, EmptySubscription)
+        .limit_depth(MAX_QUERY_DEPTH)
+        .limit_complexity(MAX_QUERY_COMPLEXITY)
+        .finish()
+}
+
+pub fn routes() -> Router<AppState> {
+    Router::new().route("/graphql", post(execute))
+}
+
+async fn execute(
+    book: BookAccess,
+    State(schema): State<LedgerSchema>,
+    State(ledger_service): State<LedgerService>,
+    request: GraphQLRequest,
+) -> GraphQLResponse {
+    // Loaders cache what they load, so they are created for each request to
+    // avoid serving stale balances or leaking them between books.
+    let balance_loader = DataLoader::new(
+        AccountBalanceLoader::new(ledger_service.account_queries.clone(), book.book_id),
+        tokio::spawn,
+    );
+    let periodic_balance_loader = DataLoader::new(
+        PeriodicBalanceLoader::new(ledger_service.account_queries.clone(), book.book_id),
+        tokio::spawn,
+    );
+
+    let request = request
+        .into_inner()
+        .data(CurrentBook(book.book_id))
+        .data(ledger_service)
+        .data(balance_loader)
+        .data(periodic_balance_loader);
+
+    schema.execute(request).await.into()
+}
+
+/// Create an error with the same `code` extension as the REST API's problem
+/// documents.
+fn coded_error(code: ErrorCode, message: &str) -> async_graphql::Error {
+    async_graphql::Error::new(message).extend_with(|_, extensions| {
+        extensions.set("code", code.as_str());
+    })
+}
+
+/// Log an unexpected error and hide its details from the client.
+fn internal_error(error: anyhow::Error) -> async_graphql::Error {
+    error!(?error, "Failed to resolve GraphQL field.");
+
+    coded_error(ErrorCode::InternalError, "Internal server error.")
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    /// The book's accounts, most used first.
+    async fn accounts(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(desc = "Only include accounts whose names contain this text.")] search: Option<
+            String,
+        >,
+    ) -> async_graphql::Result<Vec<Account>> {
+        let CurrentBook(book_id) = ctx.data()?;
+        let ledger_service = ctx.data::<LedgerService>()?;
+
+        let names = ledger_service
+            .list_accounts(*book_id, search)
+            .await
+            .map_err(internal_error)?;
+
+        Ok(names.into_iter().map(Account::new).collect())
+    }
+
+    /// The book's recently active accounts.
+    async fn active_accounts(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<Account>> {
+        let CurrentBook(book_id) = ctx.data()?;
+        let ledger_service = ctx.data::<LedgerService>()?;
+
+        let names = ledger_service
+            .list_active_accounts(*book_id)
+            .await
+            .map_err(internal_error)?;
+
+        Ok(names.into_iter().map(Account::new).collect())
+    }
+
+    /// A single account. Balances and reports for the account include its
+    /// child accounts.
+    async fn account(&self, name: String) -> Account {
+        Account::new(name)
+    }
+
+    /// A page of transactions, most recent first.
+    async fn transactions(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(
+            desc = "Only include transactions with an entry for this account or one of \
+                          its children."
+        )]
+        account: Option<String>,
+        #[graphql(desc = "The `next` cursor from the previous page.")] after: Option<String>,
+    ) -> async_graphql::Result<TransactionPage> {
+        let CurrentBook(book_id) = ctx.data()?;
+        let ledger_service = ctx.data::<LedgerService>()?;
+
+        let after = after
+            .map(|cursor| cursor.parse::<EncodedTransactionCursor>())
+            .transpose()
+            .map_err(|_| coded_error(ErrorCode::BadRequest, "The cursor is malformed."))?;
+
+        let query = TransactionQuery {
+            book_id: *book_id,
+            after: after.as_ref().map(|cursor| (&cursor.0).into()),
+            account,
+            trashed: false,
+        };
+        let transactions = ledger_service
+            .list_transactions(query)
+            .await
+            .map_err(internal_error)?;
+
+        Ok(TransactionPage {
+            items: transactions.items.into_iter().map(Transaction).collect(),
+            next: transactions
+                .next
+                .map(|cursor| EncodedTransactionCursor::from(cursor).to_string()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, sync::Arc};
+
+    use async_trait::async_trait;
+    use chrono::NaiveDate;
+    use tokio::sync::Mutex;
+
+    use super::*;
+    use crate::ledger::{
+        domain::{
+            closing::AccountBalance,
+            currency::{Currency, CurrencyAmount},
+            reports::InstantBalances,
+        },
+        queries::{AccountQueries, ReportInterval},
+    };
+
+    const BOOK_ID: Uuid = Uuid::from_u128(0x1b00c);
+
+    /// Account queries that only answer balance lookups, and record each
+    /// batch of accounts they were asked for.
+    #[derive(Default)]
+    struct RecordingAccountQueries {
+        batches: Mutex<Vec<Vec<String>>>,
+        periodic_batches: Mutex<Vec<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl AccountQueries for RecordingAccountQueries {
+        async fn get_account_balance(
+            &self,
+            _book_id: Uuid,
+            _account_name: String,
+        ) -> anyhow::Result<Vec<CurrencyAmount>> {
+            Err(anyhow::anyhow!("Unexpected query."))
+        }
+
+        async fn get_account_balances(
+            &self,
+            book_id: Uuid,
+            account_names: &[String],
+        ) -> anyhow::Result<HashMap<String, Vec<CurrencyAmount>>> {
+            assert_eq!(BOOK_ID, book_id);
+
+            let mut batch = account_names.to_vec();
+            batch.sort();
+            self.batches.lock().await.push(batch);
+
+            let usd = Currency::new("USD".to_owned(), 2);
+
+            Ok(account_names
+                .iter()
+                .filter(|name| name.as_str() == "Assets")
+                .map(|name| {
+                    (
+                        name.clone(),
+                        vec![CurrencyAmount::from_minor(usd.clone(), 1250)],
+                    )
+                })
+                .collect())
+        }
+
+        async fn get_monthly_balance(
+            &self,
+            _book_id: Uuid,
+            _account_name: &str,
+        ) -> anyhow::Result<HashMap<NaiveDate, Vec<CurrencyAmount>>> {
+            Err(anyhow::anyhow!("Unexpected query."))
+        }
+
+        async fn get_period_balances(
+            &self,
+            _book_id: Uuid,
+            _account_roots: &[&str],
+            _start: NaiveDate,
+            _end: NaiveDate,
+            _exclude_transaction: Option<Uuid>,
+        ) -> anyhow::Result<Vec<AccountBalance>> {
+            Err(anyhow::anyhow!("Unexpected query."))
+        }
+
+        async fn list_accounts_by_popularity(
+            &self,
+            _book_id: Uuid,
+            _search_string: Option<String>,
+        ) -> anyhow::Result<Vec<String>> {
+            Err(anyhow::anyhow!("Unexpected query."))
+        }
+
+        async fn list_active_accounts(&self, _book_id: Uuid) -> anyhow::Result<Vec<String>> {
+            Err(anyhow::anyhow!("Unexpected query."))
+        }
+
+        async fn periodic_cumulative_balance(
+            &self,
+            _book_id: Uuid,
+            _account: &str,
+            _interval: ReportInterval,
+        ) -> anyhow::Result<HashMap<String, InstantBalances>> {
+            Err(anyhow::anyhow!("Unexpected query."))
+        }
+
+        async fn periodic_cumulative_balances(
+            &self,
+            book_id: Uuid,
+            accounts: &[String],
+            _interval: ReportInterval,
+        ) -> anyhow::Result<HashMap<String, HashMap<String, InstantBalances>>> {
+            assert_eq!(BOOK_ID, book_id);
+
+            let mut batch = accounts.to_vec();
+            batch.sort();
+            self.periodic_batches.lock().await.push(batch);
+
+            let usd = Currency::new("USD".to_owned(), 2);
+            let instant = NaiveDate::from_ymd_opt(2023, 5, 1).unwrap();
+
+            Ok(accounts
+                .iter()
+                .filter(|name| name.as_str() == "Assets")
+                .map(|name| {
+                    (
+                        name.clone(),
+                        HashMap::from([(
+                            "USD".to_owned(),
+                            InstantBalances::new_with_balance(usd.clone(), instant, 1250),
+                        )]),
+                    )
+                })
+                .collect())
+        }
+    }
+
+    fn request(
+        account_queries: &Arc<RecordingAccountQueries>,
+        query: &str,
+    ) -> async_graphql::Request {
+        let balance_loader = DataLoader::new(
+            AccountBalanceLoader::new(account_queries.clone(), BOOK_ID),
+            tokio::spawn,
+        );
+        let periodic_balance_loader = DataLoader::new(
+            PeriodicBalanceLoader::new(account_queries.clone(), BOOK_ID),
+            tokio::spawn,
+        );
+
+        async_graphql::Request::new(query)
+            .data(CurrentBook(BOOK_ID))
+            .data(balance_loader)
+            .data(periodic_balance_loader)
+    }
+
+    #[tokio::test]
+    async fn account_balances_are_batched() {
+        let account_queries = Arc::new(RecordingAccountQueries::default());
+
+        let request = request(
+            &account_queries,
+            r#"{
+                assets: account(name: "Assets") { balance { value currency { code minorUnits } } }
+                expenses: account(name: "Expenses") { balance { value } }
+                again: account(name: "Assets") { balance { value } }
+            }"#,
+        );
+
+        let response = build_schema().execute(request).await;
+
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        assert_eq!(
+            serde_json::json!({
+                "assets": { "balance": [{ "value": 1250, "currency": { "code": "USD", "minorUnits": 2 } }] },
+                "expenses": { "balance": [] },
+                "again": { "balance": [{ "value": 1250 }] },
+            }),
+            response.data.into_json().unwrap()
+        );
+        assert_eq!(
+            vec![vec!["Assets".to_owned(), "Expenses".to_owned()]],
+            *account_queries.batches.lock().await
+        );
+    }
+
+    #[tokio::test]
+    async fn periodic_balances_are_batched() {
+        let account_queries = Arc::new(RecordingAccountQueries::default());
+
+        let request = request(
+            &account_queries,
+            r#"{
+                assets: account(name: "Assets") { periodicBalances { currency { code } balances { instant balance } } }
+                expenses: account(name: "Expenses") { periodicBalances { balances { balance } } }
+                daily: account(name: "Assets") { periodicBalances(interval: DAILY) { balances { balance } } }
+            }"#,
+        );
+
+        let response = build_schema().execute(request).await;
+
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        assert_eq!(
+            serde_json::json!({
+                "assets": { "periodicBalances": [{ "currency": { "code": "USD" }, "balances": [{ "instant": "2023-05-01", "balance": 1250 }] }] },
+                "expenses": { "periodicBalances": [] },
+                "daily": { "periodicBalances": [{ "balances": [{ "balance": 1250 }] }] },
+            }),
+            response.data.into_json().unwrap()
+        );
+
+        // One query for each interval.
+        let mut batches = account_queries.periodic_batches.lock().await.clone();
+        batches.sort();
+        assert_eq!(
+            vec![
+                vec!["Assets".to_owned()],
+                vec!["Assets".to_owned(), "Expenses".to_owned()]
+            ],
+            batches
+        );
+    }
+
+    #[tokio::test]
+    async fn complex_queries_are_rejected() {
+        let account_queries = Arc::new(RecordingAccountQueries::default());
+        let fields = (0..MAX_QUERY_COMPLEXITY)
+            .map(|i| format!(r#"a{}: account(name: "Assets") {{ name }}"#, i))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let response = build_schema()
+            .execute(request(&account_queries, &format!("{{ {} }}", fields)))
+            .await;
+
+        assert_eq!(1, response.errors.len(), "{:?}", response.errors);
+        assert!(account_queries.batches.lock().await.is_empty());
+    }
+}
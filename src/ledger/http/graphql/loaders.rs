@@ -0,0 +1,101 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_graphql::dataloader::Loader;
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use crate::ledger::{
+    domain::{currency::CurrencyAmount, reports::InstantBalances},
+    queries::DynAccountQueries,
+};
+
+use super::types::ReportInterval;
+
+/// Loads the current balances of a book's accounts, batching every balance
+/// requested while resolving a query into a single database query.
+pub struct AccountBalanceLoader {
+    account_queries: DynAccountQueries,
+    book_id: Uuid,
+}
+
+impl AccountBalanceLoader {
+    pub fn new(account_queries: DynAccountQueries, book_id: Uuid) -> Self {
+        Self {
+            account_queries,
+            book_id,
+        }
+    }
+}
+
+#[async_trait]
+impl Loader<String> for AccountBalanceLoader {
+    type Value = Vec<CurrencyAmount>;
+    type Error = Arc<anyhow::Error>;
+
+    async fn load(&self, keys: &[String]) -> Result<HashMap<String, Self::Value>, Self::Error> {
+        self.account_queries
+            .get_account_balances(self.book_id, keys)
+            .await
+            .map_err(Arc::new)
+    }
+}
+
+/// An account and the interval between the balances reported for it.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct PeriodicBalanceKey {
+    pub account: String,
+    pub interval: ReportInterval,
+}
+
+/// Loads the periodic balances of a book's accounts, making one database
+/// query for each interval requested while resolving a query.
+pub struct PeriodicBalanceLoader {
+    account_queries: DynAccountQueries,
+    book_id: Uuid,
+}
+
+impl PeriodicBalanceLoader {
+    pub fn new(account_queries: DynAccountQueries, book_id: Uuid) -> Self {
+        Self {
+            account_queries,
+            book_id,
+        }
+    }
+}
+
+#[async_trait]
+impl Loader<PeriodicBalanceKey> for PeriodicBalanceLoader {
+    /// Balances keyed by currency code.
+    type Value = HashMap<String, InstantBalances>;
+    type Error = Arc<anyhow::Error>;
+
+    async fn load(
+        &self,
+        keys: &[PeriodicBalanceKey],
+    ) -> Result<HashMap<PeriodicBalanceKey, Self::Value>, Self::Error> {
+        let mut accounts_by_interval: HashMap<ReportInterval, Vec<String>> = HashMap::new();
+        for key in keys {
+            accounts_by_interval
+                .entry(key.interval)
+                .or_default()
+                .push(key.account.clone());
+        }
+
+        let mut result = HashMap::with_capacity(keys.len());
+        for (interval, accounts) in accounts_by_interval {
+            let balances = self
+                .account_queries
+                .periodic_cumulative_balances(self.book_id, &accounts, interval.into())
+                .await
+                .map_err(Arc::new)?;
+
+            result.extend(
+                balances.into_iter().map(|(account, balances)| {
+                    (PeriodicBalanceKey { account, interval }, balances)
+                }),
+            );
+        }
+
+        Ok(result)
+    }
+}
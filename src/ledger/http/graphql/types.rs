@@ -0,0 +1,153 @@
+use std::sync::Arc;
+
+use async_graphql::{dataloader::DataLoader, Context, Enum, Object, SimpleObject};
+use chrono::{DateTime, NaiveDate, Utc};
+use uuid::Uuid;
+
+use crate::ledger::{domain, queries};
+
+use super::super::reps;
+
+use super::{
+    internal_error,
+    loaders::{AccountBalanceLoader, PeriodicBalanceKey, PeriodicBalanceLoader},
+};
+
+/// An account, identified by its name. Child accounts are separated from
+/// their parents by a `:`, eg. `Assets:Checking`.
+pub struct Account {
+    name: String,
+}
+
+impl Account {
+    pub fn new(name: String) -> Self {
+        Self { name }
+    }
+}
+
+#[Object]
+impl Account {
+    async fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The current balance in each currency, including child accounts.
+    async fn balance(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<reps::CurrencyAmount>> {
+        let loader = ctx.data::<DataLoader<AccountBalanceLoader>>()?;
+
+        let balances = loader
+            .load_one(self.name.clone())
+            .await
+            .map_err(|error: Arc<anyhow::Error>| internal_error(anyhow::anyhow!("{:?}", error)))?;
+
+        Ok(balances
+            .unwrap_or_default()
+            .iter()
+            .map(reps::CurrencyAmount::from)
+            .collect())
+    }
+
+    /// The balance at the end of each interval in each currency, ordered by
+    /// currency code.
+    async fn periodic_balances(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default)] interval: ReportInterval,
+    ) -> async_graphql::Result<Vec<reps::CurrencyInstantBalances>> {
+        let loader = ctx.data::<DataLoader<PeriodicBalanceLoader>>()?;
+
+        let balances = loader
+            .load_one(PeriodicBalanceKey {
+                account: self.name.clone(),
+                interval,
+            })
+            .await
+            .map_err(|error: Arc<anyhow::Error>| internal_error(anyhow::anyhow!("{:?}", error)))?;
+
+        let mut balances: Vec<_> = balances.unwrap_or_default().into_iter().collect();
+        balances.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        Ok(balances
+            .into_iter()
+            .map(|(_, balances)| balances.into())
+            .collect())
+    }
+}
+
+/// The interval between the balances in a report.
+#[derive(Clone, Copy, Debug, Default, Enum, Eq, Hash, PartialEq)]
+pub enum ReportInterval {
+    Daily,
+    #[default]
+    Monthly,
+    Weekly,
+}
+
+impl From<ReportInterval> for queries::ReportInterval {
+    fn from(interval: ReportInterval) -> Self {
+        match interval {
+            ReportInterval::Daily => Self::Daily,
+            ReportInterval::Monthly => Self::Monthly,
+            ReportInterval::Weekly => Self::Weekly,
+        }
+    }
+}
+
+pub struct Transaction(pub domain::transactions::Transaction);
+
+#[Object]
+impl Transaction {
+    async fn id(&self) -> Uuid {
+        self.0.id
+    }
+
+    async fn date(&self) -> NaiveDate {
+        self.0.date
+    }
+
+    async fn payee(&self) -> &str {
+        &self.0.payee
+    }
+
+    async fn notes(&self) -> &str {
+        &self.0.notes
+    }
+
+    async fn entries(&self) -> Vec<TransactionEntry> {
+        self.0
+            .entries
+            .iter()
+            .cloned()
+            .map(TransactionEntry)
+            .collect()
+    }
+
+    async fn created_at(&self) -> DateTime<Utc> {
+        self.0.created_at
+    }
+
+    async fn updated_at(&self) -> DateTime<Utc> {
+        self.0.updated_at
+    }
+}
+
+pub struct TransactionEntry(domain::transactions::TransactionEntry);
+
+#[Object]
+impl TransactionEntry {
+    async fn account(&self) -> Account {
+        Account::new(self.0.account().to_owned())
+    }
+
+    async fn amount(&self) -> reps::CurrencyAmount {
+        self.0.amount().into()
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct TransactionPage {
+    pub items: Vec<Transaction>,
+    /// The cursor to pass as `after` to get the next page. This is absent on
+    /// the last page.
+    pub next: Option<String>,
+}
@@ -1,38 +1,65 @@
-use std::collections::HashMap;
-
+use anyhow::Context;
 use axum::{
-    extract::{FromRef, Path, Query, State},
-    http::StatusCode,
-    response::IntoResponse,
-    routing::get,
-    Json, Router,
+    body::{Bytes, HttpBody},
+    extract::{FromRef, RawBody, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
+    routing::{delete, get, post},
+    Router,
 };
-use axum_jwks::Claims;
 use chrono::NaiveDate;
+use futures_util::{stream, Stream, StreamExt};
 use serde::Deserialize;
+use tokio::sync::broadcast::error::RecvError;
 use tracing::{debug, error};
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
+use validator::{ValidationError, ValidationErrors};
 
 use crate::{
-    authentication::TokenClaims,
-    database::PostgresConnection,
-    http_err::{ApiError, ApiResponse, ErrorRep},
+    authentication::Credential,
+    http_err::{ApiError, ApiResponse, ErrorCode, ProblemRep, PROBLEM_CONTENT_TYPE},
     ledger::{
-        domain::transactions::{NewTransaction, NewTransactionData},
+        domain::{
+            attachments::NewAttachmentError,
+            batch::{BatchData, BatchOperation},
+            books::Role,
+            closing::{ClosingError, DEFAULT_EQUITY_ACCOUNT},
+            concurrency::{self, Precondition},
+            idempotency::{self, Replay, StoredResponse},
+            transactions::{NewTransaction, NewTransactionData},
+            webhooks::{NewWebhookEndpoint, NewWebhookEndpointData},
+        },
+        notifications::ChangeNotifier,
         queries::ReportInterval,
-        services::{AccountBalanceType, LedgerService},
+        services::{
+            AccountBalanceType, AddAttachmentError, AttachmentService, CreateWebhookEndpointError,
+            IdempotencyService, LedgerService, WebhookService,
+        },
     },
+    monitoring,
     repos::transactions::TransactionQuery,
     server::AppState,
+    shutdown::ShutdownSignal,
 };
 
 use crate::ledger::{
-    commands::{postgres::PostgresCommands, TransactionCommands, UpdateTransactionError},
+    commands::{
+        ApplyBatchError, BatchOperationError, BatchOperationResult, ClosePeriodError,
+        DeleteTransactionError, PersistTransactionError, RestoreTransactionError,
+        RestoreVersionError, UpdateTransactionError,
+    },
     domain,
-    queries::{postgres::PostgresQueries, AccountQueries, TransactionQueries},
 };
 
-use super::reps::{self, PeriodicAccountBalances};
+use super::{
+    books::BookAccess,
+    extract::{Json, Path, Query},
+    reps::{self, PeriodicAccountBalances},
+};
 
 pub fn routes() -> Router<AppState> {
     Router::new()
@@ -47,31 +74,341 @@ pub fn routes() -> Router<AppState> {
             get(get_account_balance_periodic),
         )
         .route("/active-accounts", get(get_active_accounts))
+        .route("/changes", get(get_changes))
+        .route("/events", get(stream_events))
+        .route("/closings", post(close_period))
+        .route("/lock", get(get_ledger_lock).put(set_ledger_lock))
+        .route("/lock/history", get(get_lock_changes))
+        .route("/trash", get(get_trash))
+        .route("/trash/:transaction_id", delete(purge_trashed_transaction))
+        .route(
+            "/trash/:transaction_id/restore",
+            post(restore_trashed_transaction),
+        )
         .route(
             "/transactions",
             get(get_transactions).post(create_transaction),
         )
+        .route("/transactions/batch", post(apply_transaction_batch))
         .route(
             "/transactions/:transaction_id",
             get(get_transaction)
                 .put(update_transaction)
                 .delete(delete_transaction),
         )
+        .route(
+            "/transactions/:transaction_id/history",
+            get(get_transaction_history),
+        )
+        .route(
+            "/transactions/:transaction_id/history/:version/restore",
+            post(restore_transaction_version),
+        )
+}
+
+/// Routes for features that need the ledger's transactions to be in the
+/// application database. Attachments reference their transactions there, and
+/// webhook events are queued by the same database transaction as the change
+/// they describe, so these aren't served when the ledger is kept in memory.
+pub fn database_routes() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/transactions/:transaction_id/attachments",
+            get(get_attachments).post(create_attachment),
+        )
+        .route(
+            "/transactions/:transaction_id/attachments/:attachment_id",
+            get(get_attachment_content).delete(delete_attachment),
+        )
+        .route("/webhooks", get(get_webhooks).post(create_webhook))
+        .route("/webhooks/:endpoint_id", delete(delete_webhook))
+        .route(
+            "/webhooks/:endpoint_id/deliveries",
+            get(get_webhook_deliveries),
+        )
+}
+
+// Path parameters are extracted by name since the routes are also nested
+// under a book's ID.
+
+#[derive(Deserialize)]
+struct AccountPath {
+    account: String,
+}
+
+#[derive(Deserialize)]
+struct AttachmentPath {
+    transaction_id: Uuid,
+    attachment_id: Uuid,
+}
+
+#[derive(Deserialize)]
+struct EndpointPath {
+    endpoint_id: Uuid,
+}
+
+#[derive(Deserialize)]
+struct TransactionPath {
+    transaction_id: Uuid,
+}
+
+#[derive(Deserialize)]
+struct VersionPath {
+    transaction_id: Uuid,
+    version: i32,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct CreateAttachmentParams {
+    /// The name of the uploaded file.
+    file_name: Option<String>,
 }
 
+#[utoipa::path(
+    post,
+    path = "/ledger/transactions/{transaction_id}/attachments",
+    tag = "attachments",
+    params(("transaction_id" = Uuid, Path, description = "The ID of the transaction."), CreateAttachmentParams),
+    request_body(
+        content = [u8],
+        content_type = "application/octet-stream",
+        description = "The content of the file. The `Content-Type` header must be set to the file's type, such as `application/pdf` or `image/png`.",
+    ),
+    responses(
+        (status = 201, description = "The attachment was added.", body = Attachment),
+        (status = 400, description = "The file is empty.", body = ProblemRep),
+        (status = 404, description = "No transaction exists with the ID.", body = ProblemRep),
+        (status = 413, description = "The file is too large, or would exceed the book's storage quota.", body = ProblemRep),
+        (status = 415, description = "The file's type is not supported, or its content does not match the type.", body = ProblemRep),
+    )
+)]
+async fn create_attachment(
+    book: BookAccess,
+    State(attachment_service): State<AttachmentService>,
+    Path(TransactionPath { transaction_id }): Path<TransactionPath>,
+    Query(params): Query<CreateAttachmentParams>,
+    headers: HeaderMap,
+    RawBody(mut body): RawBody,
+) -> ApiResponse<(StatusCode, Json<reps::Attachment>)> {
+    book.require(Role::Editor)?;
+
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+
+    // The body is read incrementally so that oversized uploads are rejected
+    // without buffering the entire request.
+    let max_size = attachment_service.limits().max_size;
+    let mut content = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|error| {
+            debug!(?error, "Failed to read attachment body.");
+
+            ApiError::BadRequestReason("Failed to read request body.".to_owned())
+        })?;
+
+        if (content.len() + chunk.len()) as u64 > max_size {
+            return Err(ApiError::PayloadTooLarge(format!(
+                "Attachments may not be larger than {} bytes.",
+                max_size
+            )));
+        }
+
+        content.extend_from_slice(&chunk);
+    }
+
+    match attachment_service
+        .add_attachment(
+            book.book_id,
+            transaction_id,
+            params.file_name.as_deref().unwrap_or_default(),
+            content_type,
+            Bytes::from(content),
+        )
+        .await
+    {
+        Ok(attachment) => Ok((StatusCode::CREATED, Json((&attachment).into()))),
+        Err(AddAttachmentError::TransactionNotFound) => Err(ApiError::NotFound(
+            "No transaction found with the provided ID.".to_owned(),
+        )),
+        Err(AddAttachmentError::Invalid(error)) => Err(match error {
+            NewAttachmentError::Empty => {
+                ApiError::BadRequestReason("Attachments may not be empty.".to_owned())
+            }
+            NewAttachmentError::UnsupportedContentType(content_type) => {
+                ApiError::UnsupportedMediaType(format!(
+                    "Attachments of type '{}' are not supported.",
+                    content_type
+                ))
+            }
+            NewAttachmentError::ContentMismatch(content_type) => {
+                ApiError::UnsupportedMediaType(format!(
+                    "The attachment content is not a valid '{}' file.",
+                    content_type
+                ))
+            }
+            NewAttachmentError::TooLarge { max_size, .. } => ApiError::PayloadTooLarge(format!(
+                "Attachments may not be larger than {} bytes.",
+                max_size
+            )),
+            NewAttachmentError::QuotaExceeded { used, quota, .. } => {
+                ApiError::PayloadTooLarge(format!(
+                    "The attachment would exceed the storage quota. {} of {} bytes are in use.",
+                    used, quota
+                ))
+            }
+        }),
+        Err(AddAttachmentError::Unknown(error)) => {
+            error!(?error, %transaction_id, "Failed to add attachment.");
+
+            Err(ApiError::InternalServerError)
+        }
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/ledger/transactions/{transaction_id}/attachments/{attachment_id}",
+    tag = "attachments",
+    params(("transaction_id" = Uuid, Path, description = "The ID of the transaction."), ("attachment_id" = Uuid, Path, description = "The ID of the attachment.")),
+    responses(
+        (status = 204, description = "The attachment was deleted."),
+        (status = 404, description = "No attachment exists with the ID.", body = ProblemRep),
+    )
+)]
+async fn delete_attachment(
+    book: BookAccess,
+    State(attachment_service): State<AttachmentService>,
+    Path(AttachmentPath {
+        transaction_id,
+        attachment_id,
+    }): Path<AttachmentPath>,
+) -> ApiResponse<StatusCode> {
+    book.require(Role::Editor)?;
+
+    match attachment_service
+        .delete_attachment(book.book_id, transaction_id, attachment_id)
+        .await
+    {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(ApiError::NotFound(
+            "No attachment found with the provided ID.".to_owned(),
+        )),
+        Err(error) => {
+            error!(?error, %attachment_id, "Failed to delete attachment.");
+
+            Err(ApiError::InternalServerError)
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/ledger/transactions/{transaction_id}/attachments/{attachment_id}",
+    tag = "attachments",
+    params(("transaction_id" = Uuid, Path, description = "The ID of the transaction."), ("attachment_id" = Uuid, Path, description = "The ID of the attachment.")),
+    responses(
+        (status = 200, description = "The content of the attachment, served with the type it was uploaded with.", body = [u8], content_type = "application/octet-stream"),
+        (status = 404, description = "No attachment exists with the ID.", body = ProblemRep),
+    )
+)]
+async fn get_attachment_content(
+    book: BookAccess,
+    State(attachment_service): State<AttachmentService>,
+    Path(AttachmentPath {
+        transaction_id,
+        attachment_id,
+    }): Path<AttachmentPath>,
+) -> ApiResponse<impl IntoResponse> {
+    match attachment_service
+        .get_attachment_content(book.book_id, transaction_id, attachment_id)
+        .await
+    {
+        Ok(Some((attachment, content))) => {
+            let content_type = HeaderValue::from_str(&attachment.content_type)
+                .unwrap_or(HeaderValue::from_static("application/octet-stream"));
+            let disposition = HeaderValue::from_str(&format!(
+                "attachment; filename=\"{}\"",
+                attachment.file_name
+            ))
+            .unwrap_or(HeaderValue::from_static("attachment"));
+
+            Ok((
+                [
+                    (header::CONTENT_TYPE, content_type),
+                    (header::CONTENT_DISPOSITION, disposition),
+                ],
+                content,
+            ))
+        }
+        Ok(None) => Err(ApiError::NotFound(
+            "No attachment found with the provided ID.".to_owned(),
+        )),
+        Err(error) => {
+            error!(?error, %attachment_id, "Failed to fetch attachment.");
+
+            Err(ApiError::InternalServerError)
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/ledger/transactions/{transaction_id}/attachments",
+    tag = "attachments",
+    params(("transaction_id" = Uuid, Path, description = "The ID of the transaction.")),
+    responses(
+        (status = 200, description = "The transaction's attachments.", body = [Attachment]),
+    )
+)]
+async fn get_attachments(
+    book: BookAccess,
+    State(attachment_service): State<AttachmentService>,
+    Path(TransactionPath { transaction_id }): Path<TransactionPath>,
+) -> ApiResponse<Json<Vec<reps::Attachment>>> {
+    match attachment_service
+        .list_attachments(book.book_id, transaction_id)
+        .await
+    {
+        Ok(attachments) => Ok(Json(attachments.iter().map(Into::into).collect())),
+        Err(error) => {
+            error!(?error, %transaction_id, "Failed to list attachments.");
+
+            Err(ApiError::InternalServerError)
+        }
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/ledger/transactions/{transaction_id}",
+    tag = "transactions",
+    params(("transaction_id" = Uuid, Path, description = "The ID of the transaction.")),
+    responses(
+        (status = 204, description = "The transaction was moved to the trash."),
+        (status = 404, description = "No transaction exists with the ID.", body = ProblemRep),
+        (status = 409, description = "The transaction is dated on or before the ledger's lock date.", body = ProblemRep),
+    )
+)]
 async fn delete_transaction(
-    Claims(claims): Claims<TokenClaims>,
-    State(app_state): State<AppState>,
-    Path(transaction_id): Path<Uuid>,
+    book: BookAccess,
+    State(ledger_service): State<LedgerService>,
+    Path(TransactionPath { transaction_id }): Path<TransactionPath>,
 ) -> ApiResponse<StatusCode> {
-    let db = PostgresConnection::from_ref(&app_state);
-    let commands = PostgresCommands(&db);
+    book.require(Role::Editor)?;
 
-    match commands
-        .delete_transaction(claims.user_id(), transaction_id)
+    match ledger_service
+        .transaction_commands
+        .delete_transaction(book.book_id, &book.user_id, transaction_id)
         .await
     {
         Ok(()) => Ok(StatusCode::NO_CONTENT),
+        Err(DeleteTransactionError::TransactionNotFound) => Err(ApiError::NotFound(
+            "No transaction found with the provided ID.".to_owned(),
+        )),
+        Err(DeleteTransactionError::PeriodLocked(lock_date)) => Err(period_locked(lock_date)),
         Err(error) => {
             error!(?error, "Failed to delete transaction.");
 
@@ -80,15 +417,388 @@ async fn delete_transaction(
     }
 }
 
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct GetChangesParams {
+    /// The `next` token from the previous page. Omit it to start from the
+    /// beginning of the feed.
+    #[param(value_type = Option<EncodedChangeToken>)]
+    since: Option<reps::EncodedChangeToken>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/ledger/changes",
+    tag = "changes",
+    params(GetChangesParams),
+    responses(
+        (status = 200, description = "The changes made after the provided token, oldest first.", body = ChangeFeed),
+        (status = 400, description = "The change token is malformed."),
+    )
+)]
+async fn get_changes(
+    book: BookAccess,
+    State(ledger_service): State<LedgerService>,
+    Query(GetChangesParams { since }): Query<GetChangesParams>,
+) -> ApiResponse<Json<reps::ChangeFeed>> {
+    match ledger_service
+        .transaction_queries
+        .list_changes(book.book_id, since.map(|token| token.0))
+        .await
+    {
+        Ok(feed) => Ok(Json((&feed).into())),
+        Err(error) => {
+            error!(?error, "Failed to list transaction changes.");
+
+            Err(ApiError::InternalServerError)
+        }
+    }
+}
+
+/// Push notifications of changes to the book's transactions as server-sent
+/// events.
+///
+/// Each change is sent as a `change` event. If the client falls too far
+/// behind, a `lagged` event with the number of missed changes is sent instead,
+/// and the client should catch up using the change feed.
+#[utoipa::path(
+    get,
+    path = "/ledger/events",
+    tag = "changes",
+    responses(
+        (status = 200, description = "A stream of `change` events, each containing a `ChangeNotification`, and `lagged` events containing the number of missed changes.", body = String, content_type = "text/event-stream"),
+    )
+)]
+async fn stream_events(
+    book: BookAccess,
+    State(notifier): State<ChangeNotifier>,
+    State(shutdown): State<ShutdownSignal>,
+) -> Sse<impl Stream<Item = Result<Event, serde_json::Error>>> {
+    let book_id = book.book_id;
+    debug!(%book_id, user_id = %book.user_id, "Client subscribed to ledger events.");
+
+    let events = stream::unfold(notifier.subscribe(), move |mut receiver| async move {
+        loop {
+            let event = match receiver.recv().await {
+                Ok(change) if change.book_id == book_id => Event::default()
+                    .event("change")
+                    .json_data(reps::ChangeNotification::from(&change)),
+                Ok(_) => continue,
+                Err(RecvError::Lagged(missed)) => {
+                    Ok(Event::default().event("lagged").data(missed.to_string()))
+                }
+                Err(RecvError::Closed) => return None,
+            };
+
+            return Some((event, receiver));
+        }
+    });
+
+    // Streams never finish on their own, so they are ended when the server
+    // shuts down to let it stop waiting for in-flight requests.
+    let events = events.take_until(shutdown.into_wait());
+
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct GetTrashParams {
+    /// The `next` cursor from the previous page.
+    #[param(value_type = Option<EncodedTransactionCursor>)]
+    after: Option<reps::EncodedTransactionCursor>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/ledger/trash",
+    tag = "trash",
+    params(GetTrashParams),
+    responses(
+        (status = 200, description = "A page of deleted transactions.", body = TransactionCollection),
+        (status = 400, description = "The cursor is malformed."),
+    )
+)]
+async fn get_trash(
+    book: BookAccess,
+    State(ledger_service): State<LedgerService>,
+    Query(GetTrashParams { after }): Query<GetTrashParams>,
+) -> ApiResponse<Json<reps::ResourceCollection<reps::Transaction, reps::EncodedTransactionCursor>>>
+{
+    let query = TransactionQuery {
+        book_id: book.book_id,
+        after: after.as_ref().map(|c| (&c.0).into()),
+        account: None,
+        trashed: true,
+    };
+    match ledger_service.list_transactions(query).await {
+        Ok(transactions) => Ok(Json(reps::ResourceCollection {
+            next: transactions.next.map(Into::into),
+            items: transactions
+                .items
+                .iter()
+                .map(|transaction| transaction.into())
+                .collect(),
+        })),
+        Err(error) => {
+            error!(?error, "Failed to list trashed transactions.");
+
+            Err(ApiError::InternalServerError)
+        }
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/ledger/trash/{transaction_id}",
+    tag = "trash",
+    params(("transaction_id" = Uuid, Path, description = "The ID of the transaction.")),
+    responses(
+        (status = 204, description = "The transaction and its attachments were permanently deleted."),
+        (status = 404, description = "No deleted transaction exists with the ID.", body = ProblemRep),
+    )
+)]
+async fn purge_trashed_transaction(
+    book: BookAccess,
+    State(app_state): State<AppState>,
+    Path(TransactionPath { transaction_id }): Path<TransactionPath>,
+) -> ApiResponse<StatusCode> {
+    book.require(Role::Editor)?;
+
+    let attachment_service = AttachmentService::from_ref(&app_state);
+    let ledger_service = LedgerService::from_ref(&app_state);
+
+    match ledger_service
+        .transaction_commands
+        .purge_transaction(book.book_id, transaction_id)
+        .await
+    {
+        Ok(purged) if purged.count > 0 => {
+            // Only the content of the attachments removed by the purge is
+            // cleaned up.
+            attachment_service
+                .remove_attachment_content(&purged.attachments)
+                .await;
+
+            Ok(StatusCode::NO_CONTENT)
+        }
+        Ok(_) => Err(ApiError::NotFound(
+            "No trashed transaction found with the provided ID.".to_owned(),
+        )),
+        Err(error) => {
+            error!(?error, %transaction_id, "Failed to purge transaction.");
+
+            Err(ApiError::InternalServerError)
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/ledger/trash/{transaction_id}/restore",
+    tag = "trash",
+    params(("transaction_id" = Uuid, Path, description = "The ID of the transaction.")),
+    responses(
+        (status = 200, description = "The restored transaction.", body = Transaction),
+        (status = 404, description = "No deleted transaction exists with the ID.", body = ProblemRep),
+        (status = 409, description = "The transaction is dated on or before the ledger's lock date.", body = ProblemRep),
+    )
+)]
+async fn restore_trashed_transaction(
+    book: BookAccess,
+    State(ledger_service): State<LedgerService>,
+    Path(TransactionPath { transaction_id }): Path<TransactionPath>,
+) -> ApiResponse<Json<reps::Transaction>> {
+    book.require(Role::Editor)?;
+
+    match ledger_service
+        .transaction_commands
+        .restore_deleted_transaction(book.book_id, &book.user_id, transaction_id)
+        .await
+    {
+        Ok(transaction) => Ok(Json(reps::Transaction::from(&transaction))),
+        Err(RestoreTransactionError::TransactionNotFound) => Err(ApiError::NotFound(
+            "No trashed transaction found with the provided ID.".to_owned(),
+        )),
+        Err(RestoreTransactionError::PeriodLocked(lock_date)) => Err(period_locked(lock_date)),
+        Err(error) => {
+            error!(?error, %transaction_id, "Failed to restore transaction from trash.");
+
+            Err(ApiError::InternalServerError)
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub(super) struct ClosePeriodData {
+    period_start: NaiveDate,
+    period_end: NaiveDate,
+    equity_account: Option<String>,
+    #[serde(default)]
+    preview: bool,
+}
+
+#[utoipa::path(
+    post,
+    path = "/ledger/closings",
+    tag = "closings",
+    request_body = ClosePeriodData,
+    responses(
+        (status = 200, description = "The closing, or what it would be if `preview` is set.", body = PeriodClosing),
+        (status = 400, description = "The period or the closing transaction is invalid. Invalid transactions have a `validation_failed` code.", body = ProblemRep),
+        (status = 409, description = "The period ends on or before the ledger's lock date.", body = ProblemRep),
+    )
+)]
+async fn close_period(
+    book: BookAccess,
+    State(ledger_service): State<LedgerService>,
+    Json(data): Json<ClosePeriodData>,
+) -> ApiResponse<Json<reps::PeriodClosing>> {
+    book.require(Role::Editor)?;
+
+    let equity_account = data
+        .equity_account
+        .as_deref()
+        .unwrap_or(DEFAULT_EQUITY_ACCOUNT);
+
+    match ledger_service
+        .close_period(
+            book.book_id,
+            &book.user_id,
+            data.period_start,
+            data.period_end,
+            equity_account,
+            data.preview,
+        )
+        .await
+    {
+        Ok(outcome) => Ok(Json(reps::PeriodClosing::from_outcome(
+            &outcome,
+            data.preview,
+        ))),
+        Err(ClosePeriodError::InvalidPeriod) => Err(ApiError::BadRequestReason(
+            "The period must not end before it starts.".to_owned(),
+        )),
+        Err(ClosePeriodError::Closing(ClosingError::Invalid(errors))) => {
+            Err(ApiError::ValidationError(errors))
+        }
+        Err(ClosePeriodError::Closing(ClosingError::AmountOutOfRange { account, currency })) => {
+            Err(ApiError::BadRequestReason(format!(
+                "The {} balance of {} is too large to close in a single entry.",
+                currency, account
+            )))
+        }
+        Err(ClosePeriodError::PeriodLocked(lock_date)) => Err(period_locked(lock_date)),
+        Err(ClosePeriodError::Unknown(error)) => {
+            error!(?error, "Failed to close period.");
+
+            Err(ApiError::InternalServerError)
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/ledger/lock",
+    tag = "lock",
+    responses(
+        (status = 200, description = "The ledger's current lock date.", body = LedgerLock),
+    )
+)]
+async fn get_ledger_lock(
+    book: BookAccess,
+    State(ledger_service): State<LedgerService>,
+) -> ApiResponse<Json<reps::LedgerLock>> {
+    match ledger_service.get_ledger_lock(book.book_id).await {
+        Ok(lock) => Ok(Json(reps::LedgerLock::from(&lock))),
+        Err(error) => {
+            error!(?error, "Failed to query for ledger lock.");
+
+            Err(ApiError::InternalServerError)
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/ledger/lock/history",
+    tag = "lock",
+    responses(
+        (status = 200, description = "Every change made to the ledger's lock date.", body = [LockChange]),
+    )
+)]
+async fn get_lock_changes(
+    book: BookAccess,
+    State(ledger_service): State<LedgerService>,
+) -> ApiResponse<Json<Vec<reps::LockChange>>> {
+    match ledger_service.list_lock_changes(book.book_id).await {
+        Ok(changes) => Ok(Json(changes.iter().map(reps::LockChange::from).collect())),
+        Err(error) => {
+            error!(?error, "Failed to query for ledger lock changes.");
+
+            Err(ApiError::InternalServerError)
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+pub(super) struct SetLedgerLockData {
+    lock_date: Option<NaiveDate>,
+}
+
+#[utoipa::path(
+    put,
+    path = "/ledger/lock",
+    tag = "lock",
+    request_body = SetLedgerLockData,
+    responses(
+        (status = 200, description = "The ledger's new lock date.", body = LedgerLock),
+    )
+)]
+async fn set_ledger_lock(
+    book: BookAccess,
+    State(ledger_service): State<LedgerService>,
+    Json(SetLedgerLockData { lock_date }): Json<SetLedgerLockData>,
+) -> ApiResponse<Json<reps::LedgerLock>> {
+    book.require(Role::Owner)?;
+
+    match ledger_service
+        .set_lock_date(book.book_id, &book.user_id, lock_date)
+        .await
+    {
+        Ok(lock) => Ok(Json(reps::LedgerLock::from(&lock))),
+        Err(error) => {
+            error!(?error, "Failed to set ledger lock date.");
+
+            Err(ApiError::InternalServerError)
+        }
+    }
+}
+
+fn period_locked(lock_date: NaiveDate) -> ApiError {
+    ApiError::PeriodLocked(format!(
+        "Transactions dated on or before {} are locked.",
+        lock_date
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/ledger/accounts/{account}/balance",
+    tag = "accounts",
+    params(("account" = String, Path, description = "The name of the account. Balances include any child accounts.")),
+    responses(
+        (status = 200, description = "The account's balance in each currency.", body = [CurrencyAmount]),
+    )
+)]
 async fn get_account_balance(
-    Claims(claims): Claims<TokenClaims>,
-    State(db): State<PostgresConnection>,
-    Path(account): Path<String>,
+    book: BookAccess,
+    State(ledger_service): State<LedgerService>,
+    Path(AccountPath { account }): Path<AccountPath>,
 ) -> ApiResponse<Json<Vec<reps::CurrencyAmount>>> {
-    let queries = PostgresQueries(db);
-
-    match queries
-        .get_account_balance(claims.user_id(), account.to_owned())
+    match ledger_service
+        .account_queries
+        .get_account_balance(book.book_id, account.to_owned())
         .await
     {
         Ok(balances) => Ok(Json(
@@ -102,16 +812,25 @@ async fn get_account_balance(
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/ledger/accounts/{account}/balance/monthly",
+    tag = "accounts",
+    params(("account" = String, Path, description = "The name of the account. Balances include any child accounts.")),
+    responses(
+        (status = 200, description = "The change in the account's balance for each month of the last year, keyed by the first day of the month.", body = MonthlyBalances),
+    )
+)]
 async fn get_account_balance_monthly(
-    Claims(claims): Claims<TokenClaims>,
+    book: BookAccess,
     State(ledger_service): State<LedgerService>,
-    Path(account): Path<String>,
-) -> ApiResponse<Json<HashMap<NaiveDate, Vec<reps::CurrencyAmount>>>> {
+    Path(AccountPath { account }): Path<AccountPath>,
+) -> ApiResponse<Json<reps::MonthlyBalances>> {
     match ledger_service
-        .get_monthly_account_balance(claims.user_id(), &account)
+        .get_monthly_account_balance(book.book_id, &account)
         .await
     {
-        Ok(balances) => Ok(Json(
+        Ok(balances) => Ok(Json(reps::MonthlyBalances(
             balances
                 .iter()
                 .map(|(month, amounts)| {
@@ -121,10 +840,10 @@ async fn get_account_balance_monthly(
                     )
                 })
                 .collect(),
-        )),
+        ))),
         Err(error) => {
             error!(
-                user_id = claims.user_id(),
+                book_id = %book.book_id,
                 ?error,
                 "Failed to query for monthly account balances."
             );
@@ -134,10 +853,20 @@ async fn get_account_balance_monthly(
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/ledger/accounts/{account}/balance/periodic",
+    tag = "accounts",
+    params(("account" = String, Path, description = "The name of the account. Balances include any child accounts."), PeriodicAccountBalanceParams),
+    responses(
+        (status = 200, description = "The account's balance at the end of each interval, keyed by currency code.", body = PeriodicAccountBalances),
+        (status = 400, description = "The interval is not supported.", body = ProblemRep),
+    )
+)]
 async fn get_account_balance_periodic(
-    Claims(claims): Claims<TokenClaims>,
+    book: BookAccess,
     State(ledger_service): State<LedgerService>,
-    Path(account): Path<String>,
+    Path(AccountPath { account }): Path<AccountPath>,
     Query(params): Query<PeriodicAccountBalanceParams>,
 ) -> ApiResponse<Json<PeriodicAccountBalances>> {
     let interval = match params.interval.as_deref() {
@@ -156,7 +885,7 @@ async fn get_account_balance_periodic(
 
     match ledger_service
         .account_periodic_balance(
-            claims.user_id(),
+            book.book_id,
             &account,
             AccountBalanceType::Cummulative,
             interval,
@@ -166,7 +895,7 @@ async fn get_account_balance_periodic(
         Ok(balances) => Ok(Json(balances.into())),
         Err(error) => {
             error!(
-                user_id = claims.user_id(),
+                book_id = %book.book_id,
                 ?error,
                 "Failed to query for periodic account balances."
             );
@@ -176,25 +905,36 @@ async fn get_account_balance_periodic(
     }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 struct PeriodicAccountBalanceParams {
+    /// One of `daily`, `weekly`, or `monthly`. Defaults to `monthly`.
     interval: Option<String>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 struct GetAccountsParams {
+    /// Only include accounts whose names contain this text.
     query: Option<String>,
 }
 
+#[utoipa::path(
+    get,
+    path = "/ledger/accounts",
+    tag = "accounts",
+    params(GetAccountsParams),
+    responses(
+        (status = 200, description = "The names of the user's accounts, most used first.", body = [String]),
+    )
+)]
 async fn get_accounts(
-    Claims(claims): Claims<TokenClaims>,
-    State(db): State<PostgresConnection>,
+    book: BookAccess,
+    State(ledger_service): State<LedgerService>,
     Query(query): Query<GetAccountsParams>,
 ) -> ApiResponse<Json<Vec<String>>> {
-    let queries = PostgresQueries(db);
-
-    match queries
-        .list_accounts_by_popularity(claims.user_id(), query.query)
+    match ledger_service
+        .list_accounts(book.book_id, query.query)
         .await
     {
         Ok(accounts) => Ok(Json(accounts)),
@@ -206,16 +946,24 @@ async fn get_accounts(
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/ledger/active-accounts",
+    tag = "accounts",
+    responses(
+        (status = 200, description = "The names of the user's recently active accounts.", body = [String]),
+    )
+)]
 async fn get_active_accounts(
-    Claims(claims): Claims<TokenClaims>,
+    book: BookAccess,
     State(ledger_service): State<LedgerService>,
 ) -> ApiResponse<Json<Vec<String>>> {
-    match ledger_service.list_active_accounts(claims.user_id()).await {
+    match ledger_service.list_active_accounts(book.book_id).await {
         Ok(accounts) => Ok(Json(accounts)),
         Err(error) => {
             error!(
                 ?error,
-                user_id = claims.user_id(),
+                book_id = %book.book_id,
                 "Failed to list active accounts for user."
             );
 
@@ -224,16 +972,27 @@ async fn get_active_accounts(
     }
 }
 
+/// Build an `ETag` header identifying the current revision of a transaction.
+fn transaction_etag(
+    transaction: &domain::transactions::Transaction,
+) -> [(header::HeaderName, String); 1] {
+    [(header::ETAG, concurrency::entity_tag(transaction.revision))]
+}
+
 pub enum GetTransactionResponse {
-    Ok(reps::Transaction),
-    NotFound(ErrorRep),
+    Ok(reps::Transaction, [(header::HeaderName, String); 1]),
+    NotFound,
 }
 
 impl IntoResponse for GetTransactionResponse {
     fn into_response(self) -> axum::response::Response {
         match self {
-            Self::Ok(transaction) => (StatusCode::OK, Json(transaction)).into_response(),
-            Self::NotFound(error) => (StatusCode::NOT_FOUND, Json(error)).into_response(),
+            Self::Ok(transaction, etag) => {
+                (StatusCode::OK, etag, Json(transaction)).into_response()
+            }
+            Self::NotFound => {
+                ApiError::NotFound("Transaction not found.".to_owned()).into_response()
+            }
         }
     }
 }
@@ -241,23 +1000,35 @@ impl IntoResponse for GetTransactionResponse {
 impl From<Option<domain::transactions::Transaction>> for GetTransactionResponse {
     fn from(transaction: Option<domain::transactions::Transaction>) -> Self {
         match transaction {
-            Some(t) => Self::Ok((&t).into()),
-            None => Self::NotFound(ErrorRep {
-                message: "Transaction not found.".to_owned(),
-            }),
+            Some(t) => Self::Ok((&t).into(), transaction_etag(&t)),
+            None => Self::NotFound,
         }
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/ledger/transactions/{transaction_id}",
+    tag = "transactions",
+    params(("transaction_id" = Uuid, Path, description = "The ID of the transaction.")),
+    responses(
+        (
+            status = 200,
+            description = "The transaction.",
+            body = Transaction,
+            headers(("ETag" = String, description = "The transaction's current revision, for use with `If-Match`.")),
+        ),
+        (status = 404, description = "No transaction exists with the ID.", body = ProblemRep),
+    )
+)]
 async fn get_transaction(
-    Claims(claims): Claims<TokenClaims>,
-    State(db): State<PostgresConnection>,
-    Path(transaction_id): Path<Uuid>,
+    book: BookAccess,
+    State(ledger_service): State<LedgerService>,
+    Path(TransactionPath { transaction_id }): Path<TransactionPath>,
 ) -> Result<GetTransactionResponse, ApiError> {
-    let queries = PostgresQueries(db);
-
-    match queries
-        .get_transaction(claims.user_id(), transaction_id)
+    match ledger_service
+        .transaction_queries
+        .get_transaction(book.book_id, transaction_id)
         .await
     {
         Ok(transaction) => Ok(transaction.into()),
@@ -269,22 +1040,70 @@ async fn get_transaction(
     }
 }
 
-#[derive(Deserialize)]
+#[utoipa::path(
+    get,
+    path = "/ledger/transactions/{transaction_id}/history",
+    tag = "transactions",
+    params(("transaction_id" = Uuid, Path, description = "The ID of the transaction.")),
+    responses(
+        (status = 200, description = "Every version of the transaction, oldest first.", body = [TransactionVersion]),
+        (status = 404, description = "No transaction exists with the ID.", body = ProblemRep),
+    )
+)]
+async fn get_transaction_history(
+    book: BookAccess,
+    State(ledger_service): State<LedgerService>,
+    Path(TransactionPath { transaction_id }): Path<TransactionPath>,
+) -> ApiResponse<Json<Vec<reps::TransactionVersion>>> {
+    match ledger_service
+        .transaction_queries
+        .get_transaction_history(book.book_id, transaction_id)
+        .await
+    {
+        Ok(versions) if versions.is_empty() => Err(ApiError::NotFound(
+            "No transaction found with the provided ID.".to_owned(),
+        )),
+        Ok(versions) => Ok(Json(reps::TransactionVersion::from_history(&versions))),
+        Err(error) => {
+            error!(?error, %transaction_id, "Failed to query for transaction history.");
+
+            Err(ApiError::InternalServerError)
+        }
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 struct GetTransactionsParams {
+    /// Only include transactions with an entry for this account or one of its
+    /// children.
     account: Option<String>,
+    /// The `next` cursor from the previous page.
+    #[param(value_type = Option<EncodedTransactionCursor>)]
     after: Option<reps::EncodedTransactionCursor>,
 }
 
+#[utoipa::path(
+    get,
+    path = "/ledger/transactions",
+    tag = "transactions",
+    params(GetTransactionsParams),
+    responses(
+        (status = 200, description = "A page of transactions, most recent first.", body = TransactionCollection),
+        (status = 400, description = "The cursor is malformed."),
+    )
+)]
 async fn get_transactions(
-    Claims(claims): Claims<TokenClaims>,
+    book: BookAccess,
     State(ledger_service): State<LedgerService>,
     Query(GetTransactionsParams { account, after }): Query<GetTransactionsParams>,
 ) -> ApiResponse<Json<reps::ResourceCollection<reps::Transaction, reps::EncodedTransactionCursor>>>
 {
     let query = TransactionQuery {
-        user_id: claims.user_id().to_owned(),
+        book_id: book.book_id,
         after: after.as_ref().map(|c| (&c.0).into()),
         account: account.map(String::from),
+        trashed: false,
     };
     match ledger_service.list_transactions(query).await {
         Ok(transactions) => Ok(Json(reps::ResourceCollection {
@@ -303,64 +1122,368 @@ async fn get_transactions(
     }
 }
 
+/// The header clients use to provide an idempotency key when creating a
+/// transaction.
+const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+pub enum CreateTransactionResponse {
+    Created(reps::Transaction),
+    /// The response to an earlier request made with the same idempotency key.
+    Replayed(StoredResponse),
+}
+
+impl IntoResponse for CreateTransactionResponse {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            Self::Created(transaction) => (StatusCode::CREATED, Json(transaction)).into_response(),
+            Self::Replayed(response) => (
+                StatusCode::from_u16(response.status).unwrap_or(StatusCode::OK),
+                [("Idempotent-Replayed", "true")],
+                Json(response.body),
+            )
+                .into_response(),
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/ledger/transactions",
+    tag = "transactions",
+    params(
+        (
+            "Idempotency-Key" = Option<String>,
+            Header,
+            description = "A unique key for the request. Retrying a request with the same key returns the original response instead of creating another transaction.",
+        ),
+    ),
+    request_body = NewTransactionData,
+    responses(
+        (status = 201, description = "The transaction was created.", body = Transaction),
+        (status = 400, description = "The request body is invalid, or references a currency that does not exist.", body = ProblemRep),
+        (status = 409, description = "The transaction is dated on or before the ledger's lock date, or the idempotency key was used for a different or unfinished request.", body = ProblemRep),
+    )
+)]
 async fn create_transaction(
-    Claims(claims): Claims<TokenClaims>,
-    State(db): State<PostgresConnection>,
-    Json(new_transaction_data): Json<NewTransactionData>,
-) -> ApiResponse<(StatusCode, Json<reps::Transaction>)> {
-    let new_transaction = NewTransaction::from_data(claims.user_id(), new_transaction_data)?;
+    book: BookAccess,
+    State(app_state): State<AppState>,
+    headers: HeaderMap,
+    Json(mut new_transaction_data): Json<NewTransactionData>,
+) -> ApiResponse<CreateTransactionResponse> {
+    book.require(Role::Editor)?;
+
+    if let Some(currency) = &book.default_currency {
+        new_transaction_data.apply_default_currency(currency);
+    }
 
-    let ledger_commands = PostgresCommands(&db);
+    let idempotency_service = IdempotencyService::from_ref(&app_state);
 
-    let saved_transaction = match ledger_commands.persist_transaction(new_transaction).await {
-        Ok(t) => t,
+    let idempotency_key = match headers.get(IDEMPOTENCY_KEY_HEADER) {
+        Some(value) => {
+            let key = value.to_str().map_err(|_| {
+                ApiError::BadRequestReason(
+                    "The idempotency key must only contain visible ASCII characters.".to_owned(),
+                )
+            })?;
+            idempotency::validate_key(key).map_err(|_| {
+                ApiError::BadRequestReason(format!(
+                    "The idempotency key must be between 1 and {} characters long.",
+                    idempotency::MAX_KEY_LENGTH
+                ))
+            })?;
+
+            // Keys belong to the user, so the book is part of the request to
+            // keep a key from being replayed into another book.
+            let request_hash = idempotency::hash_request(
+                &serde_json::to_vec(&(book.book_id, &new_transaction_data))
+                    .context("Failed to serialize transaction for hashing.")?,
+            );
+
+            if let Some(record) = idempotency_service
+                .claim_key(&book.user_id, key, &request_hash)
+                .await?
+            {
+                return match record.replay(&request_hash) {
+                    Replay::Response(response) => Ok(CreateTransactionResponse::Replayed(response)),
+                    Replay::Mismatch => Err(ApiError::Conflict(
+                        "The idempotency key was already used for a different request.".to_owned(),
+                    )),
+                    Replay::InProgress => Err(ApiError::Conflict(
+                        "A request with the same idempotency key is still being processed."
+                            .to_owned(),
+                    )),
+                };
+            }
+
+            Some(key)
+        }
+        None => None,
+    };
+
+    let result = persist_new_transaction(
+        &LedgerService::from_ref(&app_state),
+        book.book_id,
+        &book.user_id,
+        new_transaction_data,
+    )
+    .await;
+
+    if let Some(key) = idempotency_key {
+        // Only successful responses are kept. If the request failed, the key
+        // is released so the client can retry with it.
+        let stored = match &result {
+            Ok(transaction) => match serde_json::to_value(transaction) {
+                Ok(body) => {
+                    idempotency_service
+                        .save_response(
+                            &book.user_id,
+                            key,
+                            &StoredResponse {
+                                status: StatusCode::CREATED.as_u16(),
+                                body,
+                            },
+                        )
+                        .await
+                }
+                Err(error) => Err(error.into()),
+            },
+            Err(_) => idempotency_service.release_key(&book.user_id, key).await,
+        };
+
+        if let Err(error) = stored {
+            error!(?error, %key, "Failed to store outcome of idempotent request.");
+        }
+    }
+
+    Ok(CreateTransactionResponse::Created(result?))
+}
+
+async fn persist_new_transaction(
+    ledger_service: &LedgerService,
+    book_id: Uuid,
+    actor: &str,
+    new_transaction_data: NewTransactionData,
+) -> ApiResponse<reps::Transaction> {
+    let new_transaction = NewTransaction::from_data(book_id, new_transaction_data)?;
+
+    match ledger_service
+        .transaction_commands
+        .persist_transaction(actor, new_transaction)
+        .await
+    {
+        Ok(transaction) => {
+            monitoring::record_transactions_created(1);
+
+            Ok(reps::Transaction::from(&transaction))
+        }
+        Err(PersistTransactionError::PeriodLocked(lock_date)) => Err(period_locked(lock_date)),
+        Err(PersistTransactionError::UnknownCurrency(currency)) => {
+            Err(ApiError::UnknownCurrency(currency))
+        }
         Err(error) => {
             error!(?error, "Failed to persist transaction.");
 
-            return Err(ApiError::InternalServerError);
+            Err(ApiError::InternalServerError)
         }
-    };
+    }
+}
 
-    Ok((
-        StatusCode::CREATED,
-        Json(reps::Transaction::from(&saved_transaction)),
-    ))
+#[utoipa::path(
+    post,
+    path = "/ledger/transactions/batch",
+    tag = "transactions",
+    request_body = BatchData,
+    responses(
+        (status = 200, description = "Every operation was applied.", body = BatchResults),
+        (status = 400, description = "The request body is invalid, or references a currency that does not exist.", body = ProblemRep),
+        (status = 403, description = "The user can't edit the book, or authenticated with an import token and the batch does more than create transactions.", body = ProblemRep),
+        (status = 404, description = "An operation references a transaction that does not exist. No changes were applied.", body = ProblemRep),
+        (status = 409, description = "An operation touches a locked transaction. No changes were applied.", body = ProblemRep),
+    )
+)]
+async fn apply_transaction_batch(
+    book: BookAccess,
+    State(ledger_service): State<LedgerService>,
+    Json(mut batch_data): Json<BatchData>,
+) -> ApiResponse<Json<reps::BatchResults>> {
+    book.require(Role::Editor)?;
+
+    if let Credential::ApiToken(scope) = book.credential {
+        if !scope.allows_batch(&batch_data) {
+            return Err(ApiError::Forbidden(format!(
+                "Personal access tokens with the {} scope can only create transactions.",
+                scope
+            )));
+        }
+    }
+
+    if let Some(currency) = &book.default_currency {
+        batch_data.apply_default_currency(currency);
+    }
+
+    let operations = BatchOperation::from_batch(book.book_id, batch_data)?;
+
+    match ledger_service
+        .transaction_commands
+        .apply_batch(book.book_id, &book.user_id, operations)
+        .await
+    {
+        Ok(results) => {
+            monitoring::record_transactions_created(
+                results
+                    .iter()
+                    .filter(|result| matches!(result, BatchOperationResult::Created(_)))
+                    .count(),
+            );
+
+            Ok(Json(reps::BatchResults {
+                results: results
+                    .iter()
+                    .map(reps::BatchOperationResult::from)
+                    .collect(),
+            }))
+        }
+        Err(ApplyBatchError::OperationFailed {
+            index,
+            error: BatchOperationError::TransactionNotFound,
+        }) => Err(ApiError::NotFound(format!(
+            "Operation {} references a transaction that does not exist. No changes were applied.",
+            index
+        ))),
+        Err(ApplyBatchError::OperationFailed {
+            index,
+            error: BatchOperationError::PeriodLocked(lock_date),
+        }) => Err(ApiError::PeriodLocked(format!(
+            "Operation {} touches a transaction dated on or before the lock date {}. No changes were applied.",
+            index, lock_date
+        ))),
+        Err(ApplyBatchError::OperationFailed {
+            error: BatchOperationError::UnknownCurrency(currency),
+            ..
+        }) => Err(ApiError::UnknownCurrency(currency)),
+        Err(error) => {
+            error!(?error, "Failed to apply transaction batch.");
+
+            Err(ApiError::InternalServerError)
+        }
+    }
 }
 
 pub enum UpdateTransactionResponse {
-    Updated(reps::Transaction),
-    NotFound(ErrorRep),
+    Updated(reps::Transaction, [(header::HeaderName, String); 1]),
+    /// The transaction changed since the revision given in `If-Match`.
+    PreconditionFailed(reps::TransactionConflict, [(header::HeaderName, String); 1]),
 }
 
 impl IntoResponse for UpdateTransactionResponse {
     fn into_response(self) -> axum::response::Response {
         match self {
-            Self::Updated(transaction) => (StatusCode::OK, Json(transaction)).into_response(),
-            Self::NotFound(error) => (StatusCode::NOT_FOUND, Json(error)).into_response(),
+            Self::Updated(transaction, etag) => {
+                (StatusCode::OK, etag, Json(transaction)).into_response()
+            }
+            Self::PreconditionFailed(conflict, etag) => (
+                StatusCode::PRECONDITION_FAILED,
+                etag,
+                [(header::CONTENT_TYPE, PROBLEM_CONTENT_TYPE)],
+                Json(conflict),
+            )
+                .into_response(),
         }
     }
 }
 
+#[utoipa::path(
+    put,
+    path = "/ledger/transactions/{transaction_id}",
+    tag = "transactions",
+    params(
+        ("transaction_id" = Uuid, Path, description = "The ID of the transaction."),
+        (
+            "If-Match" = Option<String>,
+            Header,
+            description = "Only update the transaction if it is still at the revision from this `ETag`, or `*` for any revision.",
+        ),
+    ),
+    request_body = NewTransactionData,
+    responses(
+        (
+            status = 200,
+            description = "The updated transaction.",
+            body = Transaction,
+            headers(("ETag" = String, description = "The transaction's new revision.")),
+        ),
+        (status = 400, description = "The request body is invalid, or references a currency that does not exist.", body = ProblemRep),
+        (status = 404, description = "No transaction exists with the ID.", body = ProblemRep),
+        (status = 409, description = "The transaction is dated on or before the ledger's lock date.", body = ProblemRep),
+        (
+            status = 412,
+            description = "The transaction was modified since the revision in `If-Match`.",
+            body = TransactionConflict,
+            headers(("ETag" = String, description = "The transaction's current revision.")),
+        ),
+    )
+)]
 async fn update_transaction(
-    Claims(claims): Claims<TokenClaims>,
-    State(db): State<PostgresConnection>,
-    Path(transaction_id): Path<Uuid>,
-    Json(updated_transaction_data): Json<NewTransactionData>,
+    book: BookAccess,
+    State(ledger_service): State<LedgerService>,
+    Path(TransactionPath { transaction_id }): Path<TransactionPath>,
+    headers: HeaderMap,
+    Json(mut updated_transaction_data): Json<NewTransactionData>,
 ) -> ApiResponse<UpdateTransactionResponse> {
-    let updated_transaction =
-        NewTransaction::from_data(claims.user_id(), updated_transaction_data)?;
+    book.require(Role::Editor)?;
 
-    let ledger_commands = PostgresCommands(&db);
+    let precondition = match headers.get(header::IF_MATCH) {
+        Some(value) => value
+            .to_str()
+            .ok()
+            .and_then(|value| concurrency::parse_if_match(value).ok())
+            .ok_or_else(|| {
+                ApiError::BadRequestReason(
+                    "The If-Match header must be \"*\" or an ETag returned by the API.".to_owned(),
+                )
+            })?,
+        None => Precondition::Any,
+    };
+
+    if let Some(currency) = &book.default_currency {
+        updated_transaction_data.apply_default_currency(currency);
+    }
 
-    let saved_transaction = match ledger_commands
-        .update_transaction(transaction_id, updated_transaction)
+    let updated_transaction = NewTransaction::from_data(book.book_id, updated_transaction_data)?;
+
+    let saved_transaction = match ledger_service
+        .transaction_commands
+        .update_transaction(
+            &book.user_id,
+            transaction_id,
+            updated_transaction,
+            precondition.expected_revision(),
+        )
         .await
     {
         Ok(t) => t,
         Err(UpdateTransactionError::TransactionNotFound) => {
-            return Ok(UpdateTransactionResponse::NotFound(ErrorRep {
-                message: "No transaction found with the provided ID.".to_owned(),
-            }))
+            return Err(ApiError::NotFound(
+                "No transaction found with the provided ID.".to_owned(),
+            ))
+        }
+        Err(UpdateTransactionError::Conflict(current)) => {
+            return Ok(UpdateTransactionResponse::PreconditionFailed(
+                reps::TransactionConflict {
+                    problem: ProblemRep::new(
+                        ErrorCode::PreconditionFailed,
+                        "The transaction was modified since it was last fetched.".to_owned(),
+                    ),
+                    current: reps::Transaction::from(&*current),
+                },
+                transaction_etag(&current),
+            ))
+        }
+        Err(UpdateTransactionError::PeriodLocked(lock_date)) => {
+            return Err(period_locked(lock_date))
+        }
+        Err(UpdateTransactionError::UnknownCurrency(currency)) => {
+            return Err(ApiError::UnknownCurrency(currency))
         }
         Err(error) => {
             error!(?error, %transaction_id, "Failed to update transaction.");
@@ -369,7 +1492,190 @@ async fn update_transaction(
         }
     };
 
-    Ok(UpdateTransactionResponse::Updated(reps::Transaction::from(
-        &saved_transaction,
-    )))
+    Ok(UpdateTransactionResponse::Updated(
+        reps::Transaction::from(&saved_transaction),
+        transaction_etag(&saved_transaction),
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/ledger/transactions/{transaction_id}/history/{version}/restore",
+    tag = "transactions",
+    params(("transaction_id" = Uuid, Path, description = "The ID of the transaction."), ("version" = i32, Path, description = "The version to restore.")),
+    responses(
+        (status = 200, description = "The transaction with the contents of the version.", body = Transaction),
+        (status = 400, description = "The version is no longer a valid transaction, or references a currency that no longer exists.", body = ProblemRep),
+        (status = 404, description = "The transaction or version does not exist.", body = ProblemRep),
+        (status = 409, description = "The transaction is dated on or before the ledger's lock date.", body = ProblemRep),
+    )
+)]
+async fn restore_transaction_version(
+    book: BookAccess,
+    State(ledger_service): State<LedgerService>,
+    Path(VersionPath {
+        transaction_id,
+        version,
+    }): Path<VersionPath>,
+) -> ApiResponse<Json<reps::Transaction>> {
+    book.require(Role::Editor)?;
+
+    match ledger_service
+        .transaction_commands
+        .restore_transaction_version(book.book_id, &book.user_id, transaction_id, version)
+        .await
+    {
+        Ok(transaction) => Ok(Json(reps::Transaction::from(&transaction))),
+        Err(RestoreVersionError::VersionNotFound) => Err(ApiError::NotFound(
+            "No transaction version found with the provided ID and version.".to_owned(),
+        )),
+        Err(RestoreVersionError::Invalid(errors)) => Err(ApiError::ValidationError(errors)),
+        Err(RestoreVersionError::PeriodLocked(lock_date)) => Err(period_locked(lock_date)),
+        Err(RestoreVersionError::UnknownCurrency(currency)) => {
+            Err(ApiError::UnknownCurrency(currency))
+        }
+        Err(error) => {
+            error!(?error, %transaction_id, version, "Failed to restore transaction version.");
+
+            Err(ApiError::InternalServerError)
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/ledger/webhooks",
+    tag = "webhooks",
+    request_body = NewWebhookEndpointData,
+    responses(
+        (status = 201, description = "The endpoint was registered. The response includes the endpoint's signing secret, which is not shown again.", body = WebhookEndpoint),
+        (status = 400, description = "The request body is invalid.", body = ProblemRep),
+    )
+)]
+async fn create_webhook(
+    book: BookAccess,
+    State(webhook_service): State<WebhookService>,
+    Json(endpoint_data): Json<NewWebhookEndpointData>,
+) -> ApiResponse<(StatusCode, Json<reps::WebhookEndpoint>)> {
+    book.require(Role::Owner)?;
+
+    let endpoint = NewWebhookEndpoint::from_data(book.book_id, endpoint_data)?;
+
+    match webhook_service.create_endpoint(&endpoint).await {
+        Ok(created) => Ok((
+            StatusCode::CREATED,
+            Json(reps::WebhookEndpoint::with_secret(&created)),
+        )),
+        Err(CreateWebhookEndpointError::Destination(error)) => {
+            let mut problem = ValidationError::new("address");
+            problem.message = Some(error.to_string().into());
+
+            let mut errors = ValidationErrors::new();
+            errors.add("url", problem);
+
+            Err(ApiError::ValidationError(errors))
+        }
+        Err(CreateWebhookEndpointError::Unknown(error)) => {
+            error!(?error, "Failed to create webhook endpoint.");
+
+            Err(ApiError::InternalServerError)
+        }
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/ledger/webhooks/{endpoint_id}",
+    tag = "webhooks",
+    params(("endpoint_id" = Uuid, Path, description = "The ID of the webhook endpoint.")),
+    responses(
+        (status = 204, description = "The endpoint was deleted."),
+        (status = 404, description = "No webhook endpoint exists with the ID.", body = ProblemRep),
+    )
+)]
+async fn delete_webhook(
+    book: BookAccess,
+    State(webhook_service): State<WebhookService>,
+    Path(EndpointPath { endpoint_id }): Path<EndpointPath>,
+) -> ApiResponse<StatusCode> {
+    book.require(Role::Owner)?;
+
+    match webhook_service
+        .delete_endpoint(book.book_id, endpoint_id)
+        .await
+    {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(ApiError::NotFound(
+            "No webhook endpoint found with the provided ID.".to_owned(),
+        )),
+        Err(error) => {
+            error!(?error, %endpoint_id, "Failed to delete webhook endpoint.");
+
+            Err(ApiError::InternalServerError)
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/ledger/webhooks/{endpoint_id}/deliveries",
+    tag = "webhooks",
+    params(("endpoint_id" = Uuid, Path, description = "The ID of the webhook endpoint.")),
+    responses(
+        (status = 200, description = "The most recent attempts to deliver events to the endpoint.", body = [WebhookDeliveryAttempt]),
+        (status = 404, description = "No webhook endpoint exists with the ID.", body = ProblemRep),
+    )
+)]
+async fn get_webhook_deliveries(
+    book: BookAccess,
+    State(webhook_service): State<WebhookService>,
+    Path(EndpointPath { endpoint_id }): Path<EndpointPath>,
+) -> ApiResponse<Json<Vec<reps::WebhookDeliveryAttempt>>> {
+    book.require(Role::Owner)?;
+
+    if webhook_service
+        .get_endpoint(book.book_id, endpoint_id)
+        .await?
+        .is_none()
+    {
+        return Err(ApiError::NotFound(
+            "No webhook endpoint found with the provided ID.".to_owned(),
+        ));
+    }
+
+    match webhook_service
+        .list_delivery_attempts(book.book_id, endpoint_id)
+        .await
+    {
+        Ok(attempts) => Ok(Json(attempts.iter().map(Into::into).collect())),
+        Err(error) => {
+            error!(?error, %endpoint_id, "Failed to list webhook delivery attempts.");
+
+            Err(ApiError::InternalServerError)
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/ledger/webhooks",
+    tag = "webhooks",
+    responses(
+        (status = 200, description = "The user's webhook endpoints.", body = [WebhookEndpoint]),
+    )
+)]
+async fn get_webhooks(
+    book: BookAccess,
+    State(webhook_service): State<WebhookService>,
+) -> ApiResponse<Json<Vec<reps::WebhookEndpoint>>> {
+    book.require(Role::Owner)?;
+
+    match webhook_service.list_endpoints(book.book_id).await {
+        Ok(endpoints) => Ok(Json(endpoints.iter().map(Into::into).collect())),
+        Err(error) => {
+            error!(?error, "Failed to list webhook endpoints.");
+
+            Err(ApiError::InternalServerError)
+        }
+    }
 }
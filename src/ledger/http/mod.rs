@@ -1,4 +1,12 @@
+mod books;
+mod extract;
+pub mod graphql;
 mod handlers;
+mod openapi;
 mod reps;
+mod tokens;
 
-pub use handlers::routes;
+pub use books::routes as book_routes;
+pub use handlers::{database_routes, routes};
+pub use openapi::ApiDoc;
+pub use tokens::routes as token_routes;
@@ -0,0 +1,346 @@
+//! The OpenAPI description of the HTTP API, used to generate client SDKs.
+
+use utoipa::{
+    openapi::{
+        header::Header,
+        security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+        Content, ObjectBuilder, Ref, RefOr, Response, ResponseBuilder, SchemaType,
+    },
+    Modify, OpenApi,
+};
+
+use crate::{
+    authentication::tokens::{NewApiTokenData, TokenScope},
+    http_err::{ErrorCode, FieldProblemRep, ProblemRep, PROBLEM_CONTENT_TYPE},
+    ledger::domain::{
+        batch::{BatchData, BatchOperationData},
+        books::{BookData, NewInvitationData, Role},
+        transactions::{
+            NewTransactionData, NewTransactionEntryAmountData, NewTransactionEntryData,
+        },
+        webhooks::{BalanceThreshold, NewWebhookEndpointData},
+    },
+};
+
+use super::{books, handlers, reps, tokens};
+
+/// The name of the security scheme for the bearer tokens that authenticate
+/// requests.
+const BEARER_AUTH: &str = "bearer_auth";
+
+/// Schemas that describe problem details documents, which are sent with their
+/// own media type.
+const PROBLEM_SCHEMAS: &[&str] = &["ProblemRep", "TransactionConflict"];
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Zeroed Books API",
+        description = "Double-entry bookkeeping for personal finances.\n\nEvery `/ledger` route uses the user's personal book. The same routes are available under `/books/{book_id}/ledger` for any book the user is a member of.",
+    ),
+    paths(
+        books::get_books,
+        books::create_book,
+        books::get_book,
+        books::update_book,
+        books::delete_book,
+        books::get_members,
+        books::set_member_role,
+        books::remove_member,
+        books::get_invitations,
+        books::create_invitation,
+        books::revoke_invitation,
+        books::accept_invitation,
+        handlers::get_accounts,
+        handlers::get_account_balance,
+        handlers::get_account_balance_monthly,
+        handlers::get_account_balance_periodic,
+        handlers::get_active_accounts,
+        handlers::get_changes,
+        handlers::stream_events,
+        handlers::close_period,
+        handlers::get_ledger_lock,
+        handlers::set_ledger_lock,
+        handlers::get_lock_changes,
+        handlers::get_trash,
+        handlers::purge_trashed_transaction,
+        handlers::restore_trashed_transaction,
+        handlers::get_transactions,
+        handlers::create_transaction,
+        handlers::apply_transaction_batch,
+        handlers::get_transaction,
+        handlers::update_transaction,
+        handlers::delete_transaction,
+        handlers::get_transaction_history,
+        handlers::restore_transaction_version,
+        handlers::get_attachments,
+        handlers::create_attachment,
+        handlers::get_attachment_content,
+        handlers::delete_attachment,
+        handlers::get_webhooks,
+        handlers::create_webhook,
+        handlers::delete_webhook,
+        handlers::get_webhook_deliveries,
+        tokens::get_tokens,
+        tokens::create_token,
+        tokens::revoke_token,
+    ),
+    components(schemas(
+        BalanceThreshold,
+        BatchData,
+        BatchOperationData,
+        BookData,
+        ErrorCode,
+        FieldProblemRep,
+        NewApiTokenData,
+        NewInvitationData,
+        NewTransactionData,
+        NewTransactionEntryAmountData,
+        NewTransactionEntryData,
+        NewWebhookEndpointData,
+        ProblemRep,
+        Role,
+        TokenScope,
+        books::AcceptInvitationData,
+        books::SetMemberRoleData,
+        handlers::ClosePeriodData,
+        handlers::SetLedgerLockData,
+        reps::ApiToken,
+        reps::Attachment,
+        reps::BatchOperationResult,
+        reps::BatchResults,
+        reps::Book,
+        reps::BookInvitation,
+        reps::BookMember,
+        reps::ChangeFeed,
+        reps::ChangeNotification,
+        reps::Currency,
+        reps::CurrencyAmount,
+        reps::CurrencyInstantBalances,
+        reps::DateChange,
+        reps::EncodedChangeToken,
+        reps::EncodedTransactionCursor,
+        reps::InstantBalance,
+        reps::LedgerLock,
+        reps::LockChange,
+        reps::MonthlyBalances,
+        reps::PeriodClosing,
+        reps::PeriodicAccountBalances,
+        reps::SnapshotEntry,
+        reps::TextChange,
+        reps::Transaction,
+        reps::TransactionChange,
+        reps::TransactionCollection,
+        reps::TransactionConflict,
+        reps::TransactionDiff,
+        reps::TransactionEntry,
+        reps::TransactionSnapshot,
+        reps::TransactionVersion,
+        reps::WebhookDeliveryAttempt,
+        reps::WebhookEndpoint,
+    )),
+    modifiers(&CommonResponses),
+    security(("bearer_auth" = [])),
+    tags(
+        (name = "accounts", description = "Account names and balances."),
+        (name = "attachments", description = "Files attached to transactions."),
+        (name = "books", description = "Keeping separate books and sharing them with other users."),
+        (name = "changes", description = "Keeping clients in sync with the ledger."),
+        (name = "closings", description = "Closing accounting periods."),
+        (name = "lock", description = "Locking transactions in past periods."),
+        (name = "transactions", description = "Recording and editing transactions."),
+        (name = "tokens", description = "Personal access tokens for scripts and integrations."),
+        (name = "trash", description = "Restoring or purging deleted transactions."),
+        (name = "webhooks", description = "Sending events to user provided endpoints."),
+    )
+)]
+pub struct ApiDoc;
+
+/// Adds the authentication scheme and the responses that every operation
+/// shares, so they don't have to be repeated for each handler.
+struct CommonResponses;
+
+impl Modify for CommonResponses {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+
+        components.add_security_scheme(
+            BEARER_AUTH,
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .description(Some(
+                        "A JWT issued for this API's audience by the configured authority, whose \
+                        subject identifies the user, or one of the user's personal access tokens. \
+                        JWTs grant `ledger:read`, `ledger:write`, or `ledger:admin` through their \
+                        `scope` or `permissions` claim.",
+                    ))
+                    .build(),
+            ),
+        );
+
+        components.responses.insert(
+            "Unauthorized".to_owned(),
+            ResponseBuilder::new()
+                .description("The authentication token is missing or invalid.")
+                .content(
+                    PROBLEM_CONTENT_TYPE,
+                    Content::new(Ref::from_schema_name("ProblemRep")),
+                )
+                .build()
+                .into(),
+        );
+        components.responses.insert(
+            "Forbidden".to_owned(),
+            ResponseBuilder::new()
+                .description(
+                    "The user's role in the book, or the scope of their token, does not allow \
+                    the request. A missing scope is named in the `detail`.",
+                )
+                .content(
+                    PROBLEM_CONTENT_TYPE,
+                    Content::new(Ref::from_schema_name("ProblemRep")),
+                )
+                .build()
+                .into(),
+        );
+        components.responses.insert(
+            "TooManyRequests".to_owned(),
+            ResponseBuilder::new()
+                .description(
+                    "The user has made too many requests. The `Retry-After` header has the \
+                    number of seconds to wait before retrying.",
+                )
+                .header(
+                    "Retry-After",
+                    Header::new(ObjectBuilder::new().schema_type(SchemaType::Integer)),
+                )
+                .content(
+                    PROBLEM_CONTENT_TYPE,
+                    Content::new(Ref::from_schema_name("ProblemRep")),
+                )
+                .build()
+                .into(),
+        );
+        components.responses.insert(
+            "InternalServerError".to_owned(),
+            ResponseBuilder::new()
+                .description("An unexpected error occurred.")
+                .content(
+                    PROBLEM_CONTENT_TYPE,
+                    Content::new(Ref::from_schema_name("ProblemRep")),
+                )
+                .build()
+                .into(),
+        );
+
+        for path in openapi.paths.paths.values_mut() {
+            for operation in path.operations.values_mut() {
+                let responses = &mut operation.responses.responses;
+
+                for response in responses.values_mut() {
+                    if let RefOr::T(response) = response {
+                        use_problem_content_type(response);
+                    }
+                }
+
+                responses
+                    .entry("401".to_owned())
+                    .or_insert_with(|| Ref::from_response_name("Unauthorized").into());
+                responses
+                    .entry("403".to_owned())
+                    .or_insert_with(|| Ref::from_response_name("Forbidden").into());
+                responses
+                    .entry("429".to_owned())
+                    .or_insert_with(|| Ref::from_response_name("TooManyRequests").into());
+                responses
+                    .entry("500".to_owned())
+                    .or_insert_with(|| Ref::from_response_name("InternalServerError").into());
+            }
+        }
+    }
+}
+
+/// Move problem details documents in a response from the JSON media type,
+/// which the path macros always use, to the problem details media type.
+fn use_problem_content_type(response: &mut Response) {
+    let is_problem = response
+        .content
+        .get("application/json")
+        .is_some_and(|content| match &content.schema {
+            RefOr::Ref(schema) => PROBLEM_SCHEMAS
+                .iter()
+                .any(|name| schema.ref_location == format!("#/components/schemas/{}", name)),
+            RefOr::T(_) => false,
+        });
+
+    if is_problem {
+        if let Some(content) = response.content.shift_remove("application/json") {
+            response
+                .content
+                .insert(PROBLEM_CONTENT_TYPE.to_owned(), content);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Collect the targets of every reference in a document.
+    fn collect_refs(value: &serde_json::Value, refs: &mut Vec<String>) {
+        match value {
+            serde_json::Value::Object(map) => {
+                for (key, value) in map {
+                    match (key.as_str(), value) {
+                        ("$ref", serde_json::Value::String(target)) => refs.push(target.clone()),
+                        _ => collect_refs(value, refs),
+                    }
+                }
+            }
+            serde_json::Value::Array(values) => {
+                for value in values {
+                    collect_refs(value, refs);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn document_references_resolve() {
+        let document = serde_json::to_value(ApiDoc::openapi()).unwrap();
+
+        let mut refs = Vec::new();
+        collect_refs(&document, &mut refs);
+        assert!(!refs.is_empty());
+
+        for target in refs {
+            let pointer = target
+                .strip_prefix('#')
+                .unwrap_or_else(|| panic!("unexpected external reference: {}", target));
+
+            assert!(
+                document.pointer(pointer).is_some(),
+                "dangling reference: {}",
+                target
+            );
+        }
+    }
+
+    #[test]
+    fn document_requires_authentication() {
+        let document = serde_json::to_value(ApiDoc::openapi()).unwrap();
+
+        assert_eq!(
+            serde_json::json!([{ BEARER_AUTH: [] }]),
+            document["security"]
+        );
+
+        let list_transactions = &document["paths"]["/ledger/transactions"]["get"];
+        assert_eq!(
+            "#/components/responses/Unauthorized",
+            list_transactions["responses"]["401"]["$ref"]
+        );
+    }
+}
@@ -1,20 +1,263 @@
 mod currency;
 
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt::Display, str::FromStr};
 
 use anyhow::Result;
+use async_graphql::SimpleObject;
 use base64::{engine::general_purpose, Engine};
 use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize, Serializer};
-
+use utoipa::{
+    openapi::{ArrayBuilder, ObjectBuilder, Ref, RefOr, Schema, SchemaType},
+    ToSchema,
+};
 use uuid::Uuid;
 
-use crate::ledger::domain;
+use crate::{
+    authentication::tokens::{self, TokenScope},
+    http_err::ProblemRep,
+    ledger::domain::{self, books::Role, webhooks::BalanceThreshold},
+};
 
 pub use currency::{Currency, CurrencyAmount};
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
+pub struct Attachment {
+    pub id: Uuid,
+    pub transaction_id: Uuid,
+    pub file_name: String,
+    pub content_type: String,
+    pub size: u64,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<&domain::attachments::Attachment> for Attachment {
+    fn from(domain: &domain::attachments::Attachment) -> Self {
+        Self {
+            id: domain.id,
+            transaction_id: domain.transaction_id,
+            file_name: domain.file_name.clone(),
+            content_type: domain.content_type.clone(),
+            size: domain.size,
+            created_at: domain.created_at,
+        }
+    }
+}
+
+/// A personal access token belonging to the user.
+#[derive(Serialize, ToSchema)]
+pub struct ApiToken {
+    pub id: Uuid,
+    pub name: String,
+    pub scope: TokenScope,
+    pub created_at: DateTime<Utc>,
+    /// When the token was last used to authenticate a request.
+    pub last_used_at: Option<DateTime<Utc>>,
+    /// The token used to authenticate requests. This is only included when
+    /// the token is created.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+impl ApiToken {
+    /// Build the representation of a newly created token, including its
+    /// secret.
+    pub fn with_token(domain: &tokens::ApiToken, token: String) -> Self {
+        Self {
+            token: Some(token),
+            ..domain.into()
+        }
+    }
+}
+
+impl From<&tokens::ApiToken> for ApiToken {
+    fn from(domain: &tokens::ApiToken) -> Self {
+        Self {
+            id: domain.id,
+            name: domain.name.clone(),
+            scope: domain.scope,
+            created_at: domain.created_at,
+            last_used_at: domain.last_used_at,
+            token: None,
+        }
+    }
+}
+
+/// A book the user is a member of.
+#[derive(Serialize, ToSchema)]
+pub struct Book {
+    pub id: Uuid,
+    pub name: String,
+    /// Whether this is the user's personal book, which is used by requests
+    /// that don't name a book.
+    pub personal: bool,
+    /// The currency used for amounts that don't name one.
+    pub default_currency: Option<String>,
+    /// The user's role in the book.
+    pub role: Role,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<&domain::books::Membership> for Book {
+    fn from(membership: &domain::books::Membership) -> Self {
+        Self {
+            id: membership.book.id,
+            name: membership.book.name.clone(),
+            personal: membership.book.personal,
+            default_currency: membership.book.default_currency.clone(),
+            role: membership.role,
+            created_at: membership.book.created_at,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BookInvitation {
+    pub id: Uuid,
+    /// The role the user accepting the invitation receives.
+    pub role: Role,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    /// The token used to accept the invitation. This is only included when
+    /// the invitation is created.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+impl BookInvitation {
+    /// Build the representation of a newly created invitation, including its
+    /// token.
+    pub fn with_token(domain: &domain::books::Invitation, token: String) -> Self {
+        Self {
+            token: Some(token),
+            ..domain.into()
+        }
+    }
+}
+
+impl From<&domain::books::Invitation> for BookInvitation {
+    fn from(domain: &domain::books::Invitation) -> Self {
+        Self {
+            id: domain.id,
+            role: domain.role,
+            created_by: domain.created_by.clone(),
+            created_at: domain.created_at,
+            expires_at: domain.expires_at,
+            token: None,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BookMember {
+    pub user_id: String,
+    pub role: Role,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<&domain::books::Member> for BookMember {
+    fn from(domain: &domain::books::Member) -> Self {
+        Self {
+            user_id: domain.user_id.clone(),
+            role: domain.role,
+            created_at: domain.created_at,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BatchResults {
+    pub results: Vec<BatchOperationResult>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "lowercase", tag = "op")]
+pub enum BatchOperationResult {
+    Create { transaction: Transaction },
+    Update { transaction: Transaction },
+    Delete { id: Uuid },
+}
+
+impl From<&crate::ledger::commands::BatchOperationResult> for BatchOperationResult {
+    fn from(result: &crate::ledger::commands::BatchOperationResult) -> Self {
+        use crate::ledger::commands::BatchOperationResult as Result;
+
+        match result {
+            Result::Created(transaction) => Self::Create {
+                transaction: transaction.into(),
+            },
+            Result::Updated(transaction) => Self::Update {
+                transaction: transaction.into(),
+            },
+            Result::Deleted(id) => Self::Delete { id: *id },
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct LedgerLock {
+    pub lock_date: Option<NaiveDate>,
+}
+
+impl From<&domain::locking::LedgerLock> for LedgerLock {
+    fn from(domain: &domain::locking::LedgerLock) -> Self {
+        Self {
+            lock_date: domain.lock_date,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct LockChange {
+    /// The ID of the user who changed the lock date, if it was recorded.
+    pub actor: Option<String>,
+    pub previous_lock_date: Option<NaiveDate>,
+    pub lock_date: Option<NaiveDate>,
+    pub changed_at: DateTime<Utc>,
+}
+
+impl From<&domain::locking::LockChange> for LockChange {
+    fn from(domain: &domain::locking::LockChange) -> Self {
+        Self {
+            actor: domain.actor.clone(),
+            previous_lock_date: domain.previous_lock_date,
+            lock_date: domain.lock_date,
+            changed_at: domain.changed_at,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PeriodClosing {
+    pub period_start: NaiveDate,
+    pub period_end: NaiveDate,
+    pub equity_account: String,
+    pub transaction_id: Option<Uuid>,
+    /// The contents of the closing transaction, if there was anything to
+    /// close.
+    pub transaction: Option<TransactionSnapshot>,
+    pub preview: bool,
+}
+
+impl PeriodClosing {
+    pub fn from_outcome(outcome: &crate::ledger::commands::ClosingOutcome, preview: bool) -> Self {
+        Self {
+            period_start: outcome.closing.period_start,
+            period_end: outcome.closing.period_end,
+            equity_account: outcome.closing.equity_account.clone(),
+            transaction_id: outcome.closing.transaction_id,
+            transaction: outcome.transaction.as_ref().map(TransactionSnapshot::from),
+            preview,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+#[aliases(TransactionCollection = ResourceCollection<Transaction, EncodedTransactionCursor>)]
 pub struct ResourceCollection<T: Serialize, C: Serialize> {
+    /// The cursor to pass as `after` to get the next page of items. This is
+    /// absent on the last page.
     pub next: Option<C>,
     pub items: Vec<T>,
 }
@@ -45,21 +288,62 @@ impl From<domain::transactions::TransactionCursor> for TransactionCursor {
 
 pub struct EncodedTransactionCursor(pub TransactionCursor);
 
+impl<'s> ToSchema<'s> for EncodedTransactionCursor {
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        (
+            "EncodedTransactionCursor",
+            ObjectBuilder::new()
+                .schema_type(SchemaType::String)
+                .description(Some(
+                    "An opaque cursor identifying a position in a list of transactions. It is \
+                    the URL safe base64 encoding of the date and the RFC 3339 creation time of \
+                    the last transaction on the previous page, separated by a `/`.",
+                ))
+                .example(Some(
+                    "MjAyMy0wNC0xNS8yMDIzLTA0LTE1VDEyOjAwOjAwKzAwOjAw".into(),
+                ))
+                .into(),
+        )
+    }
+}
+
 impl From<domain::transactions::TransactionCursor> for EncodedTransactionCursor {
     fn from(cursor: domain::transactions::TransactionCursor) -> Self {
         Self(cursor.into())
     }
 }
 
-impl Serialize for EncodedTransactionCursor {
-    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+impl Display for EncodedTransactionCursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let encoded = format!(
             "{}/{}",
             self.0.after_date.format("%Y-%m-%d"),
             self.0.after_created_at.to_rfc3339()
         );
 
-        serializer.collect_str(&general_purpose::URL_SAFE.encode(encoded))
+        f.write_str(&general_purpose::URL_SAFE.encode(encoded))
+    }
+}
+
+impl FromStr for EncodedTransactionCursor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let formatted = String::from_utf8(general_purpose::URL_SAFE.decode(s)?)?;
+
+        match formatted.split_once('/') {
+            Some((str_date, str_created_at)) => Ok(EncodedTransactionCursor(TransactionCursor {
+                after_date: NaiveDate::parse_from_str(str_date, "%Y-%m-%d")?,
+                after_created_at: str_created_at.parse::<DateTime<Utc>>()?,
+            })),
+            None => Err(anyhow::anyhow!("improperly encoded cursor")),
+        }
+    }
+}
+
+impl Serialize for EncodedTransactionCursor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
     }
 }
 
@@ -77,27 +361,7 @@ impl<'de> Deserialize<'de> for EncodedTransactionCursor {
             }
 
             fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
-                let formatted = general_purpose::URL_SAFE
-                    .decode(v)
-                    .map(String::from_utf8)
-                    .map_err(serde::de::Error::custom)?
-                    .map_err(serde::de::Error::custom)?;
-
-                match formatted.split_once('/') {
-                    Some((str_date, str_created_at)) => {
-                        let date = NaiveDate::parse_from_str(str_date, "%Y-%m-%d")
-                            .map_err(serde::de::Error::custom)?;
-                        let created_at = str_created_at
-                            .parse::<DateTime<Utc>>()
-                            .map_err(serde::de::Error::custom)?;
-
-                        Ok(EncodedTransactionCursor(TransactionCursor {
-                            after_date: date,
-                            after_created_at: created_at,
-                        }))
-                    }
-                    None => Err(serde::de::Error::custom("improperly encoded cursor")),
-                }
+                v.parse().map_err(serde::de::Error::custom)
             }
         }
 
@@ -105,7 +369,7 @@ impl<'de> Deserialize<'de> for EncodedTransactionCursor {
     }
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct Transaction {
     pub id: Uuid,
     pub date: NaiveDate,
@@ -114,6 +378,8 @@ pub struct Transaction {
     pub entries: Vec<TransactionEntry>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Utc>>,
 }
 
 impl From<&domain::transactions::Transaction> for Transaction {
@@ -126,11 +392,23 @@ impl From<&domain::transactions::Transaction> for Transaction {
             entries: domain.entries.iter().map(|entry| entry.into()).collect(),
             created_at: domain.created_at,
             updated_at: domain.updated_at,
+            deleted_at: domain.deleted_at,
         }
     }
 }
 
-#[derive(Serialize)]
+/// The body of a response rejecting an update to a transaction that changed
+/// since the client last fetched it.
+#[derive(Serialize, ToSchema)]
+pub struct TransactionConflict {
+    #[serde(flatten)]
+    pub problem: ProblemRep,
+    /// The current version of the transaction, so the client can merge its
+    /// changes.
+    pub current: Transaction,
+}
+
+#[derive(Serialize, ToSchema)]
 pub struct TransactionEntry {
     pub account: String,
     pub amount: CurrencyAmount,
@@ -145,16 +423,266 @@ impl From<&domain::transactions::TransactionEntry> for TransactionEntry {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Serialize, ToSchema)]
+pub struct TransactionVersion {
+    pub version: i32,
+    pub action: String,
+    pub actor: String,
+    pub recorded_at: DateTime<Utc>,
+    pub snapshot: TransactionSnapshot,
+    /// The changes made relative to the previous version. This is only absent
+    /// for the first version of a transaction.
+    pub changes: Option<TransactionDiff>,
+}
+
+impl TransactionVersion {
+    /// Build the representations of a transaction's history, including the
+    /// changes between each consecutive pair of versions.
+    ///
+    /// # Arguments
+    /// * `versions` - The transaction's versions ordered from oldest to newest.
+    pub fn from_history(versions: &[domain::history::TransactionVersion]) -> Vec<Self> {
+        let mut previous: Option<&domain::history::TransactionSnapshot> = None;
+
+        versions
+            .iter()
+            .map(|version| {
+                let changes = previous.map(|snapshot| snapshot.diff(&version.snapshot).into());
+                previous = Some(&version.snapshot);
+
+                Self {
+                    version: version.version,
+                    action: version.action.to_string(),
+                    actor: version.actor.clone(),
+                    recorded_at: version.recorded_at,
+                    snapshot: (&version.snapshot).into(),
+                    changes,
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TransactionSnapshot {
+    pub date: NaiveDate,
+    pub payee: String,
+    pub notes: String,
+    pub entries: Vec<SnapshotEntry>,
+}
+
+impl From<&domain::history::TransactionSnapshot> for TransactionSnapshot {
+    fn from(domain: &domain::history::TransactionSnapshot) -> Self {
+        Self {
+            date: domain.date,
+            payee: domain.payee.clone(),
+            notes: domain.notes.clone(),
+            entries: domain.entries.iter().map(Into::into).collect(),
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SnapshotEntry {
+    pub account: String,
+    pub currency: String,
+    pub value: i32,
+}
+
+impl From<&domain::history::SnapshotEntry> for SnapshotEntry {
+    fn from(domain: &domain::history::SnapshotEntry) -> Self {
+        Self {
+            account: domain.account.clone(),
+            currency: domain.currency.clone(),
+            value: domain.amount,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ChangeFeed {
+    pub changes: Vec<TransactionChange>,
+    /// The token to pass as `since` to get the changes after this page.
+    pub next: EncodedChangeToken,
+    pub has_more: bool,
+}
+
+impl From<&domain::changes::ChangeFeed> for ChangeFeed {
+    fn from(domain: &domain::changes::ChangeFeed) -> Self {
+        Self {
+            changes: domain.changes.iter().map(Into::into).collect(),
+            next: EncodedChangeToken(domain.next),
+            has_more: domain.has_more,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TransactionChange {
+    pub transaction_id: Uuid,
+    pub version: i32,
+    pub action: String,
+    pub recorded_at: DateTime<Utc>,
+    /// Whether the change is a tombstone for a deleted transaction.
+    pub deleted: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transaction: Option<TransactionSnapshot>,
+}
+
+impl From<&domain::changes::TransactionChange> for TransactionChange {
+    fn from(domain: &domain::changes::TransactionChange) -> Self {
+        Self {
+            transaction_id: domain.transaction_id,
+            version: domain.version,
+            action: domain.action.to_string(),
+            recorded_at: domain.recorded_at,
+            deleted: domain.snapshot.is_none(),
+            transaction: domain.snapshot.as_ref().map(Into::into),
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ChangeNotification {
+    pub transaction_id: Uuid,
+    pub action: String,
+    pub version: i32,
+}
+
+impl From<&domain::changes::ChangeNotification> for ChangeNotification {
+    fn from(domain: &domain::changes::ChangeNotification) -> Self {
+        Self {
+            transaction_id: domain.transaction_id,
+            action: domain.action.to_string(),
+            version: domain.version,
+        }
+    }
+}
+
+pub struct EncodedChangeToken(pub domain::changes::ChangeToken);
+
+impl<'s> ToSchema<'s> for EncodedChangeToken {
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        (
+            "EncodedChangeToken",
+            ObjectBuilder::new()
+                .schema_type(SchemaType::String)
+                .description(Some(
+                    "An opaque token identifying a position in the change feed. It is the URL \
+                    safe base64 encoding of the database transaction ID and the ID of the last \
+                    change seen, separated by a `/`.",
+                ))
+                .example(Some("MTIzNDUvNjc4".into()))
+                .into(),
+        )
+    }
+}
+
+impl Serialize for EncodedChangeToken {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&general_purpose::URL_SAFE.encode(self.0.to_string()))
+    }
+}
+
+impl<'de> Deserialize<'de> for EncodedChangeToken {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct Vis;
+        impl serde::de::Visitor<'_> for Vis {
+            type Value = EncodedChangeToken;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                formatter.write_str("a base64 encoded change token")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                general_purpose::URL_SAFE
+                    .decode(v)
+                    .ok()
+                    .and_then(|decoded| String::from_utf8(decoded).ok())
+                    .and_then(|formatted| formatted.parse().ok())
+                    .map(EncodedChangeToken)
+                    .ok_or_else(|| serde::de::Error::custom("improperly encoded change token"))
+            }
+        }
+
+        deserializer.deserialize_str(Vis)
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TransactionDiff {
+    #[schema(value_type = Option<DateChange>)]
+    pub date: Option<ValueChange<NaiveDate>>,
+    #[schema(value_type = Option<TextChange>)]
+    pub payee: Option<ValueChange<String>>,
+    #[schema(value_type = Option<TextChange>)]
+    pub notes: Option<ValueChange<String>>,
+    pub added_entries: Vec<SnapshotEntry>,
+    pub removed_entries: Vec<SnapshotEntry>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[aliases(DateChange = ValueChange<NaiveDate>, TextChange = ValueChange<String>)]
+pub struct ValueChange<T: Serialize> {
+    pub from: T,
+    pub to: T,
+}
+
+impl<T: Serialize> From<domain::history::ValueChange<T>> for ValueChange<T> {
+    fn from(domain: domain::history::ValueChange<T>) -> Self {
+        Self {
+            from: domain.from,
+            to: domain.to,
+        }
+    }
+}
+
+impl From<domain::history::TransactionDiff> for TransactionDiff {
+    fn from(domain: domain::history::TransactionDiff) -> Self {
+        Self {
+            date: domain.date.map(Into::into),
+            payee: domain.payee.map(Into::into),
+            notes: domain.notes.map(Into::into),
+            added_entries: domain.added_entries.iter().map(Into::into).collect(),
+            removed_entries: domain.removed_entries.iter().map(Into::into).collect(),
+        }
+    }
+}
+
+/// Changes in an account's balance keyed by the first day of each month.
+#[derive(Serialize)]
+pub struct MonthlyBalances(pub HashMap<NaiveDate, Vec<CurrencyAmount>>);
+
+impl<'s> ToSchema<'s> for MonthlyBalances {
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        (
+            "MonthlyBalances",
+            ObjectBuilder::new()
+                .description(Some(
+                    "The change in each currency's balance, keyed by the first day of each \
+                    month.",
+                ))
+                .additional_properties(Some(Schema::from(
+                    ArrayBuilder::new().items(Ref::from_schema_name("CurrencyAmount")),
+                )))
+                .into(),
+        )
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
 pub struct PeriodicAccountBalances(HashMap<String, CurrencyInstantBalances>);
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, SimpleObject, ToSchema)]
 pub struct CurrencyInstantBalances {
     pub currency: Currency,
     pub balances: Vec<InstantBalance>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, SimpleObject, ToSchema)]
 pub struct InstantBalance {
     instant: NaiveDate,
     balance: i32,
@@ -189,3 +717,70 @@ impl From<domain::reports::InstantBalances> for CurrencyInstantBalances {
         }
     }
 }
+
+#[derive(Serialize, ToSchema)]
+pub struct WebhookEndpoint {
+    pub id: Uuid,
+    pub url: String,
+    pub events: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub balance_threshold: Option<BalanceThreshold>,
+    /// The secret used to verify request signatures. This is only included
+    /// when the endpoint is created.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl WebhookEndpoint {
+    /// Build the representation of a newly created endpoint, including its
+    /// secret.
+    pub fn with_secret(domain: &domain::webhooks::WebhookEndpoint) -> Self {
+        Self {
+            secret: Some(domain.secret.clone()),
+            ..domain.into()
+        }
+    }
+}
+
+impl From<&domain::webhooks::WebhookEndpoint> for WebhookEndpoint {
+    fn from(domain: &domain::webhooks::WebhookEndpoint) -> Self {
+        Self {
+            id: domain.id,
+            url: domain.url.clone(),
+            events: domain
+                .events
+                .iter()
+                .map(|event| event.as_str().to_owned())
+                .collect(),
+            balance_threshold: domain.balance_threshold.clone(),
+            secret: None,
+            created_at: domain.created_at,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct WebhookDeliveryAttempt {
+    pub delivery_id: i64,
+    pub event_id: i64,
+    pub event_type: String,
+    pub succeeded: bool,
+    pub response_status: Option<u16>,
+    pub error: Option<String>,
+    pub attempted_at: DateTime<Utc>,
+}
+
+impl From<&domain::webhooks::DeliveryAttempt> for WebhookDeliveryAttempt {
+    fn from(domain: &domain::webhooks::DeliveryAttempt) -> Self {
+        Self {
+            delivery_id: domain.delivery_id,
+            event_id: domain.event_id,
+            event_type: domain.event_type.to_string(),
+            succeeded: domain.error.is_none(),
+            response_status: domain.response_status,
+            error: domain.error.clone(),
+            attempted_at: domain.attempted_at,
+        }
+    }
+}
@@ -1,15 +1,17 @@
+use async_graphql::SimpleObject;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 use crate::ledger::domain;
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, SimpleObject, ToSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct Currency {
     pub code: String,
     pub minor_units: u8,
 }
 
-#[derive(Clone, Deserialize, Serialize)]
+#[derive(Clone, Deserialize, Serialize, SimpleObject, ToSchema)]
 pub struct CurrencyAmount {
     pub currency: Currency,
     pub value: i32,
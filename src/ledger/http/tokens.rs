@@ -0,0 +1,124 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    routing::{delete, get},
+    Router,
+};
+use serde::Deserialize;
+use tracing::error;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    authentication::{tokens::NewApiTokenData, AuthenticatedUser},
+    http_err::{ApiError, ApiResponse},
+    ledger::services::ApiTokenService,
+    server::AppState,
+};
+
+use super::{
+    extract::{Json, Path},
+    reps,
+};
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/tokens", get(get_tokens).post(create_token))
+        .route("/tokens/:token_id", delete(revoke_token))
+}
+
+/// Create a personal access token for scripts and integrations.
+///
+/// Personal access tokens can't be used to manage tokens, so this requires a
+/// JWT. The JWT has to grant every scope the new token would have.
+#[utoipa::path(
+    post,
+    path = "/tokens",
+    tag = "tokens",
+    request_body = NewApiTokenData,
+    responses(
+        (status = 201, description = "The token was created. Its secret is only included in this response.", body = ApiToken),
+        (status = 400, description = "The request body is invalid.", body = ProblemRep),
+        (status = 403, description = "The user's JWT doesn't grant the scope needed for the token's scope.", body = ProblemRep),
+    )
+)]
+async fn create_token(
+    user: AuthenticatedUser,
+    State(api_token_service): State<ApiTokenService>,
+    Json(data): Json<NewApiTokenData>,
+) -> ApiResponse<(StatusCode, Json<reps::ApiToken>)> {
+    data.validate()?;
+    user.require_scope(data.scope.required_scope())?;
+
+    match api_token_service.create_token(&user.user_id, data).await {
+        Ok((token, secret)) => Ok((
+            StatusCode::CREATED,
+            Json(reps::ApiToken::with_token(&token, secret)),
+        )),
+        Err(error) => {
+            error!(?error, "Failed to create personal access token.");
+
+            Err(ApiError::InternalServerError)
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/tokens",
+    tag = "tokens",
+    responses(
+        (status = 200, description = "The user's personal access tokens, oldest first.", body = [ApiToken]),
+    )
+)]
+async fn get_tokens(
+    user: AuthenticatedUser,
+    State(api_token_service): State<ApiTokenService>,
+) -> ApiResponse<Json<Vec<reps::ApiToken>>> {
+    match api_token_service.list_tokens(&user.user_id).await {
+        Ok(tokens) => Ok(Json(tokens.iter().map(Into::into).collect())),
+        Err(error) => {
+            error!(?error, "Failed to list personal access tokens.");
+
+            Err(ApiError::InternalServerError)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TokenPath {
+    token_id: Uuid,
+}
+
+/// Revoke a personal access token. Requests using it are rejected
+/// immediately.
+#[utoipa::path(
+    delete,
+    path = "/tokens/{token_id}",
+    tag = "tokens",
+    params(("token_id" = Uuid, Path, description = "The ID of the token.")),
+    responses(
+        (status = 204, description = "The token was revoked."),
+        (status = 404, description = "The user has no token with the ID.", body = ProblemRep),
+    )
+)]
+async fn revoke_token(
+    user: AuthenticatedUser,
+    State(api_token_service): State<ApiTokenService>,
+    Path(TokenPath { token_id }): Path<TokenPath>,
+) -> ApiResponse<StatusCode> {
+    match api_token_service
+        .revoke_token(&user.user_id, token_id)
+        .await
+    {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(ApiError::NotFound(
+            "No personal access token found with the provided ID.".to_owned(),
+        )),
+        Err(error) => {
+            error!(?error, %token_id, "Failed to revoke personal access token.");
+
+            Err(ApiError::InternalServerError)
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::time::Duration;
+
+use chrono::Utc;
+use tracing::{error, info};
+
+use crate::shutdown::ShutdownSignal;
+
+use super::{
+    commands::DynTransactionCommands,
+    services::{AttachmentService, IdempotencyService, WebhookService},
+};
+
+/// How often expired idempotency keys are removed.
+const IDEMPOTENCY_KEY_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// How often the trash is checked for expired transactions.
+const TRASH_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// How often the outbox is checked for webhook deliveries that are due.
+const WEBHOOK_DELIVERY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Periodically deliver queued webhook events to their endpoints until the
+/// application shuts down.
+pub async fn deliver_webhooks(webhook_service: WebhookService, mut shutdown: ShutdownSignal) {
+    let mut interval = tokio::time::interval(WEBHOOK_DELIVERY_INTERVAL);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {},
+            _ = shutdown.wait() => return,
+        }
+
+        match webhook_service.deliver_due().await {
+            Ok(0) => (),
+            Ok(attempted) => info!(attempted, "Attempted webhook deliveries."),
+            Err(error) => error!(?error, "Failed to deliver webhooks."),
+        }
+    }
+}
+
+/// Periodically remove idempotency keys that are older than their TTL until
+/// the application shuts down.
+pub async fn purge_expired_idempotency_keys(
+    idempotency_service: IdempotencyService,
+    mut shutdown: ShutdownSignal,
+) {
+    let mut interval = tokio::time::interval(IDEMPOTENCY_KEY_PURGE_INTERVAL);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {},
+            _ = shutdown.wait() => return,
+        }
+
+        if let Err(error) = idempotency_service.delete_expired_keys().await {
+            error!(?error, "Failed to purge expired idempotency keys.");
+        }
+    }
+}
+
+/// Periodically purge transactions that have been in the trash for longer
+/// than the retention period.
+///
+/// # Arguments
+/// * `commands` - Used to purge the transactions.
+/// * `attachment_service` - Used to remove the content of attachments that
+///   belonged to purged transactions.
+/// * `retention` - How long transactions are kept in the trash.
+/// * `shutdown` - Stops the job once the application shuts down.
+pub async fn purge_expired_trash(
+    commands: DynTransactionCommands,
+    attachment_service: AttachmentService,
+    retention: chrono::Duration,
+    mut shutdown: ShutdownSignal,
+) {
+    let mut interval = tokio::time::interval(TRASH_PURGE_INTERVAL);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {},
+            _ = shutdown.wait() => return,
+        }
+
+        if let Err(error) = purge_trash_once(&commands, &attachment_service, retention).await {
+            error!(?error, "Failed to purge expired transactions from trash.");
+        }
+    }
+}
+
+async fn purge_trash_once(
+    commands: &DynTransactionCommands,
+    attachment_service: &AttachmentService,
+    retention: chrono::Duration,
+) -> anyhow::Result<()> {
+    let cutoff = Utc::now() - retention;
+
+    // Only the content of attachments that were actually removed by the purge
+    // is cleaned up, so a transaction restored in the meantime keeps its
+    // attachments.
+    let purged = commands.purge_deleted_transactions(cutoff).await?;
+
+    attachment_service
+        .remove_attachment_content(&purged.attachments)
+        .await;
+
+    if purged.count > 0 {
+        info!(purged = purged.count, %cutoff, "Purged expired transactions from trash.");
+    }
+
+    Ok(())
+}
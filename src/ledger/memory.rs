@@ -0,0 +1,1399 @@
+//! A ledger kept in memory.
+//!
+//! The in-memory ledger behaves the same as the Postgres one, but everything
+//! in it is lost when the server stops and it is not shared between server
+//! instances. It is meant for demos and for testing code that uses the ledger
+//! without a database.
+
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    convert::TryInto,
+    sync::{Mutex, MutexGuard},
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, Duration, DurationRound, Months, NaiveDate, Utc};
+use tracing::{debug, info, instrument};
+use uuid::Uuid;
+
+use crate::{
+    models,
+    repos::{
+        locks::LedgerLockRepo,
+        transactions::{TransactionCollection, TransactionQuery, TransactionRepo},
+    },
+};
+
+use super::{
+    commands::{
+        ApplyBatchError, BatchOperationResult, ClosePeriodError, ClosingOutcome,
+        DeleteTransactionError, PersistTransactionError, PurgedTransactions,
+        RestoreTransactionError, RestoreVersionError, TransactionCommands, UpdateTransactionError,
+    },
+    domain::{
+        batch::BatchOperation,
+        changes::{ChangeFeed, ChangeNotification, ChangeToken, TransactionChange},
+        closing::{self, AccountBalance, PeriodClosing, CLOSED_ACCOUNT_ROOTS},
+        currency::{Currency, CurrencyAmount, UnknownCurrency},
+        history::{ChangeAction, TransactionSnapshot, TransactionVersion},
+        locking::{LedgerLock, LockChange},
+        reports::InstantBalances,
+        transactions::{
+            NewTransaction, NewTransactionEntry, Transaction, TransactionCursor, TransactionEntry,
+        },
+    },
+    notifications::ChangeNotifier,
+    queries::{AccountQueries, CurrencyQueries, ReportInterval, TransactionQueries},
+};
+
+/// The maximum number of changes returned in a single page of the change
+/// feed.
+const CHANGE_PAGE_SIZE: usize = 100;
+
+/// The maximum number of accounts listed by popularity.
+const POPULAR_ACCOUNT_LIMIT: usize = 10;
+
+/// The maximum number of transactions in a single page of results.
+const TRANSACTION_PAGE_SIZE: usize = 50;
+
+/// A ledger kept in the memory of a single server instance.
+///
+/// Books' lock dates are kept alongside their transactions, so a write checks
+/// the lock date while holding the same guard as a change to it, like the
+/// Postgres ledger does by reading the lock in the write's database
+/// transaction. Changes are announced to the instance's own subscribers, but
+/// webhooks aren't sent for them.
+pub struct MemoryLedger {
+    notifier: Option<ChangeNotifier>,
+    state: Mutex<LedgerState>,
+}
+
+#[derive(Clone, Default)]
+struct LedgerState {
+    currencies: HashMap<String, Currency>,
+    accounts: HashMap<(Uuid, String), StoredAccount>,
+    transactions: HashMap<Uuid, StoredTransaction>,
+    /// The closed periods of each book, by the last day of the period.
+    closings: HashMap<(Uuid, NaiveDate), PeriodClosing>,
+    /// Every recorded version, in the order they were recorded.
+    versions: Vec<StoredVersion>,
+    /// The number of changes applied to the ledger. Versions recorded by the
+    /// same change share its number, like versions recorded by the same
+    /// database transaction share an ID in Postgres.
+    changes: i64,
+    /// The lock date of each locked book.
+    locks: HashMap<Uuid, NaiveDate>,
+    /// Every change to a lock date, in the order they were made.
+    lock_changes: Vec<StoredLockChange>,
+    last_timestamp: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone)]
+struct StoredLockChange {
+    book_id: Uuid,
+    actor: String,
+    previous_lock_date: Option<NaiveDate>,
+    lock_date: Option<NaiveDate>,
+    changed_at: DateTime<Utc>,
+}
+
+#[derive(Clone)]
+struct StoredAccount {
+    id: Uuid,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Clone)]
+struct StoredTransaction {
+    id: Uuid,
+    book_id: Uuid,
+    date: NaiveDate,
+    payee: String,
+    notes: String,
+    entries: Vec<StoredEntry>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    deleted_at: Option<DateTime<Utc>>,
+    revision: i64,
+}
+
+#[derive(Clone)]
+struct StoredEntry {
+    id: Uuid,
+    account: String,
+    currency: String,
+    amount: i32,
+}
+
+#[derive(Clone)]
+struct StoredVersion {
+    id: i64,
+    change: i64,
+    book_id: Uuid,
+    transaction_id: Uuid,
+    version: i32,
+    action: ChangeAction,
+    actor: String,
+    snapshot: TransactionSnapshot,
+    recorded_at: DateTime<Utc>,
+}
+
+impl MemoryLedger {
+    /// Create an empty ledger.
+    ///
+    /// # Arguments
+    /// * `currencies` - The currencies that entries may use.
+    /// * `notifier` - Used to announce changes to connected clients, if
+    ///   provided.
+    pub fn new<I: IntoIterator<Item = Currency>>(
+        currencies: I,
+        notifier: Option<ChangeNotifier>,
+    ) -> Self {
+        let state = LedgerState {
+            currencies: currencies
+                .into_iter()
+                .map(|currency| (currency.code().to_owned(), currency))
+                .collect(),
+            ..LedgerState::default()
+        };
+
+        Self {
+            notifier,
+            state: Mutex::new(state),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, LedgerState> {
+        self.state.lock().unwrap_or_else(|error| error.into_inner())
+    }
+
+    /// Announce the versions recorded by the most recent change.
+    fn notify(&self, state: &LedgerState) {
+        if let Some(notifier) = &self.notifier {
+            for version in state
+                .versions
+                .iter()
+                .rev()
+                .take_while(|version| version.change == state.changes)
+                .collect::<Vec<_>>()
+                .into_iter()
+                .rev()
+            {
+                notifier.publish(ChangeNotification {
+                    book_id: version.book_id,
+                    transaction_id: version.transaction_id,
+                    action: version.action,
+                    version: version.version,
+                });
+            }
+        }
+    }
+}
+
+/// Determine if an account is the root account or one of its children.
+fn matches_account(account: &str, root: &str) -> bool {
+    account == root
+        || account
+            .strip_prefix(root)
+            .is_some_and(|rest| rest.starts_with(':'))
+}
+
+/// Get the start of the interval containing a date.
+fn truncate_date(date: NaiveDate, interval: &ReportInterval) -> NaiveDate {
+    match interval {
+        ReportInterval::Daily => date,
+        ReportInterval::Monthly => date.with_day(1).unwrap_or(date),
+        ReportInterval::Weekly => {
+            date - Duration::days(date.weekday().num_days_from_monday().into())
+        }
+    }
+}
+
+/// Get the date a year before today.
+fn year_ago() -> NaiveDate {
+    let today = Utc::now().date_naive();
+
+    today.checked_sub_months(Months::new(12)).unwrap_or(today)
+}
+
+impl StoredTransaction {
+    fn is_active_in(&self, book_id: Uuid) -> bool {
+        self.book_id == book_id && self.deleted_at.is_none()
+    }
+
+    fn is_trashed_in(&self, book_id: Uuid) -> bool {
+        self.book_id == book_id && self.deleted_at.is_some()
+    }
+}
+
+impl LedgerState {
+    /// Get a book's ledger lock.
+    fn ledger_lock(&self, book_id: Uuid) -> LedgerLock {
+        LedgerLock {
+            lock_date: self.locks.get(&book_id).copied(),
+        }
+    }
+
+    /// Get the current time. Times are kept to the precision Postgres stores
+    /// them with, and every call returns a later time than the last one so
+    /// that changes are always ordered.
+    fn now(&mut self) -> DateTime<Utc> {
+        let now = Utc::now();
+        let mut now = now.duration_trunc(Duration::microseconds(1)).unwrap_or(now);
+
+        if let Some(last) = self.last_timestamp {
+            if now <= last {
+                now = last + Duration::microseconds(1);
+            }
+        }
+
+        self.last_timestamp = Some(now);
+
+        now
+    }
+
+    /// Iterate over the entries of a book's active transactions.
+    fn active_entries(
+        &self,
+        book_id: Uuid,
+    ) -> impl Iterator<Item = (&StoredTransaction, &StoredEntry)> {
+        self.transactions
+            .values()
+            .filter(move |transaction| transaction.is_active_in(book_id))
+            .flat_map(|transaction| {
+                transaction
+                    .entries
+                    .iter()
+                    .map(move |entry| (transaction, entry))
+            })
+    }
+
+    fn currency(&self, code: &str) -> Result<Currency> {
+        self.currencies
+            .get(code)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("unknown currency {:?}", code))
+    }
+
+    fn to_domain(&self, transaction: &StoredTransaction) -> Result<Transaction> {
+        Ok(Transaction {
+            id: transaction.id,
+            book_id: transaction.book_id,
+            date: transaction.date,
+            payee: transaction.payee.clone(),
+            notes: transaction.notes.clone(),
+            entries: transaction
+                .entries
+                .iter()
+                .map(|entry| {
+                    Ok(TransactionEntry::new(
+                        entry.account.clone(),
+                        CurrencyAmount::from_minor(self.currency(&entry.currency)?, entry.amount),
+                    ))
+                })
+                .collect::<Result<_>>()?,
+            created_at: transaction.created_at,
+            updated_at: transaction.updated_at,
+            deleted_at: transaction.deleted_at,
+            revision: transaction.revision,
+        })
+    }
+
+    /// Build the stored entries for a transaction, creating any accounts that
+    /// don't exist yet.
+    ///
+    /// Currencies are never created, so every currency referenced by the
+    /// entries must already exist.
+    fn build_entries(
+        &mut self,
+        book_id: Uuid,
+        entries: &[NewTransactionEntry],
+    ) -> Result<Vec<StoredEntry>, UnknownCurrency> {
+        let codes: BTreeSet<&str> = entries
+            .iter()
+            .map(|entry| entry.amount().currency())
+            .collect();
+        if let Some(unknown) = codes
+            .into_iter()
+            .find(|code| !self.currencies.contains_key(*code))
+        {
+            return Err(UnknownCurrency(unknown.to_owned()));
+        }
+
+        let now = self.now();
+
+        Ok(entries
+            .iter()
+            .map(|entry| {
+                self.accounts
+                    .entry((book_id, entry.account().to_owned()))
+                    .or_insert_with(|| StoredAccount {
+                        id: Uuid::new_v4(),
+                        created_at: now,
+                    });
+
+                StoredEntry {
+                    id: Uuid::new_v4(),
+                    account: entry.account().to_owned(),
+                    currency: entry.amount().currency().to_owned(),
+                    amount: entry.amount().value(),
+                }
+            })
+            .collect())
+    }
+
+    /// Record a new version of a transaction as part of the current change.
+    fn record_version(
+        &mut self,
+        book_id: Uuid,
+        transaction_id: Uuid,
+        action: ChangeAction,
+        actor: &str,
+        snapshot: TransactionSnapshot,
+    ) {
+        let version = self
+            .versions
+            .iter()
+            .filter(|version| version.transaction_id == transaction_id)
+            .map(|version| version.version)
+            .max()
+            .unwrap_or(0)
+            + 1;
+        let recorded_at = self.now();
+
+        self.versions.push(StoredVersion {
+            id: self.versions.len() as i64 + 1,
+            change: self.changes,
+            book_id,
+            transaction_id,
+            version,
+            action,
+            actor: actor.to_owned(),
+            snapshot,
+            recorded_at,
+        });
+
+        debug!(%transaction_id, version, %action, "Recorded transaction version.");
+    }
+
+    /// Sum the entries of a book's accounts over a period, like
+    /// [`AccountQueries::get_period_balances`].
+    fn period_balances(
+        &self,
+        book_id: Uuid,
+        account_roots: &[&str],
+        start: NaiveDate,
+        end: NaiveDate,
+        exclude_transaction: Option<Uuid>,
+    ) -> Vec<AccountBalance> {
+        let mut sums: BTreeMap<(&str, &str), i64> = BTreeMap::new();
+        for (transaction, entry) in self.active_entries(book_id) {
+            if (start..=end).contains(&transaction.date)
+                && exclude_transaction != Some(transaction.id)
+                && account_roots
+                    .iter()
+                    .any(|root| matches_account(&entry.account, root))
+            {
+                *sums.entry((&entry.account, &entry.currency)).or_default() +=
+                    i64::from(entry.amount);
+            }
+        }
+
+        sums.into_iter()
+            .filter(|(_, amount)| *amount != 0)
+            .map(|((account, currency), amount)| AccountBalance {
+                account: account.to_owned(),
+                currency: currency.to_owned(),
+                amount,
+            })
+            .collect()
+    }
+
+    fn delete_transaction(
+        &mut self,
+        lock: &LedgerLock,
+        book_id: Uuid,
+        actor: &str,
+        transaction_id: Uuid,
+    ) -> Result<(), DeleteTransactionError> {
+        let transaction = self
+            .transactions
+            .get(&transaction_id)
+            .filter(|transaction| transaction.is_active_in(book_id))
+            .ok_or(DeleteTransactionError::TransactionNotFound)?;
+
+        lock.ensure_unlocked([transaction.date])?;
+
+        let snapshot = TransactionSnapshot::from(&self.to_domain(transaction)?);
+        let now = self.now();
+        if let Some(transaction) = self.transactions.get_mut(&transaction_id) {
+            transaction.deleted_at = Some(now);
+            transaction.updated_at = now;
+            transaction.revision += 1;
+        }
+
+        self.record_version(
+            book_id,
+            transaction_id,
+            ChangeAction::Deleted,
+            actor,
+            snapshot,
+        );
+
+        info!(%book_id, %transaction_id, "Moved transaction to trash.");
+
+        Ok(())
+    }
+
+    fn persist_transaction(
+        &mut self,
+        lock: &LedgerLock,
+        actor: &str,
+        transaction: NewTransaction,
+    ) -> Result<Transaction, PersistTransactionError> {
+        lock.ensure_unlocked([transaction.date()])?;
+
+        let entries = self.build_entries(transaction.book_id(), transaction.entries())?;
+        let now = self.now();
+        let stored = StoredTransaction {
+            id: Uuid::new_v4(),
+            book_id: transaction.book_id(),
+            date: transaction.date(),
+            payee: transaction.payee().to_owned(),
+            notes: transaction.notes().unwrap_or_default().to_owned(),
+            entries,
+            created_at: now,
+            updated_at: now,
+            deleted_at: None,
+            revision: 1,
+        };
+        let persisted = self.to_domain(&stored)?;
+
+        self.transactions.insert(stored.id, stored);
+        self.record_version(
+            persisted.book_id,
+            persisted.id,
+            ChangeAction::Created,
+            actor,
+            TransactionSnapshot::from(&transaction),
+        );
+
+        info!(id = %persisted.id, "Persisted new transaction.");
+
+        Ok(persisted)
+    }
+
+    fn update_transaction(
+        &mut self,
+        lock: &LedgerLock,
+        actor: &str,
+        transaction_id: Uuid,
+        update: NewTransaction,
+        expected_revision: Option<i64>,
+    ) -> Result<Transaction, UpdateTransactionError> {
+        let current = self
+            .transactions
+            .get(&transaction_id)
+            .filter(|transaction| transaction.is_active_in(update.book_id()))
+            .ok_or(UpdateTransactionError::TransactionNotFound)?;
+        let current = self.to_domain(current)?;
+
+        if let Some(expected_revision) = expected_revision {
+            if current.revision != expected_revision {
+                debug!(%transaction_id, expected_revision, current_revision = current.revision, "Rejected update of stale transaction.");
+
+                return Err(UpdateTransactionError::Conflict(Box::new(current)));
+            }
+        }
+
+        // Moving a transaction either into or out of a locked period would
+        // change the locked period's balances.
+        lock.ensure_unlocked([current.date, update.date()])?;
+
+        let entries = self.build_entries(update.book_id(), update.entries())?;
+        let now = self.now();
+        let transaction = self
+            .transactions
+            .get_mut(&transaction_id)
+            .ok_or(UpdateTransactionError::TransactionNotFound)?;
+        transaction.date = update.date();
+        transaction.payee = update.payee().to_owned();
+        transaction.notes = update.notes().unwrap_or_default().to_owned();
+        transaction.entries = entries;
+        transaction.updated_at = now;
+        transaction.revision += 1;
+
+        let updated = self.to_domain(&self.transactions[&transaction_id])?;
+
+        self.record_version(
+            update.book_id(),
+            transaction_id,
+            ChangeAction::Updated,
+            actor,
+            TransactionSnapshot::from(&update),
+        );
+
+        info!(%transaction_id, "Updated transaction.");
+
+        Ok(updated)
+    }
+}
+
+#[async_trait]
+impl AccountQueries for MemoryLedger {
+    #[instrument(skip_all)]
+    async fn get_account_balance(
+        &self,
+        book_id: Uuid,
+        account_name: String,
+    ) -> Result<Vec<CurrencyAmount>> {
+        let state = self.state();
+
+        let mut sums: BTreeMap<&str, i64> = BTreeMap::new();
+        for (_, entry) in state.active_entries(book_id) {
+            if matches_account(&entry.account, &account_name) {
+                *sums.entry(&entry.currency).or_default() += i64::from(entry.amount);
+            }
+        }
+
+        sums.into_iter()
+            .map(|(code, amount)| {
+                Ok(CurrencyAmount::from_minor(
+                    state.currency(code)?,
+                    amount.try_into()?,
+                ))
+            })
+            .collect()
+    }
+
+    #[instrument(skip_all)]
+    async fn get_account_balances(
+        &self,
+        book_id: Uuid,
+        account_names: &[String],
+    ) -> Result<HashMap<String, Vec<CurrencyAmount>>> {
+        let state = self.state();
+
+        let mut result = HashMap::new();
+        for account_name in account_names {
+            let mut sums: BTreeMap<&str, i64> = BTreeMap::new();
+            for (_, entry) in state.active_entries(book_id) {
+                if matches_account(&entry.account, account_name) {
+                    *sums.entry(&entry.currency).or_default() += i64::from(entry.amount);
+                }
+            }
+
+            if sums.is_empty() {
+                continue;
+            }
+
+            let amounts = sums
+                .into_iter()
+                .map(|(code, amount)| {
+                    Ok(CurrencyAmount::from_minor(
+                        state.currency(code)?,
+                        amount.try_into()?,
+                    ))
+                })
+                .collect::<Result<_>>()?;
+
+            result.insert(account_name.clone(), amounts);
+        }
+
+        Ok(result)
+    }
+
+    #[instrument(skip_all)]
+    async fn get_monthly_balance(
+        &self,
+        book_id: Uuid,
+        account_name: &str,
+    ) -> Result<HashMap<NaiveDate, Vec<CurrencyAmount>>> {
+        let state = self.state();
+        let start = truncate_date(year_ago(), &ReportInterval::Monthly);
+
+        let mut sums: BTreeMap<(NaiveDate, &str), i64> = BTreeMap::new();
+        for (transaction, entry) in state.active_entries(book_id) {
+            if transaction.date >= start && matches_account(&entry.account, account_name) {
+                let month = truncate_date(transaction.date, &ReportInterval::Monthly);
+
+                *sums.entry((month, &entry.currency)).or_default() += i64::from(entry.amount);
+            }
+        }
+
+        let mut result: HashMap<NaiveDate, Vec<CurrencyAmount>> = HashMap::new();
+        for ((month, code), amount) in sums {
+            result
+                .entry(month)
+                .or_default()
+                .push(CurrencyAmount::from_minor(
+                    state.currency(code)?,
+                    amount.try_into()?,
+                ));
+        }
+
+        Ok(result)
+    }
+
+    #[instrument(skip_all)]
+    async fn get_period_balances(
+        &self,
+        book_id: Uuid,
+        account_roots: &[&str],
+        start: NaiveDate,
+        end: NaiveDate,
+        exclude_transaction: Option<Uuid>,
+    ) -> Result<Vec<AccountBalance>> {
+        Ok(self
+            .state()
+            .period_balances(book_id, account_roots, start, end, exclude_transaction))
+    }
+
+    #[instrument(skip_all)]
+    async fn list_accounts_by_popularity(
+        &self,
+        book_id: Uuid,
+        search: Option<String>,
+    ) -> Result<Vec<String>> {
+        let state = self.state();
+        let search = search.map(|search| search.to_lowercase());
+
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        for (_, entry) in state.active_entries(book_id) {
+            let matches = search
+                .as_ref()
+                .is_none_or(|search| entry.account.to_lowercase().contains(search));
+
+            if matches {
+                *counts.entry(&entry.account).or_default() += 1;
+            }
+        }
+
+        let mut accounts = counts.into_iter().collect::<Vec<_>>();
+        accounts.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+        Ok(accounts
+            .into_iter()
+            .take(POPULAR_ACCOUNT_LIMIT)
+            .map(|(account, _)| account.to_owned())
+            .collect())
+    }
+
+    #[instrument(skip_all)]
+    async fn list_active_accounts(&self, book_id: Uuid) -> Result<Vec<String>> {
+        let state = self.state();
+        let now = Utc::now();
+        let since = now.checked_sub_months(Months::new(12)).unwrap_or(now);
+
+        Ok(state
+            .active_entries(book_id)
+            .filter(|(transaction, _)| transaction.created_at >= since)
+            .map(|(_, entry)| entry.account.clone())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect())
+    }
+
+    #[instrument(skip_all)]
+    async fn periodic_cumulative_balance(
+        &self,
+        book_id: Uuid,
+        account: &str,
+        interval: ReportInterval,
+    ) -> Result<HashMap<String, InstantBalances>> {
+        let state = self.state();
+        let start = truncate_date(year_ago(), &interval);
+
+        let mut sums: BTreeMap<&str, BTreeMap<NaiveDate, i64>> = BTreeMap::new();
+        for (transaction, entry) in state.active_entries(book_id) {
+            if matches_account(&entry.account, account) {
+                *sums
+                    .entry(&entry.currency)
+                    .or_default()
+                    .entry(truncate_date(transaction.date, &interval))
+                    .or_default() += i64::from(entry.amount);
+            }
+        }
+
+        // Balances before the start of the report still count towards the
+        // cumulative balance, they just aren't reported on their own.
+        let mut balances_by_code = HashMap::new();
+        for (code, sums) in sums {
+            let mut balances = InstantBalances::new(state.currency(code)?);
+            let mut cumulative = 0;
+            for (date, amount) in sums {
+                cumulative += amount;
+
+                if date >= start {
+                    balances.push(date, cumulative.try_into()?);
+                }
+            }
+
+            if !balances.balances().is_empty() {
+                balances_by_code.insert(code.to_owned(), balances);
+            }
+        }
+
+        Ok(balances_by_code)
+    }
+
+    #[instrument(skip_all)]
+    async fn periodic_cumulative_balances(
+        &self,
+        book_id: Uuid,
+        accounts: &[String],
+        interval: ReportInterval,
+    ) -> Result<HashMap<String, HashMap<String, InstantBalances>>> {
+        let mut result = HashMap::new();
+        for account in accounts {
+            let balances = self
+                .periodic_cumulative_balance(book_id, account, interval)
+                .await?;
+
+            if !balances.is_empty() {
+                result.insert(account.clone(), balances);
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+#[async_trait]
+impl CurrencyQueries for MemoryLedger {
+    #[instrument(skip_all)]
+    async fn get_currencies_by_code(
+        &self,
+        currency_codes: Vec<String>,
+    ) -> Result<HashMap<String, Currency>> {
+        let state = self.state();
+
+        Ok(currency_codes
+            .into_iter()
+            .filter_map(|code| {
+                let currency = state.currencies.get(&code)?.clone();
+
+                Some((code, currency))
+            })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl TransactionQueries for MemoryLedger {
+    #[instrument(skip_all)]
+    async fn get_transaction(
+        &self,
+        book_id: Uuid,
+        transaction_id: Uuid,
+    ) -> Result<Option<Transaction>> {
+        let state = self.state();
+
+        state
+            .transactions
+            .get(&transaction_id)
+            .filter(|transaction| transaction.is_active_in(book_id))
+            .map(|transaction| state.to_domain(transaction))
+            .transpose()
+    }
+
+    #[instrument(skip_all)]
+    async fn get_transaction_history(
+        &self,
+        book_id: Uuid,
+        transaction_id: Uuid,
+    ) -> Result<Vec<TransactionVersion>> {
+        let state = self.state();
+
+        Ok(state
+            .versions
+            .iter()
+            .filter(|version| {
+                version.book_id == book_id && version.transaction_id == transaction_id
+            })
+            .map(|version| TransactionVersion {
+                transaction_id: version.transaction_id,
+                version: version.version,
+                action: version.action,
+                actor: version.actor.clone(),
+                snapshot: version.snapshot.clone(),
+                recorded_at: version.recorded_at,
+            })
+            .collect())
+    }
+
+    #[instrument(skip_all)]
+    async fn list_changes(&self, book_id: Uuid, since: Option<ChangeToken>) -> Result<ChangeFeed> {
+        let state = self.state();
+        let since = since.unwrap_or_default();
+
+        let mut changes = state
+            .versions
+            .iter()
+            .filter(|version| {
+                version.book_id == book_id
+                    && (version.change, version.id) > (since.after_xid, since.after_id)
+            })
+            .take(CHANGE_PAGE_SIZE + 1)
+            .collect::<Vec<_>>();
+
+        let has_more = changes.len() > CHANGE_PAGE_SIZE;
+        changes.truncate(CHANGE_PAGE_SIZE);
+
+        let next = changes
+            .last()
+            .map(|version| ChangeToken {
+                after_xid: version.change,
+                after_id: version.id,
+            })
+            .unwrap_or(since);
+
+        Ok(ChangeFeed {
+            changes: changes
+                .into_iter()
+                .map(|version| TransactionChange {
+                    transaction_id: version.transaction_id,
+                    version: version.version,
+                    action: version.action,
+                    recorded_at: version.recorded_at,
+                    snapshot: match version.action {
+                        ChangeAction::Deleted => None,
+                        _ => Some(version.snapshot.clone()),
+                    },
+                })
+                .collect(),
+            next,
+            has_more,
+        })
+    }
+}
+
+#[async_trait]
+impl TransactionRepo for MemoryLedger {
+    async fn list_transactions(
+        &self,
+        query: TransactionQuery,
+    ) -> anyhow::Result<TransactionCollection> {
+        let state = self.state();
+
+        let mut transactions = state
+            .transactions
+            .values()
+            .filter(|transaction| {
+                if query.trashed {
+                    transaction.is_trashed_in(query.book_id)
+                } else {
+                    transaction.is_active_in(query.book_id)
+                }
+            })
+            .filter(|transaction| match &query.account {
+                Some(account) => transaction
+                    .entries
+                    .iter()
+                    .any(|entry| matches_account(&entry.account, account)),
+                None => true,
+            })
+            .filter(|transaction| match &query.after {
+                Some(cursor) => {
+                    (transaction.date, transaction.created_at)
+                        < (cursor.after_date, cursor.after_created_at)
+                }
+                None => true,
+            })
+            .collect::<Vec<_>>();
+        transactions.sort_by_key(|transaction| Reverse((transaction.date, transaction.created_at)));
+
+        let has_next_page = transactions.len() > TRANSACTION_PAGE_SIZE;
+        transactions.truncate(TRANSACTION_PAGE_SIZE);
+
+        let next = if has_next_page {
+            transactions.last().map(|transaction| TransactionCursor {
+                after_date: transaction.date,
+                after_created_at: transaction.created_at,
+            })
+        } else {
+            None
+        };
+
+        let mut entries = vec![];
+        let mut accounts = vec![];
+        for transaction in &transactions {
+            for (order, entry) in transaction.entries.iter().enumerate() {
+                let account = &state.accounts[&(transaction.book_id, entry.account.clone())];
+
+                entries.push(models::ledger::TransactionEntry {
+                    id: entry.id,
+                    transaction_id: transaction.id,
+                    order: order.try_into()?,
+                    account_id: account.id,
+                    currency: entry.currency.clone(),
+                    amount: entry.amount,
+                });
+                accounts.push(models::ledger::Account {
+                    id: account.id,
+                    book_id: transaction.book_id,
+                    name: entry.account.clone(),
+                    created_at: account.created_at,
+                });
+            }
+        }
+
+        let currencies = state
+            .currencies
+            .values()
+            .map(|currency| models::ledger::Currency {
+                code: currency.code().to_owned(),
+                symbol: "".to_owned(),
+                minor_units: currency.minor_units().into(),
+            })
+            .collect::<Vec<_>>();
+
+        let transactions = transactions
+            .into_iter()
+            .map(|transaction| models::ledger::Transaction {
+                id: transaction.id,
+                book_id: transaction.book_id,
+                date: transaction.date,
+                payee: transaction.payee.clone(),
+                notes: transaction.notes.clone(),
+                created_at: transaction.created_at,
+                updated_at: transaction.updated_at,
+                deleted_at: transaction.deleted_at,
+                revision: transaction.revision,
+            })
+            .collect::<Vec<_>>();
+
+        Ok(TransactionCollection {
+            next,
+            items: models::ledger::TransactionWithEntries::zip_with_entries(
+                transactions,
+                entries,
+                currencies,
+                accounts,
+            )?,
+        })
+    }
+}
+
+#[async_trait]
+impl LedgerLockRepo for MemoryLedger {
+    async fn get_ledger_lock(&self, book_id: Uuid) -> anyhow::Result<LedgerLock> {
+        Ok(self.state().ledger_lock(book_id))
+    }
+
+    async fn list_lock_changes(&self, book_id: Uuid) -> anyhow::Result<Vec<LockChange>> {
+        Ok(self
+            .state()
+            .lock_changes
+            .iter()
+            .rev()
+            .filter(|change| change.book_id == book_id)
+            .map(|change| LockChange {
+                actor: Some(change.actor.clone()),
+                previous_lock_date: change.previous_lock_date,
+                lock_date: change.lock_date,
+                changed_at: change.changed_at,
+            })
+            .collect())
+    }
+
+    #[instrument(skip_all)]
+    async fn set_lock_date(
+        &self,
+        book_id: Uuid,
+        actor: &str,
+        lock_date: Option<NaiveDate>,
+    ) -> anyhow::Result<LedgerLock> {
+        let mut state = self.state();
+
+        let previous_lock_date = state.ledger_lock(book_id).lock_date;
+        if previous_lock_date != lock_date {
+            match lock_date {
+                Some(lock_date) => state.locks.insert(book_id, lock_date),
+                None => state.locks.remove(&book_id),
+            };
+
+            let changed_at = state.now();
+            state.lock_changes.push(StoredLockChange {
+                book_id,
+                actor: actor.to_owned(),
+                previous_lock_date,
+                lock_date,
+                changed_at,
+            });
+
+            info!(%book_id, %actor, ?previous_lock_date, ?lock_date, "Changed ledger lock date.");
+        }
+
+        Ok(LedgerLock { lock_date })
+    }
+}
+
+#[async_trait]
+impl TransactionCommands for MemoryLedger {
+    #[instrument(skip_all)]
+    async fn apply_batch(
+        &self,
+        book_id: Uuid,
+        actor: &str,
+        operations: Vec<BatchOperation>,
+    ) -> Result<Vec<BatchOperationResult>, ApplyBatchError> {
+        let mut current_state = self.state();
+        let lock = current_state.ledger_lock(book_id);
+        // The operations are applied to a copy of the ledger, which is only
+        // kept if all of them succeed.
+        let mut state = current_state.clone();
+        state.changes += 1;
+
+        let mut results = Vec::with_capacity(operations.len());
+        for (index, operation) in operations.into_iter().enumerate() {
+            let result = match operation {
+                BatchOperation::Create(transaction) => state
+                    .persist_transaction(&lock, actor, transaction)
+                    .map(BatchOperationResult::Created)
+                    .map_err(|error| ApplyBatchError::from_persist(index, error))?,
+                BatchOperation::Update {
+                    transaction_id,
+                    transaction,
+                } => state
+                    .update_transaction(&lock, actor, transaction_id, transaction, None)
+                    .map(BatchOperationResult::Updated)
+                    .map_err(|error| ApplyBatchError::from_update(index, error))?,
+                BatchOperation::Delete { transaction_id } => state
+                    .delete_transaction(&lock, book_id, actor, transaction_id)
+                    .map(|()| BatchOperationResult::Deleted(transaction_id))
+                    .map_err(|error| ApplyBatchError::from_delete(index, error))?,
+            };
+
+            results.push(result);
+        }
+
+        *current_state = state;
+        self.notify(&current_state);
+
+        info!(%book_id, operations = results.len(), "Applied transaction batch.");
+
+        Ok(results)
+    }
+
+    #[instrument(skip_all)]
+    async fn close_period(
+        &self,
+        book_id: Uuid,
+        actor: &str,
+        period_start: NaiveDate,
+        period_end: NaiveDate,
+        equity_account: &str,
+        preview: bool,
+    ) -> Result<ClosingOutcome, ClosePeriodError> {
+        let mut current_state = self.state();
+        let lock = current_state.ledger_lock(book_id);
+        // The closing is applied to a copy of the ledger, which is only kept
+        // if it succeeds.
+        let mut state = current_state.clone();
+        state.changes += 1;
+
+        let previous_transaction_id = state
+            .closings
+            .get(&(book_id, period_end))
+            .and_then(|closing| closing.transaction_id);
+
+        // The previous closing transaction is left out of the balances since
+        // it would otherwise cancel out the balances being closed.
+        let balances = state.period_balances(
+            book_id,
+            CLOSED_ACCOUNT_ROOTS,
+            period_start,
+            period_end,
+            previous_transaction_id,
+        );
+
+        let transaction =
+            closing::closing_transaction(book_id, period_end, equity_account, &balances)
+                .map_err(ClosePeriodError::Closing)?;
+        let snapshot = transaction.as_ref().map(TransactionSnapshot::from);
+
+        let mut closing = PeriodClosing {
+            period_start,
+            period_end,
+            equity_account: equity_account.to_owned(),
+            transaction_id: previous_transaction_id,
+        };
+
+        if preview {
+            return Ok(ClosingOutcome {
+                closing,
+                transaction: snapshot,
+            });
+        }
+
+        closing.transaction_id = match (previous_transaction_id, transaction) {
+            (Some(transaction_id), Some(transaction)) => {
+                match state.update_transaction(
+                    &lock,
+                    actor,
+                    transaction_id,
+                    transaction.clone(),
+                    None,
+                ) {
+                    Ok(updated) => Some(updated.id),
+                    // The previous closing transaction was deleted, so a new
+                    // one has to be created instead.
+                    Err(UpdateTransactionError::TransactionNotFound) => {
+                        Some(state.persist_transaction(&lock, actor, transaction)?.id)
+                    }
+                    Err(error) => return Err(error.into()),
+                }
+            }
+            (None, Some(transaction)) => {
+                Some(state.persist_transaction(&lock, actor, transaction)?.id)
+            }
+            (Some(transaction_id), None) => {
+                match state.delete_transaction(&lock, book_id, actor, transaction_id) {
+                    Ok(()) | Err(DeleteTransactionError::TransactionNotFound) => None,
+                    Err(error) => return Err(error.into()),
+                }
+            }
+            (None, None) => None,
+        };
+
+        state
+            .closings
+            .insert((book_id, period_end), closing.clone());
+
+        *current_state = state;
+        self.notify(&current_state);
+
+        info!(%book_id, %period_end, transaction_id = ?closing.transaction_id, "Closed period.");
+
+        Ok(ClosingOutcome {
+            closing,
+            transaction: snapshot,
+        })
+    }
+
+    #[instrument(skip_all)]
+    async fn delete_transaction(
+        &self,
+        book_id: Uuid,
+        actor: &str,
+        transaction_id: Uuid,
+    ) -> Result<(), DeleteTransactionError> {
+        let mut state = self.state();
+        let lock = state.ledger_lock(book_id);
+        state.changes += 1;
+        state.delete_transaction(&lock, book_id, actor, transaction_id)?;
+        self.notify(&state);
+
+        Ok(())
+    }
+
+    #[instrument(skip_all)]
+    async fn persist_transaction(
+        &self,
+        actor: &str,
+        transaction: NewTransaction,
+    ) -> Result<Transaction, PersistTransactionError> {
+        let mut state = self.state();
+        let lock = state.ledger_lock(transaction.book_id());
+        state.changes += 1;
+        let persisted = state.persist_transaction(&lock, actor, transaction)?;
+        self.notify(&state);
+
+        Ok(persisted)
+    }
+
+    #[instrument(skip_all)]
+    async fn purge_deleted_transactions(
+        &self,
+        deleted_before: DateTime<Utc>,
+    ) -> anyhow::Result<PurgedTransactions> {
+        let mut state = self.state();
+
+        let count = state.transactions.len();
+        state.transactions.retain(|_, transaction| {
+            transaction
+                .deleted_at
+                .is_none_or(|deleted_at| deleted_at >= deleted_before)
+        });
+        let purged = (count - state.transactions.len()).try_into()?;
+
+        info!(%deleted_before, count = purged, "Purged trashed transactions.");
+
+        // Attachments are not kept in memory, so there are none to clean up.
+        Ok(PurgedTransactions {
+            count: purged,
+            attachments: Vec::new(),
+        })
+    }
+
+    #[instrument(skip_all)]
+    async fn purge_transaction(
+        &self,
+        book_id: Uuid,
+        transaction_id: Uuid,
+    ) -> anyhow::Result<PurgedTransactions> {
+        let mut state = self.state();
+
+        let found = state
+            .transactions
+            .get(&transaction_id)
+            .is_some_and(|transaction| transaction.is_trashed_in(book_id));
+        if found {
+            state.transactions.remove(&transaction_id);
+        }
+
+        info!(%book_id, %transaction_id, found, "Purged transaction from trash.");
+
+        Ok(PurgedTransactions {
+            count: found.into(),
+            attachments: Vec::new(),
+        })
+    }
+
+    #[instrument(skip_all)]
+    async fn restore_deleted_transaction(
+        &self,
+        book_id: Uuid,
+        actor: &str,
+        transaction_id: Uuid,
+    ) -> Result<Transaction, RestoreTransactionError> {
+        let mut state = self.state();
+        let lock = state.ledger_lock(book_id);
+        let transaction = state
+            .transactions
+            .get(&transaction_id)
+            .filter(|transaction| transaction.is_trashed_in(book_id))
+            .ok_or(RestoreTransactionError::TransactionNotFound)?;
+
+        lock.ensure_unlocked([transaction.date])?;
+
+        state.changes += 1;
+        let now = state.now();
+        let transaction = state
+            .transactions
+            .get_mut(&transaction_id)
+            .ok_or(RestoreTransactionError::TransactionNotFound)?;
+        transaction.deleted_at = None;
+        transaction.updated_at = now;
+        transaction.revision += 1;
+
+        let restored = state.to_domain(&state.transactions[&transaction_id])?;
+        state.record_version(
+            book_id,
+            transaction_id,
+            ChangeAction::Restored,
+            actor,
+            TransactionSnapshot::from(&restored),
+        );
+        self.notify(&state);
+
+        info!(%book_id, %transaction_id, "Restored transaction from trash.");
+
+        Ok(restored)
+    }
+
+    #[instrument(skip_all)]
+    async fn restore_transaction_version(
+        &self,
+        book_id: Uuid,
+        actor: &str,
+        transaction_id: Uuid,
+        version: i32,
+    ) -> Result<Transaction, RestoreVersionError> {
+        let mut state = self.state();
+        let lock = state.ledger_lock(book_id);
+
+        let snapshot = state
+            .versions
+            .iter()
+            .find(|recorded| {
+                recorded.book_id == book_id
+                    && recorded.transaction_id == transaction_id
+                    && recorded.version == version
+            })
+            .map(|recorded| recorded.snapshot.clone())
+            .ok_or(RestoreVersionError::VersionNotFound)?;
+
+        let restored = snapshot
+            .to_new_transaction(book_id)
+            .map_err(RestoreVersionError::Invalid)?;
+
+        let current_date = state
+            .transactions
+            .get(&transaction_id)
+            .filter(|transaction| transaction.is_active_in(book_id))
+            .map(|transaction| transaction.date);
+
+        lock.ensure_unlocked(current_date.into_iter().chain([restored.date()]))?;
+
+        let entries = state.build_entries(book_id, restored.entries())?;
+        state.changes += 1;
+        let now = state.now();
+
+        // The transaction is taken out of the trash if it was deleted after
+        // the version was recorded, or recreated with its original ID if it
+        // has since been purged.
+        let transaction =
+            state
+                .transactions
+                .entry(transaction_id)
+                .or_insert_with(|| StoredTransaction {
+                    id: transaction_id,
+                    book_id,
+                    date: restored.date(),
+                    payee: String::new(),
+                    notes: String::new(),
+                    entries: vec![],
+                    created_at: now,
+                    updated_at: now,
+                    deleted_at: None,
+                    // Recreating the transaction counts as its first write.
+                    revision: 0,
+                });
+        if transaction.book_id != book_id {
+            return Err(RestoreVersionError::VersionNotFound);
+        }
+        transaction.date = restored.date();
+        transaction.payee = restored.payee().to_owned();
+        transaction.notes = restored.notes().unwrap_or_default().to_owned();
+        transaction.entries = entries;
+        transaction.updated_at = now;
+        transaction.deleted_at = None;
+        transaction.revision += 1;
+
+        let restored_transaction = state.to_domain(&state.transactions[&transaction_id])?;
+        state.record_version(
+            book_id,
+            transaction_id,
+            ChangeAction::Restored,
+            actor,
+            snapshot,
+        );
+        self.notify(&state);
+
+        info!(%transaction_id, version, "Restored transaction version.");
+
+        Ok(restored_transaction)
+    }
+
+    #[instrument(skip_all)]
+    async fn update_transaction(
+        &self,
+        actor: &str,
+        transaction_id: Uuid,
+        update: NewTransaction,
+        expected_revision: Option<i64>,
+    ) -> Result<Transaction, UpdateTransactionError> {
+        let mut state = self.state();
+        let lock = state.ledger_lock(update.book_id());
+        state.changes += 1;
+        let updated =
+            state.update_transaction(&lock, actor, transaction_id, update, expected_revision)?;
+        self.notify(&state);
+
+        Ok(updated)
+    }
+}
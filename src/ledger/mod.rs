@@ -1,6 +1,12 @@
 pub mod commands;
+#[cfg(test)]
+mod conformance;
 pub mod domain;
 pub mod http;
+pub mod jobs;
+pub mod memory;
 pub mod models;
+pub mod notifications;
 pub mod queries;
 pub mod services;
+pub mod webhooks;
@@ -1,13 +1,15 @@
 use std::convert::{TryFrom, TryInto};
 
 use chrono::{DateTime, NaiveDate, Utc};
-use sqlx::{postgres::PgRow, PgPool, Row};
+use sqlx::{postgres::PgRow, sqlite::SqliteRow, types::Json, PgPool, Row};
 use tracing::trace;
 use uuid::Uuid;
 
+use crate::database::Database;
+
 use super::domain;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, sqlx::FromRow)]
 pub struct Currency {
     pub code: String,
     pub symbol: String,
@@ -31,6 +33,36 @@ impl Currency {
         .await?)
     }
 
+    pub async fn list(db: &Database) -> anyhow::Result<Vec<Self>> {
+        trace!("Listing currencies.");
+
+        Ok(match db {
+            Database::Postgres(db) => {
+                sqlx::query_as!(
+                    Self,
+                    r#"
+                    SELECT code, symbol, minor_units
+                    FROM currency
+                    ORDER BY code
+                    "#
+                )
+                .fetch_all(&**db)
+                .await?
+            }
+            Database::Sqlite(db) => {
+                sqlx::query_as(
+                    r#"
+                    SELECT code, symbol, minor_units
+                    FROM currency
+                    ORDER BY code
+                    "#,
+                )
+                .fetch_all(&**db)
+                .await?
+            }
+        })
+    }
+
     pub async fn get_by_code(pool: &PgPool, currency_code: &str) -> anyhow::Result<Option<Self>> {
         trace!(currency_code, "Querying for currency by code.");
 
@@ -75,7 +107,7 @@ impl TryFrom<&Currency> for domain::currency::Currency {
 }
 
 pub struct NewTransaction {
-    pub user_id: String,
+    pub book_id: Uuid,
     pub date: NaiveDate,
     pub payee: String,
     pub notes: String,
@@ -84,7 +116,7 @@ pub struct NewTransaction {
 impl From<&domain::transactions::NewTransaction> for NewTransaction {
     fn from(transaction: &domain::transactions::NewTransaction) -> Self {
         Self {
-            user_id: transaction.user_id().to_owned(),
+            book_id: transaction.book_id(),
             date: transaction.date(),
             payee: transaction.payee().to_owned(),
             notes: transaction.notes().unwrap_or("").to_owned(),
@@ -101,24 +133,24 @@ pub struct NewTransactionEntry {
     pub amount: i32,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, sqlx::FromRow)]
 pub struct Account {
     pub id: Uuid,
-    pub user_id: String,
+    pub book_id: Uuid,
     pub name: String,
     pub created_at: DateTime<Utc>,
 }
 
 #[derive(Debug)]
 pub struct AccountByName {
-    pub user_id: String,
+    pub book_id: Uuid,
     pub name: String,
 }
 
 impl NewTransactionEntry {
     pub fn from_domain_entries(
         transaction_id: Uuid,
-        user_id: String,
+        book_id: Uuid,
         entries: &[domain::transactions::NewTransactionEntry],
     ) -> anyhow::Result<Vec<Self>> {
         entries
@@ -129,7 +161,7 @@ impl NewTransactionEntry {
                     transaction_id,
                     order: index.try_into()?,
                     account: AccountByName {
-                        user_id: user_id.clone(),
+                        book_id,
                         name: entry.account().to_owned(),
                     },
                     currency: entry.amount().currency().to_owned(),
@@ -143,12 +175,14 @@ impl NewTransactionEntry {
 #[derive(Debug, sqlx::FromRow)]
 pub struct Transaction {
     pub id: Uuid,
-    pub user_id: String,
+    pub book_id: Uuid,
     pub date: NaiveDate,
     pub payee: String,
     pub notes: String,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub deleted_at: Option<DateTime<Utc>>,
+    pub revision: i64,
 }
 
 impl Transaction {
@@ -158,7 +192,7 @@ impl Transaction {
     ) -> anyhow::Result<domain::transactions::Transaction> {
         Ok(domain::transactions::Transaction {
             id: self.id,
-            user_id: self.user_id.clone(),
+            book_id: self.book_id,
             date: self.date,
             payee: self.payee.clone(),
             notes: self.notes.clone(),
@@ -168,6 +202,8 @@ impl Transaction {
                 .collect::<anyhow::Result<Vec<domain::transactions::TransactionEntry>>>()?,
             created_at: self.created_at,
             updated_at: self.updated_at,
+            deleted_at: self.deleted_at,
+            revision: self.revision,
         })
     }
 }
@@ -204,7 +240,36 @@ impl sqlx::FromRow<'_, PgRow> for FullTransactionEntry {
             },
             account: Account {
                 id: row.try_get(6)?,
-                user_id: row.try_get(9)?,
+                book_id: row.try_get(9)?,
+                name: row.try_get(7)?,
+                created_at: row.try_get(8)?,
+            },
+            currency: Currency {
+                code: row.try_get(10)?,
+                symbol: row.try_get(11)?,
+                minor_units: row.try_get(12)?,
+            },
+        })
+    }
+}
+
+/// Entries are read from SQLite using the same column positions as from
+/// Postgres, but the columns have to be listed explicitly since SQLite's
+/// tables order them differently.
+impl sqlx::FromRow<'_, SqliteRow> for FullTransactionEntry {
+    fn from_row(row: &'_ SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Self {
+            entry: TransactionEntry {
+                id: row.try_get(0)?,
+                transaction_id: row.try_get(1)?,
+                order: row.try_get(2)?,
+                account_id: row.try_get(3)?,
+                currency: row.try_get(4)?,
+                amount: row.try_get(5)?,
+            },
+            account: Account {
+                id: row.try_get(6)?,
+                book_id: row.try_get(9)?,
                 name: row.try_get(7)?,
                 created_at: row.try_get(8)?,
             },
@@ -230,3 +295,62 @@ impl TryFrom<&FullTransactionEntry> for domain::transactions::TransactionEntry {
         ))
     }
 }
+
+/// A recorded version of a transaction.
+#[derive(Debug, sqlx::FromRow)]
+pub struct TransactionVersion {
+    pub transaction_id: Uuid,
+    pub version: i32,
+    pub action: String,
+    pub actor: String,
+    pub snapshot: Json<domain::history::TransactionSnapshot>,
+    pub recorded_at: DateTime<Utc>,
+}
+
+impl TryFrom<TransactionVersion> for domain::history::TransactionVersion {
+    type Error = anyhow::Error;
+
+    fn try_from(model: TransactionVersion) -> Result<Self, Self::Error> {
+        Ok(Self {
+            transaction_id: model.transaction_id,
+            version: model.version,
+            action: model.action.parse()?,
+            actor: model.actor,
+            snapshot: model.snapshot.0,
+            recorded_at: model.recorded_at,
+        })
+    }
+}
+
+/// A recorded version of a transaction along with its position in the change
+/// feed.
+#[derive(Debug, sqlx::FromRow)]
+pub struct TransactionChange {
+    pub id: i64,
+    pub transaction_xid: i64,
+    pub transaction_id: Uuid,
+    pub version: i32,
+    pub action: String,
+    pub snapshot: Json<domain::history::TransactionSnapshot>,
+    pub recorded_at: DateTime<Utc>,
+}
+
+impl TryFrom<TransactionChange> for domain::changes::TransactionChange {
+    type Error = anyhow::Error;
+
+    fn try_from(model: TransactionChange) -> Result<Self, Self::Error> {
+        let action = model.action.parse()?;
+        let snapshot = match action {
+            domain::history::ChangeAction::Deleted => None,
+            _ => Some(model.snapshot.0),
+        };
+
+        Ok(Self {
+            transaction_id: model.transaction_id,
+            version: model.version,
+            action,
+            recorded_at: model.recorded_at,
+            snapshot,
+        })
+    }
+}
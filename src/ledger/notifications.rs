@@ -0,0 +1,93 @@
+//! Real-time notifications of changes to users' ledgers.
+//!
+//! Changes are announced with Postgres `NOTIFY` as part of the database
+//! transaction making the change, so notifications are only sent once the
+//! change is committed and reach every server instance listening on the
+//! channel. SQLite has no equivalent, so with SQLite changes are published
+//! straight to the instance's own subscribers after they are committed.
+
+use std::time::Duration;
+
+use sqlx::{postgres::PgListener, PgPool};
+use tokio::sync::broadcast;
+use tracing::{error, info, warn};
+
+use crate::shutdown::ShutdownSignal;
+
+use super::domain::changes::ChangeNotification;
+
+/// The Postgres channel that changes are announced on.
+pub const CHANGES_CHANNEL: &str = "ledger_changes";
+
+/// The number of notifications buffered for each subscriber. Subscribers that
+/// fall further behind miss notifications and are told that they lagged.
+const SUBSCRIBER_CAPACITY: usize = 256;
+
+/// How long to wait before listening again after losing the connection.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Fans out change notifications from the database to connected clients.
+#[derive(Clone)]
+pub struct ChangeNotifier {
+    sender: broadcast::Sender<ChangeNotification>,
+}
+
+impl ChangeNotifier {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(SUBSCRIBER_CAPACITY);
+
+        Self { sender }
+    }
+
+    /// Receive every change notification. Subscribers are responsible for
+    /// filtering out other users' notifications.
+    pub fn subscribe(&self) -> broadcast::Receiver<ChangeNotification> {
+        self.sender.subscribe()
+    }
+
+    /// Send a notification straight to this instance's subscribers, for
+    /// changes that aren't announced through the database.
+    pub fn publish(&self, change: ChangeNotification) {
+        // Sending only fails if nobody is subscribed.
+        let _ = self.sender.send(change);
+    }
+
+    /// Forward notifications from the database to subscribers until the
+    /// application shuts down.
+    ///
+    /// Notifications sent while the connection is being re-established are
+    /// lost. Clients can catch up using the change feed.
+    pub async fn listen(self, pool: PgPool, mut shutdown: ShutdownSignal) {
+        loop {
+            tokio::select! {
+                result = self.forward(&pool) => {
+                    if let Err(error) = result {
+                        error!(?error, "Stopped listening for ledger changes.");
+                    }
+                },
+                _ = shutdown.wait() => return,
+            }
+
+            tokio::select! {
+                _ = tokio::time::sleep(RECONNECT_DELAY) => {},
+                _ = shutdown.wait() => return,
+            }
+        }
+    }
+
+    async fn forward(&self, pool: &PgPool) -> anyhow::Result<()> {
+        let mut listener = PgListener::connect_with(pool).await?;
+        listener.listen(CHANGES_CHANNEL).await?;
+
+        info!(channel = CHANGES_CHANNEL, "Listening for ledger changes.");
+
+        loop {
+            let notification = listener.recv().await?;
+
+            match serde_json::from_str::<ChangeNotification>(notification.payload()) {
+                Ok(change) => self.publish(change),
+                Err(error) => warn!(?error, "Ignoring malformed change notification."),
+            }
+        }
+    }
+}
@@ -4,6 +4,7 @@
 //! They never modify data.
 
 pub mod postgres;
+pub mod sqlite;
 
 use std::{collections::HashMap, sync::Arc};
 
@@ -12,7 +13,9 @@ use async_trait::async_trait;
 use chrono::NaiveDate;
 use uuid::Uuid;
 
-use super::domain::{self, currency::CurrencyAmount, reports::InstantBalances};
+use super::domain::{
+    self, closing::AccountBalance, currency::CurrencyAmount, reports::InstantBalances,
+};
 
 /// Queries for account information.
 #[async_trait]
@@ -21,7 +24,7 @@ pub trait AccountQueries {
     ///
     /// # Arguments
     ///
-    /// * `user_id` - ID of the user who owns the account.
+    /// * `book_id` - ID of the book containing the account.
     /// * `account_name` - The name of the account.
     ///
     /// # Returns
@@ -30,27 +33,68 @@ pub trait AccountQueries {
     /// the specified account.
     async fn get_account_balance(
         &self,
-        user_id: &str,
+        book_id: Uuid,
         account_name: String,
     ) -> Result<Vec<CurrencyAmount>>;
 
+    /// Get the balances of several accounts at once.
+    ///
+    /// # Arguments
+    ///
+    /// * `book_id` - ID of the book containing the accounts.
+    /// * `account_names` - The names of the accounts. Each account's balance
+    ///   includes its child accounts.
+    ///
+    /// # Returns
+    ///
+    /// A map of account names to the balance in each currency. Accounts
+    /// without any entries are omitted.
+    async fn get_account_balances(
+        &self,
+        book_id: Uuid,
+        account_names: &[String],
+    ) -> Result<HashMap<String, Vec<CurrencyAmount>>>;
+
     /// Get the monthly balance of the specified account for the last year.
     ///
     /// # Arguments
-    /// * `user_id` - The ID of the account owner.
+    /// * `book_id` - The ID of the book containing the account.
     /// * `account_name` - The name of the account to list balances for. This
     ///   matches the exact account, and any child accounts.
     async fn get_monthly_balance(
         &self,
-        user_id: &str,
+        book_id: Uuid,
         account_name: &str,
     ) -> Result<HashMap<NaiveDate, Vec<CurrencyAmount>>>;
 
+    /// Get the balance of every account within a set of subtrees for a
+    /// period.
+    ///
+    /// # Arguments
+    /// * `book_id` - The ID of the book containing the accounts.
+    /// * `account_roots` - The names of the root accounts. Each root matches
+    ///   the exact account and any child accounts.
+    /// * `start` - The first day of the period.
+    /// * `end` - The last day of the period.
+    /// * `exclude_transaction` - The ID of a transaction to leave out of the
+    ///   balances, eg. a previously generated closing transaction.
+    ///
+    /// # Returns
+    /// The non-zero balances ordered by account name and currency.
+    async fn get_period_balances(
+        &self,
+        book_id: Uuid,
+        account_roots: &[&str],
+        start: NaiveDate,
+        end: NaiveDate,
+        exclude_transaction: Option<Uuid>,
+    ) -> Result<Vec<AccountBalance>>;
+
     /// List accounts by popularity.
     ///
     /// # Arguments
     ///
-    /// * `user_id` - The ID of the user to list accounts for.
+    /// * `book_id` - The ID of the book to list accounts for.
     /// * `search_string` - An optional search string used to match account
     ///   names. If given, only accounts containing the given search string will
     ///   be matched.
@@ -61,24 +105,24 @@ pub trait AccountQueries {
     /// associated with them.
     async fn list_accounts_by_popularity(
         &self,
-        user_id: &str,
+        book_id: Uuid,
         search_string: Option<String>,
     ) -> Result<Vec<String>>;
 
     /// Get accounts that have had activity in the past year.
     ///
     /// # Arguments
-    /// * `user_id` - The ID of the user who owns the accounts.
+    /// * `book_id` - The ID of the book containing the accounts.
     ///
     /// # Returns
     /// A list containing the names of the accounts that have been recently
     /// active.
-    async fn list_active_accounts(&self, user_id: &str) -> Result<Vec<String>>;
+    async fn list_active_accounts(&self, book_id: Uuid) -> Result<Vec<String>>;
 
     /// Get the cummulative balance for an account.
     ///
     /// # Arguments
-    /// * `user_id` - The ID of the user who owns the account.
+    /// * `book_id` - The ID of the book containing the account.
     /// * `account` - The name of the account to report balances for.
     /// * `interval` - The interval between balance reports.
     ///
@@ -87,10 +131,28 @@ pub trait AccountQueries {
     /// vector of balances associated with a single currency, ordered by date.
     async fn periodic_cumulative_balance(
         &self,
-        user_id: &str,
+        book_id: Uuid,
         account: &str,
         interval: ReportInterval,
     ) -> Result<HashMap<String, InstantBalances>>;
+
+    /// Get the cummulative balances for several accounts at once.
+    ///
+    /// # Arguments
+    /// * `book_id` - The ID of the book containing the accounts.
+    /// * `accounts` - The names of the accounts to report balances for.
+    /// * `interval` - The interval between balance reports.
+    ///
+    /// # Returns
+    /// A map of account names to the same balances returned by
+    /// [`periodic_cumulative_balance`][Self::periodic_cumulative_balance].
+    /// Accounts without any balances are omitted.
+    async fn periodic_cumulative_balances(
+        &self,
+        book_id: Uuid,
+        accounts: &[String],
+        interval: ReportInterval,
+    ) -> Result<HashMap<String, HashMap<String, InstantBalances>>>;
 }
 
 pub type DynAccountQueries = Arc<dyn AccountQueries + Send + Sync>;
@@ -100,7 +162,7 @@ pub struct MonthAccountBalance {
     pub balance: CurrencyAmount,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub enum ReportInterval {
     Daily,
     Monthly,
@@ -120,13 +182,15 @@ pub trait CurrencyQueries {
     ) -> anyhow::Result<HashMap<String, domain::currency::Currency>>;
 }
 
+pub type DynCurrencyQueries = Arc<dyn CurrencyQueries + Send + Sync>;
+
 #[async_trait]
 pub trait TransactionQueries {
     /// Get a single transaction by its ID.
     ///
     /// # Arguments
     ///
-    /// * `user_id` - The ID of the transaction's owner.
+    /// * `book_id` - The ID of the book containing the transaction.
     /// * `transaction_id` - The ID of the transaction.
     ///
     /// # Returns
@@ -134,14 +198,51 @@ pub trait TransactionQueries {
     /// A [`Result`][anyhow::Result] containing the transaction if it was found.
     async fn get_transaction(
         &self,
-        user_id: &str,
+        book_id: Uuid,
         transaction_id: Uuid,
     ) -> anyhow::Result<Option<domain::transactions::Transaction>>;
+
+    /// Get every recorded version of a transaction.
+    ///
+    /// # Arguments
+    ///
+    /// * `book_id` - The ID of the book containing the transaction.
+    /// * `transaction_id` - The ID of the transaction.
+    ///
+    /// # Returns
+    ///
+    /// The transaction's versions ordered from oldest to newest. The history
+    /// of a deleted transaction is still available.
+    async fn get_transaction_history(
+        &self,
+        book_id: Uuid,
+        transaction_id: Uuid,
+    ) -> anyhow::Result<Vec<domain::history::TransactionVersion>>;
+
+    /// List the changes made to a book's transactions.
+    ///
+    /// # Arguments
+    ///
+    /// * `book_id` - The ID of the book containing the transactions.
+    /// * `since` - The token returned with the previous page of changes. If
+    ///   omitted, changes are listed from the beginning.
+    ///
+    /// # Returns
+    ///
+    /// The next page of changes, including tombstones for deleted
+    /// transactions.
+    async fn list_changes(
+        &self,
+        book_id: Uuid,
+        since: Option<domain::changes::ChangeToken>,
+    ) -> anyhow::Result<domain::changes::ChangeFeed>;
 }
 
+pub type DynTransactionQueries = Arc<dyn TransactionQueries + Send + Sync>;
+
 #[derive(Default)]
 pub struct TransactionQuery {
-    pub user_id: String,
+    pub book_id: Uuid,
     pub after: Option<domain::transactions::TransactionCursor>,
     pub account: Option<String>,
 }
@@ -3,8 +3,8 @@ use std::{collections::HashMap, convert::TryInto};
 use anyhow::Result;
 use async_trait::async_trait;
 use chrono::NaiveDate;
-use sqlx::{Postgres, QueryBuilder, Row};
-use tracing::{debug, trace};
+use sqlx::{types::Json, PgConnection, Postgres, QueryBuilder, Row};
+use tracing::{debug, instrument, trace};
 use uuid::Uuid;
 
 use crate::{
@@ -12,6 +12,8 @@ use crate::{
     ledger::{
         domain::{
             self,
+            changes::{ChangeFeed, ChangeToken},
+            closing::AccountBalance,
             currency::{Currency, CurrencyAmount},
             reports::InstantBalances,
         },
@@ -21,6 +23,58 @@ use crate::{
 
 use super::{AccountQueries, CurrencyQueries, ReportInterval, TransactionQueries};
 
+/// The maximum number of changes returned in a single page of the change
+/// feed.
+const CHANGE_PAGE_SIZE: u8 = 100;
+
+/// Fetch the balances of accounts over a period using an existing connection,
+/// so they can be read within a database transaction that changes the ledger.
+///
+/// See [`AccountQueries::get_period_balances`] for a description of the
+/// arguments.
+pub(in crate::ledger) async fn fetch_period_balances(
+    conn: &mut PgConnection,
+    book_id: Uuid,
+    account_roots: &[&str],
+    start: NaiveDate,
+    end: NaiveDate,
+    exclude_transaction: Option<Uuid>,
+) -> sqlx::Result<Vec<AccountBalance>> {
+    let account_roots = account_roots
+        .iter()
+        .map(|root| root.to_string())
+        .collect::<Vec<_>>();
+
+    sqlx::query_as!(
+        AccountBalance,
+        r#"
+        SELECT a.name AS account, e.currency, SUM(e.amount) AS "amount!"
+            FROM transaction_entry e
+                JOIN account a ON a.id = e.account_id
+                JOIN transaction t ON t.id = e.transaction_id
+        WHERE
+            t.book_id = $1
+            AND t.deleted_at IS NULL
+            AND t.date BETWEEN $2 AND $3
+            AND ($5::uuid IS NULL OR t.id <> $5)
+            AND (
+                a.name = ANY($4)
+                OR a.name LIKE ANY(SELECT root || ':%' FROM unnest($4::text[]) AS root)
+            )
+        GROUP BY a.name, e.currency
+        HAVING SUM(e.amount) <> 0
+        ORDER BY a.name, e.currency
+        "#,
+        book_id,
+        start,
+        end,
+        &account_roots,
+        exclude_transaction,
+    )
+    .fetch_all(conn)
+    .await
+}
+
 /// A struct to provide queries for the Postgres database backing the
 /// application.
 pub struct PostgresQueries(pub PostgresConnection);
@@ -34,9 +88,10 @@ struct CurrencyBalance {
 
 #[async_trait]
 impl AccountQueries for PostgresQueries {
+    #[instrument(skip_all)]
     async fn get_account_balance(
         &self,
-        user_id: &str,
+        book_id: Uuid,
         account_name: String,
     ) -> Result<Vec<domain::currency::CurrencyAmount>> {
         trace!(account = %account_name, "Fetching account balance.");
@@ -49,13 +104,14 @@ impl AccountQueries for PostgresQueries {
                     JOIN account a ON a.id = e.account_id
                     JOIN transaction t ON t.id = e.transaction_id
             WHERE
-                t.user_id = $1
+                t.book_id = $1
+                AND t.deleted_at IS NULL
                 AND
                     (a.name = $2 OR a.name LIKE $2 || ':%')
             GROUP BY e.currency
             ORDER BY e.currency
             "#,
-            user_id,
+            book_id,
             &account_name
         )
         .fetch_all(&*self.0)
@@ -91,9 +147,47 @@ impl AccountQueries for PostgresQueries {
             .collect::<Result<_>>()?)
     }
 
+    #[instrument(skip_all)]
+    async fn get_account_balances(
+        &self,
+        book_id: Uuid,
+        account_names: &[String],
+    ) -> Result<HashMap<String, Vec<CurrencyAmount>>> {
+        trace!(accounts = ?account_names, "Fetching balances of accounts.");
+
+        let balances = sqlx::query!(
+            r#"
+            SELECT r.name AS "account!", c.code, c.minor_units, COALESCE(SUM(e.amount), 0) AS "amount!"
+            FROM UNNEST($2::text[]) AS r(name)
+                JOIN account a ON a.book_id = $1 AND (a.name = r.name OR a.name LIKE r.name || ':%')
+                JOIN transaction_entry e ON e.account_id = a.id
+                JOIN transaction t ON t.id = e.transaction_id
+                JOIN currency c ON c.code = e.currency
+            WHERE t.deleted_at IS NULL
+            GROUP BY r.name, c.code
+            ORDER BY r.name, c.code
+            "#,
+            book_id,
+            account_names,
+        )
+        .fetch_all(&*self.0)
+        .await?;
+
+        let mut result: HashMap<String, Vec<CurrencyAmount>> = HashMap::default();
+        for record in balances {
+            let currency = Currency::new(record.code, record.minor_units.try_into()?);
+            let amount = CurrencyAmount::from_minor(currency, record.amount.try_into()?);
+
+            result.entry(record.account).or_default().push(amount);
+        }
+
+        Ok(result)
+    }
+
+    #[instrument(skip_all)]
     async fn get_monthly_balance(
         &self,
-        user_id: &str,
+        book_id: Uuid,
         account_name: &str,
     ) -> Result<HashMap<NaiveDate, Vec<CurrencyAmount>>> {
         let balances = sqlx::query!(
@@ -103,13 +197,14 @@ impl AccountQueries for PostgresQueries {
                 LEFT JOIN transaction t ON t.id = e.transaction_id
                 LEFT JOIN account a ON a.id = e.account_id
                 LEFT JOIN currency c ON c.code = e.currency
-            WHERE t.user_id = $1
+            WHERE t.book_id = $1
+                AND t.deleted_at IS NULL
                 AND (a.name = $2 OR a.name LIKE $2 || ':%')
                 AND t.date >= DATE_TRUNC('month', now() - INTERVAL '1 year')
             GROUP BY DATE_TRUNC('month', t.date), c.code
             ORDER BY "month!"
             "#,
-            user_id,
+            book_id,
             account_name,
         )
         .fetch_all(&*self.0)
@@ -130,9 +225,32 @@ impl AccountQueries for PostgresQueries {
         Ok(result)
     }
 
+    #[instrument(skip_all)]
+    async fn get_period_balances(
+        &self,
+        book_id: Uuid,
+        account_roots: &[&str],
+        start: NaiveDate,
+        end: NaiveDate,
+        exclude_transaction: Option<Uuid>,
+    ) -> Result<Vec<AccountBalance>> {
+        let mut conn = self.0.acquire().await?;
+
+        Ok(fetch_period_balances(
+            &mut conn,
+            book_id,
+            account_roots,
+            start,
+            end,
+            exclude_transaction,
+        )
+        .await?)
+    }
+
+    #[instrument(skip_all)]
     async fn list_accounts_by_popularity(
         &self,
-        user_id: &str,
+        book_id: Uuid,
         search: Option<String>,
     ) -> Result<Vec<String>> {
         let mut query_builder: QueryBuilder<'_, Postgres> = QueryBuilder::new(
@@ -140,14 +258,15 @@ impl AccountQueries for PostgresQueries {
             SELECT a.name
             FROM transaction_entry e
             LEFT JOIN account a ON e.account_id = a.id
-            WHERE a.user_id =
+            LEFT JOIN transaction t ON e.transaction_id = t.id
+            WHERE t.deleted_at IS NULL AND a.book_id =
             "#,
         );
-        query_builder.push_bind(user_id);
+        query_builder.push_bind(book_id);
 
         if let Some(search_str) = search {
             query_builder
-                .push("AND a.name ILIKE '%' || ")
+                .push(" AND a.name ILIKE '%' || ")
                 .push_bind(search_str)
                 .push(" || '%'");
         }
@@ -169,17 +288,19 @@ impl AccountQueries for PostgresQueries {
             .collect::<Result<Vec<_>, sqlx::Error>>()?)
     }
 
-    async fn list_active_accounts(&self, user_id: &str) -> Result<Vec<String>> {
+    #[instrument(skip_all)]
+    async fn list_active_accounts(&self, book_id: Uuid) -> Result<Vec<String>> {
         let accounts = sqlx::query!(
             r#"
             SELECT DISTINCT a.name
             FROM transaction_entry e
                 LEFT JOIN account a ON a.id = e.account_id
                 LEFT JOIN transaction t ON t.id = e.transaction_id
-            WHERE a.user_id = $1
+            WHERE a.book_id = $1
+                AND t.deleted_at IS NULL
                 AND t.created_at >= now() - INTERVAL '1 year'
             "#,
-            user_id
+            book_id
         )
         .fetch_all(&*self.0)
         .await?
@@ -190,9 +311,10 @@ impl AccountQueries for PostgresQueries {
         Ok(accounts)
     }
 
+    #[instrument(skip_all)]
     async fn periodic_cumulative_balance(
         &self,
-        user_id: &str,
+        book_id: Uuid,
         account: &str,
         interval: ReportInterval,
     ) -> Result<HashMap<String, InstantBalances>> {
@@ -215,7 +337,8 @@ impl AccountQueries for PostgresQueries {
                     LEFT JOIN transaction t ON t.id = e.transaction_id
                     LEFT JOIN account a ON a.id = e.account_id
                     LEFT JOIN currency c ON c.code = e.currency
-                WHERE t.user_id = $1
+                WHERE t.book_id = $1
+                    AND t.deleted_at IS NULL
                     AND (a.name = $2 OR a.name LIKE $2 || ':%')
                 ORDER BY "date!"
             ) AS sums
@@ -223,7 +346,7 @@ impl AccountQueries for PostgresQueries {
             GROUP BY "date!", code, minor_units, "amount!"
             ORDER BY "date!"
             "#,
-            user_id,
+            book_id,
             account,
             interval_value
         )
@@ -245,10 +368,72 @@ impl AccountQueries for PostgresQueries {
 
         Ok(balances_by_code)
     }
+
+    #[instrument(skip_all)]
+    async fn periodic_cumulative_balances(
+        &self,
+        book_id: Uuid,
+        accounts: &[String],
+        interval: ReportInterval,
+    ) -> Result<HashMap<String, HashMap<String, InstantBalances>>> {
+        trace!(?accounts, "Fetching periodic balances of accounts.");
+
+        let interval_value = match interval {
+            ReportInterval::Daily => "day",
+            ReportInterval::Monthly => "month",
+            ReportInterval::Weekly => "week",
+        };
+
+        let balances = sqlx::query!(
+            r#"
+            SELECT account AS "account!", "date!", code, minor_units, "amount!"
+            FROM (
+                SELECT
+                    r.name AS account,
+                    DATE_TRUNC($3, t.date)::date AS "date!",
+                    c.code,
+                    c.minor_units,
+                    COALESCE(SUM(e.amount) OVER (PARTITION BY r.name, c.code ORDER BY DATE_TRUNC($3, t.date)), 0) AS "amount!"
+                FROM UNNEST($2::text[]) AS r(name)
+                    JOIN account a ON a.book_id = $1 AND (a.name = r.name OR a.name LIKE r.name || ':%')
+                    JOIN transaction_entry e ON e.account_id = a.id
+                    JOIN transaction t ON t.id = e.transaction_id
+                    JOIN currency c ON c.code = e.currency
+                WHERE t.deleted_at IS NULL
+            ) AS sums
+            WHERE "date!" >= DATE_TRUNC($3, NOW() - INTERVAL '1 year')
+            GROUP BY account, "date!", code, minor_units, "amount!"
+            ORDER BY "date!"
+            "#,
+            book_id,
+            accounts,
+            interval_value
+        )
+        .fetch_all(&*self.0)
+        .await?;
+
+        let mut result: HashMap<String, HashMap<String, InstantBalances>> = HashMap::new();
+        for record in balances {
+            let currency = Currency::new(record.code, record.minor_units.try_into()?);
+            let amount: i32 = record.amount.try_into()?;
+
+            result
+                .entry(record.account)
+                .or_default()
+                .entry(currency.code().to_owned())
+                .and_modify(|amounts| amounts.push(record.date, amount))
+                .or_insert_with(|| {
+                    InstantBalances::new_with_balance(currency, record.date, amount)
+                });
+        }
+
+        Ok(result)
+    }
 }
 
 #[async_trait]
 impl CurrencyQueries for PostgresQueries {
+    #[instrument(skip_all)]
     async fn get_currencies_by_code(
         &self,
         currency_codes: Vec<String>,
@@ -275,21 +460,22 @@ impl CurrencyQueries for PostgresQueries {
 
 #[async_trait]
 impl TransactionQueries for PostgresQueries {
+    #[instrument(skip_all)]
     async fn get_transaction(
         &self,
-        user_id: &str,
+        book_id: Uuid,
         transaction_id: Uuid,
     ) -> Result<Option<domain::transactions::Transaction>> {
-        trace!(%user_id, %transaction_id, "Querying for transaction by ID.");
+        trace!(%book_id, %transaction_id, "Querying for transaction by ID.");
 
         let transaction_result = sqlx::query_as!(
             models::Transaction,
             r#"
-            SELECT id, user_id, date, payee, notes, created_at, updated_at
+            SELECT id, book_id, date, payee, notes, created_at, updated_at, deleted_at, revision
             FROM transaction
-            WHERE user_id = $1 AND id = $2
+            WHERE book_id = $1 AND id = $2 AND deleted_at IS NULL
             "#,
-            user_id,
+            book_id,
             transaction_id
         )
         .fetch_optional(&*self.0)
@@ -298,7 +484,7 @@ impl TransactionQueries for PostgresQueries {
         let transaction = match transaction_result {
             Some(t) => t,
             None => {
-                debug!(%user_id, %transaction_id, "Transaction does not exist.");
+                debug!(%book_id, %transaction_id, "Transaction does not exist.");
 
                 return Ok(None);
             }
@@ -320,4 +506,92 @@ impl TransactionQueries for PostgresQueries {
 
         Ok(Some(transaction.try_into_domain(&entries)?))
     }
+
+    #[instrument(skip_all)]
+    async fn get_transaction_history(
+        &self,
+        book_id: Uuid,
+        transaction_id: Uuid,
+    ) -> Result<Vec<domain::history::TransactionVersion>> {
+        trace!(%book_id, %transaction_id, "Querying for transaction history.");
+
+        sqlx::query_as!(
+            models::TransactionVersion,
+            r#"
+            SELECT
+                transaction_id,
+                version,
+                action,
+                actor,
+                snapshot AS "snapshot: Json<domain::history::TransactionSnapshot>",
+                recorded_at
+            FROM transaction_version
+            WHERE book_id = $1 AND transaction_id = $2
+            ORDER BY version
+            "#,
+            book_id,
+            transaction_id
+        )
+        .fetch_all(&*self.0)
+        .await?
+        .drain(..)
+        .map(TryInto::try_into)
+        .collect()
+    }
+
+    #[instrument(skip_all)]
+    async fn list_changes(&self, book_id: Uuid, since: Option<ChangeToken>) -> Result<ChangeFeed> {
+        let since = since.unwrap_or_default();
+        trace!(%book_id, %since, "Listing transaction changes.");
+
+        // Only versions recorded by database transactions that finished before
+        // the oldest one still running are listed. Sequence values are not
+        // guaranteed to become visible in order, so this prevents a client from
+        // moving its token past a change that has not been committed yet.
+        let mut changes = sqlx::query_as!(
+            models::TransactionChange,
+            r#"
+            SELECT
+                id,
+                transaction_xid::text::bigint AS "transaction_xid!",
+                transaction_id,
+                version,
+                action,
+                snapshot AS "snapshot: Json<domain::history::TransactionSnapshot>",
+                recorded_at
+            FROM transaction_version
+            WHERE book_id = $1
+                AND (transaction_xid, id) > ($2::text::xid8, $3)
+                AND transaction_xid < pg_snapshot_xmin(pg_current_snapshot())
+            ORDER BY transaction_xid, id
+            LIMIT $4
+            "#,
+            book_id,
+            since.after_xid.to_string(),
+            since.after_id,
+            i64::from(CHANGE_PAGE_SIZE) + 1,
+        )
+        .fetch_all(&*self.0)
+        .await?;
+
+        let has_more = changes.len() > usize::from(CHANGE_PAGE_SIZE);
+        changes.truncate(usize::from(CHANGE_PAGE_SIZE));
+
+        let next = changes
+            .last()
+            .map(|change| ChangeToken {
+                after_xid: change.transaction_xid,
+                after_id: change.id,
+            })
+            .unwrap_or(since);
+
+        Ok(ChangeFeed {
+            changes: changes
+                .drain(..)
+                .map(TryInto::try_into)
+                .collect::<Result<_>>()?,
+            next,
+            has_more,
+        })
+    }
 }
@@ -0,0 +1,611 @@
+use std::{collections::HashMap, convert::TryInto};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use sqlx::{types::Json, QueryBuilder, Row, Sqlite};
+use tracing::{debug, instrument, trace};
+use uuid::Uuid;
+
+use crate::{
+    database::{sqlite_now, SqliteConnection},
+    ledger::{
+        domain::{
+            self,
+            changes::{ChangeFeed, ChangeToken},
+            closing::AccountBalance,
+            currency::{Currency, CurrencyAmount},
+            reports::InstantBalances,
+        },
+        models,
+    },
+};
+
+use super::{AccountQueries, CurrencyQueries, ReportInterval, TransactionQueries};
+
+/// The maximum number of changes returned in a single page of the change
+/// feed.
+const CHANGE_PAGE_SIZE: u8 = 100;
+
+/// Fetch the balances of accounts over a period using an existing connection,
+/// so they can be read within a database transaction that changes the ledger.
+///
+/// See [`AccountQueries::get_period_balances`] for a description of the
+/// arguments.
+pub(in crate::ledger) async fn fetch_period_balances(
+    conn: &mut sqlx::SqliteConnection,
+    book_id: Uuid,
+    account_roots: &[&str],
+    start: NaiveDate,
+    end: NaiveDate,
+    exclude_transaction: Option<Uuid>,
+) -> sqlx::Result<Vec<AccountBalance>> {
+    let balances: Vec<(String, String, i64)> = sqlx::query_as(
+        r#"
+        SELECT a.name, e.currency, SUM(e.amount)
+            FROM transaction_entry e
+                JOIN account a ON a.id = e.account_id
+                JOIN "transaction" t ON t.id = e.transaction_id
+        WHERE
+            t.book_id = $1
+            AND t.deleted_at IS NULL
+            AND t.date BETWEEN $2 AND $3
+            AND ($5 IS NULL OR t.id <> $5)
+            AND EXISTS (
+                SELECT 1
+                FROM json_each($4) AS r
+                WHERE a.name = r.value
+                    OR substr(a.name, 1, length(r.value) + 1) = r.value || ':'
+            )
+        GROUP BY a.name, e.currency
+        HAVING SUM(e.amount) <> 0
+        ORDER BY a.name, e.currency
+        "#,
+    )
+    .bind(book_id)
+    .bind(start)
+    .bind(end)
+    .bind(Json(account_roots))
+    .bind(exclude_transaction)
+    .fetch_all(conn)
+    .await?;
+
+    Ok(balances
+        .into_iter()
+        .map(|(account, currency, amount)| AccountBalance {
+            account,
+            currency,
+            amount,
+        })
+        .collect())
+}
+
+/// A struct to provide queries for a SQLite database backing the application.
+pub struct SqliteQueries(pub SqliteConnection);
+
+/// Build an SQL expression truncating a date to the start of its interval,
+/// like Postgres' `DATE_TRUNC`. Weeks start on Monday.
+fn truncated_date(interval: &ReportInterval, date: &str) -> String {
+    match interval {
+        ReportInterval::Daily => format!("date({})", date),
+        ReportInterval::Monthly => format!("date({}, 'start of month')", date),
+        ReportInterval::Weekly => format!("date({}, 'weekday 0', '-6 days')", date),
+    }
+}
+
+#[async_trait]
+impl AccountQueries for SqliteQueries {
+    #[instrument(skip_all)]
+    async fn get_account_balance(
+        &self,
+        book_id: Uuid,
+        account_name: String,
+    ) -> Result<Vec<domain::currency::CurrencyAmount>> {
+        trace!(account = %account_name, "Fetching account balance.");
+
+        let balances: Vec<(String, String, i16, i64)> = sqlx::query_as(
+            r#"
+            SELECT c.code, c.symbol, c.minor_units, COALESCE(SUM(e.amount), 0)
+                FROM transaction_entry e
+                    JOIN account a ON a.id = e.account_id
+                    JOIN "transaction" t ON t.id = e.transaction_id
+                    JOIN currency c ON c.code = e.currency
+            WHERE
+                t.book_id = $1
+                AND t.deleted_at IS NULL
+                AND
+                    (a.name = $2 OR substr(a.name, 1, length($2) + 1) = $2 || ':')
+            GROUP BY c.code
+            ORDER BY c.code
+            "#,
+        )
+        .bind(book_id)
+        .bind(&account_name)
+        .fetch_all(&*self.0)
+        .await?;
+
+        balances
+            .into_iter()
+            .map(|(code, symbol, minor_units, amount)| {
+                let currency = models::Currency {
+                    code,
+                    symbol,
+                    minor_units,
+                };
+
+                Ok(domain::currency::CurrencyAmount::from_minor(
+                    domain::currency::Currency::try_from(&currency)?,
+                    amount.try_into()?,
+                ))
+            })
+            .collect()
+    }
+
+    #[instrument(skip_all)]
+    async fn get_account_balances(
+        &self,
+        book_id: Uuid,
+        account_names: &[String],
+    ) -> Result<HashMap<String, Vec<CurrencyAmount>>> {
+        trace!(accounts = ?account_names, "Fetching balances of accounts.");
+
+        let balances: Vec<(String, String, i16, i64)> = sqlx::query_as(
+            r#"
+            SELECT r.value, c.code, c.minor_units, COALESCE(SUM(e.amount), 0)
+            FROM json_each($2) AS r
+                JOIN account a ON a.book_id = $1
+                    AND (a.name = r.value OR substr(a.name, 1, length(r.value) + 1) = r.value || ':')
+                JOIN transaction_entry e ON e.account_id = a.id
+                JOIN "transaction" t ON t.id = e.transaction_id
+                JOIN currency c ON c.code = e.currency
+            WHERE t.deleted_at IS NULL
+            GROUP BY r.value, c.code
+            ORDER BY r.value, c.code
+            "#,
+        )
+        .bind(book_id)
+        .bind(Json(account_names))
+        .fetch_all(&*self.0)
+        .await?;
+
+        let mut result: HashMap<String, Vec<CurrencyAmount>> = HashMap::default();
+        for (account, code, minor_units, amount) in balances {
+            let currency = Currency::new(code, minor_units.try_into()?);
+            let amount = CurrencyAmount::from_minor(currency, amount.try_into()?);
+
+            result.entry(account).or_default().push(amount);
+        }
+
+        Ok(result)
+    }
+
+    #[instrument(skip_all)]
+    async fn get_monthly_balance(
+        &self,
+        book_id: Uuid,
+        account_name: &str,
+    ) -> Result<HashMap<NaiveDate, Vec<CurrencyAmount>>> {
+        let balances: Vec<(NaiveDate, String, i16, i64)> = sqlx::query_as(
+            r#"
+            SELECT date(t.date, 'start of month') AS month, c.code, c.minor_units, COALESCE(SUM(e.amount), 0)
+            FROM transaction_entry e
+                LEFT JOIN "transaction" t ON t.id = e.transaction_id
+                LEFT JOIN account a ON a.id = e.account_id
+                LEFT JOIN currency c ON c.code = e.currency
+            WHERE t.book_id = $1
+                AND t.deleted_at IS NULL
+                AND (a.name = $2 OR substr(a.name, 1, length($2) + 1) = $2 || ':')
+                AND t.date >= date('now', '-1 year', 'start of month')
+            GROUP BY date(t.date, 'start of month'), c.code
+            ORDER BY month
+            "#,
+        )
+        .bind(book_id)
+        .bind(account_name)
+        .fetch_all(&*self.0)
+        .await?;
+
+        let mut result: HashMap<NaiveDate, Vec<CurrencyAmount>> = HashMap::default();
+        for (month, code, minor_units, amount) in balances {
+            let currency = Currency::new(code, minor_units.try_into().unwrap_or(0));
+            let amount = CurrencyAmount::from_minor(currency, amount.try_into().unwrap_or(0));
+
+            result
+                .entry(month)
+                .and_modify(|amounts| amounts.push(amount.clone()))
+                .or_insert_with(move || vec![amount]);
+        }
+
+        Ok(result)
+    }
+
+    #[instrument(skip_all)]
+    async fn get_period_balances(
+        &self,
+        book_id: Uuid,
+        account_roots: &[&str],
+        start: NaiveDate,
+        end: NaiveDate,
+        exclude_transaction: Option<Uuid>,
+    ) -> Result<Vec<AccountBalance>> {
+        let mut conn = self.0.acquire().await?;
+
+        Ok(fetch_period_balances(
+            &mut conn,
+            book_id,
+            account_roots,
+            start,
+            end,
+            exclude_transaction,
+        )
+        .await?)
+    }
+
+    #[instrument(skip_all)]
+    async fn list_accounts_by_popularity(
+        &self,
+        book_id: Uuid,
+        search: Option<String>,
+    ) -> Result<Vec<String>> {
+        let mut query_builder: QueryBuilder<'_, Sqlite> = QueryBuilder::new(
+            r#"
+            SELECT a.name
+            FROM transaction_entry e
+            LEFT JOIN account a ON e.account_id = a.id
+            LEFT JOIN "transaction" t ON e.transaction_id = t.id
+            WHERE t.deleted_at IS NULL AND a.book_id =
+            "#,
+        );
+        query_builder.push_bind(book_id);
+
+        // SQLite's LIKE ignores the case of ASCII letters, like ILIKE.
+        if let Some(search_str) = search {
+            query_builder
+                .push(" AND a.name LIKE '%' || ")
+                .push_bind(search_str)
+                .push(" || '%'");
+        }
+
+        query_builder.push(
+            r#"
+            GROUP BY a.id
+            ORDER BY COUNT(e.id) DESC
+            LIMIT 10
+            "#,
+        );
+
+        Ok(query_builder
+            .build()
+            .fetch_all(&*self.0)
+            .await?
+            .iter()
+            .map(|row| row.try_get(0))
+            .collect::<Result<Vec<_>, sqlx::Error>>()?)
+    }
+
+    #[instrument(skip_all)]
+    async fn list_active_accounts(&self, book_id: Uuid) -> Result<Vec<String>> {
+        let active_since: DateTime<Utc> = sqlite_now() - Duration::days(365);
+
+        let accounts = sqlx::query_scalar(
+            r#"
+            SELECT DISTINCT a.name
+            FROM transaction_entry e
+                LEFT JOIN account a ON a.id = e.account_id
+                LEFT JOIN "transaction" t ON t.id = e.transaction_id
+            WHERE a.book_id = $1
+                AND t.deleted_at IS NULL
+                AND t.created_at >= $2
+            "#,
+        )
+        .bind(book_id)
+        .bind(active_since)
+        .fetch_all(&*self.0)
+        .await?;
+
+        Ok(accounts)
+    }
+
+    #[instrument(skip_all)]
+    async fn periodic_cumulative_balance(
+        &self,
+        book_id: Uuid,
+        account: &str,
+        interval: ReportInterval,
+    ) -> Result<HashMap<String, InstantBalances>> {
+        let period = truncated_date(&interval, "t.date");
+        let first_period = truncated_date(&interval, "'now', '-1 year'");
+
+        // Each period's total is added to the totals of the periods before it,
+        // and only then are the periods older than a year dropped.
+        let balances: Vec<(NaiveDate, String, i16, i64)> = sqlx::query_as(&format!(
+            r#"
+            SELECT period, code, minor_units, amount
+            FROM (
+                SELECT
+                    {period} AS period,
+                    c.code,
+                    c.minor_units,
+                    COALESCE(SUM(SUM(e.amount)) OVER (PARTITION BY c.code ORDER BY {period}), 0) AS amount
+                FROM transaction_entry e
+                    LEFT JOIN "transaction" t ON t.id = e.transaction_id
+                    LEFT JOIN account a ON a.id = e.account_id
+                    LEFT JOIN currency c ON c.code = e.currency
+                WHERE t.book_id = $1
+                    AND t.deleted_at IS NULL
+                    AND (a.name = $2 OR substr(a.name, 1, length($2) + 1) = $2 || ':')
+                GROUP BY {period}, c.code
+            ) AS sums
+            WHERE period >= {first_period}
+            ORDER BY period
+            "#,
+        ))
+        .bind(book_id)
+        .bind(account)
+        .fetch_all(&*self.0)
+        .await?;
+
+        let mut balances_by_code: HashMap<String, InstantBalances> = HashMap::new();
+        for (date, code, minor_units, amount) in balances {
+            let currency = Currency::new(code, minor_units.try_into()?);
+            let amount: i32 = amount.try_into()?;
+
+            balances_by_code
+                .entry(currency.code().to_owned())
+                .and_modify(|amounts| amounts.push(date, amount))
+                .or_insert_with(|| InstantBalances::new_with_balance(currency, date, amount));
+        }
+
+        Ok(balances_by_code)
+    }
+
+    #[instrument(skip_all)]
+    async fn periodic_cumulative_balances(
+        &self,
+        book_id: Uuid,
+        accounts: &[String],
+        interval: ReportInterval,
+    ) -> Result<HashMap<String, HashMap<String, InstantBalances>>> {
+        trace!(?accounts, "Fetching periodic balances of accounts.");
+
+        let period = truncated_date(&interval, "t.date");
+        let first_period = truncated_date(&interval, "'now', '-1 year'");
+
+        let balances: Vec<(String, NaiveDate, String, i16, i64)> = sqlx::query_as(&format!(
+            r#"
+            SELECT account, period, code, minor_units, amount
+            FROM (
+                SELECT
+                    r.value AS account,
+                    {period} AS period,
+                    c.code,
+                    c.minor_units,
+                    COALESCE(SUM(SUM(e.amount)) OVER (PARTITION BY r.value, c.code ORDER BY {period}), 0) AS amount
+                FROM json_each($2) AS r
+                    JOIN account a ON a.book_id = $1
+                        AND (a.name = r.value OR substr(a.name, 1, length(r.value) + 1) = r.value || ':')
+                    JOIN transaction_entry e ON e.account_id = a.id
+                    JOIN "transaction" t ON t.id = e.transaction_id
+                    JOIN currency c ON c.code = e.currency
+                WHERE t.deleted_at IS NULL
+                GROUP BY r.value, {period}, c.code
+            ) AS sums
+            WHERE period >= {first_period}
+            ORDER BY period
+            "#,
+        ))
+        .bind(book_id)
+        .bind(Json(accounts))
+        .fetch_all(&*self.0)
+        .await?;
+
+        let mut result: HashMap<String, HashMap<String, InstantBalances>> = HashMap::new();
+        for (account, date, code, minor_units, amount) in balances {
+            let currency = Currency::new(code, minor_units.try_into()?);
+            let amount: i32 = amount.try_into()?;
+
+            result
+                .entry(account)
+                .or_default()
+                .entry(currency.code().to_owned())
+                .and_modify(|amounts| amounts.push(date, amount))
+                .or_insert_with(|| InstantBalances::new_with_balance(currency, date, amount));
+        }
+
+        Ok(result)
+    }
+}
+
+#[async_trait]
+impl CurrencyQueries for SqliteQueries {
+    #[instrument(skip_all)]
+    async fn get_currencies_by_code(
+        &self,
+        currency_codes: Vec<String>,
+    ) -> Result<HashMap<String, domain::currency::Currency>> {
+        let currency_models: Vec<models::Currency> = sqlx::query_as(
+            r#"
+            SELECT code, symbol, minor_units FROM currency
+            WHERE code IN (SELECT value FROM json_each($1))
+            "#,
+        )
+        .bind(Json(&currency_codes))
+        .fetch_all(&*self.0)
+        .await?;
+
+        let mut currency_map = HashMap::with_capacity(currency_models.len());
+        for model in currency_models.iter() {
+            currency_map.insert(model.code.clone(), model.try_into()?);
+        }
+
+        Ok(currency_map)
+    }
+}
+
+#[async_trait]
+impl TransactionQueries for SqliteQueries {
+    #[instrument(skip_all)]
+    async fn get_transaction(
+        &self,
+        book_id: Uuid,
+        transaction_id: Uuid,
+    ) -> Result<Option<domain::transactions::Transaction>> {
+        trace!(%book_id, %transaction_id, "Querying for transaction by ID.");
+
+        let transaction_result: Option<models::Transaction> = sqlx::query_as(
+            r#"
+            SELECT id, book_id, date, payee, notes, created_at, updated_at, deleted_at, revision
+            FROM "transaction"
+            WHERE book_id = $1 AND id = $2 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(book_id)
+        .bind(transaction_id)
+        .fetch_optional(&*self.0)
+        .await?;
+
+        let transaction = match transaction_result {
+            Some(t) => t,
+            None => {
+                debug!(%book_id, %transaction_id, "Transaction does not exist.");
+
+                return Ok(None);
+            }
+        };
+
+        let entries = sqlx::query_as::<_, models::FullTransactionEntry>(
+            r#"
+            SELECT
+                e.id, e.transaction_id, e."order", e.account_id, e.currency, e.amount,
+                a.id, a.name, a.created_at, a.book_id,
+                c.code, c.symbol, c.minor_units
+            FROM transaction_entry e
+                LEFT JOIN account a ON e.account_id = a.id
+                LEFT JOIN currency c ON e.currency = c.code
+            WHERE e.transaction_id = $1
+            ORDER BY e."order"
+            "#,
+        )
+        .bind(transaction_id)
+        .fetch_all(&*self.0)
+        .await?;
+
+        Ok(Some(transaction.try_into_domain(&entries)?))
+    }
+
+    #[instrument(skip_all)]
+    async fn get_transaction_history(
+        &self,
+        book_id: Uuid,
+        transaction_id: Uuid,
+    ) -> Result<Vec<domain::history::TransactionVersion>> {
+        trace!(%book_id, %transaction_id, "Querying for transaction history.");
+
+        sqlx::query_as::<_, models::TransactionVersion>(
+            r#"
+            SELECT transaction_id, version, action, actor, snapshot, recorded_at
+            FROM transaction_version
+            WHERE book_id = $1 AND transaction_id = $2
+            ORDER BY version
+            "#,
+        )
+        .bind(book_id)
+        .bind(transaction_id)
+        .fetch_all(&*self.0)
+        .await?
+        .drain(..)
+        .map(TryInto::try_into)
+        .collect()
+    }
+
+    #[instrument(skip_all)]
+    async fn list_changes(&self, book_id: Uuid, since: Option<ChangeToken>) -> Result<ChangeFeed> {
+        let since = since.unwrap_or_default();
+        trace!(%book_id, %since, "Listing transaction changes.");
+
+        // SQLite only has a single writer, so versions are committed in the
+        // order of their IDs. The ID stands in for the transaction ID used to
+        // order changes in Postgres.
+        let mut changes = sqlx::query_as::<_, models::TransactionChange>(
+            r#"
+            SELECT
+                id,
+                id AS transaction_xid,
+                transaction_id,
+                version,
+                action,
+                snapshot,
+                recorded_at
+            FROM transaction_version
+            WHERE book_id = $1 AND id > $2
+            ORDER BY id
+            LIMIT $3
+            "#,
+        )
+        .bind(book_id)
+        .bind(since.after_id)
+        .bind(i64::from(CHANGE_PAGE_SIZE) + 1)
+        .fetch_all(&*self.0)
+        .await?;
+
+        let has_more = changes.len() > usize::from(CHANGE_PAGE_SIZE);
+        changes.truncate(usize::from(CHANGE_PAGE_SIZE));
+
+        let next = changes
+            .last()
+            .map(|change| ChangeToken {
+                after_xid: change.transaction_xid,
+                after_id: change.id,
+            })
+            .unwrap_or(since);
+
+        Ok(ChangeFeed {
+            changes: changes
+                .drain(..)
+                .map(TryInto::try_into)
+                .collect::<Result<_>>()?,
+            next,
+            has_more,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use sqlx::SqlitePool;
+
+    use super::*;
+
+    async fn truncate(pool: &SqlitePool, interval: ReportInterval, date: &str) -> NaiveDate {
+        sqlx::query_scalar(&format!("SELECT {}", truncated_date(&interval, "$1")))
+            .bind(date)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn truncates_dates_like_postgres() {
+        let pool = SqlitePool::connect("sqlite::memory:").await.unwrap();
+        let date = |s: &str| NaiveDate::parse_from_str(s, "%F").unwrap();
+
+        assert_eq!(
+            date("2023-07-12"),
+            truncate(&pool, ReportInterval::Daily, "2023-07-12").await
+        );
+        assert_eq!(
+            date("2023-07-01"),
+            truncate(&pool, ReportInterval::Monthly, "2023-07-12").await
+        );
+
+        // Weeks start on Monday, so Mondays and Sundays stay in their week.
+        for day in ["2023-07-10", "2023-07-12", "2023-07-16"] {
+            assert_eq!(
+                date("2023-07-10"),
+                truncate(&pool, ReportInterval::Weekly, day).await,
+                "{}",
+                day
+            );
+        }
+    }
+}
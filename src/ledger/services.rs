@@ -1,22 +1,55 @@
 use std::{collections::HashMap, convert::TryFrom};
 
 use anyhow::Result;
-use chrono::NaiveDate;
+use axum::body::Bytes;
+use chrono::{NaiveDate, Utc};
+use tokio::task::JoinSet;
+use tracing::{error, warn};
+use uuid::Uuid;
 
-use crate::repos::transactions::{DynTransactionRepo, TransactionQuery};
+use crate::{
+    authentication::tokens::{
+        hash_token as hash_api_token, ApiToken, NewApiToken, NewApiTokenData,
+    },
+    repos::{
+        api_tokens::DynApiTokenRepo,
+        attachments::{DynAttachmentRepo, PersistedAttachment},
+        books::DynBookRepo,
+        idempotency::DynIdempotencyRepo,
+        locks::DynLedgerLockRepo,
+        transactions::{DynTransactionRepo, TransactionQuery},
+        webhooks::DynWebhookRepo,
+    },
+    storage::DynBlobStorage,
+};
 
 use super::{
+    commands::{ClosePeriodError, ClosingOutcome, DynTransactionCommands},
     domain::{
+        attachments::{Attachment, AttachmentLimits, NewAttachment, NewAttachmentError},
+        books::{
+            hash_token, AcceptInvitationError, BookData, DeleteBookError, Invitation, Member,
+            MemberChangeError, Membership, NewInvitation, NewInvitationData, Role, SaveBookError,
+        },
         currency::CurrencyAmount,
+        idempotency::{IdempotencyRecord, StoredResponse},
+        locking::{LedgerLock, LockChange},
         reports::InstantBalances,
         transactions::{Transaction, TransactionCursor},
+        webhooks::{
+            DeliveryAttempt, NewWebhookEndpoint, NextStep, WebhookDelivery, WebhookEndpoint,
+        },
     },
-    queries::{DynAccountQueries, ReportInterval},
+    queries::{DynAccountQueries, DynTransactionQueries, ReportInterval},
+    webhooks::{DestinationError, WebhookSender},
 };
 
 #[derive(Clone)]
 pub struct LedgerService {
     pub account_queries: DynAccountQueries,
+    pub lock_repo: DynLedgerLockRepo,
+    pub transaction_commands: DynTransactionCommands,
+    pub transaction_queries: DynTransactionQueries,
     pub transaction_repo: DynTransactionRepo,
 }
 
@@ -26,9 +59,47 @@ pub struct TransactionCollection {
 }
 
 impl LedgerService {
+    /// Close a period's income and expenses into an equity account.
+    ///
+    /// Closing the same period again replaces the previously generated
+    /// closing transaction, so it is safe to re-run after transactions in the
+    /// period have changed.
+    ///
+    /// # Arguments
+    /// * `book_id` - The ID of the book to close the period in.
+    /// * `actor` - The ID of the user closing the period.
+    /// * `period_start` - The first day of the period.
+    /// * `period_end` - The last day of the period.
+    /// * `equity_account` - The account that balances are closed into.
+    /// * `preview` - If set, the closing is computed but nothing is persisted.
+    pub async fn close_period(
+        &self,
+        book_id: Uuid,
+        actor: &str,
+        period_start: NaiveDate,
+        period_end: NaiveDate,
+        equity_account: &str,
+        preview: bool,
+    ) -> Result<ClosingOutcome, ClosePeriodError> {
+        if period_end < period_start {
+            return Err(ClosePeriodError::InvalidPeriod);
+        }
+
+        self.transaction_commands
+            .close_period(
+                book_id,
+                actor,
+                period_start,
+                period_end,
+                equity_account,
+                preview,
+            )
+            .await
+    }
+
     pub async fn account_periodic_balance(
         &self,
-        user_id: &str,
+        book_id: Uuid,
         account: &str,
         balance_type: AccountBalanceType,
         interval: ReportInterval,
@@ -36,24 +107,63 @@ impl LedgerService {
         match balance_type {
             AccountBalanceType::Cummulative => {
                 self.account_queries
-                    .periodic_cumulative_balance(user_id, account, interval)
+                    .periodic_cumulative_balance(book_id, account, interval)
                     .await
             }
         }
     }
 
+    /// Get the current balances of several accounts.
+    ///
+    /// # Arguments
+    /// * `book_id` - The ID of the book containing the accounts.
+    /// * `account_names` - The names of the accounts. Each balance includes
+    ///   the account's children.
+    pub async fn get_account_balances(
+        &self,
+        book_id: Uuid,
+        account_names: &[String],
+    ) -> Result<HashMap<String, Vec<CurrencyAmount>>> {
+        self.account_queries
+            .get_account_balances(book_id, account_names)
+            .await
+    }
+
     pub async fn get_monthly_account_balance(
         &self,
-        user_id: &str,
+        book_id: Uuid,
         account_name: &str,
     ) -> Result<HashMap<NaiveDate, Vec<CurrencyAmount>>> {
         self.account_queries
-            .get_monthly_balance(user_id, account_name)
+            .get_monthly_balance(book_id, account_name)
             .await
     }
 
-    pub async fn list_active_accounts(&self, user_id: &str) -> Result<Vec<String>> {
-        self.account_queries.list_active_accounts(user_id).await
+    pub async fn get_ledger_lock(&self, book_id: Uuid) -> Result<LedgerLock> {
+        self.lock_repo.get_ledger_lock(book_id).await
+    }
+
+    /// List a book's accounts, ordered by how often they are used.
+    ///
+    /// # Arguments
+    /// * `book_id` - The ID of the book containing the accounts.
+    /// * `search` - Only include accounts whose names contain this text.
+    pub async fn list_accounts(
+        &self,
+        book_id: Uuid,
+        search: Option<String>,
+    ) -> Result<Vec<String>> {
+        self.account_queries
+            .list_accounts_by_popularity(book_id, search)
+            .await
+    }
+
+    pub async fn list_active_accounts(&self, book_id: Uuid) -> Result<Vec<String>> {
+        self.account_queries.list_active_accounts(book_id).await
+    }
+
+    pub async fn list_lock_changes(&self, book_id: Uuid) -> Result<Vec<LockChange>> {
+        self.lock_repo.list_lock_changes(book_id).await
     }
 
     pub async fn list_transactions(
@@ -73,9 +183,545 @@ impl LedgerService {
             next: model_collection.next,
         })
     }
+
+    /// Move the date on or before which a book's transactions are locked.
+    ///
+    /// # Arguments
+    /// * `book_id` - The ID of the book to lock.
+    /// * `actor` - The ID of the user changing the lock date.
+    /// * `lock_date` - The new lock date, or `None` to unlock the ledger.
+    pub async fn set_lock_date(
+        &self,
+        book_id: Uuid,
+        actor: &str,
+        lock_date: Option<NaiveDate>,
+    ) -> Result<LedgerLock> {
+        self.lock_repo
+            .set_lock_date(book_id, actor, lock_date)
+            .await
+    }
 }
 
 #[derive(Debug)]
 pub enum AccountBalanceType {
     Cummulative,
 }
+
+#[derive(Clone)]
+pub struct ApiTokenService {
+    pub api_token_repo: DynApiTokenRepo,
+}
+
+impl ApiTokenService {
+    /// Find the user a personal access token belongs to, and record that the
+    /// token was used.
+    ///
+    /// # Returns
+    /// The token, or `None` if it doesn't exist or has been revoked.
+    pub async fn authenticate(&self, token: &str) -> Result<Option<ApiToken>> {
+        self.api_token_repo.use_token(&hash_api_token(token)).await
+    }
+
+    /// Create a personal access token.
+    ///
+    /// # Returns
+    /// The token along with its secret, which is only available now.
+    pub async fn create_token(
+        &self,
+        user_id: &str,
+        data: NewApiTokenData,
+    ) -> Result<(ApiToken, String)> {
+        let new_token = NewApiToken::new(user_id, data);
+        let token = self.api_token_repo.create_token(&new_token).await?;
+
+        Ok((token, new_token.token().to_owned()))
+    }
+
+    pub async fn list_tokens(&self, user_id: &str) -> Result<Vec<ApiToken>> {
+        self.api_token_repo.list_tokens(user_id).await
+    }
+
+    /// Revoke one of a user's personal access tokens.
+    ///
+    /// # Returns
+    /// A boolean indicating if the user had a token with the ID.
+    pub async fn revoke_token(&self, user_id: &str, token_id: Uuid) -> Result<bool> {
+        self.api_token_repo.revoke_token(user_id, token_id).await
+    }
+}
+
+#[derive(Clone)]
+pub struct AttachmentService {
+    pub attachment_repo: DynAttachmentRepo,
+    pub blob_storage: DynBlobStorage,
+    pub limits: AttachmentLimits,
+}
+
+#[derive(Debug)]
+pub enum AddAttachmentError {
+    /// The attachment was rejected during validation.
+    Invalid(NewAttachmentError),
+
+    /// The book does not contain a transaction with the provided ID.
+    TransactionNotFound,
+
+    Unknown(anyhow::Error),
+}
+
+impl AttachmentService {
+    /// Attach a file to a transaction.
+    ///
+    /// # Arguments
+    /// * `book_id` - The ID of the book containing the transaction.
+    /// * `transaction_id` - The ID of the transaction to attach the file to.
+    /// * `file_name` - The name of the file.
+    /// * `content_type` - The MIME type of the file as reported by the client.
+    /// * `content` - The file's content.
+    pub async fn add_attachment(
+        &self,
+        book_id: Uuid,
+        transaction_id: Uuid,
+        file_name: &str,
+        content_type: &str,
+        content: Bytes,
+    ) -> Result<Attachment, AddAttachmentError> {
+        let used = self.attachment_repo.total_attachment_size(book_id).await?;
+        let new_attachment = NewAttachment::new(
+            book_id,
+            transaction_id,
+            file_name,
+            content_type,
+            &content,
+            &self.limits,
+            used,
+        )
+        .map_err(AddAttachmentError::Invalid)?;
+
+        // The metadata is persisted first since that is where we verify that
+        // the transaction is in the book, and where the quota is enforced
+        // against concurrent uploads. If storing the content fails, we remove
+        // the metadata again so there is no attachment without content.
+        let attachment = match self
+            .attachment_repo
+            .persist_attachment(&new_attachment, self.limits.book_quota)
+            .await?
+        {
+            PersistedAttachment::Persisted(attachment) => attachment,
+            PersistedAttachment::TransactionNotFound => {
+                return Err(AddAttachmentError::TransactionNotFound)
+            }
+            PersistedAttachment::QuotaExceeded { used } => {
+                return Err(AddAttachmentError::Invalid(
+                    NewAttachmentError::QuotaExceeded {
+                        used,
+                        size: new_attachment.size(),
+                        quota: self.limits.book_quota,
+                    },
+                ))
+            }
+        };
+
+        if let Err(error) = self
+            .blob_storage
+            .put(&attachment.storage_key(), content)
+            .await
+        {
+            if let Err(cleanup_error) = self
+                .attachment_repo
+                .delete_attachment(book_id, transaction_id, attachment.id)
+                .await
+            {
+                error!(?cleanup_error, attachment_id = %attachment.id, "Failed to remove metadata for attachment without content.");
+            }
+
+            return Err(error.into());
+        }
+
+        Ok(attachment)
+    }
+
+    /// Delete an attachment and its content.
+    ///
+    /// # Returns
+    /// A boolean indicating if a matching attachment was found.
+    pub async fn delete_attachment(
+        &self,
+        book_id: Uuid,
+        transaction_id: Uuid,
+        attachment_id: Uuid,
+    ) -> Result<bool> {
+        match self
+            .attachment_repo
+            .delete_attachment(book_id, transaction_id, attachment_id)
+            .await?
+        {
+            Some(attachment) => {
+                self.remove_attachment_content(&[attachment]).await;
+
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Get an attachment along with its content.
+    pub async fn get_attachment_content(
+        &self,
+        book_id: Uuid,
+        transaction_id: Uuid,
+        attachment_id: Uuid,
+    ) -> Result<Option<(Attachment, Bytes)>> {
+        let attachment = match self
+            .attachment_repo
+            .get_attachment(book_id, transaction_id, attachment_id)
+            .await?
+        {
+            Some(attachment) => attachment,
+            None => return Ok(None),
+        };
+
+        match self.blob_storage.get(&attachment.storage_key()).await? {
+            Some(content) => Ok(Some((attachment, content))),
+            None => {
+                warn!(%attachment_id, "Attachment exists but its content is missing.");
+
+                Ok(None)
+            }
+        }
+    }
+
+    pub fn limits(&self) -> &AttachmentLimits {
+        &self.limits
+    }
+
+    pub async fn list_attachments(
+        &self,
+        book_id: Uuid,
+        transaction_id: Uuid,
+    ) -> Result<Vec<Attachment>> {
+        self.attachment_repo
+            .list_attachments(book_id, transaction_id)
+            .await
+    }
+
+    /// Remove the stored content of attachments whose metadata has already
+    /// been removed, eg. when the transaction they were attached to was
+    /// purged.
+    ///
+    /// Failures are logged rather than returned since the attachments are no
+    /// longer reachable at this point.
+    pub async fn remove_attachment_content(&self, attachments: &[Attachment]) {
+        for attachment in attachments {
+            if let Err(error) = self.blob_storage.delete(&attachment.storage_key()).await {
+                error!(?error, attachment_id = %attachment.id, "Failed to remove attachment content.");
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct BookService {
+    pub book_repo: DynBookRepo,
+}
+
+impl BookService {
+    pub async fn accept_invitation(
+        &self,
+        token: &str,
+        user_id: &str,
+    ) -> Result<Membership, AcceptInvitationError> {
+        self.book_repo
+            .accept_invitation(&hash_token(token), user_id)
+            .await
+    }
+
+    /// Create a book and make the user creating it its owner.
+    pub async fn create_book(
+        &self,
+        user_id: &str,
+        data: &BookData,
+    ) -> Result<Membership, SaveBookError> {
+        self.book_repo.create_book(user_id, data).await
+    }
+
+    /// Invite a user to a book.
+    ///
+    /// # Returns
+    /// The persisted invitation along with the token used to accept it. The
+    /// token can't be retrieved again later.
+    pub async fn create_invitation(
+        &self,
+        book_id: Uuid,
+        created_by: &str,
+        data: NewInvitationData,
+    ) -> Result<(Invitation, String)> {
+        let new_invitation = NewInvitation::new(book_id, created_by, data);
+        let invitation = self.book_repo.create_invitation(&new_invitation).await?;
+
+        Ok((invitation, new_invitation.token().to_owned()))
+    }
+
+    /// Delete a book along with everything in it.
+    ///
+    /// # Returns
+    /// The attachments that were removed with the book. Their content has to
+    /// be removed separately.
+    pub async fn delete_book(&self, book_id: Uuid) -> Result<Vec<Attachment>, DeleteBookError> {
+        self.book_repo.delete_book(book_id).await
+    }
+
+    /// Get the book a request is made against.
+    ///
+    /// # Arguments
+    /// * `user_id` - The ID of the user making the request.
+    /// * `book_id` - The ID of the requested book, or `None` to use the
+    ///   user's personal book.
+    ///
+    /// # Returns
+    /// The user's membership in the book, or `None` if they are not a member.
+    pub async fn get_membership(
+        &self,
+        user_id: &str,
+        book_id: Option<Uuid>,
+    ) -> Result<Option<Membership>> {
+        match book_id {
+            Some(book_id) => self.book_repo.get_membership(book_id, user_id).await,
+            None => Ok(Some(
+                self.book_repo.get_or_create_personal_book(user_id).await?,
+            )),
+        }
+    }
+
+    /// List the books a user is a member of. The user's personal book is
+    /// created if it doesn't exist yet so it is always included.
+    pub async fn list_books(&self, user_id: &str) -> Result<Vec<Membership>> {
+        self.book_repo.get_or_create_personal_book(user_id).await?;
+
+        self.book_repo.list_books(user_id).await
+    }
+
+    pub async fn list_invitations(&self, book_id: Uuid) -> Result<Vec<Invitation>> {
+        self.book_repo.list_invitations(book_id).await
+    }
+
+    pub async fn list_members(&self, book_id: Uuid) -> Result<Vec<Member>> {
+        self.book_repo.list_members(book_id).await
+    }
+
+    pub async fn remove_member(
+        &self,
+        book_id: Uuid,
+        user_id: &str,
+    ) -> Result<(), MemberChangeError> {
+        self.book_repo.remove_member(book_id, user_id).await
+    }
+
+    pub async fn revoke_invitation(&self, book_id: Uuid, invitation_id: Uuid) -> Result<bool> {
+        self.book_repo
+            .revoke_invitation(book_id, invitation_id)
+            .await
+    }
+
+    pub async fn set_member_role(
+        &self,
+        book_id: Uuid,
+        user_id: &str,
+        role: Role,
+    ) -> Result<Member, MemberChangeError> {
+        self.book_repo.set_member_role(book_id, user_id, role).await
+    }
+
+    /// Change the name and settings of a book.
+    ///
+    /// # Returns
+    /// The updated book as seen by the user who changed it.
+    pub async fn update_book(
+        &self,
+        book_id: Uuid,
+        user_id: &str,
+        data: &BookData,
+    ) -> Result<Membership, SaveBookError> {
+        self.book_repo.update_book(book_id, data).await?;
+
+        self.book_repo
+            .get_membership(book_id, user_id)
+            .await?
+            .ok_or_else(|| SaveBookError::Unknown(anyhow::anyhow!("book missing after update")))
+    }
+}
+
+#[derive(Clone)]
+pub struct IdempotencyService {
+    pub idempotency_repo: DynIdempotencyRepo,
+    /// How long keys and their responses are kept.
+    pub ttl: chrono::Duration,
+}
+
+impl IdempotencyService {
+    /// Claim an idempotency key for a new request.
+    ///
+    /// # Returns
+    /// `None` if the request should be processed, or the record of the
+    /// earlier request that used the same key.
+    pub async fn claim_key(
+        &self,
+        user_id: &str,
+        key: &str,
+        request_hash: &str,
+    ) -> Result<Option<IdempotencyRecord>> {
+        self.idempotency_repo
+            .claim_key(user_id, key, request_hash, Utc::now() - self.ttl)
+            .await
+    }
+
+    /// Delete every key that is older than the TTL.
+    pub async fn delete_expired_keys(&self) -> Result<u64> {
+        self.idempotency_repo
+            .delete_expired_keys(Utc::now() - self.ttl)
+            .await
+    }
+
+    pub async fn release_key(&self, user_id: &str, key: &str) -> Result<()> {
+        self.idempotency_repo.release_key(user_id, key).await
+    }
+
+    pub async fn save_response(
+        &self,
+        user_id: &str,
+        key: &str,
+        response: &StoredResponse,
+    ) -> Result<()> {
+        self.idempotency_repo
+            .save_response(user_id, key, response)
+            .await
+    }
+}
+
+/// The number of deliveries claimed at a time by the dispatcher.
+const WEBHOOK_DELIVERY_BATCH_SIZE: i64 = 50;
+
+/// How long a claimed delivery is reserved for the attempt to be recorded.
+const WEBHOOK_DELIVERY_LEASE_SECONDS: i64 = 5 * 60;
+
+#[derive(Clone)]
+pub struct WebhookService {
+    pub webhook_repo: DynWebhookRepo,
+    pub sender: WebhookSender,
+}
+
+#[derive(Debug)]
+pub enum CreateWebhookEndpointError {
+    /// Webhooks may not be sent to the endpoint's URL.
+    Destination(DestinationError),
+
+    Unknown(anyhow::Error),
+}
+
+impl WebhookService {
+    /// Register a new endpoint after checking that its URL doesn't point at
+    /// an internal address.
+    pub async fn create_endpoint(
+        &self,
+        endpoint: &NewWebhookEndpoint,
+    ) -> Result<WebhookEndpoint, CreateWebhookEndpointError> {
+        self.sender
+            .check_destination(endpoint.url())
+            .await
+            .map_err(CreateWebhookEndpointError::Destination)?;
+
+        self.webhook_repo
+            .create_endpoint(endpoint)
+            .await
+            .map_err(CreateWebhookEndpointError::Unknown)
+    }
+
+    pub async fn delete_endpoint(&self, book_id: Uuid, endpoint_id: Uuid) -> Result<bool> {
+        self.webhook_repo
+            .delete_endpoint(book_id, endpoint_id)
+            .await
+    }
+
+    /// Attempt every delivery that is currently due.
+    ///
+    /// # Returns
+    /// The number of deliveries that were attempted.
+    pub async fn deliver_due(&self) -> Result<usize> {
+        let mut attempted = 0;
+
+        loop {
+            let deliveries = self
+                .webhook_repo
+                .claim_due_deliveries(
+                    WEBHOOK_DELIVERY_BATCH_SIZE,
+                    Utc::now() + chrono::Duration::seconds(WEBHOOK_DELIVERY_LEASE_SECONDS),
+                )
+                .await?;
+            let claimed = deliveries.len();
+
+            let mut attempts = JoinSet::new();
+            for delivery in deliveries {
+                let service = self.clone();
+                attempts.spawn(async move { service.attempt_delivery(delivery).await });
+            }
+
+            while let Some(result) = attempts.join_next().await {
+                match result {
+                    Ok(Ok(())) => attempted += 1,
+                    Ok(Err(error)) => error!(?error, "Failed to record webhook delivery."),
+                    Err(error) => error!(?error, "Webhook delivery task failed."),
+                }
+            }
+
+            if claimed < WEBHOOK_DELIVERY_BATCH_SIZE as usize {
+                return Ok(attempted);
+            }
+        }
+    }
+
+    async fn attempt_delivery(&self, delivery: WebhookDelivery) -> Result<()> {
+        let result = self.sender.send(&delivery).await;
+        let next_step = delivery.next_step(&result, Utc::now());
+
+        match &next_step {
+            NextStep::Delivered => (),
+            NextStep::RetryAt(retry_at) => {
+                warn!(delivery_id = delivery.id, error = ?result.error, %retry_at, "Webhook delivery failed.")
+            }
+            NextStep::Abandon => {
+                warn!(delivery_id = delivery.id, error = ?result.error, "Abandoned webhook delivery.")
+            }
+        }
+
+        self.webhook_repo
+            .record_attempt(delivery.id, &result, &next_step)
+            .await
+    }
+
+    pub async fn get_endpoint(
+        &self,
+        book_id: Uuid,
+        endpoint_id: Uuid,
+    ) -> Result<Option<WebhookEndpoint>> {
+        self.webhook_repo.get_endpoint(book_id, endpoint_id).await
+    }
+
+    pub async fn list_delivery_attempts(
+        &self,
+        book_id: Uuid,
+        endpoint_id: Uuid,
+    ) -> Result<Vec<DeliveryAttempt>> {
+        self.webhook_repo
+            .list_delivery_attempts(book_id, endpoint_id)
+            .await
+    }
+
+    pub async fn list_endpoints(&self, book_id: Uuid) -> Result<Vec<WebhookEndpoint>> {
+        self.webhook_repo.list_endpoints(book_id).await
+    }
+}
+
+impl From<anyhow::Error> for AddAttachmentError {
+    fn from(error: anyhow::Error) -> Self {
+        Self::Unknown(error)
+    }
+}
@@ -0,0 +1,306 @@
+//! Delivery of webhook events to the endpoints registered by users.
+
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use chrono::Utc;
+use hyper::client::connect::dns::Name;
+use reqwest::{
+    dns::{Addrs, Resolve, Resolving},
+    header::CONTENT_TYPE,
+    redirect, Url,
+};
+use thiserror::Error;
+use tracing::debug;
+
+use super::domain::webhooks::{self, AttemptResult, WebhookDelivery};
+
+/// Why webhooks can't be sent to a URL.
+#[derive(Debug, Error)]
+pub enum DestinationError {
+    #[error("the URL has no host")]
+    InvalidUrl,
+    #[error("the host could not be resolved")]
+    Unresolved(#[source] std::io::Error),
+    #[error("the host resolves to {0}, which is not a public address")]
+    NonPublicAddress(IpAddr),
+}
+
+/// Sends signed webhook requests.
+#[derive(Clone)]
+pub struct WebhookSender {
+    client: reqwest::Client,
+    allow_private_addresses: bool,
+}
+
+impl WebhookSender {
+    /// Create a new sender.
+    ///
+    /// # Arguments
+    /// * `timeout` - How long to wait for an endpoint to respond.
+    /// * `allow_private_addresses` - Whether endpoints may be on loopback,
+    ///   private or link-local addresses. This is only safe if users can't
+    ///   reach anything they shouldn't from the server's network.
+    pub fn new(timeout: Duration, allow_private_addresses: bool) -> anyhow::Result<Self> {
+        let mut builder = reqwest::Client::builder()
+            .timeout(timeout)
+            // A redirect could point anywhere, including at an internal
+            // address.
+            .redirect(redirect::Policy::none())
+            .user_agent(concat!("zeroed-books-webhooks/", env!("CARGO_PKG_VERSION")));
+        if !allow_private_addresses {
+            // Resolving the host again when connecting keeps it from changing
+            // to an internal address after it was checked.
+            builder = builder.dns_resolver(Arc::new(PublicResolver));
+        }
+
+        Ok(Self {
+            client: builder.build()?,
+            allow_private_addresses,
+        })
+    }
+
+    /// Check that webhooks may be sent to a URL by resolving its host.
+    pub async fn check_destination(&self, url: &str) -> Result<(), DestinationError> {
+        if self.allow_private_addresses {
+            return Ok(());
+        }
+
+        let url = Url::parse(url).map_err(|_| DestinationError::InvalidUrl)?;
+        match url.host_str() {
+            // IPv6 hosts are enclosed in brackets.
+            Some(host) => resolve_public(host.trim_start_matches('[').trim_end_matches(']'))
+                .await
+                .map(drop),
+            None => Err(DestinationError::InvalidUrl),
+        }
+    }
+
+    /// Make a single attempt to deliver an event.
+    ///
+    /// The request body is the JSON encoded event, signed with the endpoint's
+    /// secret in the [signature header][webhooks::SIGNATURE_HEADER].
+    pub async fn send(&self, delivery: &WebhookDelivery) -> AttemptResult {
+        let body = match serde_json::to_vec(&delivery.event) {
+            Ok(body) => body,
+            Err(error) => {
+                return AttemptResult {
+                    response_status: None,
+                    error: Some(format!("Failed to encode event: {}", error)),
+                }
+            }
+        };
+        if let Err(error) = self.check_destination(&delivery.url).await {
+            return AttemptResult {
+                response_status: None,
+                error: Some(format!("Refused to send webhook: {}.", error)),
+            };
+        }
+
+        let signature = webhooks::sign_payload(&delivery.secret, Utc::now().timestamp(), &body);
+
+        let response = self
+            .client
+            .post(&delivery.url)
+            .header(CONTENT_TYPE, "application/json")
+            .header(webhooks::SIGNATURE_HEADER, signature)
+            .body(body)
+            .send()
+            .await;
+
+        match response {
+            Ok(response) => {
+                let status = response.status();
+                debug!(delivery_id = delivery.id, %status, "Endpoint responded to webhook.");
+
+                AttemptResult {
+                    response_status: Some(status.as_u16()),
+                    error: if status.is_success() {
+                        None
+                    } else {
+                        Some(format!("Endpoint responded with {}.", status))
+                    },
+                }
+            }
+            Err(error) => AttemptResult {
+                response_status: None,
+                error: Some(error.to_string()),
+            },
+        }
+    }
+}
+
+/// Resolve a host, requiring every address it resolves to to be public.
+async fn resolve_public(host: &str) -> Result<Vec<SocketAddr>, DestinationError> {
+    // The port is replaced by the one from the URL when connecting.
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, 0))
+        .await
+        .map_err(DestinationError::Unresolved)?
+        .collect();
+
+    match addrs
+        .iter()
+        .find(|addr| !webhooks::is_public_address(addr.ip()))
+    {
+        Some(addr) => Err(DestinationError::NonPublicAddress(addr.ip())),
+        None => Ok(addrs),
+    }
+}
+
+/// Resolves the hosts of webhook endpoints, refusing any host with an address
+/// that isn't public.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs = resolve_public(name.as_str()).await?;
+            let addrs: Addrs = Box::new(addrs.into_iter());
+
+            Ok(addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{net::SocketAddr, sync::Arc};
+
+    use axum::{
+        body::Bytes, extract::State, http::HeaderMap, http::StatusCode, routing::post, Router,
+    };
+    use tokio::sync::Mutex;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::ledger::domain::webhooks::{EventType, WebhookEvent};
+
+    type Received = Arc<Mutex<Vec<(HeaderMap, Bytes)>>>;
+
+    /// Start a local stand-in for a user's endpoint that records every request
+    /// and responds with the given status.
+    async fn stand_in(status: StatusCode) -> (SocketAddr, Received) {
+        let received: Received = Arc::default();
+        let app = Router::new()
+            .route(
+                "/hooks",
+                post(
+                    move |State(received): State<Received>, headers: HeaderMap, body: Bytes| async move {
+                        received.lock().await.push((headers, body));
+
+                        status
+                    },
+                ),
+            )
+            .with_state(received.clone());
+
+        let server =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(app.into_make_service());
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        (addr, received)
+    }
+
+    fn delivery(addr: SocketAddr) -> WebhookDelivery {
+        WebhookDelivery {
+            id: 1,
+            endpoint_id: Uuid::new_v4(),
+            url: format!("http://{}/hooks", addr),
+            secret: "whsec_test".to_owned(),
+            event: WebhookEvent {
+                id: 42,
+                event_type: EventType::TransactionCreated,
+                created_at: Utc::now(),
+                data: serde_json::json!({ "transaction_id": Uuid::new_v4() }),
+            },
+            attempts: 0,
+        }
+    }
+
+    fn sender() -> WebhookSender {
+        // The stand-in endpoints listen on the loopback address.
+        WebhookSender::new(Duration::from_secs(5), true).unwrap()
+    }
+
+    #[tokio::test]
+    async fn send_signed_event() {
+        let (addr, received) = stand_in(StatusCode::NO_CONTENT).await;
+
+        let result = sender().send(&delivery(addr)).await;
+
+        assert!(result.succeeded(), "unexpected result: {:?}", result);
+
+        let received = received.lock().await;
+        let (headers, body) = &received[0];
+        let event: serde_json::Value = serde_json::from_slice(body).unwrap();
+        assert_eq!("transaction.created", event["type"]);
+        assert_eq!(42, event["id"]);
+
+        let signature = headers[webhooks::SIGNATURE_HEADER].to_str().unwrap();
+        let timestamp = signature
+            .strip_prefix("t=")
+            .and_then(|rest| rest.split(',').next())
+            .and_then(|timestamp| timestamp.parse().ok())
+            .expect("signature should include a timestamp");
+        assert_eq!(
+            webhooks::sign_payload("whsec_test", timestamp, body),
+            signature
+        );
+    }
+
+    #[tokio::test]
+    async fn send_error_status() {
+        let (addr, _) = stand_in(StatusCode::INTERNAL_SERVER_ERROR).await;
+
+        let result = sender().send(&delivery(addr)).await;
+
+        assert_eq!(Some(500), result.response_status);
+        assert!(!result.succeeded());
+    }
+
+    #[tokio::test]
+    async fn send_unreachable() {
+        // Nothing listens on port 1, so the connection is refused.
+        let result = sender()
+            .send(&delivery("127.0.0.1:1".parse().unwrap()))
+            .await;
+
+        assert_eq!(None, result.response_status);
+        assert!(result.error.is_some());
+    }
+
+    #[tokio::test]
+    async fn send_to_private_address() {
+        let (addr, received) = stand_in(StatusCode::NO_CONTENT).await;
+        let sender = WebhookSender::new(Duration::from_secs(5), false).unwrap();
+
+        let result = sender.send(&delivery(addr)).await;
+
+        assert_eq!(None, result.response_status);
+        assert!(received.lock().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn check_destination_private_host() {
+        let sender = WebhookSender::new(Duration::from_secs(5), false).unwrap();
+
+        for url in [
+            "http://localhost/hooks",
+            "http://169.254.169.254/latest/meta-data",
+            "http://[::1]:8000/hooks",
+        ] {
+            assert!(
+                matches!(
+                    sender.check_destination(url).await,
+                    Err(DestinationError::NonPublicAddress(_))
+                ),
+                "{} was allowed",
+                url
+            );
+        }
+    }
+}
@@ -7,8 +7,14 @@
 pub mod authentication;
 pub mod cli;
 mod database;
+mod health;
 mod http_err;
 pub mod ledger;
 mod models;
+mod monitoring;
+mod rate_limit;
 mod repos;
 mod server;
+mod shutdown;
+mod storage;
+mod telemetry;
@@ -0,0 +1,34 @@
+use std::convert::TryFrom;
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::authentication::tokens;
+
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct ApiToken {
+    pub id: Uuid,
+    pub user_id: String,
+    pub name: String,
+    pub scope: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+impl TryFrom<ApiToken> for tokens::ApiToken {
+    type Error = anyhow::Error;
+
+    fn try_from(model: ApiToken) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: model.id,
+            user_id: model.user_id,
+            name: model.name,
+            scope: model
+                .scope
+                .parse()
+                .map_err(|_| anyhow::anyhow!("unknown token scope {:?}", model.scope))?,
+            created_at: model.created_at,
+            last_used_at: model.last_used_at,
+        })
+    }
+}
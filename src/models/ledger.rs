@@ -9,15 +9,15 @@ use uuid::Uuid;
 
 use crate::ledger::domain;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, sqlx::FromRow)]
 pub struct Account {
     pub id: Uuid,
-    pub user_id: String,
+    pub book_id: Uuid,
     pub name: String,
     pub created_at: DateTime<Utc>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, sqlx::FromRow)]
 pub struct Currency {
     pub code: String,
     pub symbol: String,
@@ -28,15 +28,17 @@ pub struct Currency {
 #[derive(Debug, sqlx::FromRow)]
 pub struct Transaction {
     pub id: Uuid,
-    pub user_id: Option<String>,
+    pub book_id: Uuid,
     pub date: NaiveDate,
     pub payee: String,
     pub notes: String,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub deleted_at: Option<DateTime<Utc>>,
+    pub revision: i64,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, sqlx::FromRow)]
 pub struct TransactionEntry {
     pub id: Uuid,
     pub transaction_id: Uuid,
@@ -163,13 +165,15 @@ impl TryFrom<TransactionWithEntries> for domain::transactions::Transaction {
 
         Ok(Self {
             id: model.transaction.id,
-            user_id: model.transaction.user_id.unwrap(),
+            book_id: model.transaction.book_id,
             date: model.transaction.date,
             payee: model.transaction.payee,
             notes: model.transaction.notes,
             entries,
             created_at: model.transaction.created_at,
             updated_at: model.transaction.updated_at,
+            deleted_at: model.transaction.deleted_at,
+            revision: model.transaction.revision,
         })
     }
 }
@@ -181,3 +185,233 @@ pub enum TransactionCollationError {
     #[error("the transaction references the currency {0:?} which is not present")]
     UnmatchedCurrency(String),
 }
+
+/// Metadata about a file attached to a transaction.
+#[derive(Debug, sqlx::FromRow)]
+pub struct TransactionAttachment {
+    pub id: Uuid,
+    pub transaction_id: Uuid,
+    pub book_id: Uuid,
+    pub file_name: String,
+    pub content_type: String,
+    pub size: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+impl TryFrom<TransactionAttachment> for domain::attachments::Attachment {
+    type Error = anyhow::Error;
+
+    fn try_from(model: TransactionAttachment) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: model.id,
+            transaction_id: model.transaction_id,
+            book_id: model.book_id,
+            file_name: model.file_name,
+            content_type: model.content_type,
+            size: model.size.try_into()?,
+            created_at: model.created_at,
+        })
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub struct LedgerLockChange {
+    pub actor: Option<String>,
+    pub previous_lock_date: Option<NaiveDate>,
+    pub lock_date: Option<NaiveDate>,
+    pub changed_at: DateTime<Utc>,
+}
+
+impl From<LedgerLockChange> for domain::locking::LockChange {
+    fn from(model: LedgerLockChange) -> Self {
+        Self {
+            actor: model.actor,
+            previous_lock_date: model.previous_lock_date,
+            lock_date: model.lock_date,
+            changed_at: model.changed_at,
+        }
+    }
+}
+
+pub struct WebhookEndpoint {
+    pub id: Uuid,
+    pub book_id: Uuid,
+    pub url: String,
+    pub secret: String,
+    pub events: Vec<String>,
+    pub threshold_account: Option<String>,
+    pub threshold_currency: Option<String>,
+    pub threshold_amount: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl TryFrom<WebhookEndpoint> for domain::webhooks::WebhookEndpoint {
+    type Error = anyhow::Error;
+
+    fn try_from(model: WebhookEndpoint) -> Result<Self, Self::Error> {
+        let balance_threshold = match (
+            model.threshold_account,
+            model.threshold_currency,
+            model.threshold_amount,
+        ) {
+            (Some(account), Some(currency), Some(amount)) => {
+                Some(domain::webhooks::BalanceThreshold {
+                    account,
+                    currency,
+                    amount,
+                })
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            id: model.id,
+            book_id: model.book_id,
+            url: model.url,
+            secret: model.secret,
+            events: model
+                .events
+                .iter()
+                .map(|event| event.parse())
+                .collect::<anyhow::Result<_>>()?,
+            balance_threshold,
+            created_at: model.created_at,
+        })
+    }
+}
+
+/// A pending delivery joined with its endpoint and event.
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub endpoint_id: Uuid,
+    pub url: String,
+    pub secret: String,
+    pub attempts: i32,
+    pub event_id: i64,
+    pub event_type: String,
+    pub data: serde_json::Value,
+    pub event_created_at: DateTime<Utc>,
+}
+
+impl TryFrom<WebhookDelivery> for domain::webhooks::WebhookDelivery {
+    type Error = anyhow::Error;
+
+    fn try_from(model: WebhookDelivery) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: model.id,
+            endpoint_id: model.endpoint_id,
+            url: model.url,
+            secret: model.secret,
+            event: domain::webhooks::WebhookEvent {
+                id: model.event_id,
+                event_type: model.event_type.parse()?,
+                created_at: model.event_created_at,
+                data: model.data,
+            },
+            attempts: model.attempts,
+        })
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub struct WebhookDeliveryAttempt {
+    pub delivery_id: i64,
+    pub event_id: i64,
+    pub event_type: String,
+    pub response_status: Option<i16>,
+    pub error: Option<String>,
+    pub attempted_at: DateTime<Utc>,
+}
+
+impl TryFrom<WebhookDeliveryAttempt> for domain::webhooks::DeliveryAttempt {
+    type Error = anyhow::Error;
+
+    fn try_from(model: WebhookDeliveryAttempt) -> Result<Self, Self::Error> {
+        Ok(Self {
+            delivery_id: model.delivery_id,
+            event_id: model.event_id,
+            event_type: model.event_type.parse()?,
+            response_status: model.response_status.map(u16::try_from).transpose()?,
+            error: model.error,
+            attempted_at: model.attempted_at,
+        })
+    }
+}
+
+/// A book joined with the role of one of its members.
+#[derive(sqlx::FromRow)]
+pub struct BookMembership {
+    pub id: Uuid,
+    pub name: String,
+    /// Whether the book is the member's personal book.
+    pub personal: bool,
+    pub default_currency: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub role: String,
+}
+
+impl TryFrom<BookMembership> for domain::books::Membership {
+    type Error = anyhow::Error;
+
+    fn try_from(model: BookMembership) -> Result<Self, Self::Error> {
+        Ok(Self {
+            role: parse_role(&model.role)?,
+            book: domain::books::Book {
+                id: model.id,
+                name: model.name,
+                personal: model.personal,
+                default_currency: model.default_currency,
+                created_at: model.created_at,
+            },
+        })
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub struct BookMember {
+    pub user_id: String,
+    pub role: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl TryFrom<BookMember> for domain::books::Member {
+    type Error = anyhow::Error;
+
+    fn try_from(model: BookMember) -> Result<Self, Self::Error> {
+        Ok(Self {
+            user_id: model.user_id,
+            role: parse_role(&model.role)?,
+            created_at: model.created_at,
+        })
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub struct BookInvitation {
+    pub id: Uuid,
+    pub book_id: Uuid,
+    pub role: String,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl TryFrom<BookInvitation> for domain::books::Invitation {
+    type Error = anyhow::Error;
+
+    fn try_from(model: BookInvitation) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: model.id,
+            book_id: model.book_id,
+            role: parse_role(&model.role)?,
+            created_by: model.created_by,
+            created_at: model.created_at,
+            expires_at: model.expires_at,
+        })
+    }
+}
+
+fn parse_role(role: &str) -> anyhow::Result<domain::books::Role> {
+    role.parse()
+        .map_err(|_| anyhow::anyhow!("unknown book role {:?}", role))
+}
@@ -1 +1,2 @@
+pub mod authentication;
 pub mod ledger;
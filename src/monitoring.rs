@@ -0,0 +1,218 @@
+//! Prometheus metrics describing how the application is performing.
+//!
+//! Metrics are recorded through the `metrics` facade from anywhere in the
+//! application, and rendered in the Prometheus text format by the `/metrics`
+//! endpoint. The endpoint doesn't require authentication, so it can be served
+//! on a separate port that isn't exposed publicly.
+
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{MatchedPath, State},
+    http::Request,
+    middleware::Next,
+    response::Response,
+    routing::get,
+    Router,
+};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use validator::{ValidationErrors, ValidationErrorsKind};
+
+use crate::database::Database;
+
+const HTTP_REQUESTS_TOTAL: &str = "http_requests_total";
+const HTTP_REQUEST_DURATION_SECONDS: &str = "http_request_duration_seconds";
+
+const DB_POOL_CONNECTIONS: &str = "db_pool_connections";
+const DB_POOL_IDLE_CONNECTIONS: &str = "db_pool_idle_connections";
+const DB_POOL_MAX_CONNECTIONS: &str = "db_pool_max_connections";
+const DB_POOL_ACQUIRE_WAIT_SECONDS: &str = "db_pool_acquire_wait_seconds";
+
+const RATE_LIMITED_REQUESTS_TOTAL: &str = "rate_limited_requests_total";
+const TRANSACTIONS_CREATED_TOTAL: &str = "transactions_created_total";
+const VALIDATION_FAILURES_TOTAL: &str = "validation_failures_total";
+
+/// Buckets for request latencies, from 5 milliseconds to 10 seconds.
+const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// The longest a scrape waits for a database connection. A saturated pool
+/// would otherwise hold up the scrape until the pool's own acquire timeout,
+/// which Prometheus may give up on before then. A wait this long is reported
+/// as is.
+const POOL_ACQUIRE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The route label of requests that didn't match any route. Using the raw
+/// path would create a new time series for every path that is probed.
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+/// Install the global metrics recorder. This can only be done once per
+/// process.
+pub fn install_recorder() -> anyhow::Result<PrometheusHandle> {
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            Matcher::Full(HTTP_REQUEST_DURATION_SECONDS.to_owned()),
+            LATENCY_BUCKETS,
+        )?
+        .install_recorder()?;
+
+    Ok(handle)
+}
+
+/// State needed to render the metrics endpoint.
+#[derive(Clone)]
+pub struct MetricsState {
+    pub handle: PrometheusHandle,
+    pub db: Database,
+    /// The configured size limit of the pool.
+    pub pool_max_connections: u32,
+}
+
+/// The routes serving the metrics endpoint. These have their own state so
+/// they can be served on a separate port from the rest of the application.
+pub fn routes(state: MetricsState) -> Router {
+    Router::new()
+        .route("/metrics", get(get_metrics))
+        .with_state(state)
+}
+
+async fn get_metrics(State(state): State<MetricsState>) -> String {
+    record_pool_stats(&state.db, state.pool_max_connections).await;
+
+    state.handle.render()
+}
+
+/// Record the current saturation of the database pool. The acquire wait is
+/// measured by checking out a connection, so it reflects how long a request
+/// arriving at the time of the scrape would wait.
+async fn record_pool_stats(db: &Database, max_connections: u32) {
+    metrics::gauge!(DB_POOL_CONNECTIONS, f64::from(db.size()));
+    metrics::gauge!(DB_POOL_IDLE_CONNECTIONS, db.num_idle() as f64);
+    metrics::gauge!(DB_POOL_MAX_CONNECTIONS, f64::from(max_connections));
+
+    let started = Instant::now();
+    let wait = match tokio::time::timeout(POOL_ACQUIRE_TIMEOUT, db.acquire()).await {
+        Ok(_) => started.elapsed(),
+        Err(_) => POOL_ACQUIRE_TIMEOUT,
+    };
+    metrics::gauge!(DB_POOL_ACQUIRE_WAIT_SECONDS, wait.as_secs_f64());
+}
+
+/// Middleware recording the number and latency of requests to each route.
+pub async fn track_requests<B>(request: Request<B>, next: Next<B>) -> Response {
+    let started = Instant::now();
+    let method = request.method().to_string();
+    let route = matched_route(&request);
+
+    let response = next.run(request).await;
+
+    let labels = [
+        ("method", method),
+        ("route", route),
+        ("status", response.status().as_u16().to_string()),
+    ];
+
+    metrics::increment_counter!(HTTP_REQUESTS_TOTAL, &labels);
+    metrics::histogram!(
+        HTTP_REQUEST_DURATION_SECONDS,
+        started.elapsed().as_secs_f64(),
+        &labels
+    );
+
+    response
+}
+
+/// The route template a request matched, such as
+/// `/ledger/transactions/:transaction_id`, for labelling it without creating
+/// a label for every ID.
+pub fn matched_route<B>(request: &Request<B>) -> String {
+    request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(MatchedPath::as_str)
+        // Axum matches unknown paths with an internal catch-all route.
+        .filter(|path| !path.contains("__private__axum_fallback"))
+        .map(str::to_owned)
+        .unwrap_or_else(|| UNMATCHED_ROUTE.to_owned())
+}
+
+/// Record that a request was rejected for exceeding a rate limit.
+pub fn record_rate_limited(class: &'static str) {
+    metrics::increment_counter!(RATE_LIMITED_REQUESTS_TOTAL, "class" => class);
+}
+
+/// Record that transactions were added to a ledger.
+pub fn record_transactions_created(count: usize) {
+    metrics::counter!(TRANSACTIONS_CREATED_TOTAL, count as u64);
+}
+
+/// Record each problem in a request rejected for having invalid fields.
+pub fn record_validation_failures(errors: &ValidationErrors) {
+    for code in validation_failure_codes(errors) {
+        metrics::increment_counter!(VALIDATION_FAILURES_TOTAL, "code" => code);
+    }
+}
+
+/// The code of every field error, including those of nested objects and
+/// lists.
+fn validation_failure_codes(errors: &ValidationErrors) -> Vec<String> {
+    errors
+        .errors()
+        .values()
+        .flat_map(|kind| match kind {
+            ValidationErrorsKind::Field(errors) => {
+                errors.iter().map(|error| error.code.to_string()).collect()
+            }
+            ValidationErrorsKind::Struct(errors) => validation_failure_codes(errors),
+            ValidationErrorsKind::List(errors) => errors
+                .values()
+                .flat_map(|errors| validation_failure_codes(errors))
+                .collect(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use validator::ValidationError;
+
+    use super::*;
+
+    #[test]
+    fn validation_failure_codes_include_nested_errors() {
+        let mut entry_errors = ValidationErrors::new();
+        entry_errors.add("account", ValidationError::new("length"));
+
+        let mut errors = ValidationErrors::new();
+        errors.add("payee", ValidationError::new("length"));
+        errors.add("entries", ValidationError::new("unbalanced"));
+        errors.errors_mut().insert(
+            "operations",
+            ValidationErrorsKind::List(BTreeMap::from([(2, Box::new(entry_errors))])),
+        );
+
+        let mut codes = validation_failure_codes(&errors);
+        codes.sort();
+
+        assert_eq!(vec!["length", "length", "unbalanced"], codes);
+    }
+
+    #[tokio::test]
+    async fn record_pool_stats_saturated_pool() {
+        let db = Database::connect("sqlite::memory:", 1, Duration::from_secs(30))
+            .await
+            .unwrap();
+        let Database::Sqlite(pool) = &db else {
+            unreachable!("the URL is a SQLite URL");
+        };
+        let _held = pool.acquire().await.unwrap();
+
+        let started = Instant::now();
+        record_pool_stats(&db, 1).await;
+
+        assert!(started.elapsed() < POOL_ACQUIRE_TIMEOUT * 2);
+    }
+}
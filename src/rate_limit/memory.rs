@@ -0,0 +1,81 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use async_trait::async_trait;
+use chrono::Utc;
+
+use super::{Decision, Limit, RateLimitStore, RequestClass, TokenBucket};
+
+/// Keeps buckets in the memory of a single server instance. When several
+/// instances serve requests, each one enforces the limits separately.
+///
+/// There is a bucket for each user and class of request that has been seen
+/// since the server started.
+#[derive(Default)]
+pub struct MemoryRateLimitStore {
+    buckets: Mutex<HashMap<(String, RequestClass), TokenBucket>>,
+}
+
+impl MemoryRateLimitStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for MemoryRateLimitStore {
+    async fn take(
+        &self,
+        user_id: &str,
+        class: RequestClass,
+        limit: &Limit,
+    ) -> anyhow::Result<Decision> {
+        let now = Utc::now();
+        let mut buckets = self
+            .buckets
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Rate limit buckets were poisoned."))?;
+
+        let bucket = buckets
+            .entry((user_id.to_owned(), class))
+            .or_insert_with(|| TokenBucket::full(limit, now));
+
+        Ok(bucket.take(limit, now))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn users_and_classes_have_separate_buckets() {
+        let store = MemoryRateLimitStore::new();
+        let limit = Limit { per_minute: 1 };
+
+        assert_eq!(
+            Decision::Allowed,
+            store
+                .take("alice", RequestClass::Read, &limit)
+                .await
+                .unwrap()
+        );
+        assert!(matches!(
+            store
+                .take("alice", RequestClass::Read, &limit)
+                .await
+                .unwrap(),
+            Decision::Limited { .. }
+        ));
+        assert_eq!(
+            Decision::Allowed,
+            store
+                .take("alice", RequestClass::Write, &limit)
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            Decision::Allowed,
+            store.take("bob", RequestClass::Read, &limit).await.unwrap()
+        );
+    }
+}
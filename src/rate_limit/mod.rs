@@ -0,0 +1,357 @@
+//! Per-user rate limiting.
+//!
+//! Each user has a token bucket for each class of request. Reads, writes, and
+//! expensive reports have separate budgets so that a script generating reports
+//! doesn't stop the same user from recording transactions. Buckets hold a
+//! minute's worth of requests and refill continuously.
+
+pub mod memory;
+pub mod postgres;
+pub mod sqlite;
+
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRef, FromRequestParts, State},
+    http::{Method, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use tracing::error;
+
+use crate::{
+    authentication::{keys::KeyStore, AuthenticatedUser},
+    http_err::ApiError,
+    ledger::services::ApiTokenService,
+    monitoring,
+};
+
+pub use memory::MemoryRateLimitStore;
+
+/// Routes whose requests are expensive enough to have their own budget.
+const REPORT_ROUTES: &[&str] = &[
+    "/graphql",
+    "/ledger/accounts/:account/balance/monthly",
+    "/ledger/accounts/:account/balance/periodic",
+    "/books/:book_id/graphql",
+    "/books/:book_id/ledger/accounts/:account/balance/monthly",
+    "/books/:book_id/ledger/accounts/:account/balance/periodic",
+];
+
+/// The classes of requests that are limited separately.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum RequestClass {
+    Read,
+    Write,
+    Report,
+}
+
+impl RequestClass {
+    /// Classify a request by its method and the route it matched.
+    pub fn of(method: &Method, route: &str) -> Self {
+        if REPORT_ROUTES.contains(&route) {
+            Self::Report
+        } else if method == Method::GET || method == Method::HEAD || method == Method::OPTIONS {
+            Self::Read
+        } else {
+            Self::Write
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Read => "read",
+            Self::Write => "write",
+            Self::Report => "report",
+        }
+    }
+}
+
+/// The number of requests of a class that a user may make each minute.
+#[derive(Clone, Copy, Debug)]
+pub struct Limit {
+    pub per_minute: u32,
+}
+
+impl Limit {
+    /// The most tokens a bucket can hold.
+    pub fn capacity(&self) -> f64 {
+        f64::from(self.per_minute)
+    }
+
+    /// The number of tokens added to a bucket each second.
+    pub fn refill_rate(&self) -> f64 {
+        f64::from(self.per_minute) / 60.0
+    }
+
+    /// How long until a bucket with the given number of tokens has a whole
+    /// token to spend.
+    pub fn wait_for_token(&self, tokens: f64) -> Duration {
+        if tokens >= 1.0 {
+            Duration::ZERO
+        } else if self.per_minute == 0 {
+            Duration::from_secs(60)
+        } else {
+            Duration::from_secs_f64((1.0 - tokens) / self.refill_rate())
+        }
+    }
+}
+
+/// The limits for each class of request.
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    pub read: Limit,
+    pub write: Limit,
+    pub report: Limit,
+}
+
+impl Limits {
+    pub fn for_class(&self, class: RequestClass) -> &Limit {
+        match class {
+            RequestClass::Read => &self.read,
+            RequestClass::Write => &self.write,
+            RequestClass::Report => &self.report,
+        }
+    }
+}
+
+/// Whether a request may proceed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Decision {
+    Allowed,
+    Limited { retry_after: Duration },
+}
+
+/// A bucket of tokens that each request spends one of.
+#[derive(Clone, Debug)]
+pub struct TokenBucket {
+    pub tokens: f64,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl TokenBucket {
+    /// Create a bucket that hasn't been used yet.
+    pub fn full(limit: &Limit, now: DateTime<Utc>) -> Self {
+        Self {
+            tokens: limit.capacity(),
+            updated_at: now,
+        }
+    }
+
+    /// Add the tokens accumulated since the bucket was last updated, then try
+    /// to spend one.
+    pub fn take(&mut self, limit: &Limit, now: DateTime<Utc>) -> Decision {
+        let elapsed = (now - self.updated_at)
+            .to_std()
+            .unwrap_or_default()
+            .as_secs_f64();
+
+        self.tokens = (self.tokens + elapsed * limit.refill_rate()).min(limit.capacity());
+        self.updated_at = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+
+            Decision::Allowed
+        } else {
+            Decision::Limited {
+                retry_after: limit.wait_for_token(self.tokens),
+            }
+        }
+    }
+}
+
+pub type DynRateLimitStore = Arc<dyn RateLimitStore + Send + Sync>;
+
+/// Keeps track of the tokens in users' buckets.
+#[async_trait]
+pub trait RateLimitStore {
+    /// Try to spend a token from a user's bucket.
+    ///
+    /// # Arguments
+    /// * `user_id` - The ID of the user making the request.
+    /// * `class` - The class of the request.
+    /// * `limit` - The limit for that class of request.
+    async fn take(
+        &self,
+        user_id: &str,
+        class: RequestClass,
+        limit: &Limit,
+    ) -> anyhow::Result<Decision>;
+}
+
+/// Where rate limiting state is kept.
+#[derive(Clone, Copy, Debug)]
+pub enum RateLimitBackend {
+    /// Each server instance limits requests independently.
+    Memory,
+    /// Server instances share limits through the application database,
+    /// whether that is Postgres or SQLite.
+    Database,
+}
+
+#[derive(Clone)]
+pub struct RateLimiter {
+    pub limits: Limits,
+    pub store: DynRateLimitStore,
+}
+
+impl RateLimiter {
+    /// Decide whether a user may make a request.
+    ///
+    /// If the store can't be reached, requests are allowed rather than
+    /// making every request fail.
+    pub async fn check(&self, user_id: &str, class: RequestClass) -> Decision {
+        match self
+            .store
+            .take(user_id, class, self.limits.for_class(class))
+            .await
+        {
+            Ok(decision) => decision,
+            Err(error) => {
+                error!(?error, "Failed to check rate limit.");
+
+                Decision::Allowed
+            }
+        }
+    }
+}
+
+/// State for the rate limiting middleware.
+#[derive(Clone)]
+pub struct RateLimitState {
+    pub api_token_service: ApiTokenService,
+    pub key_store: KeyStore,
+    pub limiter: RateLimiter,
+}
+
+impl FromRef<RateLimitState> for ApiTokenService {
+    fn from_ref(state: &RateLimitState) -> Self {
+        state.api_token_service.clone()
+    }
+}
+
+impl FromRef<RateLimitState> for KeyStore {
+    fn from_ref(state: &RateLimitState) -> Self {
+        state.key_store.clone()
+    }
+}
+
+/// Middleware rejecting requests from users who have exceeded their limit.
+///
+/// Requests without a valid token are passed through, and are rejected by the
+/// handlers that require authentication. Requests made with a personal access
+/// token count against the budget of the user who owns it.
+pub async fn limit_requests<B>(
+    State(state): State<RateLimitState>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let class = RequestClass::of(request.method(), &monitoring::matched_route(&request));
+
+    let (mut parts, body) = request.into_parts();
+    let user = AuthenticatedUser::from_request_parts(&mut parts, &state).await;
+    let request = Request::from_parts(parts, body);
+
+    if let Ok(user) = user {
+        if let Decision::Limited { retry_after } = state.limiter.check(&user.user_id, class).await {
+            monitoring::record_rate_limited(class.as_str());
+
+            return ApiError::TooManyRequests { retry_after }.into_response();
+        }
+    }
+
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn at(seconds: i64) -> DateTime<Utc> {
+        DateTime::from_utc(
+            chrono::NaiveDateTime::from_timestamp_opt(1_700_000_000 + seconds, 0).unwrap(),
+            Utc,
+        )
+    }
+
+    #[test]
+    fn bucket_allows_a_minute_of_requests_at_once() {
+        let limit = Limit { per_minute: 3 };
+        let mut bucket = TokenBucket::full(&limit, at(0));
+
+        for _ in 0..3 {
+            assert_eq!(Decision::Allowed, bucket.take(&limit, at(0)));
+        }
+
+        assert_eq!(
+            Decision::Limited {
+                retry_after: Duration::from_secs(20)
+            },
+            bucket.take(&limit, at(0))
+        );
+    }
+
+    #[test]
+    fn bucket_refills_over_time() {
+        let limit = Limit { per_minute: 6 };
+        let mut bucket = TokenBucket {
+            tokens: 0.0,
+            updated_at: at(0),
+        };
+
+        assert_eq!(
+            Decision::Limited {
+                retry_after: Duration::from_secs(6)
+            },
+            bucket.take(&limit, at(4))
+        );
+        assert_eq!(Decision::Allowed, bucket.take(&limit, at(10)));
+        assert!(bucket.tokens.abs() < 1e-9);
+    }
+
+    #[test]
+    fn bucket_does_not_refill_past_capacity() {
+        let limit = Limit { per_minute: 2 };
+        let mut bucket = TokenBucket {
+            tokens: 0.0,
+            updated_at: at(0),
+        };
+
+        assert_eq!(Decision::Allowed, bucket.take(&limit, at(3600)));
+        assert!((bucket.tokens - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn classify_requests() {
+        assert_eq!(
+            RequestClass::Read,
+            RequestClass::of(&Method::GET, "/ledger/transactions")
+        );
+        assert_eq!(
+            RequestClass::Write,
+            RequestClass::of(&Method::POST, "/ledger/transactions")
+        );
+        assert_eq!(
+            RequestClass::Write,
+            RequestClass::of(&Method::DELETE, "/ledger/transactions/:transaction_id")
+        );
+        assert_eq!(
+            RequestClass::Report,
+            RequestClass::of(&Method::GET, "/ledger/accounts/:account/balance/periodic")
+        );
+        assert_eq!(
+            RequestClass::Report,
+            RequestClass::of(&Method::POST, "/graphql")
+        );
+        assert_eq!(
+            RequestClass::Report,
+            RequestClass::of(
+                &Method::GET,
+                "/books/:book_id/ledger/accounts/:account/balance/monthly"
+            )
+        );
+    }
+}
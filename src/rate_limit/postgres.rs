@@ -0,0 +1,73 @@
+use async_trait::async_trait;
+
+use crate::database::PostgresConnection;
+
+use super::{Decision, Limit, RateLimitStore, RequestClass};
+
+/// Keeps buckets in the database so that every server instance enforces the
+/// same limits.
+///
+/// Buckets are refilled using the database's clock, so instances with
+/// skewed clocks still agree on how many tokens a bucket holds.
+#[async_trait]
+impl RateLimitStore for PostgresConnection {
+    async fn take(
+        &self,
+        user_id: &str,
+        class: RequestClass,
+        limit: &Limit,
+    ) -> anyhow::Result<Decision> {
+        // Refilling and spending a token happen in a single statement so that
+        // concurrent requests can't spend the same token.
+        let spent = sqlx::query_scalar!(
+            r#"
+            INSERT INTO rate_limit_bucket AS bucket (user_id, request_class, tokens)
+            VALUES ($1, $2, $3::float8 - 1)
+            ON CONFLICT (user_id, request_class) DO UPDATE
+            SET
+                tokens = LEAST(
+                    $3::float8,
+                    bucket.tokens
+                        + EXTRACT(EPOCH FROM now() - bucket.updated_at)::float8 * $4::float8
+                ) - 1,
+                updated_at = now()
+            WHERE LEAST(
+                $3::float8,
+                bucket.tokens + EXTRACT(EPOCH FROM now() - bucket.updated_at)::float8 * $4::float8
+            ) >= 1
+            RETURNING tokens
+            "#,
+            user_id,
+            class.as_str(),
+            limit.capacity(),
+            limit.refill_rate(),
+        )
+        .fetch_optional(&**self)
+        .await?;
+
+        if spent.is_some() {
+            return Ok(Decision::Allowed);
+        }
+
+        let tokens = sqlx::query_scalar!(
+            r#"
+            SELECT LEAST(
+                $3::float8,
+                tokens + EXTRACT(EPOCH FROM now() - updated_at)::float8 * $4::float8
+            ) AS "tokens!"
+            FROM rate_limit_bucket
+            WHERE user_id = $1 AND request_class = $2
+            "#,
+            user_id,
+            class.as_str(),
+            limit.capacity(),
+            limit.refill_rate(),
+        )
+        .fetch_one(&**self)
+        .await?;
+
+        Ok(Decision::Limited {
+            retry_after: limit.wait_for_token(tokens),
+        })
+    }
+}
@@ -0,0 +1,60 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::database::{sqlite_now, SqliteConnection};
+
+use super::{Decision, Limit, RateLimitStore, RequestClass, TokenBucket};
+
+/// Keeps buckets in a SQLite database so that limits survive restarts.
+///
+/// Only one connection can write to the database at a time, so a bucket is
+/// read, refilled, and written back within a single database transaction.
+#[async_trait]
+impl RateLimitStore for SqliteConnection {
+    async fn take(
+        &self,
+        user_id: &str,
+        class: RequestClass,
+        limit: &Limit,
+    ) -> anyhow::Result<Decision> {
+        let now = sqlite_now();
+        let mut tx = self.begin().await?;
+
+        let stored: Option<(f64, DateTime<Utc>)> = sqlx::query_as(
+            r#"
+            SELECT tokens, updated_at
+            FROM rate_limit_bucket
+            WHERE user_id = $1 AND request_class = $2
+            "#,
+        )
+        .bind(user_id)
+        .bind(class.as_str())
+        .fetch_optional(&mut tx)
+        .await?;
+
+        let mut bucket = match stored {
+            Some((tokens, updated_at)) => TokenBucket { tokens, updated_at },
+            None => TokenBucket::full(limit, now),
+        };
+        let decision = bucket.take(limit, now);
+
+        sqlx::query(
+            r#"
+            INSERT INTO rate_limit_bucket (user_id, request_class, tokens, updated_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (user_id, request_class) DO UPDATE
+            SET tokens = excluded.tokens, updated_at = excluded.updated_at
+            "#,
+        )
+        .bind(user_id)
+        .bind(class.as_str())
+        .bind(bucket.tokens)
+        .bind(bucket.updated_at)
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(decision)
+    }
+}
@@ -0,0 +1,114 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+    authentication::tokens::{ApiToken, NewApiToken},
+    database::PostgresConnection,
+    models,
+};
+
+pub type DynApiTokenRepo = Arc<dyn ApiTokenRepo + Send + Sync>;
+
+#[async_trait]
+pub trait ApiTokenRepo {
+    async fn create_token(&self, token: &NewApiToken) -> anyhow::Result<ApiToken>;
+
+    /// List a user's personal access tokens, oldest first.
+    async fn list_tokens(&self, user_id: &str) -> anyhow::Result<Vec<ApiToken>>;
+
+    /// Revoke one of a user's personal access tokens.
+    ///
+    /// # Returns
+    ///
+    /// A boolean indicating if a matching token was found.
+    async fn revoke_token(&self, user_id: &str, token_id: Uuid) -> anyhow::Result<bool>;
+
+    /// Find the token with a hash and record that it was used.
+    ///
+    /// # Returns
+    ///
+    /// The token, or `None` if there is no token with the hash. Revoked
+    /// tokens are deleted, so they are never found.
+    async fn use_token(&self, token_hash: &str) -> anyhow::Result<Option<ApiToken>>;
+}
+
+#[async_trait]
+impl ApiTokenRepo for PostgresConnection {
+    async fn create_token(&self, token: &NewApiToken) -> anyhow::Result<ApiToken> {
+        let token = sqlx::query_as!(
+            models::authentication::ApiToken,
+            r#"
+            INSERT INTO api_token (user_id, name, scope, token_hash)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id, user_id, name, scope, created_at, last_used_at
+            "#,
+            token.user_id(),
+            token.name(),
+            token.scope().as_str(),
+            token.token_hash(),
+        )
+        .fetch_one(&**self)
+        .await?;
+
+        info!(token_id = %token.id, user_id = %token.user_id, "Created personal access token.");
+
+        token.try_into()
+    }
+
+    async fn list_tokens(&self, user_id: &str) -> anyhow::Result<Vec<ApiToken>> {
+        sqlx::query_as!(
+            models::authentication::ApiToken,
+            r#"
+            SELECT id, user_id, name, scope, created_at, last_used_at
+            FROM api_token
+            WHERE user_id = $1
+            ORDER BY created_at, id
+            "#,
+            user_id,
+        )
+        .fetch_all(&**self)
+        .await?
+        .drain(..)
+        .map(TryInto::try_into)
+        .collect()
+    }
+
+    async fn revoke_token(&self, user_id: &str, token_id: Uuid) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM api_token
+            WHERE user_id = $1 AND id = $2
+            "#,
+            user_id,
+            token_id,
+        )
+        .execute(&**self)
+        .await?;
+
+        let found = result.rows_affected() > 0;
+
+        info!(%token_id, user_id, found, "Revoked personal access token.");
+
+        Ok(found)
+    }
+
+    async fn use_token(&self, token_hash: &str) -> anyhow::Result<Option<ApiToken>> {
+        sqlx::query_as!(
+            models::authentication::ApiToken,
+            r#"
+            UPDATE api_token
+            SET last_used_at = now()
+            WHERE token_hash = $1
+            RETURNING id, user_id, name, scope, created_at, last_used_at
+            "#,
+            token_hash,
+        )
+        .fetch_optional(&**self)
+        .await?
+        .map(TryInto::try_into)
+        .transpose()
+    }
+}
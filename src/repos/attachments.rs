@@ -0,0 +1,261 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tracing::{debug, info};
+use uuid::Uuid;
+
+use crate::{
+    database::PostgresConnection,
+    ledger::domain::attachments::{Attachment, NewAttachment},
+    models,
+};
+
+pub type DynAttachmentRepo = Arc<dyn AttachmentRepo + Send + Sync>;
+
+/// The outcome of persisting an attachment's metadata.
+pub enum PersistedAttachment {
+    Persisted(Attachment),
+    /// The book does not contain an active transaction with the attachment's
+    /// transaction ID.
+    TransactionNotFound,
+    /// Storing the attachment would exceed the book's quota. The value is the
+    /// number of bytes already used by the book's attachments.
+    QuotaExceeded {
+        used: u64,
+    },
+}
+
+#[async_trait]
+pub trait AttachmentRepo {
+    /// Delete an attachment's metadata.
+    ///
+    /// # Arguments
+    ///
+    /// * `book_id` - The ID of the book containing the attachment.
+    /// * `transaction_id` - The ID of the transaction the file is attached to.
+    /// * `attachment_id` - The ID of the attachment to delete.
+    ///
+    /// # Returns
+    ///
+    /// The deleted attachment, or `None` if there was no matching attachment.
+    async fn delete_attachment(
+        &self,
+        book_id: Uuid,
+        transaction_id: Uuid,
+        attachment_id: Uuid,
+    ) -> anyhow::Result<Option<Attachment>>;
+
+    /// Get a single attachment.
+    ///
+    /// # Arguments
+    ///
+    /// * `book_id` - The ID of the book containing the attachment.
+    /// * `transaction_id` - The ID of the transaction the file is attached to.
+    /// * `attachment_id` - The ID of the attachment.
+    async fn get_attachment(
+        &self,
+        book_id: Uuid,
+        transaction_id: Uuid,
+        attachment_id: Uuid,
+    ) -> anyhow::Result<Option<Attachment>>;
+
+    /// List the attachments of a transaction ordered by creation time.
+    ///
+    /// # Arguments
+    ///
+    /// * `book_id` - The ID of the book containing the transaction.
+    /// * `transaction_id` - The ID of the transaction.
+    async fn list_attachments(
+        &self,
+        book_id: Uuid,
+        transaction_id: Uuid,
+    ) -> anyhow::Result<Vec<Attachment>>;
+
+    /// Persist a new attachment's metadata.
+    ///
+    /// The book's usage is checked against the quota in the same database
+    /// transaction as the insert, so concurrent uploads can't exceed it
+    /// together.
+    ///
+    /// # Arguments
+    ///
+    /// * `attachment` - The attachment to persist.
+    /// * `quota` - The most bytes the book's attachments may use in total.
+    async fn persist_attachment(
+        &self,
+        attachment: &NewAttachment,
+        quota: u64,
+    ) -> anyhow::Result<PersistedAttachment>;
+
+    /// Get the combined size in bytes of all attachments in a book.
+    ///
+    /// # Arguments
+    ///
+    /// * `book_id` - The ID of the book.
+    async fn total_attachment_size(&self, book_id: Uuid) -> anyhow::Result<u64>;
+}
+
+#[async_trait]
+impl AttachmentRepo for PostgresConnection {
+    async fn delete_attachment(
+        &self,
+        book_id: Uuid,
+        transaction_id: Uuid,
+        attachment_id: Uuid,
+    ) -> anyhow::Result<Option<Attachment>> {
+        let deleted = sqlx::query_as!(
+            models::ledger::TransactionAttachment,
+            r#"
+            DELETE FROM transaction_attachment
+            WHERE book_id = $1 AND transaction_id = $2 AND id = $3
+            RETURNING id, transaction_id, book_id, file_name, content_type, size, created_at
+            "#,
+            book_id,
+            transaction_id,
+            attachment_id,
+        )
+        .fetch_optional(&**self)
+        .await?;
+
+        info!(%book_id, %transaction_id, %attachment_id, found = deleted.is_some(), "Deleted attachment.");
+
+        deleted.map(Attachment::try_from).transpose()
+    }
+
+    async fn get_attachment(
+        &self,
+        book_id: Uuid,
+        transaction_id: Uuid,
+        attachment_id: Uuid,
+    ) -> anyhow::Result<Option<Attachment>> {
+        sqlx::query_as!(
+            models::ledger::TransactionAttachment,
+            r#"
+            SELECT id, transaction_id, book_id, file_name, content_type, size, created_at
+            FROM transaction_attachment
+            WHERE book_id = $1 AND transaction_id = $2 AND id = $3
+            "#,
+            book_id,
+            transaction_id,
+            attachment_id,
+        )
+        .fetch_optional(&**self)
+        .await?
+        .map(Attachment::try_from)
+        .transpose()
+    }
+
+    async fn list_attachments(
+        &self,
+        book_id: Uuid,
+        transaction_id: Uuid,
+    ) -> anyhow::Result<Vec<Attachment>> {
+        sqlx::query_as!(
+            models::ledger::TransactionAttachment,
+            r#"
+            SELECT id, transaction_id, book_id, file_name, content_type, size, created_at
+            FROM transaction_attachment
+            WHERE book_id = $1 AND transaction_id = $2
+            ORDER BY created_at
+            "#,
+            book_id,
+            transaction_id,
+        )
+        .fetch_all(&**self)
+        .await?
+        .drain(..)
+        .map(Attachment::try_from)
+        .collect()
+    }
+
+    async fn persist_attachment(
+        &self,
+        attachment: &NewAttachment,
+        quota: u64,
+    ) -> anyhow::Result<PersistedAttachment> {
+        let mut tx = self.begin().await?;
+
+        // Locking the book makes concurrent uploads to it wait for each other,
+        // so each one sees the usage including the others.
+        sqlx::query!(
+            r#"
+            SELECT id
+            FROM book
+            WHERE id = $1
+            FOR UPDATE
+            "#,
+            attachment.book_id(),
+        )
+        .fetch_optional(&mut tx)
+        .await?;
+
+        let used: u64 = sqlx::query_scalar!(
+            r#"
+            SELECT COALESCE(SUM(size), 0)::BIGINT AS "total!"
+            FROM transaction_attachment
+            WHERE book_id = $1
+            "#,
+            attachment.book_id(),
+        )
+        .fetch_one(&mut tx)
+        .await?
+        .try_into()?;
+
+        if used.saturating_add(attachment.size()) > quota {
+            debug!(book_id = %attachment.book_id(), used, quota, "Attachment would exceed the book's quota.");
+
+            return Ok(PersistedAttachment::QuotaExceeded { used });
+        }
+
+        // Only insert the attachment if the transaction it is attached to is in
+        // the attachment's book, and the transaction is not in the trash.
+        let persisted = sqlx::query_as!(
+            models::ledger::TransactionAttachment,
+            r#"
+            INSERT INTO transaction_attachment (id, transaction_id, book_id, file_name, content_type, size)
+            SELECT $1, t.id, t.book_id, $4, $5, $6
+            FROM transaction t
+            WHERE t.id = $2 AND t.book_id = $3 AND t.deleted_at IS NULL
+            RETURNING id, transaction_id, book_id, file_name, content_type, size, created_at
+            "#,
+            attachment.id(),
+            attachment.transaction_id(),
+            attachment.book_id(),
+            attachment.file_name(),
+            attachment.content_type(),
+            i64::try_from(attachment.size())?,
+        )
+        .fetch_optional(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        match persisted {
+            Some(model) => {
+                info!(id = %model.id, transaction_id = %model.transaction_id, "Persisted new attachment.");
+
+                Ok(PersistedAttachment::Persisted(model.try_into()?))
+            }
+            None => {
+                debug!(transaction_id = %attachment.transaction_id(), "Attachment references an unknown transaction.");
+
+                Ok(PersistedAttachment::TransactionNotFound)
+            }
+        }
+    }
+
+    async fn total_attachment_size(&self, book_id: Uuid) -> anyhow::Result<u64> {
+        let total = sqlx::query_scalar!(
+            r#"
+            SELECT COALESCE(SUM(size), 0)::BIGINT AS "total!"
+            FROM transaction_attachment
+            WHERE book_id = $1
+            "#,
+            book_id,
+        )
+        .fetch_one(&**self)
+        .await?;
+
+        Ok(total.try_into()?)
+    }
+}
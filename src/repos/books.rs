@@ -0,0 +1,679 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use sqlx::PgConnection;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+    database::PostgresConnection,
+    ledger::domain::{
+        attachments::Attachment,
+        books::{
+            AcceptInvitationError, BookData, DeleteBookError, Invitation, Member,
+            MemberChangeError, Membership, NewInvitation, Role, SaveBookError,
+        },
+    },
+    models,
+};
+
+/// The name given to the personal book created for each user.
+pub(super) const PERSONAL_BOOK_NAME: &str = "Personal";
+
+pub type DynBookRepo = Arc<dyn BookRepo + Send + Sync>;
+
+#[async_trait]
+pub trait BookRepo {
+    /// Add a user to a book using an invitation. Each invitation can only be
+    /// accepted once.
+    ///
+    /// # Arguments
+    ///
+    /// * `token_hash` - The hash of the invitation's token.
+    /// * `user_id` - The ID of the user accepting the invitation.
+    async fn accept_invitation(
+        &self,
+        token_hash: &str,
+        user_id: &str,
+    ) -> Result<Membership, AcceptInvitationError>;
+
+    /// Create a book owned by a user.
+    async fn create_book(
+        &self,
+        user_id: &str,
+        data: &BookData,
+    ) -> Result<Membership, SaveBookError>;
+
+    async fn create_invitation(&self, invitation: &NewInvitation) -> anyhow::Result<Invitation>;
+
+    /// Delete a book along with everything in it.
+    ///
+    /// # Returns
+    ///
+    /// The attachments that were removed from the book, so their content can
+    /// be cleaned up.
+    async fn delete_book(&self, book_id: Uuid) -> Result<Vec<Attachment>, DeleteBookError>;
+
+    /// Get a user's membership in a book.
+    ///
+    /// # Returns
+    ///
+    /// The membership, or `None` if the user is not a member of the book.
+    async fn get_membership(
+        &self,
+        book_id: Uuid,
+        user_id: &str,
+    ) -> anyhow::Result<Option<Membership>>;
+
+    /// Get a user's personal book, creating it if the user doesn't have one
+    /// yet.
+    async fn get_or_create_personal_book(&self, user_id: &str) -> anyhow::Result<Membership>;
+
+    /// List every book a user is a member of, ordered by name.
+    async fn list_books(&self, user_id: &str) -> anyhow::Result<Vec<Membership>>;
+
+    /// List the invitations to a book that can still be accepted, oldest
+    /// first.
+    async fn list_invitations(&self, book_id: Uuid) -> anyhow::Result<Vec<Invitation>>;
+
+    /// List the members of a book in the order they joined.
+    async fn list_members(&self, book_id: Uuid) -> anyhow::Result<Vec<Member>>;
+
+    /// Remove a user from a book.
+    ///
+    /// A book's last owner, and the owner of a personal book, can't be
+    /// removed.
+    async fn remove_member(&self, book_id: Uuid, user_id: &str) -> Result<(), MemberChangeError>;
+
+    /// Revoke an invitation that has not been accepted yet.
+    ///
+    /// # Returns
+    ///
+    /// A boolean indicating if a matching invitation was found.
+    async fn revoke_invitation(&self, book_id: Uuid, invitation_id: Uuid) -> anyhow::Result<bool>;
+
+    /// Change the role of a book's member.
+    ///
+    /// A book's last owner, and the owner of a personal book, can't be
+    /// demoted.
+    async fn set_member_role(
+        &self,
+        book_id: Uuid,
+        user_id: &str,
+        role: Role,
+    ) -> Result<Member, MemberChangeError>;
+
+    /// Change the name and settings of a book.
+    async fn update_book(&self, book_id: Uuid, data: &BookData) -> Result<(), SaveBookError>;
+}
+
+#[async_trait]
+impl BookRepo for PostgresConnection {
+    async fn accept_invitation(
+        &self,
+        token_hash: &str,
+        user_id: &str,
+    ) -> Result<Membership, AcceptInvitationError> {
+        let mut tx = self.begin().await?;
+
+        let invitation = sqlx::query!(
+            r#"
+            UPDATE book_invitation
+            SET accepted_by = $2, accepted_at = now()
+            WHERE token_hash = $1 AND accepted_at IS NULL AND expires_at > now()
+            RETURNING id, book_id, role
+            "#,
+            token_hash,
+            user_id,
+        )
+        .fetch_optional(&mut tx)
+        .await?
+        .ok_or(AcceptInvitationError::InvitationNotFound)?;
+
+        // Returning early rolls back the database transaction, so the
+        // invitation can still be used by someone else.
+        let added = sqlx::query!(
+            r#"
+            INSERT INTO book_member (book_id, user_id, role)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (book_id, user_id) DO NOTHING
+            "#,
+            invitation.book_id,
+            user_id,
+            invitation.role,
+        )
+        .execute(&mut tx)
+        .await?;
+        if added.rows_affected() == 0 {
+            return Err(AcceptInvitationError::AlreadyMember);
+        }
+
+        let membership = fetch_membership(&mut tx, invitation.book_id, user_id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("membership missing after accepting invitation"))?;
+
+        tx.commit().await?;
+
+        info!(invitation_id = %invitation.id, book_id = %invitation.book_id, %user_id, "Accepted book invitation.");
+
+        Ok(membership)
+    }
+
+    async fn create_book(
+        &self,
+        user_id: &str,
+        data: &BookData,
+    ) -> Result<Membership, SaveBookError> {
+        let mut tx = self.begin().await?;
+
+        ensure_currency_exists(&mut tx, data.default_currency.as_deref()).await?;
+
+        let book_id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO book (name, default_currency)
+            VALUES ($1, $2)
+            RETURNING id
+            "#,
+            data.name,
+            data.default_currency,
+        )
+        .fetch_one(&mut tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO book_member (book_id, user_id, role)
+            VALUES ($1, $2, $3)
+            "#,
+            book_id,
+            user_id,
+            Role::Owner.as_str(),
+        )
+        .execute(&mut tx)
+        .await?;
+
+        let membership = fetch_membership(&mut tx, book_id, user_id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("membership missing after creating book"))?;
+
+        tx.commit().await?;
+
+        info!(%book_id, %user_id, "Created book.");
+
+        Ok(membership)
+    }
+
+    async fn create_invitation(&self, invitation: &NewInvitation) -> anyhow::Result<Invitation> {
+        let created = sqlx::query_as!(
+            models::ledger::BookInvitation,
+            r#"
+            INSERT INTO book_invitation (book_id, role, token_hash, created_by, expires_at)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id, book_id, role, created_by, created_at, expires_at
+            "#,
+            invitation.book_id(),
+            invitation.role().as_str(),
+            invitation.token_hash(),
+            invitation.created_by(),
+            invitation.expires_at(),
+        )
+        .fetch_one(&**self)
+        .await?;
+
+        info!(invitation_id = %created.id, book_id = %created.book_id, role = %created.role, "Created book invitation.");
+
+        created.try_into()
+    }
+
+    async fn delete_book(&self, book_id: Uuid) -> Result<Vec<Attachment>, DeleteBookError> {
+        let mut tx = self.begin().await?;
+
+        let personal_user_id = sqlx::query_scalar!(
+            r#"
+            SELECT personal_user_id
+            FROM book
+            WHERE id = $1
+            FOR UPDATE
+            "#,
+            book_id,
+        )
+        .fetch_optional(&mut tx)
+        .await?;
+        match personal_user_id {
+            // The book was already deleted by another request.
+            None => return Ok(Vec::new()),
+            Some(Some(_)) => return Err(DeleteBookError::PersonalBook),
+            Some(None) => (),
+        }
+
+        let attachments = sqlx::query_as!(
+            models::ledger::TransactionAttachment,
+            r#"
+            DELETE FROM transaction_attachment
+            WHERE book_id = $1
+            RETURNING id, transaction_id, book_id, file_name, content_type, size, created_at
+            "#,
+            book_id,
+        )
+        .fetch_all(&mut tx)
+        .await?
+        .drain(..)
+        .map(Attachment::try_from)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+        // Entries can't outlive their accounts, so the transactions have to
+        // be removed before the rest of the book.
+        sqlx::query!(
+            r#"
+            DELETE FROM transaction
+            WHERE book_id = $1
+            "#,
+            book_id,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM book
+            WHERE id = $1
+            "#,
+            book_id,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        info!(%book_id, attachments = attachments.len(), "Deleted book.");
+
+        Ok(attachments)
+    }
+
+    async fn get_membership(
+        &self,
+        book_id: Uuid,
+        user_id: &str,
+    ) -> anyhow::Result<Option<Membership>> {
+        let mut conn = self.acquire().await?;
+
+        Ok(fetch_membership(&mut conn, book_id, user_id).await?)
+    }
+
+    async fn get_or_create_personal_book(&self, user_id: &str) -> anyhow::Result<Membership> {
+        if let Some(membership) = fetch_personal_membership(self, user_id).await? {
+            return Ok(membership);
+        }
+
+        let mut tx = self.begin().await?;
+
+        // If another request creates the book first, the insert waits for it
+        // to commit and then does nothing, so the book is only created once.
+        let created = sqlx::query_scalar!(
+            r#"
+            INSERT INTO book (name, personal_user_id)
+            VALUES ($1, $2)
+            ON CONFLICT (personal_user_id) DO NOTHING
+            RETURNING id
+            "#,
+            PERSONAL_BOOK_NAME,
+            user_id,
+        )
+        .fetch_optional(&mut tx)
+        .await?;
+
+        if let Some(book_id) = created {
+            sqlx::query!(
+                r#"
+                INSERT INTO book_member (book_id, user_id, role)
+                VALUES ($1, $2, $3)
+                "#,
+                book_id,
+                user_id,
+                Role::Owner.as_str(),
+            )
+            .execute(&mut tx)
+            .await?;
+
+            info!(%book_id, %user_id, "Created personal book.");
+        }
+
+        tx.commit().await?;
+
+        fetch_personal_membership(self, user_id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("personal book missing after creating it"))
+    }
+
+    async fn list_books(&self, user_id: &str) -> anyhow::Result<Vec<Membership>> {
+        sqlx::query_as!(
+            models::ledger::BookMembership,
+            r#"
+            SELECT b.id, b.name, b.personal_user_id IS NOT DISTINCT FROM m.user_id AS "personal!", b.default_currency, b.created_at, m.role
+            FROM book_member m
+                JOIN book b ON b.id = m.book_id
+            WHERE m.user_id = $1
+            ORDER BY b.name, b.created_at
+            "#,
+            user_id,
+        )
+        .fetch_all(&**self)
+        .await?
+        .drain(..)
+        .map(TryInto::try_into)
+        .collect()
+    }
+
+    async fn list_invitations(&self, book_id: Uuid) -> anyhow::Result<Vec<Invitation>> {
+        sqlx::query_as!(
+            models::ledger::BookInvitation,
+            r#"
+            SELECT id, book_id, role, created_by, created_at, expires_at
+            FROM book_invitation
+            WHERE book_id = $1 AND accepted_at IS NULL AND expires_at > now()
+            ORDER BY created_at
+            "#,
+            book_id,
+        )
+        .fetch_all(&**self)
+        .await?
+        .drain(..)
+        .map(TryInto::try_into)
+        .collect()
+    }
+
+    async fn list_members(&self, book_id: Uuid) -> anyhow::Result<Vec<Member>> {
+        sqlx::query_as!(
+            models::ledger::BookMember,
+            r#"
+            SELECT user_id, role, created_at
+            FROM book_member
+            WHERE book_id = $1
+            ORDER BY created_at, user_id
+            "#,
+            book_id,
+        )
+        .fetch_all(&**self)
+        .await?
+        .drain(..)
+        .map(TryInto::try_into)
+        .collect()
+    }
+
+    async fn remove_member(&self, book_id: Uuid, user_id: &str) -> Result<(), MemberChangeError> {
+        let mut tx = self.begin().await?;
+
+        ensure_member_can_change(&mut tx, book_id, user_id, None).await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM book_member
+            WHERE book_id = $1 AND user_id = $2
+            "#,
+            book_id,
+            user_id,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        info!(%book_id, %user_id, "Removed book member.");
+
+        Ok(())
+    }
+
+    async fn revoke_invitation(&self, book_id: Uuid, invitation_id: Uuid) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM book_invitation
+            WHERE book_id = $1 AND id = $2 AND accepted_at IS NULL
+            "#,
+            book_id,
+            invitation_id,
+        )
+        .execute(&**self)
+        .await?;
+
+        let found = result.rows_affected() > 0;
+
+        info!(%book_id, %invitation_id, found, "Revoked book invitation.");
+
+        Ok(found)
+    }
+
+    async fn set_member_role(
+        &self,
+        book_id: Uuid,
+        user_id: &str,
+        role: Role,
+    ) -> Result<Member, MemberChangeError> {
+        let mut tx = self.begin().await?;
+
+        ensure_member_can_change(&mut tx, book_id, user_id, Some(role)).await?;
+
+        let member = sqlx::query_as!(
+            models::ledger::BookMember,
+            r#"
+            UPDATE book_member
+            SET role = $3
+            WHERE book_id = $1 AND user_id = $2
+            RETURNING user_id, role, created_at
+            "#,
+            book_id,
+            user_id,
+            role.as_str(),
+        )
+        .fetch_one(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        info!(%book_id, %user_id, %role, "Changed role of book member.");
+
+        Ok(member.try_into()?)
+    }
+
+    async fn update_book(&self, book_id: Uuid, data: &BookData) -> Result<(), SaveBookError> {
+        let mut conn = self.acquire().await?;
+
+        ensure_currency_exists(&mut conn, data.default_currency.as_deref()).await?;
+
+        sqlx::query!(
+            r#"
+            UPDATE book
+            SET name = $2, default_currency = $3
+            WHERE id = $1
+            "#,
+            book_id,
+            data.name,
+            data.default_currency,
+        )
+        .execute(&mut conn)
+        .await?;
+
+        info!(%book_id, "Updated book.");
+
+        Ok(())
+    }
+}
+
+/// Ensure that a book's default currency exists.
+async fn ensure_currency_exists(
+    conn: &mut PgConnection,
+    currency: Option<&str>,
+) -> Result<(), SaveBookError> {
+    let currency = match currency {
+        Some(currency) => currency,
+        None => return Ok(()),
+    };
+
+    let exists = sqlx::query_scalar!(
+        r#"SELECT EXISTS (SELECT 1 FROM currency WHERE code = $1) AS "exists!""#,
+        currency,
+    )
+    .fetch_one(conn)
+    .await?;
+
+    if exists {
+        Ok(())
+    } else {
+        Err(SaveBookError::UnknownCurrency(currency.to_owned()))
+    }
+}
+
+/// Ensure that a member's role can be changed, or that they can be removed if
+/// no new role is given.
+///
+/// The book is locked until the end of the database transaction so that
+/// concurrent changes can't remove every owner between them.
+async fn ensure_member_can_change(
+    conn: &mut PgConnection,
+    book_id: Uuid,
+    user_id: &str,
+    new_role: Option<Role>,
+) -> Result<(), MemberChangeError> {
+    let personal_user_id = sqlx::query_scalar!(
+        r#"
+        SELECT personal_user_id
+        FROM book
+        WHERE id = $1
+        FOR UPDATE
+        "#,
+        book_id,
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(MemberChangeError::MemberNotFound)?;
+
+    let current_role: Role = sqlx::query_scalar!(
+        r#"
+        SELECT role
+        FROM book_member
+        WHERE book_id = $1 AND user_id = $2
+        "#,
+        book_id,
+        user_id,
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(MemberChangeError::MemberNotFound)?
+    .parse()
+    .map_err(|_| anyhow::anyhow!("member {} of book {} has an unknown role", user_id, book_id))?;
+
+    if current_role != Role::Owner || new_role == Some(Role::Owner) {
+        return Ok(());
+    }
+
+    if personal_user_id.as_deref() == Some(user_id) {
+        return Err(MemberChangeError::PersonalBookOwner);
+    }
+
+    let owners = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM book_member
+        WHERE book_id = $1 AND role = $2
+        "#,
+        book_id,
+        Role::Owner.as_str(),
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    if owners <= 1 {
+        return Err(MemberChangeError::LastOwner);
+    }
+
+    Ok(())
+}
+
+async fn fetch_membership(
+    conn: &mut PgConnection,
+    book_id: Uuid,
+    user_id: &str,
+) -> anyhow::Result<Option<Membership>> {
+    sqlx::query_as!(
+        models::ledger::BookMembership,
+        r#"
+        SELECT b.id, b.name, b.personal_user_id IS NOT DISTINCT FROM m.user_id AS "personal!", b.default_currency, b.created_at, m.role
+        FROM book_member m
+            JOIN book b ON b.id = m.book_id
+        WHERE m.book_id = $1 AND m.user_id = $2
+        "#,
+        book_id,
+        user_id,
+    )
+    .fetch_optional(conn)
+    .await?
+    .map(TryInto::try_into)
+    .transpose()
+}
+
+async fn fetch_personal_membership(
+    db: &PostgresConnection,
+    user_id: &str,
+) -> anyhow::Result<Option<Membership>> {
+    sqlx::query_as!(
+        models::ledger::BookMembership,
+        r#"
+        SELECT b.id, b.name, b.personal_user_id IS NOT DISTINCT FROM m.user_id AS "personal!", b.default_currency, b.created_at, m.role
+        FROM book b
+            JOIN book_member m ON m.book_id = b.id AND m.user_id = b.personal_user_id
+        WHERE b.personal_user_id = $1
+        "#,
+        user_id,
+    )
+    .fetch_optional(&**db)
+    .await?
+    .map(TryInto::try_into)
+    .transpose()
+}
+
+impl From<anyhow::Error> for AcceptInvitationError {
+    fn from(error: anyhow::Error) -> Self {
+        Self::Unknown(error)
+    }
+}
+
+impl From<sqlx::Error> for AcceptInvitationError {
+    fn from(error: sqlx::Error) -> Self {
+        Self::Unknown(error.into())
+    }
+}
+
+impl From<anyhow::Error> for MemberChangeError {
+    fn from(error: anyhow::Error) -> Self {
+        Self::Unknown(error)
+    }
+}
+
+impl From<sqlx::Error> for MemberChangeError {
+    fn from(error: sqlx::Error) -> Self {
+        Self::Unknown(error.into())
+    }
+}
+
+impl From<anyhow::Error> for DeleteBookError {
+    fn from(error: anyhow::Error) -> Self {
+        Self::Unknown(error)
+    }
+}
+
+impl From<sqlx::Error> for DeleteBookError {
+    fn from(error: sqlx::Error) -> Self {
+        Self::Unknown(error.into())
+    }
+}
+
+impl From<anyhow::Error> for SaveBookError {
+    fn from(error: anyhow::Error) -> Self {
+        Self::Unknown(error)
+    }
+}
+
+impl From<sqlx::Error> for SaveBookError {
+    fn from(error: sqlx::Error) -> Self {
+        Self::Unknown(error.into())
+    }
+}
@@ -0,0 +1,183 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::types::Json;
+use tracing::{debug, info};
+
+use crate::{
+    database::PostgresConnection,
+    ledger::domain::idempotency::{IdempotencyRecord, StoredResponse},
+};
+
+pub type DynIdempotencyRepo = Arc<dyn IdempotencyRepo + Send + Sync>;
+
+#[async_trait]
+pub trait IdempotencyRepo {
+    /// Claim an idempotency key for a new request.
+    ///
+    /// Keys created before `expired_before` are treated as unused and are
+    /// claimed again.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The ID of the user making the request.
+    /// * `key` - The idempotency key provided by the client.
+    /// * `request_hash` - The hash of the request body.
+    /// * `expired_before` - The cutoff for when keys expire.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the key was claimed for this request, or the existing record
+    /// if the key has already been used.
+    async fn claim_key(
+        &self,
+        user_id: &str,
+        key: &str,
+        request_hash: &str,
+        expired_before: DateTime<Utc>,
+    ) -> anyhow::Result<Option<IdempotencyRecord>>;
+
+    /// Delete every key that was created before a cutoff.
+    ///
+    /// # Returns
+    ///
+    /// The number of keys that were deleted.
+    async fn delete_expired_keys(&self, expired_before: DateTime<Utc>) -> anyhow::Result<u64>;
+
+    /// Release a claimed key so that the request can be retried, eg. because
+    /// the original request failed.
+    async fn release_key(&self, user_id: &str, key: &str) -> anyhow::Result<()>;
+
+    /// Store the response to the request that claimed a key.
+    async fn save_response(
+        &self,
+        user_id: &str,
+        key: &str,
+        response: &StoredResponse,
+    ) -> anyhow::Result<()>;
+}
+
+#[async_trait]
+impl IdempotencyRepo for PostgresConnection {
+    async fn claim_key(
+        &self,
+        user_id: &str,
+        key: &str,
+        request_hash: &str,
+        expired_before: DateTime<Utc>,
+    ) -> anyhow::Result<Option<IdempotencyRecord>> {
+        let claimed = sqlx::query_scalar!(
+            r#"
+            INSERT INTO idempotency_key (user_id, key, request_hash)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (user_id, key) DO UPDATE
+            SET
+                request_hash = EXCLUDED.request_hash,
+                response_status = NULL,
+                response_body = NULL,
+                created_at = now()
+            WHERE idempotency_key.created_at < $4
+            RETURNING key
+            "#,
+            user_id,
+            key,
+            request_hash,
+            expired_before,
+        )
+        .fetch_optional(&**self)
+        .await?;
+
+        if claimed.is_some() {
+            debug!(%user_id, %key, "Claimed idempotency key.");
+
+            return Ok(None);
+        }
+
+        let existing = sqlx::query!(
+            r#"
+            SELECT
+                request_hash,
+                response_status,
+                response_body AS "response_body: Json<serde_json::Value>"
+            FROM idempotency_key
+            WHERE user_id = $1 AND key = $2
+            "#,
+            user_id,
+            key,
+        )
+        .fetch_one(&**self)
+        .await?;
+
+        let response = match (existing.response_status, existing.response_body) {
+            (Some(status), Some(Json(body))) => Some(StoredResponse {
+                status: status.try_into()?,
+                body,
+            }),
+            _ => None,
+        };
+
+        Ok(Some(IdempotencyRecord {
+            request_hash: existing.request_hash,
+            response,
+        }))
+    }
+
+    async fn delete_expired_keys(&self, expired_before: DateTime<Utc>) -> anyhow::Result<u64> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM idempotency_key
+            WHERE created_at < $1
+            "#,
+            expired_before,
+        )
+        .execute(&**self)
+        .await?;
+
+        info!(%expired_before, count = result.rows_affected(), "Deleted expired idempotency keys.");
+
+        Ok(result.rows_affected())
+    }
+
+    async fn release_key(&self, user_id: &str, key: &str) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+            DELETE FROM idempotency_key
+            WHERE user_id = $1 AND key = $2 AND response_status IS NULL
+            "#,
+            user_id,
+            key,
+        )
+        .execute(&**self)
+        .await?;
+
+        debug!(%user_id, %key, "Released idempotency key.");
+
+        Ok(())
+    }
+
+    async fn save_response(
+        &self,
+        user_id: &str,
+        key: &str,
+        response: &StoredResponse,
+    ) -> anyhow::Result<()> {
+        sqlx::query!(
+            r#"
+            UPDATE idempotency_key
+            SET response_status = $3, response_body = $4
+            WHERE user_id = $1 AND key = $2
+            "#,
+            user_id,
+            key,
+            i16::try_from(response.status)?,
+            Json(&response.body) as _,
+        )
+        .execute(&**self)
+        .await?;
+
+        debug!(%user_id, %key, status = response.status, "Saved idempotent response.");
+
+        Ok(())
+    }
+}
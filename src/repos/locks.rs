@@ -0,0 +1,152 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+    database::PostgresConnection,
+    ledger::domain::locking::{LedgerLock, LockChange},
+    models,
+};
+
+pub type DynLedgerLockRepo = Arc<dyn LedgerLockRepo + Send + Sync>;
+
+#[async_trait]
+pub trait LedgerLockRepo {
+    /// Get a book's ledger lock.
+    ///
+    /// # Arguments
+    ///
+    /// * `book_id` - The ID of the book.
+    async fn get_ledger_lock(&self, book_id: Uuid) -> anyhow::Result<LedgerLock>;
+
+    /// List the changes made to a book's lock date, most recent first.
+    ///
+    /// # Arguments
+    ///
+    /// * `book_id` - The ID of the book.
+    async fn list_lock_changes(&self, book_id: Uuid) -> anyhow::Result<Vec<LockChange>>;
+
+    /// Move a book's lock date, recording the change if the date differs from
+    /// the current one.
+    ///
+    /// # Arguments
+    ///
+    /// * `book_id` - The ID of the book.
+    /// * `actor` - The ID of the user changing the lock date.
+    /// * `lock_date` - The new lock date, or `None` to unlock the ledger.
+    async fn set_lock_date(
+        &self,
+        book_id: Uuid,
+        actor: &str,
+        lock_date: Option<NaiveDate>,
+    ) -> anyhow::Result<LedgerLock>;
+}
+
+#[async_trait]
+impl LedgerLockRepo for PostgresConnection {
+    async fn get_ledger_lock(&self, book_id: Uuid) -> anyhow::Result<LedgerLock> {
+        let lock_date = sqlx::query_scalar!(
+            r#"
+            SELECT lock_date
+            FROM ledger_lock
+            WHERE book_id = $1
+            "#,
+            book_id,
+        )
+        .fetch_optional(&**self)
+        .await?
+        .flatten();
+
+        Ok(LedgerLock { lock_date })
+    }
+
+    async fn list_lock_changes(&self, book_id: Uuid) -> anyhow::Result<Vec<LockChange>> {
+        Ok(sqlx::query_as!(
+            models::ledger::LedgerLockChange,
+            r#"
+            SELECT actor, previous_lock_date, lock_date, changed_at
+            FROM ledger_lock_change
+            WHERE book_id = $1
+            ORDER BY changed_at DESC, id DESC
+            "#,
+            book_id,
+        )
+        .fetch_all(&**self)
+        .await?
+        .drain(..)
+        .map(LockChange::from)
+        .collect())
+    }
+
+    async fn set_lock_date(
+        &self,
+        book_id: Uuid,
+        actor: &str,
+        lock_date: Option<NaiveDate>,
+    ) -> anyhow::Result<LedgerLock> {
+        let mut tx = self.begin().await?;
+
+        // A book that was never locked has no row to lock, so an unlocked one
+        // is created first. Concurrent changes then wait for each other on
+        // that row and are recorded in order.
+        sqlx::query!(
+            r#"
+            INSERT INTO ledger_lock (book_id, lock_date)
+            VALUES ($1, NULL)
+            ON CONFLICT (book_id) DO NOTHING
+            "#,
+            book_id,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        let previous_lock_date = sqlx::query_scalar!(
+            r#"
+            SELECT lock_date
+            FROM ledger_lock
+            WHERE book_id = $1
+            FOR UPDATE
+            "#,
+            book_id,
+        )
+        .fetch_optional(&mut tx)
+        .await?
+        .flatten();
+
+        if previous_lock_date != lock_date {
+            sqlx::query!(
+                r#"
+                UPDATE ledger_lock
+                SET lock_date = $2, updated_at = now()
+                WHERE book_id = $1
+                "#,
+                book_id,
+                lock_date,
+            )
+            .execute(&mut tx)
+            .await?;
+
+            sqlx::query!(
+                r#"
+                INSERT INTO ledger_lock_change (book_id, actor, previous_lock_date, lock_date)
+                VALUES ($1, $2, $3, $4)
+                "#,
+                book_id,
+                actor,
+                previous_lock_date,
+                lock_date,
+            )
+            .execute(&mut tx)
+            .await?;
+
+            info!(%book_id, %actor, ?previous_lock_date, ?lock_date, "Changed ledger lock date.");
+        }
+
+        tx.commit().await?;
+
+        Ok(LedgerLock { lock_date })
+    }
+}
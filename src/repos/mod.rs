@@ -1 +1,8 @@
+pub mod api_tokens;
+pub mod attachments;
+pub mod books;
+pub mod idempotency;
+pub mod locks;
+pub mod sqlite;
 pub mod transactions;
+pub mod webhooks;
@@ -0,0 +1,88 @@
+use async_trait::async_trait;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+    authentication::tokens::{ApiToken, NewApiToken},
+    database::{sqlite_now, SqliteConnection},
+    models,
+    repos::api_tokens::ApiTokenRepo,
+};
+
+#[async_trait]
+impl ApiTokenRepo for SqliteConnection {
+    async fn create_token(&self, token: &NewApiToken) -> anyhow::Result<ApiToken> {
+        let token: models::authentication::ApiToken = sqlx::query_as(
+            r#"
+            INSERT INTO api_token (id, user_id, name, scope, token_hash, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id, user_id, name, scope, created_at, last_used_at
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(token.user_id())
+        .bind(token.name())
+        .bind(token.scope().as_str())
+        .bind(token.token_hash())
+        .bind(sqlite_now())
+        .fetch_one(&**self)
+        .await?;
+
+        info!(token_id = %token.id, user_id = %token.user_id, "Created personal access token.");
+
+        token.try_into()
+    }
+
+    async fn list_tokens(&self, user_id: &str) -> anyhow::Result<Vec<ApiToken>> {
+        sqlx::query_as::<_, models::authentication::ApiToken>(
+            r#"
+            SELECT id, user_id, name, scope, created_at, last_used_at
+            FROM api_token
+            WHERE user_id = $1
+            ORDER BY created_at, id
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&**self)
+        .await?
+        .drain(..)
+        .map(TryInto::try_into)
+        .collect()
+    }
+
+    async fn revoke_token(&self, user_id: &str, token_id: Uuid) -> anyhow::Result<bool> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM api_token
+            WHERE user_id = $1 AND id = $2
+            "#,
+        )
+        .bind(user_id)
+        .bind(token_id)
+        .execute(&**self)
+        .await?;
+
+        let found = result.rows_affected() > 0;
+
+        info!(%token_id, user_id, found, "Revoked personal access token.");
+
+        Ok(found)
+    }
+
+    async fn use_token(&self, token_hash: &str) -> anyhow::Result<Option<ApiToken>> {
+        sqlx::query_as::<_, models::authentication::ApiToken>(
+            r#"
+            UPDATE api_token
+            SET last_used_at = $2
+            WHERE token_hash = $1
+            RETURNING id, user_id, name, scope, created_at, last_used_at
+            "#,
+        )
+        .bind(token_hash)
+        .bind(sqlite_now())
+        .fetch_optional(&**self)
+        .await?
+        .map(TryInto::try_into)
+        .transpose()
+    }
+}
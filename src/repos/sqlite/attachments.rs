@@ -0,0 +1,160 @@
+use async_trait::async_trait;
+use tracing::{debug, info};
+use uuid::Uuid;
+
+use crate::{
+    database::{sqlite_now, SqliteConnection},
+    ledger::domain::attachments::{Attachment, NewAttachment},
+    models,
+    repos::attachments::{AttachmentRepo, PersistedAttachment},
+};
+
+#[async_trait]
+impl AttachmentRepo for SqliteConnection {
+    async fn delete_attachment(
+        &self,
+        book_id: Uuid,
+        transaction_id: Uuid,
+        attachment_id: Uuid,
+    ) -> anyhow::Result<Option<Attachment>> {
+        let deleted: Option<models::ledger::TransactionAttachment> = sqlx::query_as(
+            r#"
+            DELETE FROM transaction_attachment
+            WHERE book_id = $1 AND transaction_id = $2 AND id = $3
+            RETURNING id, transaction_id, book_id, file_name, content_type, size, created_at
+            "#,
+        )
+        .bind(book_id)
+        .bind(transaction_id)
+        .bind(attachment_id)
+        .fetch_optional(&**self)
+        .await?;
+
+        info!(%book_id, %transaction_id, %attachment_id, found = deleted.is_some(), "Deleted attachment.");
+
+        deleted.map(Attachment::try_from).transpose()
+    }
+
+    async fn get_attachment(
+        &self,
+        book_id: Uuid,
+        transaction_id: Uuid,
+        attachment_id: Uuid,
+    ) -> anyhow::Result<Option<Attachment>> {
+        sqlx::query_as::<_, models::ledger::TransactionAttachment>(
+            r#"
+            SELECT id, transaction_id, book_id, file_name, content_type, size, created_at
+            FROM transaction_attachment
+            WHERE book_id = $1 AND transaction_id = $2 AND id = $3
+            "#,
+        )
+        .bind(book_id)
+        .bind(transaction_id)
+        .bind(attachment_id)
+        .fetch_optional(&**self)
+        .await?
+        .map(Attachment::try_from)
+        .transpose()
+    }
+
+    async fn list_attachments(
+        &self,
+        book_id: Uuid,
+        transaction_id: Uuid,
+    ) -> anyhow::Result<Vec<Attachment>> {
+        sqlx::query_as::<_, models::ledger::TransactionAttachment>(
+            r#"
+            SELECT id, transaction_id, book_id, file_name, content_type, size, created_at
+            FROM transaction_attachment
+            WHERE book_id = $1 AND transaction_id = $2
+            ORDER BY created_at
+            "#,
+        )
+        .bind(book_id)
+        .bind(transaction_id)
+        .fetch_all(&**self)
+        .await?
+        .drain(..)
+        .map(Attachment::try_from)
+        .collect()
+    }
+
+    async fn persist_attachment(
+        &self,
+        attachment: &NewAttachment,
+        quota: u64,
+    ) -> anyhow::Result<PersistedAttachment> {
+        // SQLite only has a single writer, so no other upload can change the
+        // book's usage before this database transaction commits.
+        let mut tx = self.begin().await?;
+
+        let used: i64 = sqlx::query_scalar(
+            r#"
+            SELECT COALESCE(SUM(size), 0)
+            FROM transaction_attachment
+            WHERE book_id = $1
+            "#,
+        )
+        .bind(attachment.book_id())
+        .fetch_one(&mut tx)
+        .await?;
+        let used = u64::try_from(used)?;
+
+        if used.saturating_add(attachment.size()) > quota {
+            debug!(book_id = %attachment.book_id(), used, quota, "Attachment would exceed the book's quota.");
+
+            return Ok(PersistedAttachment::QuotaExceeded { used });
+        }
+
+        // Only insert the attachment if the transaction it is attached to is in
+        // the attachment's book, and the transaction is not in the trash.
+        let persisted: Option<models::ledger::TransactionAttachment> = sqlx::query_as(
+            r#"
+            INSERT INTO transaction_attachment (id, transaction_id, book_id, file_name, content_type, size, created_at)
+            SELECT $1, t.id, t.book_id, $4, $5, $6, $7
+            FROM "transaction" t
+            WHERE t.id = $2 AND t.book_id = $3 AND t.deleted_at IS NULL
+            RETURNING id, transaction_id, book_id, file_name, content_type, size, created_at
+            "#,
+        )
+        .bind(attachment.id())
+        .bind(attachment.transaction_id())
+        .bind(attachment.book_id())
+        .bind(attachment.file_name())
+        .bind(attachment.content_type())
+        .bind(i64::try_from(attachment.size())?)
+        .bind(sqlite_now())
+        .fetch_optional(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        match persisted {
+            Some(model) => {
+                info!(id = %model.id, transaction_id = %model.transaction_id, "Persisted new attachment.");
+
+                Ok(PersistedAttachment::Persisted(model.try_into()?))
+            }
+            None => {
+                debug!(transaction_id = %attachment.transaction_id(), "Attachment references an unknown transaction.");
+
+                Ok(PersistedAttachment::TransactionNotFound)
+            }
+        }
+    }
+
+    async fn total_attachment_size(&self, book_id: Uuid) -> anyhow::Result<u64> {
+        let total: i64 = sqlx::query_scalar(
+            r#"
+            SELECT COALESCE(SUM(size), 0)
+            FROM transaction_attachment
+            WHERE book_id = $1
+            "#,
+        )
+        .bind(book_id)
+        .fetch_one(&**self)
+        .await?;
+
+        Ok(total.try_into()?)
+    }
+}
@@ -0,0 +1,527 @@
+use async_trait::async_trait;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+    database::{sqlite_now, SqliteConnection},
+    ledger::domain::{
+        attachments::Attachment,
+        books::{
+            AcceptInvitationError, BookData, DeleteBookError, Invitation, Member,
+            MemberChangeError, Membership, NewInvitation, Role, SaveBookError,
+        },
+    },
+    models,
+    repos::books::{BookRepo, PERSONAL_BOOK_NAME},
+};
+
+#[async_trait]
+impl BookRepo for SqliteConnection {
+    async fn accept_invitation(
+        &self,
+        token_hash: &str,
+        user_id: &str,
+    ) -> Result<Membership, AcceptInvitationError> {
+        let now = sqlite_now();
+        let mut tx = self.begin().await?;
+
+        let (invitation_id, book_id, role): (Uuid, Uuid, String) = sqlx::query_as(
+            r#"
+            UPDATE book_invitation
+            SET accepted_by = $2, accepted_at = $3
+            WHERE token_hash = $1 AND accepted_at IS NULL AND expires_at > $3
+            RETURNING id, book_id, role
+            "#,
+        )
+        .bind(token_hash)
+        .bind(user_id)
+        .bind(now)
+        .fetch_optional(&mut tx)
+        .await?
+        .ok_or(AcceptInvitationError::InvitationNotFound)?;
+
+        // Returning early rolls back the database transaction, so the
+        // invitation can still be used by someone else.
+        let added = sqlx::query(
+            r#"
+            INSERT INTO book_member (book_id, user_id, role, created_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (book_id, user_id) DO NOTHING
+            "#,
+        )
+        .bind(book_id)
+        .bind(user_id)
+        .bind(role)
+        .bind(now)
+        .execute(&mut tx)
+        .await?;
+        if added.rows_affected() == 0 {
+            return Err(AcceptInvitationError::AlreadyMember);
+        }
+
+        let membership = fetch_membership(&mut tx, book_id, user_id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("membership missing after accepting invitation"))?;
+
+        tx.commit().await?;
+
+        info!(%invitation_id, %book_id, %user_id, "Accepted book invitation.");
+
+        Ok(membership)
+    }
+
+    async fn create_book(
+        &self,
+        user_id: &str,
+        data: &BookData,
+    ) -> Result<Membership, SaveBookError> {
+        let book_id = Uuid::new_v4();
+        let now = sqlite_now();
+        let mut tx = self.begin().await?;
+
+        ensure_currency_exists(&mut tx, data.default_currency.as_deref()).await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO book (id, name, default_currency, created_at)
+            VALUES ($1, $2, $3, $4)
+            "#,
+        )
+        .bind(book_id)
+        .bind(&data.name)
+        .bind(&data.default_currency)
+        .bind(now)
+        .execute(&mut tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO book_member (book_id, user_id, role, created_at)
+            VALUES ($1, $2, $3, $4)
+            "#,
+        )
+        .bind(book_id)
+        .bind(user_id)
+        .bind(Role::Owner.as_str())
+        .bind(now)
+        .execute(&mut tx)
+        .await?;
+
+        let membership = fetch_membership(&mut tx, book_id, user_id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("membership missing after creating book"))?;
+
+        tx.commit().await?;
+
+        info!(%book_id, %user_id, "Created book.");
+
+        Ok(membership)
+    }
+
+    async fn create_invitation(&self, invitation: &NewInvitation) -> anyhow::Result<Invitation> {
+        let created: models::ledger::BookInvitation = sqlx::query_as(
+            r#"
+            INSERT INTO book_invitation (id, book_id, role, token_hash, created_by, created_at, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING id, book_id, role, created_by, created_at, expires_at
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(invitation.book_id())
+        .bind(invitation.role().as_str())
+        .bind(invitation.token_hash())
+        .bind(invitation.created_by())
+        .bind(sqlite_now())
+        .bind(invitation.expires_at())
+        .fetch_one(&**self)
+        .await?;
+
+        info!(invitation_id = %created.id, book_id = %created.book_id, role = %created.role, "Created book invitation.");
+
+        created.try_into()
+    }
+
+    async fn delete_book(&self, book_id: Uuid) -> Result<Vec<Attachment>, DeleteBookError> {
+        let mut tx = self.begin().await?;
+
+        let personal_user_id: Option<Option<String>> = sqlx::query_scalar(
+            r#"
+            SELECT personal_user_id
+            FROM book
+            WHERE id = $1
+            "#,
+        )
+        .bind(book_id)
+        .fetch_optional(&mut tx)
+        .await?;
+        match personal_user_id {
+            // The book was already deleted by another request.
+            None => return Ok(Vec::new()),
+            Some(Some(_)) => return Err(DeleteBookError::PersonalBook),
+            Some(None) => (),
+        }
+
+        let attachments = sqlx::query_as::<_, models::ledger::TransactionAttachment>(
+            r#"
+            DELETE FROM transaction_attachment
+            WHERE book_id = $1
+            RETURNING id, transaction_id, book_id, file_name, content_type, size, created_at
+            "#,
+        )
+        .bind(book_id)
+        .fetch_all(&mut tx)
+        .await?
+        .drain(..)
+        .map(Attachment::try_from)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+        // Entries can't outlive their accounts, so the transactions have to
+        // be removed before the rest of the book.
+        for statement in [
+            r#"DELETE FROM "transaction" WHERE book_id = $1"#,
+            r#"DELETE FROM book WHERE id = $1"#,
+        ] {
+            sqlx::query(statement)
+                .bind(book_id)
+                .execute(&mut tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+
+        info!(%book_id, attachments = attachments.len(), "Deleted book.");
+
+        Ok(attachments)
+    }
+
+    async fn get_membership(
+        &self,
+        book_id: Uuid,
+        user_id: &str,
+    ) -> anyhow::Result<Option<Membership>> {
+        let mut conn = self.acquire().await?;
+
+        fetch_membership(&mut conn, book_id, user_id).await
+    }
+
+    async fn get_or_create_personal_book(&self, user_id: &str) -> anyhow::Result<Membership> {
+        if let Some(membership) = fetch_personal_membership(self, user_id).await? {
+            return Ok(membership);
+        }
+
+        let book_id = Uuid::new_v4();
+        let now = sqlite_now();
+        let mut tx = self.begin().await?;
+
+        let created = sqlx::query(
+            r#"
+            INSERT INTO book (id, name, personal_user_id, created_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (personal_user_id) DO NOTHING
+            "#,
+        )
+        .bind(book_id)
+        .bind(PERSONAL_BOOK_NAME)
+        .bind(user_id)
+        .bind(now)
+        .execute(&mut tx)
+        .await?;
+
+        if created.rows_affected() > 0 {
+            sqlx::query(
+                r#"
+                INSERT INTO book_member (book_id, user_id, role, created_at)
+                VALUES ($1, $2, $3, $4)
+                "#,
+            )
+            .bind(book_id)
+            .bind(user_id)
+            .bind(Role::Owner.as_str())
+            .bind(now)
+            .execute(&mut tx)
+            .await?;
+
+            info!(%book_id, %user_id, "Created personal book.");
+        }
+
+        tx.commit().await?;
+
+        fetch_personal_membership(self, user_id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("personal book missing after creating it"))
+    }
+
+    async fn list_books(&self, user_id: &str) -> anyhow::Result<Vec<Membership>> {
+        sqlx::query_as::<_, models::ledger::BookMembership>(
+            r#"
+            SELECT b.id, b.name, b.personal_user_id IS m.user_id AS personal, b.default_currency, b.created_at, m.role
+            FROM book_member m
+                JOIN book b ON b.id = m.book_id
+            WHERE m.user_id = $1
+            ORDER BY b.name, b.created_at
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&**self)
+        .await?
+        .drain(..)
+        .map(TryInto::try_into)
+        .collect()
+    }
+
+    async fn list_invitations(&self, book_id: Uuid) -> anyhow::Result<Vec<Invitation>> {
+        sqlx::query_as::<_, models::ledger::BookInvitation>(
+            r#"
+            SELECT id, book_id, role, created_by, created_at, expires_at
+            FROM book_invitation
+            WHERE book_id = $1 AND accepted_at IS NULL AND expires_at > $2
+            ORDER BY created_at
+            "#,
+        )
+        .bind(book_id)
+        .bind(sqlite_now())
+        .fetch_all(&**self)
+        .await?
+        .drain(..)
+        .map(TryInto::try_into)
+        .collect()
+    }
+
+    async fn list_members(&self, book_id: Uuid) -> anyhow::Result<Vec<Member>> {
+        sqlx::query_as::<_, models::ledger::BookMember>(
+            r#"
+            SELECT user_id, role, created_at
+            FROM book_member
+            WHERE book_id = $1
+            ORDER BY created_at, user_id
+            "#,
+        )
+        .bind(book_id)
+        .fetch_all(&**self)
+        .await?
+        .drain(..)
+        .map(TryInto::try_into)
+        .collect()
+    }
+
+    async fn remove_member(&self, book_id: Uuid, user_id: &str) -> Result<(), MemberChangeError> {
+        let mut tx = self.begin().await?;
+
+        ensure_member_can_change(&mut tx, book_id, user_id, None).await?;
+
+        sqlx::query(
+            r#"
+            DELETE FROM book_member
+            WHERE book_id = $1 AND user_id = $2
+            "#,
+        )
+        .bind(book_id)
+        .bind(user_id)
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        info!(%book_id, %user_id, "Removed book member.");
+
+        Ok(())
+    }
+
+    async fn revoke_invitation(&self, book_id: Uuid, invitation_id: Uuid) -> anyhow::Result<bool> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM book_invitation
+            WHERE book_id = $1 AND id = $2 AND accepted_at IS NULL
+            "#,
+        )
+        .bind(book_id)
+        .bind(invitation_id)
+        .execute(&**self)
+        .await?;
+
+        let found = result.rows_affected() > 0;
+
+        info!(%book_id, %invitation_id, found, "Revoked book invitation.");
+
+        Ok(found)
+    }
+
+    async fn set_member_role(
+        &self,
+        book_id: Uuid,
+        user_id: &str,
+        role: Role,
+    ) -> Result<Member, MemberChangeError> {
+        let mut tx = self.begin().await?;
+
+        ensure_member_can_change(&mut tx, book_id, user_id, Some(role)).await?;
+
+        let member: models::ledger::BookMember = sqlx::query_as(
+            r#"
+            UPDATE book_member
+            SET role = $3
+            WHERE book_id = $1 AND user_id = $2
+            RETURNING user_id, role, created_at
+            "#,
+        )
+        .bind(book_id)
+        .bind(user_id)
+        .bind(role.as_str())
+        .fetch_one(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        info!(%book_id, %user_id, %role, "Changed role of book member.");
+
+        Ok(member.try_into()?)
+    }
+
+    async fn update_book(&self, book_id: Uuid, data: &BookData) -> Result<(), SaveBookError> {
+        let mut conn = self.acquire().await?;
+
+        ensure_currency_exists(&mut conn, data.default_currency.as_deref()).await?;
+
+        sqlx::query(
+            r#"
+            UPDATE book
+            SET name = $2, default_currency = $3
+            WHERE id = $1
+            "#,
+        )
+        .bind(book_id)
+        .bind(&data.name)
+        .bind(&data.default_currency)
+        .execute(&mut conn)
+        .await?;
+
+        info!(%book_id, "Updated book.");
+
+        Ok(())
+    }
+}
+
+/// Ensure that a book's default currency exists.
+async fn ensure_currency_exists(
+    conn: &mut sqlx::SqliteConnection,
+    currency: Option<&str>,
+) -> Result<(), SaveBookError> {
+    let currency = match currency {
+        Some(currency) => currency,
+        None => return Ok(()),
+    };
+
+    let exists: bool =
+        sqlx::query_scalar(r#"SELECT EXISTS (SELECT 1 FROM currency WHERE code = $1)"#)
+            .bind(currency)
+            .fetch_one(conn)
+            .await?;
+
+    if exists {
+        Ok(())
+    } else {
+        Err(SaveBookError::UnknownCurrency(currency.to_owned()))
+    }
+}
+
+/// Ensure that a member's role can be changed, or that they can be removed if
+/// no new role is given.
+async fn ensure_member_can_change(
+    conn: &mut sqlx::SqliteConnection,
+    book_id: Uuid,
+    user_id: &str,
+    new_role: Option<Role>,
+) -> Result<(), MemberChangeError> {
+    let personal_user_id: Option<String> = sqlx::query_scalar(
+        r#"
+        SELECT personal_user_id
+        FROM book
+        WHERE id = $1
+        "#,
+    )
+    .bind(book_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(MemberChangeError::MemberNotFound)?;
+
+    let current_role: Role = sqlx::query_scalar::<_, String>(
+        r#"
+        SELECT role
+        FROM book_member
+        WHERE book_id = $1 AND user_id = $2
+        "#,
+    )
+    .bind(book_id)
+    .bind(user_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(MemberChangeError::MemberNotFound)?
+    .parse()
+    .map_err(|_| anyhow::anyhow!("member {} of book {} has an unknown role", user_id, book_id))?;
+
+    if current_role != Role::Owner || new_role == Some(Role::Owner) {
+        return Ok(());
+    }
+
+    if personal_user_id.as_deref() == Some(user_id) {
+        return Err(MemberChangeError::PersonalBookOwner);
+    }
+
+    let owners: i64 = sqlx::query_scalar(
+        r#"
+        SELECT COUNT(*)
+        FROM book_member
+        WHERE book_id = $1 AND role = $2
+        "#,
+    )
+    .bind(book_id)
+    .bind(Role::Owner.as_str())
+    .fetch_one(&mut *conn)
+    .await?;
+
+    if owners <= 1 {
+        return Err(MemberChangeError::LastOwner);
+    }
+
+    Ok(())
+}
+
+async fn fetch_membership(
+    conn: &mut sqlx::SqliteConnection,
+    book_id: Uuid,
+    user_id: &str,
+) -> anyhow::Result<Option<Membership>> {
+    sqlx::query_as::<_, models::ledger::BookMembership>(
+        r#"
+        SELECT b.id, b.name, b.personal_user_id IS m.user_id AS personal, b.default_currency, b.created_at, m.role
+        FROM book_member m
+            JOIN book b ON b.id = m.book_id
+        WHERE m.book_id = $1 AND m.user_id = $2
+        "#,
+    )
+    .bind(book_id)
+    .bind(user_id)
+    .fetch_optional(conn)
+    .await?
+    .map(TryInto::try_into)
+    .transpose()
+}
+
+async fn fetch_personal_membership(
+    db: &SqliteConnection,
+    user_id: &str,
+) -> anyhow::Result<Option<Membership>> {
+    sqlx::query_as::<_, models::ledger::BookMembership>(
+        r#"
+        SELECT b.id, b.name, b.personal_user_id IS m.user_id AS personal, b.default_currency, b.created_at, m.role
+        FROM book b
+            JOIN book_member m ON m.book_id = b.id AND m.user_id = b.personal_user_id
+        WHERE b.personal_user_id = $1
+        "#,
+    )
+    .bind(user_id)
+    .fetch_optional(&**db)
+    .await?
+    .map(TryInto::try_into)
+    .transpose()
+}
@@ -0,0 +1,136 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::types::Json;
+use tracing::{debug, info};
+
+use crate::{
+    database::{sqlite_now, SqliteConnection},
+    ledger::domain::idempotency::{IdempotencyRecord, StoredResponse},
+    repos::idempotency::IdempotencyRepo,
+};
+
+#[async_trait]
+impl IdempotencyRepo for SqliteConnection {
+    async fn claim_key(
+        &self,
+        user_id: &str,
+        key: &str,
+        request_hash: &str,
+        expired_before: DateTime<Utc>,
+    ) -> anyhow::Result<Option<IdempotencyRecord>> {
+        let claimed: Option<String> = sqlx::query_scalar(
+            r#"
+            INSERT INTO idempotency_key (user_id, key, request_hash, created_at)
+            VALUES ($1, $2, $3, $5)
+            ON CONFLICT (user_id, key) DO UPDATE
+            SET
+                request_hash = excluded.request_hash,
+                response_status = NULL,
+                response_body = NULL,
+                created_at = excluded.created_at
+            WHERE idempotency_key.created_at < $4
+            RETURNING key
+            "#,
+        )
+        .bind(user_id)
+        .bind(key)
+        .bind(request_hash)
+        .bind(expired_before)
+        .bind(sqlite_now())
+        .fetch_optional(&**self)
+        .await?;
+
+        if claimed.is_some() {
+            debug!(%user_id, %key, "Claimed idempotency key.");
+
+            return Ok(None);
+        }
+
+        let (request_hash, response_status, response_body): (
+            String,
+            Option<i16>,
+            Option<Json<serde_json::Value>>,
+        ) = sqlx::query_as(
+            r#"
+            SELECT request_hash, response_status, response_body
+            FROM idempotency_key
+            WHERE user_id = $1 AND key = $2
+            "#,
+        )
+        .bind(user_id)
+        .bind(key)
+        .fetch_one(&**self)
+        .await?;
+
+        let response = match (response_status, response_body) {
+            (Some(status), Some(Json(body))) => Some(StoredResponse {
+                status: status.try_into()?,
+                body,
+            }),
+            _ => None,
+        };
+
+        Ok(Some(IdempotencyRecord {
+            request_hash,
+            response,
+        }))
+    }
+
+    async fn delete_expired_keys(&self, expired_before: DateTime<Utc>) -> anyhow::Result<u64> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM idempotency_key
+            WHERE created_at < $1
+            "#,
+        )
+        .bind(expired_before)
+        .execute(&**self)
+        .await?;
+
+        info!(%expired_before, count = result.rows_affected(), "Deleted expired idempotency keys.");
+
+        Ok(result.rows_affected())
+    }
+
+    async fn release_key(&self, user_id: &str, key: &str) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            DELETE FROM idempotency_key
+            WHERE user_id = $1 AND key = $2 AND response_status IS NULL
+            "#,
+        )
+        .bind(user_id)
+        .bind(key)
+        .execute(&**self)
+        .await?;
+
+        debug!(%user_id, %key, "Released idempotency key.");
+
+        Ok(())
+    }
+
+    async fn save_response(
+        &self,
+        user_id: &str,
+        key: &str,
+        response: &StoredResponse,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE idempotency_key
+            SET response_status = $3, response_body = $4
+            WHERE user_id = $1 AND key = $2
+            "#,
+        )
+        .bind(user_id)
+        .bind(key)
+        .bind(i16::try_from(response.status)?)
+        .bind(Json(&response.body))
+        .execute(&**self)
+        .await?;
+
+        debug!(%user_id, %key, status = response.status, "Saved idempotent response.");
+
+        Ok(())
+    }
+}
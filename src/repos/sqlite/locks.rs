@@ -0,0 +1,118 @@
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+    database::{sqlite_now, SqliteConnection},
+    ledger::domain::locking::{LedgerLock, LockChange},
+    models,
+    repos::locks::LedgerLockRepo,
+};
+
+#[async_trait]
+impl LedgerLockRepo for SqliteConnection {
+    async fn get_ledger_lock(&self, book_id: Uuid) -> anyhow::Result<LedgerLock> {
+        let lock_date = sqlx::query_scalar::<_, Option<NaiveDate>>(
+            r#"
+            SELECT lock_date
+            FROM ledger_lock
+            WHERE book_id = $1
+            "#,
+        )
+        .bind(book_id)
+        .fetch_optional(&**self)
+        .await?
+        .flatten();
+
+        Ok(LedgerLock { lock_date })
+    }
+
+    async fn list_lock_changes(&self, book_id: Uuid) -> anyhow::Result<Vec<LockChange>> {
+        Ok(sqlx::query_as::<_, models::ledger::LedgerLockChange>(
+            r#"
+            SELECT actor, previous_lock_date, lock_date, changed_at
+            FROM ledger_lock_change
+            WHERE book_id = $1
+            ORDER BY changed_at DESC, id DESC
+            "#,
+        )
+        .bind(book_id)
+        .fetch_all(&**self)
+        .await?
+        .drain(..)
+        .map(LockChange::from)
+        .collect())
+    }
+
+    async fn set_lock_date(
+        &self,
+        book_id: Uuid,
+        actor: &str,
+        lock_date: Option<NaiveDate>,
+    ) -> anyhow::Result<LedgerLock> {
+        let now = sqlite_now();
+        let mut tx = self.begin().await?;
+
+        // Writing first takes SQLite's write lock, so concurrent changes wait
+        // for each other instead of both reading the same previous lock date.
+        sqlx::query(
+            r#"
+            INSERT INTO ledger_lock (book_id, lock_date, updated_at)
+            VALUES ($1, NULL, $2)
+            ON CONFLICT (book_id) DO NOTHING
+            "#,
+        )
+        .bind(book_id)
+        .bind(now)
+        .execute(&mut tx)
+        .await?;
+
+        let previous_lock_date = sqlx::query_scalar::<_, Option<NaiveDate>>(
+            r#"
+            SELECT lock_date
+            FROM ledger_lock
+            WHERE book_id = $1
+            "#,
+        )
+        .bind(book_id)
+        .fetch_optional(&mut tx)
+        .await?
+        .flatten();
+
+        if previous_lock_date != lock_date {
+            sqlx::query(
+                r#"
+                UPDATE ledger_lock
+                SET lock_date = $2, updated_at = $3
+                WHERE book_id = $1
+                "#,
+            )
+            .bind(book_id)
+            .bind(lock_date)
+            .bind(now)
+            .execute(&mut tx)
+            .await?;
+
+            sqlx::query(
+                r#"
+                INSERT INTO ledger_lock_change (book_id, actor, previous_lock_date, lock_date, changed_at)
+                VALUES ($1, $2, $3, $4, $5)
+                "#,
+            )
+            .bind(book_id)
+            .bind(actor)
+            .bind(previous_lock_date)
+            .bind(lock_date)
+            .bind(now)
+            .execute(&mut tx)
+            .await?;
+
+            info!(%book_id, %actor, ?previous_lock_date, ?lock_date, "Changed ledger lock date.");
+        }
+
+        tx.commit().await?;
+
+        Ok(LedgerLock { lock_date })
+    }
+}
@@ -0,0 +1,13 @@
+//! Implementations of the repositories for a SQLite database.
+//!
+//! These mirror the Postgres implementations next to each repository's trait.
+//! SQLite only allows one database transaction to write at a time, so the row
+//! locks the Postgres implementations take are unnecessary here.
+
+mod api_tokens;
+mod attachments;
+mod books;
+mod idempotency;
+mod locks;
+mod transactions;
+pub mod webhooks;
@@ -0,0 +1,161 @@
+use async_trait::async_trait;
+use sqlx::{FromRow, QueryBuilder, Sqlite};
+
+use crate::{
+    database::SqliteConnection,
+    ledger::domain::transactions::TransactionCursor,
+    models,
+    repos::transactions::{
+        TransactionCollection, TransactionQuery, TransactionRepo, TRANSACTION_PAGE_SIZE,
+    },
+};
+
+#[async_trait]
+impl TransactionRepo for SqliteConnection {
+    async fn list_transactions(
+        &self,
+        query: TransactionQuery,
+    ) -> anyhow::Result<TransactionCollection> {
+        let mut query_builder: QueryBuilder<'_, Sqlite> = QueryBuilder::new(
+            r#"
+            SELECT t.id, t.book_id, t.date, t.payee, t.notes, t.created_at, t.updated_at, t.deleted_at, t.revision
+            FROM "transaction" t
+            WHERE t.book_id = "#,
+        );
+        query_builder.push_bind(query.book_id);
+
+        if query.trashed {
+            query_builder.push(" AND t.deleted_at IS NOT NULL");
+        } else {
+            query_builder.push(" AND t.deleted_at IS NULL");
+        }
+
+        if let Some(account) = query.account.as_ref() {
+            query_builder
+                .push(
+                    r#"
+                    AND t.id IN (
+                        SELECT e.transaction_id
+                        FROM transaction_entry e
+                            JOIN account a ON e.account_id = a.id
+                        WHERE a.name = "#,
+                )
+                .push_bind(account)
+                .push(" OR substr(a.name, 1, length(")
+                .push_bind(account)
+                .push(") + 1) = ")
+                .push_bind(account)
+                .push(" || ':')");
+        }
+
+        if let Some(cursor) = query.after {
+            query_builder
+                .push(" AND (t.date < ")
+                .push_bind(cursor.after_date)
+                .push(" OR (t.date = ")
+                .push_bind(cursor.after_date)
+                .push(" AND t.created_at < ")
+                .push_bind(cursor.after_created_at)
+                .push("))");
+        }
+
+        query_builder
+            .push(" ORDER BY t.date DESC, t.created_at DESC LIMIT ")
+            // Select one more than the page size so we can determine if there
+            // is a next page.
+            .push_bind(i16::from(TRANSACTION_PAGE_SIZE) + 1);
+
+        let mut transactions_data: Vec<models::ledger::Transaction> = query_builder
+            .build()
+            .fetch_all(&**self)
+            .await?
+            .iter()
+            .map(models::ledger::Transaction::from_row)
+            .collect::<Result<Vec<_>, sqlx::Error>>()?;
+
+        let has_next_page = transactions_data.len() > usize::from(TRANSACTION_PAGE_SIZE);
+        if has_next_page {
+            transactions_data.pop();
+        }
+
+        let mut entry_query_builder = related_rows(
+            r#"
+            SELECT e.id, e.transaction_id, e."order", e.account_id, e.currency, e.amount
+            FROM transaction_entry e
+            WHERE e.transaction_id IN ("#,
+            &transactions_data,
+        );
+        entry_query_builder.push(r#" ORDER BY e."order""#);
+
+        let entries: Vec<models::ledger::TransactionEntry> = entry_query_builder
+            .build_query_as()
+            .fetch_all(&**self)
+            .await?;
+
+        // Accounts and currencies are found by joining the page's entries
+        // again, rather than binding every ID the entries reference.
+        let accounts: Vec<models::ledger::Account> = related_rows(
+            r#"
+            SELECT DISTINCT a.id, a.book_id, a.name, a.created_at
+            FROM account a
+                JOIN transaction_entry e ON e.account_id = a.id
+            WHERE e.transaction_id IN ("#,
+            &transactions_data,
+        )
+        .build_query_as()
+        .fetch_all(&**self)
+        .await?;
+
+        let currencies: Vec<models::ledger::Currency> = related_rows(
+            r#"
+            SELECT DISTINCT c.code, c.symbol, c.minor_units
+            FROM currency c
+                JOIN transaction_entry e ON e.currency = c.code
+            WHERE e.transaction_id IN ("#,
+            &transactions_data,
+        )
+        .build_query_as()
+        .fetch_all(&**self)
+        .await?;
+
+        let cursor = if has_next_page {
+            let last_transaction = &transactions_data[transactions_data.len() - 1];
+
+            Some(TransactionCursor {
+                after_date: last_transaction.date,
+                after_created_at: last_transaction.created_at,
+            })
+        } else {
+            None
+        };
+
+        let transactions = models::ledger::TransactionWithEntries::zip_with_entries(
+            transactions_data,
+            entries,
+            currencies,
+            accounts,
+        )?;
+
+        Ok(TransactionCollection {
+            next: cursor,
+            items: transactions,
+        })
+    }
+}
+
+/// Build a query for rows related to a set of transactions, finishing an
+/// `IN (` clause left open at the end of `sql` with the transactions' IDs.
+fn related_rows<'a>(
+    sql: &str,
+    transactions: &[models::ledger::Transaction],
+) -> QueryBuilder<'a, Sqlite> {
+    let mut query_builder = QueryBuilder::new(sql);
+
+    let mut transaction_ids = query_builder.separated(", ");
+    for transaction in transactions {
+        transaction_ids.push_bind(transaction.id);
+    }
+    query_builder.push(")");
+
+    query_builder
+}
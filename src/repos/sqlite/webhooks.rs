@@ -0,0 +1,540 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use sqlx::{types::Json, QueryBuilder, Sqlite};
+use tracing::{debug, info};
+use uuid::Uuid;
+
+use crate::{
+    database::{sqlite_now, SqliteConnection},
+    ledger::domain::webhooks::{
+        AttemptResult, DeliveryAttempt, EventType, NewWebhookEndpoint, NextStep, WebhookDelivery,
+        WebhookEndpoint,
+    },
+    models,
+    repos::webhooks::{WebhookRepo, DELIVERY_LOG_SIZE},
+};
+
+/// An endpoint as stored in SQLite, which keeps its events as a JSON array.
+#[derive(sqlx::FromRow)]
+struct EndpointRow {
+    id: Uuid,
+    book_id: Uuid,
+    url: String,
+    secret: String,
+    events: Json<Vec<String>>,
+    threshold_account: Option<String>,
+    threshold_currency: Option<String>,
+    threshold_amount: Option<i64>,
+    created_at: DateTime<Utc>,
+}
+
+impl From<EndpointRow> for models::ledger::WebhookEndpoint {
+    fn from(row: EndpointRow) -> Self {
+        Self {
+            id: row.id,
+            book_id: row.book_id,
+            url: row.url,
+            secret: row.secret,
+            events: row.events.0,
+            threshold_account: row.threshold_account,
+            threshold_currency: row.threshold_currency,
+            threshold_amount: row.threshold_amount,
+            created_at: row.created_at,
+        }
+    }
+}
+
+/// A due delivery joined with its endpoint and event.
+#[derive(sqlx::FromRow)]
+struct DeliveryRow {
+    id: i64,
+    endpoint_id: Uuid,
+    url: String,
+    secret: String,
+    attempts: i32,
+    event_id: i64,
+    event_type: String,
+    data: Json<serde_json::Value>,
+    event_created_at: DateTime<Utc>,
+}
+
+impl From<DeliveryRow> for models::ledger::WebhookDelivery {
+    fn from(row: DeliveryRow) -> Self {
+        Self {
+            id: row.id,
+            endpoint_id: row.endpoint_id,
+            url: row.url,
+            secret: row.secret,
+            attempts: row.attempts,
+            event_id: row.event_id,
+            event_type: row.event_type,
+            data: row.data.0,
+            event_created_at: row.event_created_at,
+        }
+    }
+}
+
+/// The balance of an account watched by an endpoint, along with the side of
+/// the threshold it was on after the previous change.
+#[derive(sqlx::FromRow)]
+struct ThresholdRow {
+    id: Uuid,
+    account: String,
+    currency: String,
+    threshold: i64,
+    previous: Option<bool>,
+    balance: i64,
+}
+
+#[async_trait]
+impl WebhookRepo for SqliteConnection {
+    async fn claim_due_deliveries(
+        &self,
+        limit: i64,
+        lease_until: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<WebhookDelivery>> {
+        let mut tx = self.begin().await?;
+
+        let due: Vec<DeliveryRow> = sqlx::query_as(
+            r#"
+            SELECT
+                d.id,
+                d.endpoint_id,
+                ep.url,
+                ep.secret,
+                d.attempts,
+                ev.id AS event_id,
+                ev.event_type,
+                ev.data,
+                ev.created_at AS event_created_at
+            FROM webhook_delivery d
+                JOIN webhook_endpoint ep ON ep.id = d.endpoint_id
+                JOIN webhook_event ev ON ev.id = d.event_id
+            WHERE d.delivered_at IS NULL
+                AND d.abandoned_at IS NULL
+                AND d.next_attempt_at <= $2
+            ORDER BY d.next_attempt_at
+            LIMIT $1
+            "#,
+        )
+        .bind(limit)
+        .bind(sqlite_now())
+        .fetch_all(&mut tx)
+        .await?;
+
+        if !due.is_empty() {
+            let mut query_builder: QueryBuilder<'_, Sqlite> =
+                QueryBuilder::new("UPDATE webhook_delivery SET next_attempt_at = ");
+            query_builder.push_bind(lease_until).push(" WHERE id IN (");
+            let mut ids = query_builder.separated(", ");
+            for delivery in &due {
+                ids.push_bind(delivery.id);
+            }
+            query_builder.push(")");
+
+            query_builder.build().execute(&mut tx).await?;
+        }
+
+        tx.commit().await?;
+
+        due.into_iter()
+            .map(|row| models::ledger::WebhookDelivery::from(row).try_into())
+            .collect()
+    }
+
+    async fn create_endpoint(
+        &self,
+        endpoint: &NewWebhookEndpoint,
+    ) -> anyhow::Result<WebhookEndpoint> {
+        let events = endpoint
+            .events()
+            .iter()
+            .map(|event| event.as_str().to_owned())
+            .collect::<Vec<_>>();
+        let threshold = endpoint.balance_threshold();
+
+        // The side of the threshold the balance starts on is recorded so that
+        // only later changes that cross it produce events.
+        let created: EndpointRow = sqlx::query_as(
+            r#"
+            INSERT INTO webhook_endpoint (
+                id,
+                book_id,
+                url,
+                secret,
+                events,
+                threshold_account,
+                threshold_currency,
+                threshold_amount,
+                threshold_above,
+                created_at
+            )
+            VALUES (
+                $1, $2, $3, $4, $5, $6, $7, $8,
+                CASE WHEN $6 IS NULL THEN NULL ELSE (
+                    SELECT COALESCE(SUM(e.amount), 0) >= $8
+                    FROM transaction_entry e
+                        JOIN account a ON a.id = e.account_id
+                        JOIN "transaction" t ON t.id = e.transaction_id
+                    WHERE t.book_id = $2
+                        AND t.deleted_at IS NULL
+                        AND e.currency = $7
+                        AND (a.name = $6 OR substr(a.name, 1, length($6) + 1) = $6 || ':')
+                ) END,
+                $9
+            )
+            RETURNING
+                id,
+                book_id,
+                url,
+                secret,
+                events,
+                threshold_account,
+                threshold_currency,
+                threshold_amount,
+                created_at
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(endpoint.book_id())
+        .bind(endpoint.url())
+        .bind(endpoint.secret())
+        .bind(Json(&events))
+        .bind(threshold.map(|threshold| threshold.account.as_str()))
+        .bind(threshold.map(|threshold| threshold.currency.as_str()))
+        .bind(threshold.map(|threshold| threshold.amount))
+        .bind(sqlite_now())
+        .fetch_one(&**self)
+        .await?;
+
+        info!(endpoint_id = %created.id, book_id = %created.book_id, "Created webhook endpoint.");
+
+        models::ledger::WebhookEndpoint::from(created).try_into()
+    }
+
+    async fn delete_endpoint(&self, book_id: Uuid, endpoint_id: Uuid) -> anyhow::Result<bool> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM webhook_endpoint
+            WHERE id = $1 AND book_id = $2
+            "#,
+        )
+        .bind(endpoint_id)
+        .bind(book_id)
+        .execute(&**self)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_endpoint(
+        &self,
+        book_id: Uuid,
+        endpoint_id: Uuid,
+    ) -> anyhow::Result<Option<WebhookEndpoint>> {
+        sqlx::query_as::<_, EndpointRow>(
+            r#"
+            SELECT
+                id,
+                book_id,
+                url,
+                secret,
+                events,
+                threshold_account,
+                threshold_currency,
+                threshold_amount,
+                created_at
+            FROM webhook_endpoint
+            WHERE id = $1 AND book_id = $2
+            "#,
+        )
+        .bind(endpoint_id)
+        .bind(book_id)
+        .fetch_optional(&**self)
+        .await?
+        .map(|row| models::ledger::WebhookEndpoint::from(row).try_into())
+        .transpose()
+    }
+
+    async fn list_delivery_attempts(
+        &self,
+        book_id: Uuid,
+        endpoint_id: Uuid,
+    ) -> anyhow::Result<Vec<DeliveryAttempt>> {
+        sqlx::query_as::<_, models::ledger::WebhookDeliveryAttempt>(
+            r#"
+            SELECT
+                a.delivery_id,
+                d.event_id,
+                ev.event_type,
+                a.response_status,
+                a.error,
+                a.attempted_at
+            FROM webhook_delivery_attempt a
+                JOIN webhook_delivery d ON d.id = a.delivery_id
+                JOIN webhook_endpoint ep ON ep.id = d.endpoint_id
+                JOIN webhook_event ev ON ev.id = d.event_id
+            WHERE ep.book_id = $1 AND ep.id = $2
+            ORDER BY a.attempted_at DESC, a.id DESC
+            LIMIT $3
+            "#,
+        )
+        .bind(book_id)
+        .bind(endpoint_id)
+        .bind(DELIVERY_LOG_SIZE)
+        .fetch_all(&**self)
+        .await?
+        .drain(..)
+        .map(TryInto::try_into)
+        .collect()
+    }
+
+    async fn list_endpoints(&self, book_id: Uuid) -> anyhow::Result<Vec<WebhookEndpoint>> {
+        sqlx::query_as::<_, EndpointRow>(
+            r#"
+            SELECT
+                id,
+                book_id,
+                url,
+                secret,
+                events,
+                threshold_account,
+                threshold_currency,
+                threshold_amount,
+                created_at
+            FROM webhook_endpoint
+            WHERE book_id = $1
+            ORDER BY created_at
+            "#,
+        )
+        .bind(book_id)
+        .fetch_all(&**self)
+        .await?
+        .drain(..)
+        .map(|row| models::ledger::WebhookEndpoint::from(row).try_into())
+        .collect()
+    }
+
+    async fn record_attempt(
+        &self,
+        delivery_id: i64,
+        result: &AttemptResult,
+        next_step: &NextStep,
+    ) -> anyhow::Result<()> {
+        let now = sqlite_now();
+        let (delivered_at, next_attempt_at, abandoned_at) = match next_step {
+            NextStep::Delivered => (Some(now), None, None),
+            NextStep::RetryAt(retry_at) => (None, Some(*retry_at), None),
+            NextStep::Abandon => (None, None, Some(now)),
+        };
+
+        let mut tx = self.begin().await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO webhook_delivery_attempt (delivery_id, response_status, error, attempted_at)
+            VALUES ($1, $2, $3, $4)
+            "#,
+        )
+        .bind(delivery_id)
+        .bind(result.response_status.map(i16::try_from).transpose()?)
+        .bind(&result.error)
+        .bind(now)
+        .execute(&mut tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+            UPDATE webhook_delivery
+            SET
+                attempts = attempts + 1,
+                delivered_at = $2,
+                next_attempt_at = COALESCE($3, next_attempt_at),
+                abandoned_at = $4
+            WHERE id = $1
+            "#,
+        )
+        .bind(delivery_id)
+        .bind(delivered_at)
+        .bind(next_attempt_at)
+        .bind(abandoned_at)
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+
+        debug!(
+            delivery_id,
+            ?result,
+            ?next_step,
+            "Recorded webhook delivery attempt."
+        );
+
+        Ok(())
+    }
+}
+
+/// Queue an event for every endpoint of a book that subscribes to it.
+///
+/// Like its Postgres counterpart, this is meant to be called within the
+/// database transaction making the change that caused the event.
+pub async fn enqueue_event(
+    conn: &mut sqlx::SqliteConnection,
+    book_id: Uuid,
+    event_type: EventType,
+    data: &serde_json::Value,
+) -> sqlx::Result<()> {
+    let endpoint_ids: Vec<Uuid> = sqlx::query_scalar(
+        r#"
+        SELECT id
+        FROM webhook_endpoint
+        WHERE book_id = $1
+            AND EXISTS (SELECT 1 FROM json_each(events) WHERE value = $2)
+        "#,
+    )
+    .bind(book_id)
+    .bind(event_type.as_str())
+    .fetch_all(&mut *conn)
+    .await?;
+
+    if endpoint_ids.is_empty() {
+        return Ok(());
+    }
+
+    let now = sqlite_now();
+    let event_id = insert_event(conn, book_id, event_type, data, now).await?;
+    for endpoint_id in &endpoint_ids {
+        insert_delivery(conn, event_id, *endpoint_id, now).await?;
+    }
+
+    debug!(%book_id, %event_type, deliveries = endpoint_ids.len(), "Queued webhook event.");
+
+    Ok(())
+}
+
+/// Queue an event for every endpoint whose balance threshold was crossed by
+/// the changes made so far in the current database transaction.
+pub async fn enqueue_threshold_events(
+    conn: &mut sqlx::SqliteConnection,
+    book_id: Uuid,
+) -> sqlx::Result<()> {
+    let endpoints: Vec<ThresholdRow> = sqlx::query_as(
+        r#"
+        SELECT
+            ep.id,
+            ep.threshold_account AS account,
+            ep.threshold_currency AS currency,
+            ep.threshold_amount AS threshold,
+            ep.threshold_above AS previous,
+            (
+                SELECT COALESCE(SUM(e.amount), 0)
+                FROM transaction_entry e
+                    JOIN account a ON a.id = e.account_id
+                    JOIN "transaction" t ON t.id = e.transaction_id
+                WHERE t.book_id = ep.book_id
+                    AND t.deleted_at IS NULL
+                    AND e.currency = ep.threshold_currency
+                    AND (
+                        a.name = ep.threshold_account
+                        OR substr(a.name, 1, length(ep.threshold_account) + 1) = ep.threshold_account || ':'
+                    )
+            ) AS balance
+        FROM webhook_endpoint ep
+        WHERE ep.book_id = $1 AND ep.threshold_account IS NOT NULL
+        "#,
+    )
+    .bind(book_id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    for endpoint in endpoints {
+        let above = endpoint.balance >= endpoint.threshold;
+        if endpoint.previous == Some(above) {
+            continue;
+        }
+
+        sqlx::query(
+            r#"
+            UPDATE webhook_endpoint
+            SET threshold_above = $2
+            WHERE id = $1
+            "#,
+        )
+        .bind(endpoint.id)
+        .bind(above)
+        .execute(&mut *conn)
+        .await?;
+
+        // Endpoints without a recorded side have nothing to compare against.
+        if endpoint.previous.is_none() {
+            continue;
+        }
+
+        let data = json!({
+            "account": endpoint.account,
+            "currency": endpoint.currency,
+            "threshold": endpoint.threshold,
+            "balance": endpoint.balance,
+            "direction": if above { "above" } else { "below" },
+        });
+
+        let now = sqlite_now();
+        let event_id = insert_event(
+            conn,
+            book_id,
+            EventType::BalanceThresholdCrossed,
+            &data,
+            now,
+        )
+        .await?;
+        insert_delivery(conn, event_id, endpoint.id, now).await?;
+
+        debug!(endpoint_id = %endpoint.id, %book_id, "Queued balance threshold event.");
+    }
+
+    Ok(())
+}
+
+async fn insert_event(
+    conn: &mut sqlx::SqliteConnection,
+    book_id: Uuid,
+    event_type: EventType,
+    data: &serde_json::Value,
+    now: DateTime<Utc>,
+) -> sqlx::Result<i64> {
+    sqlx::query_scalar(
+        r#"
+        INSERT INTO webhook_event (book_id, event_type, data, created_at)
+        VALUES ($1, $2, $3, $4)
+        RETURNING id
+        "#,
+    )
+    .bind(book_id)
+    .bind(event_type.as_str())
+    .bind(Json(data))
+    .bind(now)
+    .fetch_one(conn)
+    .await
+}
+
+async fn insert_delivery(
+    conn: &mut sqlx::SqliteConnection,
+    event_id: i64,
+    endpoint_id: Uuid,
+    now: DateTime<Utc>,
+) -> sqlx::Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO webhook_delivery (event_id, endpoint_id, next_attempt_at, created_at)
+        VALUES ($1, $2, $3, $3)
+        "#,
+    )
+    .bind(event_id)
+    .bind(endpoint_id)
+    .bind(now)
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
@@ -2,6 +2,7 @@ use std::sync::Arc;
 
 use async_trait::async_trait;
 use sqlx::{FromRow, Postgres, QueryBuilder};
+use uuid::Uuid;
 
 use crate::{
     database::PostgresConnection, ledger::domain::transactions::TransactionCursor, models,
@@ -10,8 +11,8 @@ use crate::{
 /// Query parameters for listing transactions.
 #[derive(Default)]
 pub struct TransactionQuery {
-    /// The owner of the transactions to search for.
-    pub user_id: String,
+    /// The book containing the transactions to search for.
+    pub book_id: Uuid,
     /// An optional cursor into the transaction list indicating that only
     /// results occurring after the specified position in the list should be
     /// returned.
@@ -19,6 +20,9 @@ pub struct TransactionQuery {
     /// Only list transactions with at least one entry that references the
     /// specified account.
     pub account: Option<String>,
+    /// List transactions that have been moved to the trash instead of active
+    /// transactions.
+    pub trashed: bool,
 }
 
 pub struct TransactionCollection {
@@ -45,7 +49,7 @@ pub trait TransactionRepo {
     ) -> anyhow::Result<TransactionCollection>;
 }
 
-const TRANSACTION_PAGE_SIZE: u8 = 50;
+pub(super) const TRANSACTION_PAGE_SIZE: u8 = 50;
 
 #[async_trait]
 impl TransactionRepo for PostgresConnection {
@@ -77,9 +81,15 @@ impl TransactionRepo for PostgresConnection {
                 r#"
                 SELECT t.*
                 FROM transaction t
-                WHERE t.user_id = "#,
+                WHERE t.book_id = "#,
             )
-            .push_bind(query.user_id);
+            .push_bind(query.book_id);
+
+        if query.trashed {
+            query_builder.push(" AND t.deleted_at IS NOT NULL");
+        } else {
+            query_builder.push(" AND t.deleted_at IS NULL");
+        }
 
         if query.account.is_some() {
             query_builder.push(" AND t.id = ANY(SELECT * FROM account_transaction_ids)");
@@ -137,7 +147,7 @@ impl TransactionRepo for PostgresConnection {
         let accounts = sqlx::query_as!(
             models::ledger::Account,
             r#"
-            SELECT DISTINCT id, user_id, name, created_at
+            SELECT DISTINCT id, book_id, name, created_at
             FROM account a
             WHERE a.id = ANY($1)
             "#,
@@ -0,0 +1,470 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use sqlx::PgConnection;
+use tracing::{debug, info};
+use uuid::Uuid;
+
+use crate::{
+    database::PostgresConnection,
+    ledger::domain::webhooks::{
+        AttemptResult, DeliveryAttempt, EventType, NewWebhookEndpoint, NextStep, WebhookDelivery,
+        WebhookEndpoint,
+    },
+    models,
+};
+
+/// The maximum number of attempts listed in an endpoint's delivery log.
+pub(super) const DELIVERY_LOG_SIZE: i64 = 100;
+
+pub type DynWebhookRepo = Arc<dyn WebhookRepo + Send + Sync>;
+
+#[async_trait]
+pub trait WebhookRepo {
+    /// Claim deliveries that are due to be attempted.
+    ///
+    /// Claimed deliveries are not handed out again until `lease_until`, so
+    /// a delivery whose attempt is never recorded is eventually retried.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - The maximum number of deliveries to claim.
+    /// * `lease_until` - The time until which the deliveries are claimed.
+    async fn claim_due_deliveries(
+        &self,
+        limit: i64,
+        lease_until: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<WebhookDelivery>>;
+
+    /// Register a new endpoint.
+    async fn create_endpoint(
+        &self,
+        endpoint: &NewWebhookEndpoint,
+    ) -> anyhow::Result<WebhookEndpoint>;
+
+    /// Remove an endpoint along with its pending deliveries.
+    ///
+    /// # Returns
+    ///
+    /// A boolean indicating if a matching endpoint was found.
+    async fn delete_endpoint(&self, book_id: Uuid, endpoint_id: Uuid) -> anyhow::Result<bool>;
+
+    async fn get_endpoint(
+        &self,
+        book_id: Uuid,
+        endpoint_id: Uuid,
+    ) -> anyhow::Result<Option<WebhookEndpoint>>;
+
+    /// List the most recent delivery attempts for an endpoint, newest first.
+    async fn list_delivery_attempts(
+        &self,
+        book_id: Uuid,
+        endpoint_id: Uuid,
+    ) -> anyhow::Result<Vec<DeliveryAttempt>>;
+
+    async fn list_endpoints(&self, book_id: Uuid) -> anyhow::Result<Vec<WebhookEndpoint>>;
+
+    /// Record an attempt to deliver an event and schedule what comes next.
+    async fn record_attempt(
+        &self,
+        delivery_id: i64,
+        result: &AttemptResult,
+        next_step: &NextStep,
+    ) -> anyhow::Result<()>;
+}
+
+#[async_trait]
+impl WebhookRepo for PostgresConnection {
+    async fn claim_due_deliveries(
+        &self,
+        limit: i64,
+        lease_until: DateTime<Utc>,
+    ) -> anyhow::Result<Vec<WebhookDelivery>> {
+        sqlx::query_as!(
+            models::ledger::WebhookDelivery,
+            r#"
+            WITH due AS (
+                SELECT id
+                FROM webhook_delivery
+                WHERE delivered_at IS NULL
+                    AND abandoned_at IS NULL
+                    AND next_attempt_at <= now()
+                ORDER BY next_attempt_at
+                LIMIT $1
+                FOR UPDATE SKIP LOCKED
+            ), claimed AS (
+                UPDATE webhook_delivery d
+                SET next_attempt_at = $2
+                FROM due
+                WHERE d.id = due.id
+                RETURNING d.id, d.endpoint_id, d.event_id, d.attempts
+            )
+            SELECT
+                c.id AS "id!",
+                c.endpoint_id AS "endpoint_id!",
+                ep.url,
+                ep.secret,
+                c.attempts AS "attempts!",
+                ev.id AS event_id,
+                ev.event_type,
+                ev.data,
+                ev.created_at AS event_created_at
+            FROM claimed c
+                JOIN webhook_endpoint ep ON ep.id = c.endpoint_id
+                JOIN webhook_event ev ON ev.id = c.event_id
+            "#,
+            limit,
+            lease_until,
+        )
+        .fetch_all(&**self)
+        .await?
+        .drain(..)
+        .map(TryInto::try_into)
+        .collect()
+    }
+
+    async fn create_endpoint(
+        &self,
+        endpoint: &NewWebhookEndpoint,
+    ) -> anyhow::Result<WebhookEndpoint> {
+        let events = endpoint
+            .events()
+            .iter()
+            .map(|event| event.as_str().to_owned())
+            .collect::<Vec<_>>();
+        let threshold = endpoint.balance_threshold();
+
+        // The side of the threshold the balance starts on is recorded so that
+        // only later changes that cross it produce events.
+        let created = sqlx::query_as!(
+            models::ledger::WebhookEndpoint,
+            r#"
+            INSERT INTO webhook_endpoint (
+                book_id,
+                url,
+                secret,
+                events,
+                threshold_account,
+                threshold_currency,
+                threshold_amount,
+                threshold_above
+            )
+            VALUES (
+                $1, $2, $3, $4, $5, $6, $7,
+                CASE WHEN $5::text IS NULL THEN NULL ELSE (
+                    SELECT COALESCE(SUM(e.amount), 0) >= $7
+                    FROM transaction_entry e
+                        JOIN account a ON a.id = e.account_id
+                        JOIN transaction t ON t.id = e.transaction_id
+                    WHERE t.book_id = $1
+                        AND t.deleted_at IS NULL
+                        AND e.currency = $6
+                        AND (a.name = $5 OR a.name LIKE $5 || ':%')
+                ) END
+            )
+            RETURNING
+                id,
+                book_id,
+                url,
+                secret,
+                events,
+                threshold_account,
+                threshold_currency,
+                threshold_amount,
+                created_at
+            "#,
+            endpoint.book_id(),
+            endpoint.url(),
+            endpoint.secret(),
+            &events,
+            threshold.map(|threshold| threshold.account.as_str()),
+            threshold.map(|threshold| threshold.currency.as_str()),
+            threshold.map(|threshold| threshold.amount),
+        )
+        .fetch_one(&**self)
+        .await?;
+
+        info!(endpoint_id = %created.id, book_id = %created.book_id, "Created webhook endpoint.");
+
+        created.try_into()
+    }
+
+    async fn delete_endpoint(&self, book_id: Uuid, endpoint_id: Uuid) -> anyhow::Result<bool> {
+        let result = sqlx::query!(
+            r#"
+            DELETE FROM webhook_endpoint
+            WHERE id = $1 AND book_id = $2
+            "#,
+            endpoint_id,
+            book_id,
+        )
+        .execute(&**self)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_endpoint(
+        &self,
+        book_id: Uuid,
+        endpoint_id: Uuid,
+    ) -> anyhow::Result<Option<WebhookEndpoint>> {
+        sqlx::query_as!(
+            models::ledger::WebhookEndpoint,
+            r#"
+            SELECT
+                id,
+                book_id,
+                url,
+                secret,
+                events,
+                threshold_account,
+                threshold_currency,
+                threshold_amount,
+                created_at
+            FROM webhook_endpoint
+            WHERE id = $1 AND book_id = $2
+            "#,
+            endpoint_id,
+            book_id,
+        )
+        .fetch_optional(&**self)
+        .await?
+        .map(TryInto::try_into)
+        .transpose()
+    }
+
+    async fn list_delivery_attempts(
+        &self,
+        book_id: Uuid,
+        endpoint_id: Uuid,
+    ) -> anyhow::Result<Vec<DeliveryAttempt>> {
+        sqlx::query_as!(
+            models::ledger::WebhookDeliveryAttempt,
+            r#"
+            SELECT
+                a.delivery_id,
+                d.event_id,
+                ev.event_type,
+                a.response_status,
+                a.error,
+                a.attempted_at
+            FROM webhook_delivery_attempt a
+                JOIN webhook_delivery d ON d.id = a.delivery_id
+                JOIN webhook_endpoint ep ON ep.id = d.endpoint_id
+                JOIN webhook_event ev ON ev.id = d.event_id
+            WHERE ep.book_id = $1 AND ep.id = $2
+            ORDER BY a.attempted_at DESC, a.id DESC
+            LIMIT $3
+            "#,
+            book_id,
+            endpoint_id,
+            DELIVERY_LOG_SIZE,
+        )
+        .fetch_all(&**self)
+        .await?
+        .drain(..)
+        .map(TryInto::try_into)
+        .collect()
+    }
+
+    async fn list_endpoints(&self, book_id: Uuid) -> anyhow::Result<Vec<WebhookEndpoint>> {
+        sqlx::query_as!(
+            models::ledger::WebhookEndpoint,
+            r#"
+            SELECT
+                id,
+                book_id,
+                url,
+                secret,
+                events,
+                threshold_account,
+                threshold_currency,
+                threshold_amount,
+                created_at
+            FROM webhook_endpoint
+            WHERE book_id = $1
+            ORDER BY created_at
+            "#,
+            book_id,
+        )
+        .fetch_all(&**self)
+        .await?
+        .drain(..)
+        .map(TryInto::try_into)
+        .collect()
+    }
+
+    async fn record_attempt(
+        &self,
+        delivery_id: i64,
+        result: &AttemptResult,
+        next_step: &NextStep,
+    ) -> anyhow::Result<()> {
+        let now = Utc::now();
+        let (delivered_at, next_attempt_at, abandoned_at) = match next_step {
+            NextStep::Delivered => (Some(now), None, None),
+            NextStep::RetryAt(retry_at) => (None, Some(*retry_at), None),
+            NextStep::Abandon => (None, None, Some(now)),
+        };
+
+        sqlx::query!(
+            r#"
+            WITH attempt AS (
+                INSERT INTO webhook_delivery_attempt (delivery_id, response_status, error)
+                VALUES ($1, $2, $3)
+            )
+            UPDATE webhook_delivery
+            SET
+                attempts = attempts + 1,
+                delivered_at = $4,
+                next_attempt_at = COALESCE($5, next_attempt_at),
+                abandoned_at = $6
+            WHERE id = $1
+            "#,
+            delivery_id,
+            result.response_status.map(i16::try_from).transpose()?,
+            result.error,
+            delivered_at,
+            next_attempt_at,
+            abandoned_at,
+        )
+        .execute(&**self)
+        .await?;
+
+        debug!(
+            delivery_id,
+            ?result,
+            ?next_step,
+            "Recorded webhook delivery attempt."
+        );
+
+        Ok(())
+    }
+}
+
+/// Queue an event for every endpoint of a book that subscribes to it.
+///
+/// This is meant to be called within the database transaction making the
+/// change that caused the event, so that the event is only sent if the change
+/// is committed.
+pub async fn enqueue_event(
+    conn: &mut PgConnection,
+    book_id: Uuid,
+    event_type: EventType,
+    data: &serde_json::Value,
+) -> sqlx::Result<()> {
+    let queued = sqlx::query!(
+        r#"
+        WITH endpoints AS (
+            SELECT id
+            FROM webhook_endpoint
+            WHERE book_id = $1 AND $2 = ANY(events)
+        ), event AS (
+            INSERT INTO webhook_event (book_id, event_type, data)
+            SELECT $1, $2, $3
+            WHERE EXISTS (SELECT 1 FROM endpoints)
+            RETURNING id
+        )
+        INSERT INTO webhook_delivery (event_id, endpoint_id)
+        SELECT event.id, endpoints.id
+        FROM event, endpoints
+        "#,
+        book_id,
+        event_type.as_str(),
+        data,
+    )
+    .execute(conn)
+    .await?;
+
+    if queued.rows_affected() > 0 {
+        debug!(%book_id, %event_type, deliveries = queued.rows_affected(), "Queued webhook event.");
+    }
+
+    Ok(())
+}
+
+/// Queue an event for every endpoint whose balance threshold was crossed by
+/// the changes made so far in the current database transaction.
+pub async fn enqueue_threshold_events(conn: &mut PgConnection, book_id: Uuid) -> sqlx::Result<()> {
+    let crossed = sqlx::query!(
+        r#"
+        WITH balances AS (
+            SELECT
+                ep.id,
+                ep.threshold_above AS previous,
+                (
+                    SELECT COALESCE(SUM(e.amount), 0)
+                    FROM transaction_entry e
+                        JOIN account a ON a.id = e.account_id
+                        JOIN transaction t ON t.id = e.transaction_id
+                    WHERE t.book_id = ep.book_id
+                        AND t.deleted_at IS NULL
+                        AND e.currency = ep.threshold_currency
+                        AND (
+                            a.name = ep.threshold_account
+                            OR a.name LIKE ep.threshold_account || ':%'
+                        )
+                ) AS balance
+            FROM webhook_endpoint ep
+            WHERE ep.book_id = $1 AND ep.threshold_account IS NOT NULL
+            FOR UPDATE OF ep
+        )
+        UPDATE webhook_endpoint ep
+        SET threshold_above = b.balance >= ep.threshold_amount
+        FROM balances b
+        WHERE ep.id = b.id
+            AND ep.threshold_above IS DISTINCT FROM (b.balance >= ep.threshold_amount)
+        RETURNING
+            ep.id,
+            ep.threshold_account AS "account!",
+            ep.threshold_currency AS "currency!",
+            ep.threshold_amount AS "threshold!",
+            b.balance AS "balance!",
+            b.previous
+        "#,
+        book_id,
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    for endpoint in crossed {
+        // Endpoints without a recorded side have nothing to compare against.
+        if endpoint.previous.is_none() {
+            continue;
+        }
+
+        let data = json!({
+            "account": endpoint.account,
+            "currency": endpoint.currency,
+            "threshold": endpoint.threshold,
+            "balance": endpoint.balance,
+            "direction": if endpoint.balance >= endpoint.threshold { "above" } else { "below" },
+        });
+
+        sqlx::query!(
+            r#"
+            WITH event AS (
+                INSERT INTO webhook_event (book_id, event_type, data)
+                VALUES ($1, $2, $3)
+                RETURNING id
+            )
+            INSERT INTO webhook_delivery (event_id, endpoint_id)
+            SELECT id, $4
+            FROM event
+            "#,
+            book_id,
+            EventType::BalanceThresholdCrossed.as_str(),
+            data,
+            endpoint.id,
+        )
+        .execute(&mut *conn)
+        .await?;
+
+        debug!(endpoint_id = %endpoint.id, %book_id, "Queued balance threshold event.");
+    }
+
+    Ok(())
+}
@@ -1,76 +1,455 @@
-use std::{sync::Arc, time::Duration};
+use std::{
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
 
-use axum::{extract::FromRef, Router};
-use sqlx::postgres::PgPoolOptions;
+use anyhow::Context;
+use axum::{extract::FromRef, middleware, routing::get, Json, Router};
+use tracing::info;
+use utoipa::OpenApi;
 
 use crate::{
-    database::PostgresConnection,
+    authentication::keys::{KeyStore, KeyStoreOptions},
+    database::Database,
     ledger::{
-        queries::{postgres::PostgresQueries, DynAccountQueries},
-        services::LedgerService,
+        commands::{postgres::PostgresCommands, sqlite::SqliteCommands, DynTransactionCommands},
+        domain::attachments::AttachmentLimits,
+        domain::currency::Currency,
+        http::graphql::{self, LedgerSchema},
+        memory::MemoryLedger,
+        models,
+        notifications::ChangeNotifier,
+        queries::{
+            postgres::PostgresQueries, sqlite::SqliteQueries, DynAccountQueries,
+            DynTransactionQueries,
+        },
+        services::{
+            ApiTokenService, AttachmentService, BookService, IdempotencyService, LedgerService,
+            WebhookService,
+        },
+        webhooks::WebhookSender,
+    },
+    monitoring::{self, MetricsState},
+    rate_limit::{
+        self, DynRateLimitStore, Limits, MemoryRateLimitStore, RateLimitBackend, RateLimitState,
+        RateLimitStore, RateLimiter,
+    },
+    repos::{
+        api_tokens::{ApiTokenRepo, DynApiTokenRepo},
+        attachments::{AttachmentRepo, DynAttachmentRepo},
+        books::{BookRepo, DynBookRepo},
+        idempotency::{DynIdempotencyRepo, IdempotencyRepo},
+        locks::{DynLedgerLockRepo, LedgerLockRepo},
+        transactions::DynTransactionRepo,
+        webhooks::{DynWebhookRepo, WebhookRepo},
     },
-    repos::transactions::DynTransactionRepo,
+    shutdown::{self, ShutdownSignal},
+    storage::{DynBlobStorage, LocalFileStorage},
 };
 
 pub struct Options {
+    pub attachment_max_size: u64,
+    pub attachment_quota: u64,
+    pub attachment_storage_path: PathBuf,
+
+    pub bind_address: IpAddr,
+    pub port: u16,
+
     pub database_pool_size: u32,
     pub database_timeout_seconds: u8,
-    pub database_url: String,
+    /// The application database's URL, which isn't used when the ledger is
+    /// kept in memory.
+    pub database_url: Option<String>,
+
+    pub idempotency_key_ttl_hours: u32,
+
+    pub jwt_keys: KeyStoreOptions,
+
+    pub ledger_backend: LedgerBackend,
+
+    pub metrics_port: Option<u16>,
+
+    pub rate_limit_backend: Option<RateLimitBackend>,
+    pub rate_limits: Limits,
+
+    pub trash_retention_days: u32,
+
+    pub webhook_allow_private_addresses: bool,
+    pub webhook_timeout_seconds: u8,
+}
 
-    pub jwt_audience: String,
-    pub jwt_authority: String,
+/// Where the ledger's transactions are kept.
+#[derive(Clone, Copy, Debug)]
+pub enum LedgerBackend {
+    /// Transactions are kept in the memory of the server instance, and lost
+    /// when it stops. Books, tokens and the rest of the application's state
+    /// are kept in a private in-memory database, so no database is needed.
+    Memory,
+    /// Transactions are kept in the application database, whether that is
+    /// Postgres or SQLite.
+    Database,
 }
 
 #[derive(Clone)]
 pub struct AppState {
-    db: PostgresConnection,
-    jwks: axum_jwks::Jwks,
+    api_token_service: ApiTokenService,
+    attachment_service: AttachmentService,
+    book_service: BookService,
+    db: Database,
+    graphql_schema: LedgerSchema,
+    idempotency_service: IdempotencyService,
+    key_store: KeyStore,
     ledger_service: LedgerService,
+    notifier: ChangeNotifier,
+    shutdown: ShutdownSignal,
+    webhook_service: WebhookService,
+}
+
+/// The repositories and stores kept in the application database, or in the
+/// private in-memory database when the ledger is kept in memory.
+struct Repos {
+    api_token_repo: DynApiTokenRepo,
+    attachment_repo: DynAttachmentRepo,
+    book_repo: DynBookRepo,
+    idempotency_repo: DynIdempotencyRepo,
+    lock_repo: DynLedgerLockRepo,
+    rate_limit_store: DynRateLimitStore,
+    webhook_repo: DynWebhookRepo,
+}
+
+impl Repos {
+    fn new<D>(db: D) -> Self
+    where
+        D: ApiTokenRepo
+            + AttachmentRepo
+            + BookRepo
+            + IdempotencyRepo
+            + LedgerLockRepo
+            + RateLimitStore
+            + WebhookRepo
+            + Clone
+            + Send
+            + Sync
+            + 'static,
+    {
+        Self {
+            api_token_repo: Arc::new(db.clone()),
+            attachment_repo: Arc::new(db.clone()),
+            book_repo: Arc::new(db.clone()),
+            idempotency_repo: Arc::new(db.clone()),
+            lock_repo: Arc::new(db.clone()),
+            rate_limit_store: Arc::new(db.clone()),
+            webhook_repo: Arc::new(db),
+        }
+    }
 }
 
 pub async fn serve(opts: Options) -> anyhow::Result<()> {
-    let db_pool = PgPoolOptions::new()
-        .max_connections(opts.database_pool_size)
-        .acquire_timeout(Duration::from_secs(opts.database_timeout_seconds.into()))
-        .connect(&opts.database_url)
-        .await?;
+    let metrics_handle = monitoring::install_recorder()?;
+
+    let acquire_timeout = Duration::from_secs(opts.database_timeout_seconds.into());
+    let db = match opts.ledger_backend {
+        LedgerBackend::Memory => Database::Sqlite(Database::in_memory(acquire_timeout).await?),
+        LedgerBackend::Database => {
+            let url = opts
+                .database_url
+                .as_deref()
+                .context("A database URL is required unless the ledger is kept in memory.")?;
+
+            Database::connect(url, opts.database_pool_size, acquire_timeout).await?
+        }
+    };
+
+    let key_store = KeyStore::new(opts.jwt_keys).await?;
 
-    let jwks = axum_jwks::Jwks::from_authority(&opts.jwt_authority, opts.jwt_audience).await?;
+    let repos = match &db {
+        Database::Postgres(db) => Repos::new(db.clone()),
+        Database::Sqlite(db) => {
+            if let LedgerBackend::Database = opts.ledger_backend {
+                info!("Using SQLite. Only a single server instance should use the database.");
+            }
 
-    let db_connection = PostgresConnection::new(db_pool.clone());
+            Repos::new(db.clone())
+        }
+    };
 
-    let account_queries: DynAccountQueries = Arc::new(PostgresQueries(db_connection.clone()));
-    let transaction_repo: DynTransactionRepo = Arc::new(db_connection.clone());
+    let notifier = ChangeNotifier::new();
+
+    let (account_queries, lock_repo, transaction_commands, transaction_queries, transaction_repo): (
+        DynAccountQueries,
+        DynLedgerLockRepo,
+        DynTransactionCommands,
+        DynTransactionQueries,
+        DynTransactionRepo,
+    ) = match (opts.ledger_backend, &db) {
+        (LedgerBackend::Memory, _) => {
+            let currencies = models::Currency::list(&db)
+                .await?
+                .iter()
+                .map(Currency::try_from)
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            // The ledger keeps lock dates itself, so that moving one can't
+            // slip between the check and a write.
+            let ledger = Arc::new(MemoryLedger::new(currencies, Some(notifier.clone())));
+
+            info!(
+                "Keeping the ledger in memory. Everything is lost when the server stops, and \
+                 attachments and webhooks aren't available."
+            );
+
+            (
+                ledger.clone(),
+                ledger.clone(),
+                ledger.clone(),
+                ledger.clone(),
+                ledger,
+            )
+        }
+        (LedgerBackend::Database, Database::Postgres(db)) => (
+            Arc::new(PostgresQueries(db.clone())),
+            repos.lock_repo,
+            Arc::new(PostgresCommands(db.clone())),
+            Arc::new(PostgresQueries(db.clone())),
+            Arc::new(db.clone()),
+        ),
+        (LedgerBackend::Database, Database::Sqlite(db)) => (
+            Arc::new(SqliteQueries(db.clone())),
+            repos.lock_repo,
+            Arc::new(SqliteCommands::new(db.clone(), Some(notifier.clone()))),
+            Arc::new(SqliteQueries(db.clone())),
+            Arc::new(db.clone()),
+        ),
+    };
 
     let ledger_service = LedgerService {
         account_queries,
+        lock_repo,
+        transaction_commands,
+        transaction_queries,
         transaction_repo,
     };
 
+    let attachment_repo = repos.attachment_repo;
+    let blob_storage: DynBlobStorage =
+        Arc::new(LocalFileStorage::new(opts.attachment_storage_path));
+
+    let attachment_service = AttachmentService {
+        attachment_repo,
+        blob_storage,
+        limits: AttachmentLimits {
+            max_size: opts.attachment_max_size,
+            book_quota: opts.attachment_quota,
+        },
+    };
+
+    let api_token_repo = repos.api_token_repo;
+    let api_token_service = ApiTokenService { api_token_repo };
+
+    let book_repo = repos.book_repo;
+    let book_service = BookService { book_repo };
+
+    let idempotency_repo = repos.idempotency_repo;
+    let idempotency_service = IdempotencyService {
+        idempotency_repo,
+        ttl: chrono::Duration::hours(opts.idempotency_key_ttl_hours.into()),
+    };
+
+    let webhook_repo = repos.webhook_repo;
+    let webhook_service = WebhookService {
+        webhook_repo,
+        sender: WebhookSender::new(
+            Duration::from_secs(opts.webhook_timeout_seconds.into()),
+            opts.webhook_allow_private_addresses,
+        )?,
+    };
+
+    let (shutdown_trigger, shutdown) = shutdown::channel();
+
+    let mut background_tasks = vec![
+        tokio::spawn(key_store.clone().refresh_periodically(shutdown.clone())),
+        tokio::spawn(crate::ledger::jobs::deliver_webhooks(
+            webhook_service.clone(),
+            shutdown.clone(),
+        )),
+        tokio::spawn(crate::ledger::jobs::purge_expired_idempotency_keys(
+            idempotency_service.clone(),
+            shutdown.clone(),
+        )),
+        tokio::spawn(crate::ledger::jobs::purge_expired_trash(
+            ledger_service.transaction_commands.clone(),
+            attachment_service.clone(),
+            chrono::Duration::days(opts.trash_retention_days.into()),
+            shutdown.clone(),
+        )),
+    ];
+
+    // Changes made through SQLite are announced by the instance making them,
+    // so there is nothing to listen for.
+    if let Database::Postgres(db) = &db {
+        background_tasks.push(tokio::spawn(
+            notifier.clone().listen((**db).clone(), shutdown.clone()),
+        ));
+    }
+
+    let rate_limit_store = repos.rate_limit_store;
+    let rate_limit_state = opts.rate_limit_backend.map(|backend| {
+        let store: DynRateLimitStore = match backend {
+            RateLimitBackend::Memory => Arc::new(MemoryRateLimitStore::new()),
+            RateLimitBackend::Database => rate_limit_store,
+        };
+
+        RateLimitState {
+            api_token_service: api_token_service.clone(),
+            key_store: key_store.clone(),
+            limiter: RateLimiter {
+                limits: opts.rate_limits,
+                store,
+            },
+        }
+    });
+
     let state = AppState {
-        db: db_connection,
-        jwks,
+        api_token_service,
+        attachment_service,
+        book_service,
+        db: db.clone(),
+        graphql_schema: graphql::build_schema(),
+        idempotency_service,
+        key_store,
         ledger_service,
+        notifier,
+        shutdown: shutdown.clone(),
+        webhook_service,
+    };
+
+    let metrics_routes = monitoring::routes(MetricsState {
+        handle: metrics_handle,
+        db: db.clone(),
+        pool_max_connections: match db {
+            Database::Postgres(_) => opts.database_pool_size,
+            Database::Sqlite(_) => 1,
+        },
+    });
+
+    let ledger_routes = match opts.ledger_backend {
+        LedgerBackend::Memory => crate::ledger::http::routes(),
+        LedgerBackend::Database => {
+            crate::ledger::http::routes().merge(crate::ledger::http::database_routes())
+        }
     };
 
     let app = Router::new()
-        .nest("/ledger", crate::ledger::http::routes())
+        .nest("/ledger", ledger_routes.clone())
+        .nest("/books/:book_id/ledger", ledger_routes)
+        .merge(graphql::routes())
+        .nest("/books/:book_id", graphql::routes())
+        .merge(crate::ledger::http::book_routes())
+        .merge(crate::ledger::http::token_routes())
+        .route("/openapi.json", get(get_openapi_document))
+        .merge(crate::health::routes())
         .with_state(state);
 
-    axum::Server::bind(&"0.0.0.0:8000".parse().unwrap())
+    let app = match rate_limit_state {
+        Some(rate_limit_state) => app.layer(middleware::from_fn_with_state(
+            rate_limit_state,
+            rate_limit::limit_requests,
+        )),
+        None => app,
+    };
+
+    // Metrics are served with the rest of the application unless they have a
+    // port of their own, which lets them be kept off the public network.
+    let (app, metrics_server) = match opts.metrics_port {
+        Some(port) => {
+            let address = SocketAddr::new(opts.bind_address, port);
+            info!(%address, "Serving metrics.");
+
+            let server = axum::Server::try_bind(&address)?
+                .serve(metrics_routes.into_make_service())
+                .with_graceful_shutdown(shutdown.clone().into_wait());
+
+            (app, Some(tokio::spawn(server)))
+        }
+        None => (app.merge(metrics_routes), None),
+    };
+    let app = crate::telemetry::trace_requests(
+        app.layer(middleware::from_fn(monitoring::track_requests)),
+    );
+
+    let address = SocketAddr::new(opts.bind_address, opts.port);
+    info!(%address, "Listening for requests.");
+
+    axum::Server::try_bind(&address)?
         .serve(app.into_make_service())
+        .with_graceful_shutdown(async move {
+            shutdown::terminate_requested().await;
+            info!("Shutting down after in-flight requests finish.");
+
+            shutdown_trigger.trigger();
+        })
         .await?;
 
+    if let Some(metrics_server) = metrics_server {
+        metrics_server.await??;
+    }
+
+    // Jobs finish the work they are doing before they stop, so they have to
+    // be waited for before their database connections are closed.
+    for task in background_tasks {
+        task.await?;
+    }
+
+    db.close().await;
+    info!("Shut down.");
+
     Ok(())
 }
 
-impl FromRef<AppState> for axum_jwks::Jwks {
+/// Serve the OpenAPI description of the API. This doesn't require
+/// authentication so that SDK generators can fetch it.
+async fn get_openapi_document() -> Json<utoipa::openapi::OpenApi> {
+    Json(crate::ledger::http::ApiDoc::openapi())
+}
+
+impl FromRef<AppState> for ApiTokenService {
+    fn from_ref(state: &AppState) -> Self {
+        state.api_token_service.clone()
+    }
+}
+
+impl FromRef<AppState> for AttachmentService {
+    fn from_ref(state: &AppState) -> Self {
+        state.attachment_service.clone()
+    }
+}
+
+impl FromRef<AppState> for BookService {
+    fn from_ref(state: &AppState) -> Self {
+        state.book_service.clone()
+    }
+}
+
+impl FromRef<AppState> for LedgerSchema {
+    fn from_ref(state: &AppState) -> Self {
+        state.graphql_schema.clone()
+    }
+}
+
+impl FromRef<AppState> for IdempotencyService {
+    fn from_ref(state: &AppState) -> Self {
+        state.idempotency_service.clone()
+    }
+}
+
+impl FromRef<AppState> for KeyStore {
     fn from_ref(state: &AppState) -> Self {
-        state.jwks.clone()
+        state.key_store.clone()
     }
 }
 
-impl FromRef<AppState> for PostgresConnection {
+impl FromRef<AppState> for Database {
     fn from_ref(state: &AppState) -> Self {
         state.db.clone()
     }
@@ -81,3 +460,21 @@ impl FromRef<AppState> for LedgerService {
         state.ledger_service.clone()
     }
 }
+
+impl FromRef<AppState> for ChangeNotifier {
+    fn from_ref(state: &AppState) -> Self {
+        state.notifier.clone()
+    }
+}
+
+impl FromRef<AppState> for ShutdownSignal {
+    fn from_ref(state: &AppState) -> Self {
+        state.shutdown.clone()
+    }
+}
+
+impl FromRef<AppState> for WebhookService {
+    fn from_ref(state: &AppState) -> Self {
+        state.webhook_service.clone()
+    }
+}
@@ -0,0 +1,101 @@
+//! Coordinating a graceful shutdown of the application.
+//!
+//! Background jobs and long lived responses like event streams hold on to a
+//! [`ShutdownSignal`] and stop once the [`ShutdownTrigger`] is pulled, so the
+//! server can finish in-flight requests and close its database connections
+//! cleanly.
+
+use tokio::sync::watch;
+
+/// Starts the shutdown of every task holding a matching [`ShutdownSignal`].
+pub struct ShutdownTrigger(watch::Sender<()>);
+
+impl ShutdownTrigger {
+    pub fn trigger(self) {
+        // Receivers are woken up when the sender is dropped.
+        drop(self.0);
+    }
+}
+
+/// Resolves once the application starts shutting down.
+#[derive(Clone)]
+pub struct ShutdownSignal(watch::Receiver<()>);
+
+impl ShutdownSignal {
+    /// Wait until shutdown has been triggered. This returns immediately if it
+    /// already has been.
+    pub async fn wait(&mut self) {
+        while self.0.changed().await.is_ok() {}
+    }
+
+    /// Like [`ShutdownSignal::wait`], but for use where the future must own
+    /// the signal, such as with `StreamExt::take_until`.
+    pub async fn into_wait(mut self) {
+        self.wait().await
+    }
+}
+
+/// Create a trigger and the signal it fires.
+pub fn channel() -> (ShutdownTrigger, ShutdownSignal) {
+    let (sender, receiver) = watch::channel(());
+
+    (ShutdownTrigger(sender), ShutdownSignal(receiver))
+}
+
+/// Wait for the process to be asked to stop, either with `SIGTERM`, which is
+/// what container orchestrators send, or with `Ctrl+C`.
+pub async fn terminate_requested() {
+    let ctrl_c = async {
+        if let Err(error) = tokio::signal::ctrl_c().await {
+            tracing::error!(?error, "Failed to listen for Ctrl+C.");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut stream) => {
+                stream.recv().await;
+            }
+            Err(error) => {
+                tracing::error!(?error, "Failed to listen for SIGTERM.");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn every_signal_resolves_after_trigger() {
+        let (trigger, mut signal) = channel();
+        let other = signal.clone();
+
+        let pending = tokio::time::timeout(Duration::from_millis(10), signal.wait()).await;
+        assert!(pending.is_err(), "signal resolved before trigger");
+
+        trigger.trigger();
+
+        signal.wait().await;
+        other.into_wait().await;
+
+        // Waiting again after shutdown returns immediately.
+        signal.wait().await;
+    }
+}
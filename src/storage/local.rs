@@ -0,0 +1,142 @@
+use std::{io::ErrorKind, path::PathBuf};
+
+use anyhow::{bail, Context};
+use async_trait::async_trait;
+use axum::body::Bytes;
+use tracing::{debug, trace};
+
+use super::BlobStorage;
+
+/// Blob storage backed by a directory on the local filesystem.
+///
+/// Each blob is stored as a single file named after its key.
+pub struct LocalFileStorage {
+    root: PathBuf,
+}
+
+impl LocalFileStorage {
+    /// Create a new storage rooted at the given directory.
+    ///
+    /// The directory is created when the first blob is stored if it does not
+    /// already exist.
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Get the path of the file that holds the blob with the given key.
+    ///
+    /// Keys are restricted to a conservative set of characters so that they
+    /// can never be used to escape the storage directory.
+    fn blob_path(&self, key: &str) -> anyhow::Result<PathBuf> {
+        let is_valid = !key.is_empty()
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+        if !is_valid {
+            bail!("invalid blob key: {:?}", key);
+        }
+
+        Ok(self.root.join(key))
+    }
+}
+
+#[async_trait]
+impl BlobStorage for LocalFileStorage {
+    async fn put(&self, key: &str, content: Bytes) -> anyhow::Result<()> {
+        let path = self.blob_path(key)?;
+
+        tokio::fs::create_dir_all(&self.root)
+            .await
+            .with_context(|| format!("Failed to create storage directory {:?}.", self.root))?;
+        tokio::fs::write(&path, content)
+            .await
+            .with_context(|| format!("Failed to write blob to {:?}.", path))?;
+
+        debug!(?path, "Stored blob.");
+
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Bytes>> {
+        let path = self.blob_path(key)?;
+
+        match tokio::fs::read(&path).await {
+            Ok(content) => Ok(Some(content.into())),
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                trace!(?path, "Blob does not exist.");
+
+                Ok(None)
+            }
+            Err(error) => Err(error).with_context(|| format!("Failed to read blob {:?}.", path)),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> anyhow::Result<()> {
+        let path = self.blob_path(key)?;
+
+        match tokio::fs::remove_file(&path).await {
+            Ok(()) => {
+                debug!(?path, "Deleted blob.");
+
+                Ok(())
+            }
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(()),
+            Err(error) => Err(error).with_context(|| format!("Failed to delete blob {:?}.", path)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn temp_storage() -> LocalFileStorage {
+        LocalFileStorage::new(std::env::temp_dir().join(format!("blobs-{}", Uuid::new_v4())))
+    }
+
+    #[tokio::test]
+    async fn put_get_delete_round_trip() {
+        let storage = temp_storage();
+        let key = Uuid::new_v4().to_string();
+        let content = Bytes::from_static(b"%PDF-1.7 receipt");
+
+        storage
+            .put(&key, content.clone())
+            .await
+            .expect("failed to store blob");
+
+        let stored = storage.get(&key).await.expect("failed to read blob");
+        assert_eq!(Some(content), stored);
+
+        storage.delete(&key).await.expect("failed to delete blob");
+
+        let deleted = storage.get(&key).await.expect("failed to read blob");
+        assert_eq!(None, deleted);
+    }
+
+    #[tokio::test]
+    async fn delete_missing_blob() {
+        let storage = temp_storage();
+
+        storage
+            .delete("does-not-exist")
+            .await
+            .expect("deleting a missing blob should succeed");
+    }
+
+    #[test]
+    fn blob_path_rejects_traversal() {
+        let storage = temp_storage();
+
+        for key in ["", "../secret", "a/b", "..", "key with spaces"] {
+            assert!(
+                storage.blob_path(key).is_err(),
+                "Expected key {:?} to be rejected",
+                key
+            );
+        }
+    }
+}
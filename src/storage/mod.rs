@@ -0,0 +1,43 @@
+//! Storage for binary objects such as transaction attachments.
+//!
+//! Blobs are addressed by an opaque key chosen by the caller. The storage
+//! backend is responsible only for the content; any metadata about the blob is
+//! kept by the caller.
+
+pub mod local;
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use axum::body::Bytes;
+
+pub use local::LocalFileStorage;
+
+#[async_trait]
+pub trait BlobStorage {
+    /// Store a blob, replacing any existing blob with the same key.
+    ///
+    /// # Arguments
+    /// * `key` - The key to store the blob under.
+    /// * `content` - The content of the blob.
+    async fn put(&self, key: &str, content: Bytes) -> anyhow::Result<()>;
+
+    /// Retrieve a blob.
+    ///
+    /// # Arguments
+    /// * `key` - The key of the blob to retrieve.
+    ///
+    /// # Returns
+    /// The blob's content, or `None` if no blob exists with the given key.
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Bytes>>;
+
+    /// Delete a blob.
+    ///
+    /// Attempting to delete a blob that does not exist is not an error.
+    ///
+    /// # Arguments
+    /// * `key` - The key of the blob to delete.
+    async fn delete(&self, key: &str) -> anyhow::Result<()>;
+}
+
+pub type DynBlobStorage = Arc<dyn BlobStorage + Send + Sync>;
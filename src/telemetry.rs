@@ -0,0 +1,192 @@
+//! Request IDs and tracing spans for following a request through the
+//! application.
+//!
+//! Every request is given an ID, taken from the `X-Request-Id` header if the
+//! client or a proxy provided one. The ID is echoed in the response headers
+//! and in error bodies, and recorded on a span covering the request. When
+//! OpenTelemetry export is enabled, the span continues the trace described by
+//! an incoming `traceparent` header.
+
+use axum::{
+    body::Body,
+    http::{HeaderMap, Request, Response},
+    middleware::{self, Next},
+    Router,
+};
+use opentelemetry::{
+    propagation::Extractor,
+    sdk::{trace, Resource},
+    KeyValue,
+};
+use opentelemetry_otlp::WithExportConfig;
+use tower_http::{
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, RequestId, SetRequestIdLayer},
+    trace::TraceLayer,
+};
+use tracing::{field::Empty, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use crate::monitoring;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// The ID of the request currently being handled, if any.
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+/// Record the ID of the authenticated user on the current request's span.
+pub fn record_user_id(user_id: &str) {
+    Span::current().record("user_id", user_id);
+}
+
+/// Wrap an application so each of its requests has an ID and a span.
+pub fn trace_requests(app: Router) -> Router {
+    // Layers added last run first, so requests are given an ID before their
+    // span is created.
+    app.layer(middleware::from_fn(scope_request_id))
+        .layer(
+            TraceLayer::new_for_http()
+                .make_span_with(make_request_span)
+                .on_response(record_response),
+        )
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+}
+
+/// Create an OpenTelemetry tracer that exports spans over OTLP.
+///
+/// # Arguments
+/// * `endpoint` - The gRPC endpoint of the collector.
+/// * `service_name` - The name spans are reported under.
+pub fn otlp_tracer(endpoint: &str, service_name: String) -> anyhow::Result<trace::Tracer> {
+    opentelemetry::global::set_text_map_propagator(
+        opentelemetry::sdk::propagation::TraceContextPropagator::new(),
+    );
+
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(
+            trace::config()
+                .with_resource(Resource::new([KeyValue::new("service.name", service_name)])),
+        )
+        .install_batch(opentelemetry::runtime::Tokio)?;
+
+    Ok(tracer)
+}
+
+fn make_request_span(request: &Request<Body>) -> Span {
+    let route = monitoring::matched_route(request);
+    let request_id = request_id_of(request).unwrap_or_default();
+
+    let span = tracing::info_span!(
+        "request",
+        otel.name = %format!("{} {}", request.method(), route),
+        otel.kind = "server",
+        method = %request.method(),
+        route,
+        request_id,
+        user_id = Empty,
+        status = Empty,
+    );
+
+    let parent = opentelemetry::global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(request.headers()))
+    });
+    span.set_parent(parent);
+
+    span
+}
+
+fn record_response<B>(response: &Response<B>, _latency: std::time::Duration, span: &Span) {
+    span.record("status", response.status().as_u16());
+}
+
+async fn scope_request_id<B>(request: Request<B>, next: Next<B>) -> axum::response::Response {
+    let request_id = request_id_of(&request).unwrap_or_default();
+
+    REQUEST_ID.scope(request_id, next.run(request)).await
+}
+
+fn request_id_of<B>(request: &Request<B>) -> Option<String> {
+    request
+        .extensions()
+        .get::<RequestId>()
+        .and_then(|id| id.header_value().to_str().ok())
+        .map(str::to_owned)
+}
+
+/// Reads trace context from request headers.
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl<'a> Extractor for HeaderExtractor<'a> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|name| name.as_str()).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::SocketAddr;
+
+    use axum::routing::get;
+
+    use super::*;
+
+    async fn echo_request_id() -> String {
+        current_request_id().unwrap_or_default()
+    }
+
+    /// Serve an application that responds with the ID of each request.
+    fn serve_echo() -> SocketAddr {
+        let app = trace_requests(Router::new().route("/", get(echo_request_id)));
+
+        let server =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(app.into_make_service());
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        addr
+    }
+
+    #[tokio::test]
+    async fn generated_request_id_is_echoed() {
+        let addr = serve_echo();
+
+        let response = reqwest::get(format!("http://{}/", addr)).await.unwrap();
+        let header = response.headers()["x-request-id"]
+            .to_str()
+            .unwrap()
+            .to_owned();
+        let body = response.text().await.unwrap();
+
+        assert!(uuid::Uuid::parse_str(&header).is_ok());
+        assert_eq!(header, body);
+    }
+
+    #[tokio::test]
+    async fn provided_request_id_is_kept() {
+        let addr = serve_echo();
+
+        let response = reqwest::Client::new()
+            .get(format!("http://{}/", addr))
+            .header("x-request-id", "from-proxy")
+            .send()
+            .await
+            .unwrap();
+
+        assert_eq!("from-proxy", response.headers()["x-request-id"]);
+        assert_eq!("from-proxy", response.text().await.unwrap());
+    }
+}